@@ -0,0 +1,3808 @@
+use anyhow::{Context as _, Result};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
+use crossterm::execute;
+use openmetrics_parser::{PrometheusType, PrometheusValue};
+use ratatui::layout::Rect;
+use ratatui::widgets::TableState;
+use ratatui::Terminal;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cli::{FamilyFilter, ViewerArgs};
+use crate::columns::{Column, Columns};
+use crate::events::{self, EventLog};
+use crate::external;
+use crate::fetch::{self, FetchResult, Fetcher, HttpFetcher, Schedule, Source};
+use crate::fuzzy;
+use crate::highlight;
+use crate::history::{self, History};
+use crate::humanize;
+use crate::keys::{self, Action, Context};
+use crate::lint;
+use crate::model::{
+    self, Aggregation, Change, Exposition, Family, FamilyType, Format, Movement, QuantileEstimate, Series, SeriesKey,
+    SkippedLine, Snapshot,
+};
+use crate::output;
+use crate::query;
+use crate::record;
+use crate::replay::Replay;
+use crate::search;
+use crate::state;
+use crate::targets;
+use crate::textfile;
+use crate::theme::{Theme, ThemeName};
+use crate::ui::{aggregated_metric, find_ignore_case, grouped_series, ui};
+
+/// Families are grouped by at most this many leading name segments, so
+/// `node_*` can contain `node_cpu_*` but no deeper.
+const MAX_GROUP_SEGMENTS: usize = 2;
+
+/// How many matches the fuzzy finder lists.
+const FINDER_MATCHES: usize = 10;
+
+/// How many characters `<` and `>` scroll by.
+const SCROLL_STEP: usize = 8;
+
+/// How many rows a notch of the mouse wheel moves the cursor.
+const WHEEL_ROWS: usize = 3;
+
+/// A row drawn on screen, and its index in the view: the table row, the
+/// series in the detail view, or the raw view's line.
+pub(crate) struct ClickTarget {
+    pub(crate) area: Rect,
+    pub(crate) index: usize,
+}
+
+impl ClickTarget {
+    pub(crate) fn contains(&self, column: u16, row: u16) -> bool {
+        (self.area.x..self.area.right()).contains(&column) && (self.area.y..self.area.bottom()).contains(&row)
+    }
+}
+
+/// The first `segments` underscore-delimited segments of `name`, if it has
+/// more than that.
+fn name_prefix(name: &str, segments: usize) -> Option<&str> {
+    name.match_indices('_').nth(segments - 1).map(|(index, _)| &name[..index])
+}
+
+fn in_group(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('_'))
+}
+
+/// One line of the main table once families are folded into prefix groups.
+pub(crate) enum TableRow<'a> {
+    Group {
+        prefix: &'a str,
+        depth: usize,
+        families: usize,
+        series: usize,
+        expanded: bool,
+    },
+    Family {
+        name: &'a String,
+        fam: &'a Family,
+        depth: usize,
+    },
+}
+
+/// Why the latest refresh kept the scrape already shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Unchanged {
+    /// The server answered a conditional request with 304 Not Modified.
+    NotModified,
+    /// The body was the same as the one parsed last.
+    SameContent,
+}
+
+impl Unchanged {
+    pub(crate) fn text(self) -> &'static str {
+        match self {
+            Unchanged::NotModified => "304",
+            Unchanged::SameContent => "same content",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum InputMode {
+    Normal,
+    /// Typing into the `/` filter prompt.
+    Search,
+    /// Typing a time into the `@` prompt of a replay.
+    Jump,
+    /// Editing the expression of `promtool query`.
+    Query,
+    /// Typing into the `f` fuzzy finder.
+    Find,
+    /// Typing another endpoint into the `:` prompt.
+    Endpoint,
+    /// Typing a note for the event log after `m`.
+    Note,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Name,
+    Type,
+    SeriesCount,
+    /// Only meaningful for single-series families; the rest sort last.
+    Value,
+}
+
+impl SortKey {
+    pub(crate) fn next(self) -> SortKey {
+        match self {
+            SortKey::Name => SortKey::Type,
+            SortKey::Type => SortKey::SeriesCount,
+            SortKey::SeriesCount => SortKey::Value,
+            SortKey::Value => SortKey::Name,
+        }
+    }
+
+    pub(crate) fn compare(self, a: (&String, &Family), b: (&String, &Family)) -> Ordering {
+        let by_key = match self {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Type => a.1.family_type.to_string().cmp(&b.1.family_type.to_string()),
+            SortKey::SeriesCount => a.1.iter_samples().count().cmp(&b.1.iter_samples().count()),
+            SortKey::Value => match (single_value(a.1), single_value(b.1)) {
+                (Some(x), Some(y)) => model::compare_values(x, y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        by_key.then_with(|| a.0.cmp(b.0))
+    }
+}
+
+/// The value of a family with exactly one scalar series.
+pub(crate) fn single_value(fam: &Family) -> Option<f64> {
+    let mut samples = fam.iter_samples();
+    match (samples.next(), samples.next()) {
+        (Some(sample), None) => model::scalar_value(&sample.value),
+        _ => None,
+    }
+}
+
+pub(crate) enum View {
+    Table,
+    /// Every series of one family, opened with Enter on a table row.
+    Detail(DetailState),
+    /// The parse error and raw text of the last rejected scrape.
+    ParseError(SourceState),
+    /// Families ranked by series count, toggled with `C`.
+    Cardinality(CardinalityState),
+    /// Naming convention findings for the current scrape, toggled with `L`.
+    Lint(LintState),
+    /// The series changing fastest since the previous scrape, toggled with `H`.
+    Movers(MoversState),
+    /// The current scrape's text, or one family of it, opened with `v` or `V`.
+    Raw(RawState),
+    /// The files of a `--textfile-dir`, toggled with `I`.
+    Textfiles(TextfilesState),
+    /// The event log, newest first, toggled with `E`.
+    Events(EventsState),
+}
+
+pub(crate) struct CardinalityState {
+    pub(crate) selected: usize,
+}
+
+pub(crate) struct LintState {
+    pub(crate) selected: usize,
+}
+
+pub(crate) struct MoversState {
+    pub(crate) selected: usize,
+}
+
+pub(crate) struct TextfilesState {
+    pub(crate) selected: usize,
+}
+
+pub(crate) struct EventsState {
+    pub(crate) selected: usize,
+    /// Only events of this kind are listed; `t` cycles through them.
+    pub(crate) kind: Option<events::Kind>,
+}
+
+pub(crate) struct RawState {
+    /// The family whose lines are shown, or `None` for the whole response.
+    pub(crate) family: Option<String>,
+    /// Index into the lines shown.
+    pub(crate) cursor: usize,
+    /// What `/` looks for and `n`/`N` step between.
+    pub(crate) search: String,
+}
+
+pub(crate) struct DetailState {
+    pub(crate) family: String,
+    /// Index into `grouped_series`, so headers are never selected.
+    pub(crate) selected: usize,
+    /// Label the series are grouped under, chosen with `L`.
+    pub(crate) group_by: Option<String>,
+    /// Labels aggregated away with `w`; while there are any, `selected`
+    /// indexes the aggregated series instead.
+    pub(crate) without: Vec<String>,
+    /// How the series left alike by `without` are combined, cycled with `A`.
+    pub(crate) aggregation: Aggregation,
+    pub(crate) picker: Option<LabelPicker>,
+    /// The `C` pane breaking the family's series count down by label.
+    pub(crate) breakdown: Option<LabelBreakdown>,
+    /// The `label:` search the family was opened under, which only its
+    /// matching series are listed for.
+    pub(crate) label_search: Option<String>,
+}
+
+/// The family's labels as `model::label_cardinality` ranks them, worked
+/// out once when the pane opens rather than on every frame.
+pub(crate) struct LabelBreakdown {
+    pub(crate) series: usize,
+    pub(crate) labels: Vec<model::LabelCardinality>,
+    /// Index into `labels`; starts on the worst one.
+    pub(crate) cursor: usize,
+}
+
+/// The `L` and `w` popups over the detail view.
+pub(crate) struct LabelPicker {
+    pub(crate) cursor: usize,
+    /// The labels ticked so far for `w`; `None` for `L`, whose first entry
+    /// is "no grouping".
+    pub(crate) ticked: Option<Vec<String>>,
+}
+
+/// The `:` prompt for another endpoint.
+#[derive(Default)]
+pub(crate) struct EndpointPrompt {
+    pub(crate) input: String,
+    /// The `endpoint_history` entry ↑ and ↓ last put in `input`.
+    pub(crate) recalled: Option<usize>,
+    /// Alt+Enter asked for a new tab rather than replacing this one.
+    pub(crate) new_tab: bool,
+    /// From Enter until the endpoint's first scrape is in.
+    pub(crate) opening: bool,
+    /// Why the endpoint typed couldn't be opened.
+    pub(crate) error: Option<String>,
+}
+
+/// The expression behind `promtool query`'s results and what the server
+/// said about it.
+pub(crate) struct QueryState {
+    pub(crate) server: String,
+    pub(crate) expr: String,
+    /// The `:` prompt's text.
+    pub(crate) input: String,
+    /// Why the latest evaluation failed, shown under the expression.
+    pub(crate) error: Option<String>,
+    pub(crate) warnings: Vec<String>,
+    /// Kept to start a new fetcher whenever the expression changes.
+    pub(crate) http: HttpFetcher,
+}
+
+/// Position in the raw text of a scrape that failed to parse.
+pub(crate) struct SourceState {
+    /// 0-based line under the cursor.
+    pub(crate) cursor: usize,
+    /// Show only the raw text, without the error panel.
+    pub(crate) raw: bool,
+}
+
+/// The text of the scrape being shown and where each family is in it.
+struct RawScrape {
+    pub(crate) text: String,
+    pub(crate) lines: usize,
+    pub(crate) families: HashMap<String, std::ops::Range<usize>>,
+}
+
+/// The display frozen with space. Scrapes keep arriving underneath, for
+/// counters, history and the baseline, and the latest is shown on resuming.
+#[derive(Default)]
+struct Paused {
+    pub(crate) latest: Option<Pending>,
+    /// Scrapes that arrived since pausing.
+    behind: usize,
+}
+
+impl Paused {
+    /// `PAUSED — 6 refreshes behind`
+    pub(crate) fn text(&self) -> String {
+        match self.behind {
+            0 => String::from("PAUSED"),
+            1 => String::from("PAUSED — 1 refresh behind"),
+            behind => format!("PAUSED — {behind} refreshes behind"),
+        }
+    }
+}
+
+/// A scrape that arrived while paused.
+struct Pending {
+    pub(crate) metrics: Exposition,
+    pub(crate) taken_at: Instant,
+    pub(crate) raw: Option<RawScrape>,
+    /// The scrape before it, for rates once it's shown.
+    pub(crate) previous: Option<Snapshot>,
+}
+
+/// A scrape the parser rejected, kept so the offending lines can be shown.
+struct ParseFailure {
+    pub(crate) message: String,
+    /// 1-based, when the error message names one.
+    pub(crate) line: Option<usize>,
+    pub(crate) text: String,
+}
+
+pub(crate) struct App {
+    /// The URL or file path metrics are read from, for display.
+    pub(crate) endpoint: String,
+    /// Host or file name used in export file names.
+    export_name: String,
+    /// The last exposition that parsed. Kept when a later refresh fails, so
+    /// the table keeps showing stale-but-useful data.
+    pub(crate) current: Option<Exposition>,
+    /// The current scrape's labels, for `label:` searches.
+    label_index: search::LabelIndex,
+    /// The families a `label:` filter matches; `None` for a name filter.
+    pub(crate) label_matches: Option<search::LabelMatches>,
+    /// Why the most recent fetch or parse failed; cleared by the next success.
+    pub(crate) last_error: Option<anyhow::Error>,
+    /// Families pinned with `p`. They sort first and ignore the `/` filter.
+    pub(crate) pinned: HashSet<String>,
+    /// Series and families that appeared or disappeared in recent scrapes.
+    pub(crate) churn: model::Churn,
+    /// Every time a counter went backwards since launch.
+    pub(crate) counter_resets: model::CounterResets,
+    /// Notes and what happened to series, for the `E` view; while replaying,
+    /// the recorded log up to the scrape shown.
+    pub(crate) events: EventLog,
+    /// The `top` fastest-changing series, re-ranked only when a scrape
+    /// arrives so the list holds still while it's being read.
+    pub(crate) movers: Vec<model::Mover>,
+    pub(crate) top: usize,
+    pub(crate) highlight_rules: Vec<highlight::Rule>,
+    /// Which series the rules hold for in the current scrape.
+    pub(crate) highlights: highlight::Highlights,
+    pub(crate) keep_disappeared: u32,
+    pub(crate) show_disappeared: bool,
+    /// Hide series that are exactly zero (`z`).
+    pub(crate) hide_zero: bool,
+    /// Hide series that kept their value through `unchanged_after`
+    /// refreshes (`Z`).
+    pub(crate) hide_unchanged: bool,
+    pub(crate) unchanged_after: u32,
+    pub(crate) steadiness: model::Steadiness,
+    /// Per family, how many series `z` and `Z` hide, counted once per scrape.
+    pub(crate) quiet: HashMap<String, model::Quiet>,
+    /// Series counts per family, recomputed once per scrape rather than on
+    /// every frame.
+    pub(crate) cardinality: Vec<model::Cardinality>,
+    /// The value column of families with several series.
+    pub(crate) summaries: HashMap<String, model::FamilySummary>,
+    /// Naming convention findings for the current scrape, errors first.
+    pub(crate) lints: Vec<lint::Finding>,
+    /// Series and metadata the last scrape's text repeats, whether or not it parsed.
+    pub(crate) duplicates: Vec<lint::Finding>,
+    /// What the fetch layer found off about the last response, like a
+    /// Content-Type Prometheus would reject.
+    pub(crate) fetch_warnings: Vec<String>,
+    /// Series of the current scrape whose own timestamp is further than
+    /// `max_timestamp_skew` from when it was scraped, furthest first.
+    pub(crate) skewed: Vec<model::Skewed>,
+    pub(crate) max_timestamp_skew: Duration,
+    /// Whether the current scrape is OpenMetrics, which names counters differently.
+    pub(crate) openmetrics: bool,
+    /// Set while the most recent scrape failed to parse.
+    pub(crate) parse_failure: Option<ParseFailure>,
+    /// The text behind `current`; `None` for query results and the ALL tab.
+    pub(crate) raw: Option<RawScrape>,
+    /// OpenMetrics `info` and `stateset` families of the current scrape.
+    declared_label_families: HashMap<String, model::LabelFamily>,
+    /// Persisted across frames so ratatui can keep the selected row in view.
+    pub(crate) table_state: TableState,
+    /// Rows that fit in the table or series list, measured on the last draw.
+    pub(crate) viewport_height: usize,
+    pub(crate) fetcher: Fetcher,
+    /// `None` without an `--interval`.
+    schedule: Option<Schedule>,
+    /// Refreshes dropped because the last fetch was still in flight.
+    pub(crate) skipped_refreshes: u64,
+    /// Informational message shown in the status line for a few seconds.
+    pub(crate) notice: Option<(String, Instant)>,
+    /// False for stdin input, which can't be read a second time.
+    pub(crate) refreshable: bool,
+    /// Set by `promtool replay`, which steps through recorded scrapes
+    /// instead of refreshing.
+    pub(crate) replay: Option<Replay>,
+    /// The `@` prompt's text.
+    pub(crate) jump: String,
+    /// The `m` prompt's text.
+    pub(crate) note: String,
+    pub(crate) endpoint_prompt: EndpointPrompt,
+    /// Endpoints opened from the `:` prompt, newest first.
+    pub(crate) endpoint_history: Vec<String>,
+    /// Whether `run_app` can open endpoints typed into the `:` prompt, which
+    /// only the viewer of live endpoints can.
+    pub(crate) opens_endpoints: bool,
+    /// The rows the last frame drew, for finding what a click landed on.
+    pub(crate) click_targets: Vec<ClickTarget>,
+    /// How many characters names and labels are scrolled left by, with `<`
+    /// and `>`.
+    pub(crate) scroll: usize,
+    /// The fuzzy finder's text, and which of its matches is highlighted.
+    pub(crate) finder: String,
+    pub(crate) finder_selected: usize,
+    /// Set by `promtool query`, whose "scrapes" are query results.
+    pub(crate) query: Option<QueryState>,
+    /// Set for the ALL tab, which shows every other tab's families merged
+    /// rather than fetching anything itself. Cycled with `a`.
+    pub(crate) aggregation: Option<Aggregation>,
+    /// Labels from `--targets-file` added to every series of this tab.
+    pub(crate) target_labels: Vec<(String, String)>,
+    /// Entries of `--targets-file` that didn't get a tab, shown in the ALL tab.
+    pub(crate) invalid_targets: Vec<String>,
+    /// TYPE conflicts between endpoints found while merging the ALL tab, or
+    /// between the files of a `--textfile-dir`.
+    pub(crate) merge_warnings: Vec<String>,
+    /// How each file of a `--textfile-dir` fared on the last refresh; `None`
+    /// for every other source.
+    pub(crate) textfiles: Option<Vec<textfile::FileStatus>>,
+    pub(crate) last_success_at: Option<Instant>,
+    /// When the latest refresh found nothing new, why and when it was.
+    pub(crate) unchanged: Option<(Unchanged, Instant)>,
+    /// A hash of the last body parsed, content type included, so the same
+    /// body isn't parsed again.
+    body_hash: Option<u64>,
+    pub(crate) last_fetch_duration: Duration,
+    /// Bytes on the wire and after decompression for the last good fetch.
+    pub(crate) last_body_size: Option<(usize, usize)>,
+    pub(crate) last_status: Option<reqwest::StatusCode>,
+    pub(crate) last_parse_duration: Duration,
+    pub(crate) input_mode: InputMode,
+    /// Case-insensitive substring matched against family names.
+    pub(crate) filter: String,
+    /// Cycled with `t`; composes with `filter`.
+    pub(crate) type_filter: Option<FamilyType>,
+    /// Prefix groups folded with ←, kept across refreshes.
+    collapsed: HashSet<String>,
+    pub(crate) family_filter: FamilyFilter,
+    pub(crate) format: Format,
+    pub(crate) lenient: bool,
+    pub(crate) max_series: Option<usize>,
+    /// What `max_series` left out of the latest scrape.
+    pub(crate) truncated: Option<model::Truncated>,
+    /// Lines `--lenient` dropped from the latest scrape.
+    pub(crate) skipped_lines: Vec<SkippedLine>,
+    pub(crate) show_skipped: bool,
+    /// The request and response panel, opened with `d`.
+    pub(crate) show_exchange: bool,
+    /// What was sent and received on the last HTTP scrape, failed or not.
+    pub(crate) exchange: Option<fetch::Exchange>,
+    /// Opened on first use and kept so X11 selections outlive the copy.
+    clipboard: Option<arboard::Clipboard>,
+    pub(crate) view: View,
+    /// Cycled with `T`.
+    pub(crate) theme: ThemeName,
+    /// Exact values instead of humanized ones (`u`).
+    pub(crate) raw_values: bool,
+    /// Set by `--no-external` to turn off `o`, `O`, `X` and `W`.
+    pub(crate) no_external: bool,
+    pub(crate) trace_url_template: Option<String>,
+    /// Where `W` graphs the selected series: `--grafana-url` wins over
+    /// `--prometheus-url`.
+    pub(crate) prometheus_url: Option<String>,
+    pub(crate) grafana_url: Option<String>,
+    /// Whether the HELP footer under the table is shown (toggled with `h`).
+    pub(crate) show_help_footer: bool,
+    /// Values from the scrape before `current`, for rates and deltas.
+    pub(crate) previous: Option<Snapshot>,
+    /// Recent values of pinned and detail-view series, for charts.
+    pub(crate) history: History,
+    /// Show rates/deltas instead of raw values in the main table (`R`).
+    pub(crate) show_rates: bool,
+    /// What the detail view estimates from a histogram's buckets.
+    pub(crate) quantiles: Vec<f64>,
+    /// Shown instead of a histogram family's observations in the table.
+    pub(crate) table_quantile: Option<f64>,
+    /// Set while the display is frozen on `current`.
+    pub(crate) paused: Option<Paused>,
+    /// What `B` took to compare later scrapes against.
+    pub(crate) baseline: Option<model::Baseline>,
+    /// Show the change since `baseline` (`b`), which taking one turns on.
+    pub(crate) show_baseline: bool,
+    /// Per-family movement since the previous scrape, computed once per refresh.
+    pub(crate) movements: HashMap<String, Movement>,
+    /// Color values that moved since the previous scrape (`c`).
+    pub(crate) highlight_changes: bool,
+    pub(crate) sort_key: SortKey,
+    pub(crate) sort_reversed: bool,
+    /// The current scrape's families by `sort_key`, sorted once per scrape
+    /// or change of sort rather than on every frame.
+    order: Vec<String>,
+    /// Where the pins, filter, sort, theme and columns are saved between
+    /// sessions; `None` with `--no-state` and for stdin, queries, replays
+    /// and the ALL tab.
+    state_path: Option<PathBuf>,
+    /// What was last saved or loaded, so only changes are written.
+    saved_state: Option<state::State>,
+    /// The theme last picked with `T`, in this session or a saved one.
+    chosen_theme: Option<ThemeName>,
+    /// The main table's columns, from `--columns` or picked with `|`.
+    pub(crate) columns: Columns,
+    /// The columns last picked with `|`, in this session or a saved one.
+    chosen_columns: Option<Columns>,
+    /// The `|` picker is open, its cursor on this entry of
+    /// [`Columns::listed`].
+    pub(crate) column_picker: Option<usize>,
+    /// The `?` keybinding overlay is open.
+    pub(crate) show_keybindings: bool,
+}
+
+impl App {
+    /// `fetcher` must have been spawned for `source`.
+    pub(crate) fn new(
+        source: Source,
+        fetcher: Fetcher,
+        refresh_interval: Option<Duration>,
+        family_filter: FamilyFilter,
+        format: Format,
+        lenient: bool,
+        keep_disappeared: u32,
+    ) -> App {
+        let mut app = App {
+            endpoint: source.to_string(),
+            export_name: source.short_name(),
+            refreshable: source.is_refreshable(),
+            replay: None,
+            jump: String::new(),
+            note: String::new(),
+            endpoint_prompt: EndpointPrompt::default(),
+            endpoint_history: Vec::new(),
+            opens_endpoints: false,
+            click_targets: Vec::new(),
+            scroll: 0,
+            finder: String::new(),
+            finder_selected: 0,
+            query: None,
+            aggregation: None,
+            target_labels: Vec::new(),
+            invalid_targets: Vec::new(),
+            merge_warnings: Vec::new(),
+            textfiles: matches!(source, Source::TextfileDir(_)).then(Vec::new),
+            fetcher,
+            current: None,
+            label_index: search::LabelIndex::default(),
+            label_matches: None,
+            last_error: None,
+            pinned: HashSet::new(),
+            churn: model::Churn::default(),
+            counter_resets: model::CounterResets::default(),
+            events: EventLog::default(),
+            movers: Vec::new(),
+            top: 20,
+            highlight_rules: Vec::new(),
+            highlights: highlight::Highlights::default(),
+            keep_disappeared,
+            show_disappeared: true,
+            hide_zero: false,
+            hide_unchanged: false,
+            unchanged_after: 5,
+            steadiness: model::Steadiness::default(),
+            quiet: HashMap::new(),
+            cardinality: Vec::new(),
+            summaries: HashMap::new(),
+            lints: Vec::new(),
+            duplicates: Vec::new(),
+            fetch_warnings: Vec::new(),
+            skewed: Vec::new(),
+            max_timestamp_skew: Duration::from_secs(300),
+            openmetrics: false,
+            parse_failure: None,
+            raw: None,
+            declared_label_families: HashMap::new(),
+            table_state: TableState::default().with_selected(Some(0)),
+            viewport_height: 1,
+            schedule: refresh_interval.map(|interval| Schedule::start(&source.to_string(), interval)),
+            skipped_refreshes: 0,
+            notice: None,
+            last_success_at: None,
+            unchanged: None,
+            body_hash: None,
+            last_fetch_duration: Duration::ZERO,
+            last_body_size: None,
+            last_status: None,
+            last_parse_duration: Duration::ZERO,
+            input_mode: InputMode::Normal,
+            filter: String::new(),
+            type_filter: None,
+            collapsed: HashSet::new(),
+            family_filter,
+            format,
+            lenient,
+            max_series: None,
+            truncated: None,
+            skipped_lines: Vec::new(),
+            show_skipped: false,
+            show_exchange: false,
+            exchange: None,
+            clipboard: None,
+            theme: ThemeName::Dark,
+            raw_values: false,
+            no_external: false,
+            trace_url_template: None,
+            prometheus_url: None,
+            grafana_url: None,
+            view: View::Table,
+            show_help_footer: true,
+            previous: None,
+            history: History::new(history::DEFAULT_CAPACITY),
+            show_rates: false,
+            quantiles: vec![0.5, 0.9, 0.99],
+            table_quantile: None,
+            paused: None,
+            baseline: None,
+            show_baseline: false,
+            movements: HashMap::new(),
+            highlight_changes: true,
+            sort_key: SortKey::Name,
+            sort_reversed: false,
+            order: Vec::new(),
+            state_path: None,
+            saved_state: None,
+            chosen_theme: None,
+            columns: Columns::default(),
+            chosen_columns: None,
+            column_picker: None,
+            show_keybindings: false,
+        };
+        if app.refreshable {
+            app.refresh();
+        }
+        app
+    }
+
+    /// The viewer options that aren't needed until the terminal opens.
+    pub(crate) fn apply_args(&mut self, args: &ViewerArgs, pins: Vec<String>) {
+        self.pinned.extend(pins);
+        self.theme = ThemeName::from_env(args.theme);
+        self.raw_values = args.raw_values;
+        self.no_external = args.no_external;
+        self.trace_url_template = args.trace_url_template.clone();
+        self.prometheus_url = args.prometheus_url.clone();
+        self.grafana_url = args.grafana_url.clone();
+        self.history = History::new(args.history);
+        self.max_timestamp_skew = args.max_timestamp_skew;
+        self.top = args.top;
+        self.quantiles = args.quantiles.clone();
+        self.table_quantile = args.table_quantile;
+        self.unchanged_after = args.unchanged_after;
+        self.highlight_rules = args.highlight.clone();
+        self.max_series = args.max_series;
+        if let Some(columns) = &args.columns {
+            self.columns = columns.clone();
+        }
+    }
+
+    /// Families that pass the current filter, in display order.
+    pub(crate) fn visible_families(&self) -> Vec<(&String, &Family)> {
+        let mut families: Vec<(&String, &Family)> = match &self.current {
+            Some(metrics) => self
+                .order
+                .iter()
+                .filter_map(|name| metrics.families.get_key_value(name))
+                .filter(|(name, fam)| {
+                    self.pinned.contains(*name)
+                        || (self.matches_filter(name)
+                            && self.type_filter.is_none_or(|t| t == FamilyType::of(fam))
+                            && !self.hides_family(name))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // Pinned families stay on top whichever way the rest is sorted.
+        families.sort_by_key(|(name, _)| !self.pinned.contains(*name));
+        families
+    }
+
+    /// Whether `z` or `Z` hides the series `sample` of `family`.
+    pub(crate) fn hides_series(&self, family: &str, sample: &Series) -> bool {
+        (self.hide_zero && model::series_number(&sample.value) == Some(0.0))
+            || (self.hide_unchanged && self.steadiness.unchanged(&SeriesKey::new(family, sample), self.unchanged_after))
+    }
+
+    /// How many series `z` and `Z` hide in `quiet`, each counted once.
+    fn hidden_in(&self, quiet: &model::Quiet) -> usize {
+        match (self.hide_zero, self.hide_unchanged) {
+            (true, true) => quiet.either,
+            (true, false) => quiet.zero,
+            (false, true) => quiet.unchanged,
+            (false, false) => 0,
+        }
+    }
+
+    /// Whether `z` and `Z` hide every series of the family `name`, and so
+    /// the family too.
+    fn hides_family(&self, name: &str) -> bool {
+        self.quiet.get(name).is_some_and(|quiet| quiet.series > 0 && self.hidden_in(quiet) == quiet.series)
+    }
+
+    /// Series of the whole scrape `z` and `Z` hide: those that are zero, then
+    /// those that are only unchanged.
+    pub(crate) fn hidden_series(&self) -> (usize, usize) {
+        let zero: usize = self.quiet.values().map(|quiet| quiet.zero).sum();
+        let hidden: usize = self.quiet.values().map(|quiet| self.hidden_in(quiet)).sum();
+        if self.hide_zero {
+            (zero, hidden - zero)
+        } else {
+            (0, hidden)
+        }
+    }
+
+    /// The detail view's series in display order, less those `z` and `Z` hide.
+    pub(crate) fn detail_series<'a>(&self, fam: &'a Family, detail: &DetailState) -> Vec<(Option<String>, &'a Series)> {
+        let mut series = grouped_series(fam, detail.group_by.as_deref(), detail.label_search.as_deref());
+        series.retain(|(_, sample)| !self.hides_series(&detail.family, sample));
+        series
+    }
+
+    /// Hides or shows again the series that are zero, or with `unchanged`
+    /// those that don't move, keeping the cursor where it was.
+    fn toggle_hidden(&mut self, unchanged: bool) {
+        let selected_series = self.selected_series_key();
+        self.keeping_selection(|app| {
+            if unchanged {
+                app.hide_unchanged = !app.hide_unchanged;
+            } else {
+                app.hide_zero = !app.hide_zero;
+            }
+        });
+        if let Some(key) = selected_series {
+            self.reselect_series(&key);
+        }
+    }
+
+    /// Sorts the current scrape's families into `order` again.
+    fn sort_families(&mut self) {
+        let mut families: Vec<(&String, &Family)> = match &self.current {
+            Some(metrics) => metrics.families.iter().collect(),
+            None => Vec::new(),
+        };
+        families.sort_by(|a, b| {
+            let ordering = self.sort_key.compare(*a, *b);
+            if self.sort_reversed {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        self.order = families.into_iter().map(|(name, _)| name.clone()).collect();
+    }
+
+    /// `visible_families` folded into prefix groups: what the table shows and
+    /// what its selection indexes into. Pinned families stay ungrouped on top.
+    pub(crate) fn table_rows(&self) -> Vec<TableRow<'_>> {
+        let (pinned, rest): (Vec<_>, Vec<_>) =
+            self.visible_families().into_iter().partition(|(name, _)| self.pinned.contains(*name));
+        let mut rows: Vec<TableRow> = pinned
+            .into_iter()
+            .map(|(name, fam)| TableRow::Family { name, fam, depth: 0 })
+            .collect();
+        self.push_groups(&mut rows, rest, 1, 0);
+        rows
+    }
+
+    /// Appends `families`, already in display order, with those sharing their
+    /// first `segments` name segments pulled together under a group row.
+    /// A group holding every family is skipped in favour of the next level.
+    fn push_groups<'a>(
+        &self,
+        rows: &mut Vec<TableRow<'a>>,
+        families: Vec<(&'a String, &'a Family)>,
+        segments: usize,
+        depth: usize,
+    ) {
+        if segments > MAX_GROUP_SEGMENTS {
+            rows.extend(families.into_iter().map(|(name, fam)| TableRow::Family { name, fam, depth }));
+            return;
+        }
+        let total = families.len();
+        let mut buckets: Vec<(Option<&'a str>, Vec<(&'a String, &'a Family)>)> = Vec::new();
+        // Where each prefix's bucket is, as there can be as many as families.
+        let mut by_prefix: HashMap<&'a str, usize> = HashMap::new();
+        for (name, fam) in families {
+            let prefix = name_prefix(name, segments);
+            match prefix.and_then(|prefix| by_prefix.get(prefix)) {
+                Some(&index) => buckets[index].1.push((name, fam)),
+                None => {
+                    if let Some(prefix) = prefix {
+                        by_prefix.insert(prefix, buckets.len());
+                    }
+                    buckets.push((prefix, vec![(name, fam)]));
+                }
+            }
+        }
+        // A filter means every family shown is a match, so nothing that
+        // contains one stays folded away.
+        let filtering = !self.filter.is_empty() || self.type_filter.is_some();
+        for (prefix, members) in buckets {
+            match prefix {
+                Some(_) if members.len() == total => self.push_groups(rows, members, segments + 1, depth),
+                Some(prefix) if members.len() > 1 => {
+                    let expanded = filtering || !self.collapsed.contains(prefix);
+                    rows.push(TableRow::Group {
+                        prefix,
+                        depth,
+                        families: members.len(),
+                        series: members.iter().map(|(name, fam)| self.series_count(name, fam)).sum(),
+                        expanded,
+                    });
+                    if expanded {
+                        self.push_groups(rows, members, segments + 1, depth + 1);
+                    }
+                }
+                _ => rows.extend(members.into_iter().map(|(name, fam)| TableRow::Family { name, fam, depth })),
+            }
+        }
+    }
+
+    /// How many series `fam` has, counted once per scrape.
+    fn series_count(&self, name: &str, fam: &Family) -> usize {
+        self.summaries.get(name).map_or_else(|| fam.iter_samples().count(), |summary| summary.series)
+    }
+
+    /// The table row showing `name`: its own, or that of the folded group
+    /// hiding it.
+    fn row_index(&self, name: &str) -> Option<usize> {
+        self.table_rows().iter().position(|row| match row {
+            TableRow::Family { name: own, .. } => own.as_str() == name,
+            TableRow::Group {
+                prefix, expanded: false, ..
+            } => in_group(name, prefix),
+            TableRow::Group { .. } => false,
+        })
+    }
+
+    /// Expands or folds the group under the cursor. Folding on a family
+    /// folds the group it's in and moves the cursor up to it.
+    fn set_expanded(&mut self, expand: bool) {
+        let rows = self.table_rows();
+        let prefix = match rows.get(self.selected()) {
+            Some(TableRow::Group { prefix, .. }) => Some(prefix.to_string()),
+            Some(TableRow::Family { name, depth, .. }) if !expand && *depth > 0 => rows[..self.selected()]
+                .iter()
+                .rev()
+                .find_map(|row| match row {
+                    TableRow::Group { prefix, depth: d, .. } if d < depth && in_group(name, prefix) => {
+                        Some(prefix.to_string())
+                    }
+                    _ => None,
+                }),
+            _ => None,
+        };
+        let Some(prefix) = prefix else {
+            return;
+        };
+        if expand {
+            self.collapsed.remove(&prefix);
+        } else {
+            self.collapsed.insert(prefix.clone());
+        }
+        let index = self.table_rows().iter().position(|row| matches!(row, TableRow::Group { prefix: p, .. } if *p == prefix));
+        self.select(index.unwrap_or(0));
+    }
+
+    /// Pins or unpins the selected family, keeping it selected as it moves.
+    fn toggle_pin(&mut self) {
+        let Some(name) = self.selected_family_name() else {
+            return;
+        };
+        if !self.pinned.remove(&name) {
+            self.pinned.insert(name.clone());
+        }
+        self.reselect(&name);
+    }
+
+    fn clear_pins(&mut self) {
+        self.keeping_selection(|app| app.pinned.clear());
+    }
+
+    /// Changes the table's rows, by a refresh, filter or sort, with the
+    /// selected family still selected wherever it ends up.
+    fn keeping_selection(&mut self, change: impl FnOnce(&mut App)) {
+        let selected = self.selected_family_name();
+        change(self);
+        self.update_label_search();
+        match selected {
+            Some(name) => self.reselect(&name),
+            None => self.clamp_selection(),
+        }
+    }
+
+    /// Re-runs a `label:` filter's search if its text or the scrape changed,
+    /// and drops it once the filter isn't one.
+    fn update_label_search(&mut self) {
+        let Some(needle) = search::label_needle(&self.filter) else {
+            self.label_matches = None;
+            return;
+        };
+        if self.label_matches.as_ref().is_none_or(|matches| matches.needle != needle) {
+            self.label_matches = Some(self.label_index.search(needle));
+        }
+    }
+
+    /// Whether the `/` filter keeps `name`: by name, or for a `label:`
+    /// filter by having a matching series.
+    pub(crate) fn matches_filter(&self, name: &str) -> bool {
+        match &self.label_matches {
+            Some(matches) => matches.families.contains_key(name),
+            None => find_ignore_case(name, &self.filter).is_some(),
+        }
+    }
+
+    /// The part of the filter found in family names, to highlight; none
+    /// for a `label:` filter.
+    pub(crate) fn name_filter(&self) -> &str {
+        match self.label_matches {
+            Some(_) => "",
+            None => &self.filter,
+        }
+    }
+
+    /// Selects `name` again after the visible rows were reordered, or the
+    /// nearest row if it's no longer visible.
+    fn reselect(&mut self, name: &str) {
+        match self.row_index(name) {
+            Some(index) => self.select(index),
+            None => self.clamp_selection(),
+        }
+    }
+
+    /// What's saved between sessions, as it is now.
+    pub(crate) fn state(&self) -> state::State {
+        let mut pinned: Vec<String> = self.pinned.iter().cloned().collect();
+        pinned.sort();
+        state::State {
+            pinned,
+            filter: self.filter.clone(),
+            sort: self.sort_key,
+            sort_reversed: self.sort_reversed,
+            theme: self.chosen_theme,
+            columns: self.chosen_columns.clone(),
+            ..state::State::new(&self.endpoint)
+        }
+    }
+
+    /// Picks up the pins, filter, sort, theme and columns saved at `path`,
+    /// and saves them there from now on. The saved theme and columns don't
+    /// override `--theme` and `--columns`.
+    pub(crate) fn restore_state(&mut self, path: PathBuf, keep_theme: bool, keep_columns: bool) {
+        match state::load(&path, &self.endpoint) {
+            Ok(Some(saved)) => {
+                self.pinned.extend(saved.pinned);
+                self.filter = saved.filter;
+                self.sort_key = saved.sort;
+                self.sort_reversed = saved.sort_reversed;
+                self.sort_families();
+                self.chosen_theme = saved.theme;
+                if let Some(theme) = saved.theme.filter(|_| !keep_theme) {
+                    self.theme = ThemeName::from_env(theme);
+                }
+                self.chosen_columns = saved.columns.clone();
+                if let Some(columns) = saved.columns.filter(|_| !keep_columns) {
+                    self.columns = columns;
+                }
+            }
+            Ok(None) => {}
+            Err(warning) => self.notify(warning),
+        }
+        self.saved_state = Some(self.state());
+        self.state_path = Some(path);
+    }
+
+    /// Saves the state if it changed since it was last saved or loaded. Not
+    /// while a prompt is open, so the filter isn't written per keystroke.
+    fn save_state(&mut self) {
+        let Some(path) = self.state_path.clone() else {
+            return;
+        };
+        let state = self.state();
+        if self.input_mode != InputMode::Normal || self.saved_state.as_ref() == Some(&state) {
+            return;
+        }
+        if let Err(e) = state::save(&path, &state) {
+            self.notify(format!("{e:#}"));
+        }
+        self.saved_state = Some(state);
+    }
+
+    /// Deletes the state saved for this endpoint. Nothing is saved again
+    /// until something changes.
+    fn forget_state(&mut self) {
+        let Some(path) = self.state_path.clone() else {
+            self.notify("nothing is saved for this tab");
+            return;
+        };
+        match state::clear(&path) {
+            Ok(()) => {
+                self.saved_state = Some(self.state());
+                self.notify("forgot the saved pins, filter, sort, theme and columns");
+            }
+            Err(e) => self.notify(format!("{e:#}")),
+        }
+    }
+
+    /// Re-sorts while keeping the same family selected.
+    fn set_sort(&mut self, key: SortKey, reversed: bool) {
+        self.keeping_selection(|app| {
+            app.sort_key = key;
+            app.sort_reversed = reversed;
+            app.sort_families();
+        });
+    }
+
+    /// The series under the cursor in the detail view, unless it's showing
+    /// aggregates.
+    fn selected_series_key(&self) -> Option<SeriesKey> {
+        let View::Detail(detail) = &self.view else {
+            return None;
+        };
+        if !detail.without.is_empty() {
+            return None;
+        }
+        let fam = self.family(&detail.family)?;
+        let (_, sample) = self.detail_series(fam, detail).into_iter().nth(detail.selected)?;
+        Some(SeriesKey::new(&detail.family, sample))
+    }
+
+    /// Moves the detail view's cursor back onto `key` after its series were
+    /// re-read or regrouped, leaving it in place when the series is gone.
+    fn reselect_series(&mut self, key: &SeriesKey) {
+        let View::Detail(detail) = &self.view else {
+            return;
+        };
+        let index = self.family(&detail.family).and_then(|fam| {
+            self.detail_series(fam, detail)
+                .iter()
+                .position(|(_, sample)| SeriesKey::new(&detail.family, sample) == *key)
+        });
+        if let (Some(index), View::Detail(detail)) = (index, &mut self.view) {
+            detail.selected = index;
+        }
+    }
+
+    pub(crate) fn family(&self, name: &str) -> Option<&Family> {
+        self.current.as_ref()?.families.get(name)
+    }
+
+    /// Whether the table is showing an older scrape because the latest
+    /// refresh failed.
+    pub(crate) fn is_stale(&self) -> bool {
+        self.current.is_some() && self.last_error.is_some()
+    }
+
+    /// The family under the cursor in the table; `None` on a group row.
+    pub(crate) fn selected_family_name(&self) -> Option<String> {
+        match self.table_rows().get(self.selected()) {
+            Some(TableRow::Family { name, .. }) => Some(name.to_string()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn selected(&self) -> usize {
+        self.table_state.selected().unwrap_or(0)
+    }
+
+    /// Selects `index`, clamped to the rows currently visible.
+    pub(crate) fn select(&mut self, index: usize) {
+        let visible = self.table_rows().len();
+        self.table_state.select(Some(index.min(visible.saturating_sub(1))));
+    }
+
+    /// Keeps the selection pointing at a row after the visible set shrinks.
+    fn clamp_selection(&mut self) {
+        self.select(self.selected());
+    }
+
+    /// Opens the selected family, or unfolds/folds the selected group.
+    fn open_detail(&mut self) {
+        if let View::Movers(state) = &self.view {
+            let Some(key) = self.movers.get(state.selected).map(|mover| mover.key.clone()) else {
+                return;
+            };
+            let selected = self.family(&key.family).and_then(|fam| {
+                grouped_series(fam, None, None)
+                    .iter()
+                    .filter(|(_, sample)| !self.hides_series(&key.family, sample))
+                    .position(|(_, sample)| SeriesKey::new(&key.family, sample) == key)
+            });
+            self.show_detail(key.family, selected.unwrap_or(0));
+            return;
+        }
+        if let Some(family) = self.selected_family_name() {
+            let label_search = self.label_matches.as_ref().map(|matches| matches.needle.clone());
+            self.show_detail(family, 0);
+            if let View::Detail(detail) = &mut self.view {
+                detail.label_search = label_search;
+            }
+            return;
+        }
+        let expanded = match self.table_rows().get(self.selected()) {
+            Some(TableRow::Group { expanded, .. }) => *expanded,
+            _ => return,
+        };
+        self.set_expanded(!expanded);
+    }
+
+    fn show_detail(&mut self, family: String, selected: usize) {
+        self.view = View::Detail(DetailState {
+            family,
+            selected,
+            group_by: None,
+            without: Vec::new(),
+            aggregation: Aggregation::Sum,
+            picker: None,
+            breakdown: None,
+            label_search: None,
+        });
+    }
+
+    /// Opens the selected family's lines as exposed, or with `whole` the
+    /// entire response with the cursor on the family's first line.
+    fn open_raw(&mut self, whole: bool) {
+        let family = match &self.view {
+            View::Detail(detail) => Some(detail.family.clone()),
+            _ => self.selected_family_name(),
+        };
+        let Some(raw) = &self.raw else {
+            self.notify("the raw text is only kept for scrapes");
+            return;
+        };
+        let start = family.as_ref().and_then(|family| raw.families.get(family)).map(|lines| lines.start);
+        let (family, cursor) = match (whole, family, start) {
+            (true, _, start) => (None, start.unwrap_or(0)),
+            (false, Some(family), Some(_)) => (Some(family), 0),
+            (false, Some(family), None) => {
+                self.notify(format!("couldn't find {family} in the raw text"));
+                return;
+            }
+            (false, None, _) => {
+                self.notify("select a family first");
+                return;
+            }
+        };
+        self.view = View::Raw(RawState {
+            family,
+            cursor,
+            search: String::new(),
+        });
+    }
+
+    /// The raw text and which of its lines the raw view shows.
+    pub(crate) fn raw_lines(&self, state: &RawState) -> Option<(&RawScrape, std::ops::Range<usize>)> {
+        let raw = self.raw.as_ref()?;
+        let lines = match &state.family {
+            Some(family) => raw.families.get(family)?.clone(),
+            None => 0..raw.lines,
+        };
+        Some((raw, lines))
+    }
+
+    /// Moves the raw view's cursor to the next line containing the search,
+    /// wrapping around; `skip_current` looks past the line under the cursor.
+    fn find_raw_match(&mut self, skip_current: bool, forward: bool) {
+        let View::Raw(state) = &self.view else {
+            return;
+        };
+        let Some((raw, lines)) = self.raw_lines(state).filter(|(_, lines)| !lines.is_empty()) else {
+            return;
+        };
+        if state.search.is_empty() {
+            return;
+        }
+        let shown: Vec<&str> = raw.text.lines().skip(lines.start).take(lines.len()).collect();
+        let count = shown.len();
+        let start = state.cursor.min(count.saturating_sub(1));
+        let step = usize::from(skip_current);
+        let found = (0..count)
+            .map(|i| if forward { (start + step + i) % count } else { (start + 2 * count - step - i) % count })
+            .find(|&index| find_ignore_case(shown[index], &state.search).is_some());
+        let Some(index) = found else {
+            let message = format!("no line contains {:?}", state.search);
+            self.notify(message);
+            return;
+        };
+        if let View::Raw(state) = &mut self.view {
+            state.cursor = index;
+        }
+    }
+
+    /// Suspends the viewer to show the raw text in the pager or editor: the
+    /// rejected scrape from the parse error view, otherwise the current one.
+    fn open_external<B: ratatui::backend::Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        program: external::Program,
+        mouse: bool,
+    ) {
+        if self.no_external {
+            self.notify("starting other programs is turned off by --no-external");
+            return;
+        }
+        let text = match (&self.view, &self.parse_failure, &self.raw) {
+            (View::ParseError(_), Some(failure), _) => &failure.text,
+            (_, _, Some(raw)) => &raw.text,
+            _ => {
+                self.notify("the raw text is only kept for scrapes");
+                return;
+            }
+        };
+        if let Err(e) = external::open(terminal, program, text, mouse) {
+            self.notify(format!("{e:#}"));
+        }
+    }
+
+    pub(crate) fn label_family(&self, name: &str, fam: &Family) -> Option<model::LabelFamily> {
+        model::label_family(name, fam, &self.declared_label_families)
+    }
+
+    /// What the `/` prompt edits: the raw view's search or the family filter.
+    pub(crate) fn search_text(&self) -> &str {
+        match &self.view {
+            View::Raw(state) => &state.search,
+            _ => &self.filter,
+        }
+    }
+
+    fn toggle_movers(&mut self) {
+        self.view = match self.view {
+            View::Movers(_) => View::Table,
+            _ => View::Movers(MoversState { selected: 0 }),
+        };
+    }
+
+    fn toggle_events(&mut self) {
+        self.view = match self.view {
+            View::Events(_) => View::Table,
+            _ => View::Events(EventsState {
+                selected: 0,
+                kind: None,
+            }),
+        };
+    }
+
+    /// The events the `E` view lists, newest first.
+    pub(crate) fn listed_events(&self) -> Vec<&events::Event> {
+        let kind = match &self.view {
+            View::Events(state) => state.kind,
+            _ => None,
+        };
+        self.events.newest_first(kind).collect()
+    }
+
+    fn toggle_textfiles(&mut self) {
+        if matches!(self.view, View::Textfiles(_)) {
+            self.view = View::Table;
+        } else if self.textfiles.is_some() {
+            self.view = View::Textfiles(TextfilesState { selected: 0 });
+        } else {
+            self.notify("only --textfile-dir has files to list");
+        }
+    }
+
+    fn toggle_cardinality(&mut self) {
+        if matches!(self.view, View::Cardinality(_)) {
+            self.view = View::Table;
+            return;
+        }
+        let current = self.selected_family_name();
+        let selected = current
+            .and_then(|name| self.cardinality.iter().position(|c| c.family == name))
+            .unwrap_or(0);
+        self.view = View::Cardinality(CardinalityState { selected });
+    }
+
+    /// What the lint view lists: duplicates in the raw text, then the
+    /// naming convention findings.
+    pub(crate) fn findings(&self) -> impl Iterator<Item = &lint::Finding> {
+        self.duplicates.iter().chain(&self.lints)
+    }
+
+    fn toggle_lint(&mut self) {
+        if matches!(self.view, View::Lint(_)) {
+            self.view = View::Table;
+            return;
+        }
+        let current = self.selected_family_name();
+        let selected = current
+            .and_then(|name| self.findings().position(|finding| finding.family == name))
+            .unwrap_or(0);
+        self.view = View::Lint(LintState { selected });
+    }
+
+    /// Leaves the cardinality or lint view with its selected family selected
+    /// in the table, clearing the `/` filter if that would hide it.
+    fn jump_to_family(&mut self) {
+        let name = match &self.view {
+            View::Cardinality(state) => self.cardinality.get(state.selected).map(|c| c.family.clone()),
+            View::Lint(state) => self.findings().nth(state.selected).map(|finding| finding.family.clone()),
+            _ => None,
+        };
+        if let Some(name) = name {
+            self.show_family(&name);
+        }
+    }
+
+    /// Goes back to the table with `name` selected, dropping a filter or
+    /// fold that would hide it.
+    fn show_family(&mut self, name: &str) {
+        if !self.matches_filter(name) {
+            self.filter.clear();
+            self.label_matches = None;
+        }
+        let fam = self.family(name);
+        if self.type_filter.is_some() && fam.map(FamilyType::of) != self.type_filter {
+            self.type_filter = None;
+        }
+        self.collapsed.retain(|prefix| !in_group(name, prefix));
+        self.view = View::Table;
+        let index = self.row_index(name);
+        self.select(index.unwrap_or(0));
+    }
+
+    /// The furthest `>` scrolls: far enough to bring the end of the longest
+    /// name, or of the open family's longest labelset, into view.
+    fn scroll_limit(&self) -> usize {
+        let longest = match &self.view {
+            View::Detail(detail) => self.family(&detail.family).map_or(0, |fam| {
+                fam.iter_samples()
+                    .map(|sample| model::format_labels(&model::label_pairs(sample)).chars().count())
+                    .max()
+                    .unwrap_or(0)
+            }),
+            _ => self.visible_families().iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0),
+        };
+        longest.saturating_sub(SCROLL_STEP)
+    }
+
+    /// The fuzzy finder's best matches among every family of the scrape,
+    /// filtered or not.
+    pub(crate) fn finder_matches(&self) -> Vec<fuzzy::Match<'_>> {
+        let names = self.current.iter().flat_map(|metrics| metrics.families.keys().map(String::as_str));
+        fuzzy::rank(&self.finder, names, FINDER_MATCHES)
+    }
+
+    /// PromQL selector for the series under the cursor in the detail view.
+    fn selected_series_selector(&self) -> Option<String> {
+        let View::Detail(detail) = &self.view else {
+            return None;
+        };
+        let fam = self.family(&detail.family)?;
+        if let Some(aggregates) = self.detail_aggregates(detail) {
+            let aggregate = aggregates.get(detail.selected)?;
+            let selector = model::promql_selector(&aggregated_metric(&detail.family, fam), &aggregate.labels);
+            let rate = self.aggregates_rates(fam);
+            return Some(model::aggregation_expression(detail.aggregation, &detail.without, &selector, rate));
+        }
+        let (_, sample) = self.detail_series(fam, detail).into_iter().nth(detail.selected)?;
+        Some(model::promql_selector(&detail.family, &model::label_pairs(sample)))
+    }
+
+    /// The trace ID of the selected series' newest exemplar, for `x` and `X`.
+    fn selected_trace_id(&self) -> Option<String> {
+        let View::Detail(detail) = &self.view else {
+            return None;
+        };
+        if !detail.without.is_empty() {
+            return None;
+        }
+        let fam = self.family(&detail.family)?;
+        let (_, sample) = self.detail_series(fam, detail).into_iter().nth(detail.selected)?;
+        let observed = |exemplar: &openmetrics_parser::Exemplar| exemplar.timestamp.unwrap_or(f64::NEG_INFINITY);
+        model::exemplars(&sample.value)
+            .into_iter()
+            .filter(|(_, exemplar)| model::trace_id(exemplar).is_some())
+            .max_by(|a, b| observed(a.1).total_cmp(&observed(b.1)))
+            .and_then(|(_, exemplar)| model::trace_id(exemplar))
+            .map(String::from)
+    }
+
+    /// Opens the selected series' trace in the browser.
+    fn open_trace(&mut self) {
+        let Some(template) = &self.trace_url_template else {
+            self.notify("set --trace-url-template to open traces");
+            return;
+        };
+        let Some(trace_id) = self.selected_trace_id() else {
+            self.notify("the selected series has no exemplar with a trace ID");
+            return;
+        };
+        let url = template.replace("{trace_id}", &trace_id);
+        if self.no_external {
+            self.notify(format!("--no-external is set; the trace is at {url}"));
+            return;
+        }
+        match external::open_url(&url) {
+            Ok(()) => self.notify(format!("opened {url}")),
+            Err(e) => self.notify(format!("{e:#}")),
+        }
+    }
+
+    /// What `W` graphs: the selected series' selector, as a rate for counters
+    /// and for a histogram's buckets, or the `w` aggregation as `Y` copies it.
+    fn graph_expression(&self) -> Option<String> {
+        let View::Detail(detail) = &self.view else {
+            return None;
+        };
+        let fam = self.family(&detail.family)?;
+        if self.detail_aggregates(detail).is_some() {
+            return self.selected_series_selector();
+        }
+        let (_, sample) = self.detail_series(fam, detail).into_iter().nth(detail.selected)?;
+        let labels = model::label_pairs(sample);
+        Some(match fam.family_type {
+            PrometheusType::Counter => format!("rate({}[5m])", model::promql_selector(&detail.family, &labels)),
+            PrometheusType::Histogram => {
+                format!("rate({}[5m])", model::promql_selector(&aggregated_metric(&detail.family, fam), &labels))
+            }
+            _ => model::promql_selector(&detail.family, &labels),
+        })
+    }
+
+    /// Where `W` graphs the selected series: Grafana's Explore when there's
+    /// a `--grafana-url`, else the `--prometheus-url` graph page.
+    fn graph_url(&self) -> Option<Result<String>> {
+        let expr = self.graph_expression()?;
+        match (&self.grafana_url, &self.prometheus_url) {
+            (Some(grafana), _) => Some(query::explore_url(grafana, &expr)),
+            (None, Some(server)) => Some(query::graph_url(server, &expr)),
+            (None, None) => None,
+        }
+    }
+
+    /// Opens the selected series' graph in the browser. Without one, as over
+    /// SSH, the URL is copied instead, or shown when there's no clipboard
+    /// either.
+    fn open_graph(&mut self) {
+        if self.grafana_url.is_none() && self.prometheus_url.is_none() {
+            self.notify("set --prometheus-url or --grafana-url to graph series");
+            return;
+        }
+        match self.graph_url() {
+            Some(Ok(url)) if self.no_external || !external::has_browser() => self.copy(url),
+            Some(Ok(url)) => match external::open_url(&url) {
+                Ok(()) => self.notify(format!("opened {url}")),
+                Err(_) => self.copy(url),
+            },
+            Some(Err(e)) => self.notify(format!("{e:#}")),
+            None => self.notify("no series is selected"),
+        }
+    }
+
+    /// The label names of the family open in the detail view, for `L`.
+    pub(crate) fn group_labels(&self) -> Vec<String> {
+        match &self.view {
+            View::Detail(detail) => self
+                .family(&detail.family)
+                .map_or(Vec::new(), |fam| fam.get_label_names().to_vec()),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Opens the `L` picker, or with `without` the `w` one.
+    fn open_label_picker(&mut self, without: bool) {
+        let labels = self.group_labels();
+        if labels.is_empty() {
+            self.notify("this family has no labels");
+            return;
+        }
+        if let View::Detail(detail) = &mut self.view {
+            detail.picker = Some(if without {
+                LabelPicker {
+                    cursor: 0,
+                    ticked: Some(detail.without.clone()),
+                }
+            } else {
+                let current = detail.group_by.as_ref().and_then(|label| labels.iter().position(|l| l == label));
+                LabelPicker {
+                    cursor: current.map_or(0, |index| index + 1),
+                    ticked: None,
+                }
+            });
+        }
+    }
+
+    /// Opens the `C` pane on the detail view's family.
+    fn open_label_breakdown(&mut self) {
+        let View::Detail(detail) = &self.view else {
+            return;
+        };
+        let Some(fam) = self.family(&detail.family) else {
+            return;
+        };
+        let breakdown = LabelBreakdown {
+            series: fam.iter_samples().count(),
+            labels: model::label_cardinality(fam),
+            cursor: 0,
+        };
+        if breakdown.labels.is_empty() {
+            self.notify("this family has no labels");
+            return;
+        }
+        if let View::Detail(detail) = &mut self.view {
+            detail.breakdown = Some(breakdown);
+        }
+    }
+
+    fn breaking_down(&self) -> bool {
+        matches!(&self.view, View::Detail(DetailState { breakdown: Some(_), .. }))
+    }
+
+    /// Keys while the `C` pane is open: `y` copies a rule dropping the
+    /// selected label.
+    fn handle_breakdown_key(&mut self, code: KeyCode) {
+        if code == KeyCode::Char('y') {
+            if let Some(rule) = self.breakdown_rule() {
+                self.copy(rule);
+            }
+            return;
+        }
+        let View::Detail(detail) = &mut self.view else {
+            return;
+        };
+        let Some(breakdown) = &mut detail.breakdown else {
+            return;
+        };
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => breakdown.cursor = breakdown.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                breakdown.cursor = (breakdown.cursor + 1).min(breakdown.labels.len().saturating_sub(1))
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('C') => detail.breakdown = None,
+            _ => {}
+        }
+    }
+
+    fn breakdown_rule(&self) -> Option<String> {
+        let View::Detail(detail) = &self.view else {
+            return None;
+        };
+        let breakdown = detail.breakdown.as_ref()?;
+        let label = breakdown.labels.get(breakdown.cursor)?;
+        Some(model::drop_label_rule(&detail.family, self.family(&detail.family)?, &label.label))
+    }
+
+    fn picking_label(&self) -> bool {
+        matches!(&self.view, View::Detail(DetailState { picker: Some(_), .. }))
+    }
+
+    /// Keys while the `|` picker is open: Space or Enter shows or hides the
+    /// column under the cursor, `K` and `J` move it left and right.
+    fn handle_column_picker_key(&mut self, code: KeyCode) {
+        let Some(cursor) = self.column_picker else {
+            return;
+        };
+        let listed = self.columns.listed();
+        let Some(&(column, _)) = listed.get(cursor) else {
+            return;
+        };
+        let changed = match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.column_picker = Some(cursor.saturating_sub(1));
+                false
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.column_picker = Some((cursor + 1).min(listed.len() - 1));
+                false
+            }
+            KeyCode::Char(' ') | KeyCode::Enter if column == Column::Name => {
+                self.notify("the name column is always shown");
+                false
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => self.columns.toggle(column),
+            KeyCode::Char('K') | KeyCode::Char('J') => self.columns.shift(column, code == KeyCode::Char('J')),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('|') => {
+                self.column_picker = None;
+                false
+            }
+            _ => false,
+        };
+        if changed {
+            // The cursor stays on the column it toggled or moved.
+            self.column_picker = self.columns.listed().iter().position(|(listed, _)| *listed == column);
+            self.chosen_columns = Some(self.columns.clone());
+        }
+    }
+
+    /// Keys while a label picker is open.
+    fn handle_picker_key(&mut self, code: KeyCode) {
+        let labels = self.group_labels();
+        let selected_series = self.selected_series_key();
+        let mut regrouped = false;
+        let View::Detail(detail) = &mut self.view else {
+            return;
+        };
+        let Some(picker) = &mut detail.picker else {
+            return;
+        };
+        let entries = labels.len() + usize::from(picker.ticked.is_none());
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => picker.cursor = picker.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => picker.cursor = (picker.cursor + 1).min(entries.saturating_sub(1)),
+            KeyCode::Char(' ') => {
+                if let (Some(ticked), Some(label)) = (&mut picker.ticked, labels.get(picker.cursor)) {
+                    match ticked.iter().position(|l| l == label) {
+                        Some(index) => {
+                            ticked.remove(index);
+                        }
+                        None => ticked.push(label.clone()),
+                    }
+                }
+            }
+            KeyCode::Enter => {
+                match &picker.ticked {
+                    // Keep the family's label order so the expression reads naturally.
+                    Some(ticked) => detail.without = labels.iter().filter(|l| ticked.contains(l)).cloned().collect(),
+                    None => {
+                        detail.group_by = picker.cursor.checked_sub(1).and_then(|index| labels.get(index)).cloned();
+                        regrouped = true;
+                    }
+                }
+                detail.picker = None;
+                detail.selected = 0;
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('L') | KeyCode::Char('w') => detail.picker = None,
+            _ => {}
+        }
+        // Grouping only reorders the series, so the cursor stays on the same one.
+        if let (true, Some(key)) = (regrouped, selected_series) {
+            self.reselect_series(&key);
+        }
+    }
+
+    fn cycle_detail_aggregation(&mut self) {
+        let View::Detail(detail) = &mut self.view else {
+            return;
+        };
+        if detail.without.is_empty() {
+            self.notify("press w to pick labels to aggregate away first");
+            return;
+        }
+        // Keeping every series apart is what no `w` labels already means.
+        detail.aggregation = match detail.aggregation.next() {
+            Aggregation::Each => Aggregation::Sum,
+            next => next,
+        };
+    }
+
+    /// Whether the detail view's aggregation combines rates rather than
+    /// values: for counters while `R` is on.
+    pub(crate) fn aggregates_rates(&self, fam: &Family) -> bool {
+        self.show_rates && matches!(fam.family_type, PrometheusType::Counter)
+    }
+
+    /// The series of the detail view's family with its `w` labels
+    /// aggregated away, or `None` when no labels are being dropped.
+    pub(crate) fn detail_aggregates(&self, detail: &DetailState) -> Option<Vec<model::Aggregate>> {
+        if detail.without.is_empty() {
+            return None;
+        }
+        let fam = self.family(&detail.family)?;
+        let rate = self.aggregates_rates(fam);
+        Some(model::aggregate_without(fam, &detail.without, detail.aggregation, |sample| {
+            if rate {
+                match self.series_change(&detail.family, fam, sample) {
+                    Some(Change::Rate(rate)) => Some(rate),
+                    _ => None,
+                }
+            } else {
+                model::scalar_value(&sample.value)
+            }
+        }))
+    }
+
+    /// Puts `text` on the system clipboard, or shows it in the status line
+    /// when there is none (e.g. over SSH) so it can be copied by hand.
+    fn copy(&mut self, text: String) {
+        if self.clipboard.is_none() {
+            self.clipboard = arboard::Clipboard::new().ok();
+        }
+        let copied = self
+            .clipboard
+            .as_mut()
+            .is_some_and(|clipboard| clipboard.set_text(text.as_str()).is_ok());
+        if copied {
+            self.notify(format!("copied {text}"));
+        } else {
+            self.notify(format!("no clipboard available: {text}"));
+        }
+    }
+
+    /// Writes what's on screen — the filtered, sorted table or the open
+    /// family — to `metrics-<host>-<time>.csv` in the working directory.
+    fn export_csv(&mut self) {
+        let families: Vec<(&str, &Family)> = match &self.view {
+            View::Detail(detail) => self
+                .family(&detail.family)
+                .map(|fam| (detail.family.as_str(), fam))
+                .into_iter()
+                .collect(),
+            _ => self
+                .visible_families()
+                .into_iter()
+                .map(|(name, fam)| (name.as_str(), fam))
+                .collect(),
+        };
+        if families.is_empty() {
+            self.notify("nothing to export");
+            return;
+        }
+
+        let stamp: String = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':'))
+            .collect();
+        let path = format!("metrics-{}-{stamp}.csv", self.export_name);
+        let written = std::fs::File::create(&path).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            let written = output::write_csv(families, &mut out)?;
+            io::Write::flush(&mut out)?;
+            Ok(written)
+        });
+        match written {
+            Ok(count) => self.notify(format!("exported {count} series to {path}")),
+            Err(e) => self.notify(format!("couldn't write {path}: {e}")),
+        }
+    }
+
+    /// Opens the raw text of the last rejected scrape at the offending line,
+    /// or the list of lines `--lenient` dropped from an accepted one.
+    fn open_parse_error(&mut self) {
+        if let Some(failure) = &self.parse_failure {
+            self.view = View::ParseError(SourceState {
+                cursor: failure.line.map_or(0, |line| line.saturating_sub(1)),
+                raw: false,
+            });
+        } else if !self.skipped_lines.is_empty() {
+            self.show_skipped = true;
+        } else {
+            self.notify("the last scrape parsed cleanly");
+        }
+    }
+
+    fn context(&self) -> Context {
+        match self.view {
+            View::Table => Context::Table,
+            View::Detail(_) => Context::Detail,
+            View::ParseError(_) => Context::ParseError,
+            View::Cardinality(_) => Context::Cardinality,
+            View::Lint(_) => Context::Lint,
+            View::Movers(_) => Context::Movers,
+            View::Raw(_) => Context::Raw,
+            View::Textfiles(_) => Context::Textfiles,
+            View::Events(_) => Context::Events,
+        }
+    }
+
+    /// Handles a key press: an open overlay or prompt takes it first,
+    /// otherwise the current view's keybinding is performed. Returns the
+    /// action when it's for `run_app` to carry out, such as quitting or
+    /// switching tabs, which need more than this tab's App. Nothing here
+    /// touches the terminal, so tests drive the App through it. It takes the
+    /// whole `KeyEvent` rather than a `KeyCode` for Ctrl-d, Ctrl-e, Alt+Enter
+    /// and the like.
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if self.show_keybindings {
+            // The overlay swallows everything except the keys that close it.
+            if matches!(key.code, KeyCode::Char('?') | KeyCode::Esc | KeyCode::Char('q')) {
+                self.show_keybindings = false;
+            }
+        } else if self.show_skipped {
+            if matches!(key.code, KeyCode::Char('e') | KeyCode::Esc | KeyCode::Char('q')) {
+                self.show_skipped = false;
+            }
+        } else if self.show_exchange {
+            if matches!(key.code, KeyCode::Char('d') | KeyCode::Esc | KeyCode::Char('q')) {
+                self.show_exchange = false;
+            }
+        } else if self.column_picker.is_some() {
+            self.handle_column_picker_key(key.code);
+        } else if self.picking_label() {
+            self.handle_picker_key(key.code);
+        } else if self.breaking_down() {
+            self.handle_breakdown_key(key.code);
+        } else if self.input_mode == InputMode::Search {
+            self.handle_search_key(key.code);
+        } else if self.input_mode == InputMode::Jump {
+            self.handle_jump_key(key.code);
+        } else if self.input_mode == InputMode::Query {
+            self.handle_query_key(key.code);
+        } else if self.input_mode == InputMode::Find {
+            self.handle_find_key(key.code);
+        } else if self.input_mode == InputMode::Endpoint {
+            return self.handle_endpoint_key(key);
+        } else if self.input_mode == InputMode::Note {
+            self.handle_note_key(key.code);
+        } else if let Some(action) = keys::lookup(self.context(), key) {
+            tracing::debug!(key = ?key.code, ?action, "key");
+            match action {
+                Action::Quit
+                | Action::NextTab
+                | Action::PreviousTab
+                | Action::SelectTab
+                | Action::CycleAggregation
+                | Action::ReloadTargets
+                | Action::OpenInPager
+                | Action::OpenInEditor
+                | Action::ToggleMouse => return Some(action),
+                // In the ALL tab, every tab refreshes.
+                Action::Refresh if self.aggregation.is_some() => return Some(action),
+                action => self.perform(action),
+            }
+        }
+        None
+    }
+
+    /// Applies a keybinding action.
+    pub(crate) fn perform(&mut self, action: Action) {
+        match action {
+            Action::Back => self.view = View::Table,
+            Action::Refresh => self.refresh(),
+            Action::ShowKeybindings => self.show_keybindings = true,
+            Action::ShowExchange => self.show_exchange = true,
+            Action::Up
+            | Action::Down
+            | Action::HalfPageUp
+            | Action::HalfPageDown
+            | Action::PageUp
+            | Action::PageDown
+            | Action::Top
+            | Action::Bottom => self.move_cursor(action),
+            Action::StartSearch => {
+                if let View::Raw(state) = &mut self.view {
+                    state.search.clear();
+                }
+                self.input_mode = InputMode::Search;
+            }
+            Action::ScrollLeft => self.scroll = self.scroll.saturating_sub(SCROLL_STEP),
+            Action::ScrollRight => self.scroll = (self.scroll + SCROLL_STEP).min(self.scroll_limit()),
+            Action::FindFamily => {
+                self.finder.clear();
+                self.finder_selected = 0;
+                self.input_mode = InputMode::Find;
+            }
+            Action::ClearFilter => self.keeping_selection(|app| app.filter.clear()),
+            Action::OpenDetail => self.open_detail(),
+            Action::Expand => self.set_expanded(true),
+            Action::Collapse => self.set_expanded(false),
+            Action::ToggleHelpFooter => self.show_help_footer = !self.show_help_footer,
+            Action::ToggleRates => self.show_rates = !self.show_rates,
+            Action::SetBaseline => self.set_baseline(),
+            Action::ToggleBaseline if self.baseline.is_none() => self.notify("press B to take a baseline first"),
+            Action::ToggleBaseline => self.show_baseline = !self.show_baseline,
+            Action::ToggleHighlight => self.highlight_changes = !self.highlight_changes,
+            Action::CycleSort => self.set_sort(self.sort_key.next(), self.sort_reversed),
+            Action::ReverseSort => self.set_sort(self.sort_key, !self.sort_reversed),
+            Action::ShowParseError => self.open_parse_error(),
+            Action::ExportCsv => self.export_csv(),
+            Action::ToggleCardinality => self.toggle_cardinality(),
+            Action::ToggleLint => self.toggle_lint(),
+            Action::ToggleMovers => self.toggle_movers(),
+            Action::ToggleTextfiles => self.toggle_textfiles(),
+            Action::ToggleEvents => self.toggle_events(),
+            Action::AddNote if self.replay.is_some() => self.notify("notes can't be added to a recording"),
+            Action::AddNote => {
+                self.note.clear();
+                self.input_mode = InputMode::Note;
+            }
+            Action::PickColumns => self.column_picker = Some(0),
+            Action::ToggleDisappeared => self.show_disappeared = !self.show_disappeared,
+            Action::HideZero => self.toggle_hidden(false),
+            Action::HideUnchanged => self.toggle_hidden(true),
+            Action::CycleTypeFilter if matches!(self.view, View::Events(_)) => self.cycle_event_kind(),
+            Action::CycleTypeFilter => self.keeping_selection(|app| {
+                app.type_filter = match app.type_filter {
+                    None => Some(FamilyType::ALL[0]),
+                    Some(current) => FamilyType::ALL.iter().skip_while(|t| **t != current).nth(1).copied(),
+                };
+            }),
+            Action::ToggleRawValues => self.raw_values = !self.raw_values,
+            Action::CycleTheme => {
+                self.theme = self.theme.next();
+                self.chosen_theme = Some(self.theme);
+                self.notify(format!("theme: {}", self.theme().name));
+            }
+            Action::OpenPrompt => match &mut self.query {
+                Some(query) => {
+                    query.input = query.expr.clone();
+                    self.input_mode = InputMode::Query;
+                }
+                None if self.opens_endpoints => {
+                    self.endpoint_prompt = EndpointPrompt::default();
+                    self.input_mode = InputMode::Endpoint;
+                }
+                None => self.notify("only the viewer of live endpoints can open another"),
+            },
+            Action::PreviousScrape => self.step_scrape(false),
+            Action::NextScrape => self.step_scrape(true),
+            Action::TogglePause if self.replay.is_none() => self.toggle_pause(),
+            Action::TogglePause | Action::FasterPlayback | Action::SlowerPlayback | Action::JumpToTime => {
+                let Some(replay) = &mut self.replay else {
+                    self.notify("only available when replaying a recording");
+                    return;
+                };
+                match action {
+                    Action::TogglePause => replay.toggle_play(),
+                    Action::FasterPlayback => replay.change_speed(true),
+                    Action::SlowerPlayback => replay.change_speed(false),
+                    _ => self.input_mode = InputMode::Jump,
+                }
+            }
+            // Quitting, switching tabs and re-merging the ALL tab need every
+            // tab's App, so run_app handles them.
+            Action::Quit
+            | Action::NextTab
+            | Action::PreviousTab
+            | Action::SelectTab
+            | Action::CycleAggregation
+            | Action::ReloadTargets
+            | Action::OpenInPager
+            | Action::OpenInEditor
+            | Action::OpenEndpoint
+            | Action::ToggleMouse => {}
+            Action::PickGroupLabel => self.open_label_picker(false),
+            Action::DropLabels => self.open_label_picker(true),
+            Action::ShowLabelCardinality => self.open_label_breakdown(),
+            Action::CycleDetailAggregation => self.cycle_detail_aggregation(),
+            Action::ForgetState => self.forget_state(),
+            Action::TogglePin => self.toggle_pin(),
+            Action::ClearPins => self.clear_pins(),
+            Action::JumpToFamily => self.jump_to_family(),
+            Action::CopyName => {
+                let name = match &self.view {
+                    View::Detail(detail) => Some(detail.family.clone()),
+                    _ => self.selected_family_name(),
+                };
+                if let Some(name) = name {
+                    self.copy(name);
+                }
+            }
+            Action::CopySelector => {
+                if let Some(selector) = self.selected_series_selector() {
+                    self.copy(selector);
+                }
+            }
+            Action::CopyTraceId => match self.selected_trace_id() {
+                Some(trace_id) => self.copy(trace_id),
+                None => self.notify("the selected series has no exemplar with a trace ID"),
+            },
+            Action::OpenTrace => self.open_trace(),
+            Action::OpenGraph => self.open_graph(),
+            Action::ToggleRawText => {
+                if let View::ParseError(state) = &mut self.view {
+                    state.raw = !state.raw;
+                }
+            }
+            Action::ShowRaw => self.open_raw(false),
+            Action::ShowRawPayload => self.open_raw(true),
+            Action::NextMatch => self.find_raw_match(true, true),
+            Action::PreviousMatch => self.find_raw_match(true, false),
+        }
+    }
+
+    /// Whether mouse events reach the view, rather than a prompt or popup.
+    pub(crate) fn takes_mouse(&self) -> bool {
+        self.input_mode == InputMode::Normal
+            && !self.show_keybindings
+            && !self.show_skipped
+            && !self.show_exchange
+            && self.column_picker.is_none()
+            && !self.picking_label()
+            && !self.breaking_down()
+    }
+
+    /// The wheel moves the cursor a few rows at a time; a left click selects
+    /// the row under it, or opens the table row that already was selected.
+    pub(crate) fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let step = match mouse.kind {
+            MouseEventKind::ScrollUp => Action::Up,
+            MouseEventKind::ScrollDown => Action::Down,
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.click(mouse.column, mouse.row);
+                return;
+            }
+            _ => return,
+        };
+        for _ in 0..WHEEL_ROWS {
+            self.move_cursor(step);
+        }
+    }
+
+    pub(crate) fn click(&mut self, column: u16, row: u16) {
+        let Some(index) = self
+            .click_targets
+            .iter()
+            .find(|target| target.contains(column, row))
+            .map(|target| target.index)
+        else {
+            return;
+        };
+        if matches!(self.view, View::Table) {
+            if index == self.selected() {
+                self.open_detail();
+            } else {
+                self.select(index);
+            }
+            return;
+        }
+        match &mut self.view {
+            View::Detail(detail) => detail.selected = index,
+            View::Raw(state) => state.cursor = index,
+            _ => {}
+        }
+    }
+
+    /// Moves the table selection, or the series selection in the detail view.
+    fn move_cursor(&mut self, action: Action) {
+        let (current, count) = match &self.view {
+            View::Table => (self.selected(), self.table_rows().len()),
+            View::Detail(detail) => (
+                detail.selected,
+                match self.detail_aggregates(detail) {
+                    Some(aggregates) => aggregates.len(),
+                    None => self.family(&detail.family).map_or(0, |fam| {
+                        self.detail_series(fam, detail).len()
+                    }),
+                },
+            ),
+            View::ParseError(state) => (
+                state.cursor,
+                self.parse_failure.as_ref().map_or(0, |failure| failure.text.lines().count()),
+            ),
+            View::Cardinality(state) => (state.selected, self.cardinality.len()),
+            View::Lint(state) => (state.selected, self.findings().count()),
+            View::Movers(state) => (state.selected, self.movers.len()),
+            View::Raw(state) => (state.cursor, self.raw_lines(state).map_or(0, |(_, lines)| lines.len())),
+            View::Textfiles(state) => (state.selected, self.textfiles.as_ref().map_or(0, Vec::len)),
+            View::Events(state) => (state.selected, self.listed_events().len()),
+        };
+        let page = self.viewport_height;
+        let last = count.saturating_sub(1);
+        let target = match action {
+            Action::Up => current.saturating_sub(1),
+            Action::Down => current + 1,
+            Action::HalfPageUp => current.saturating_sub((page / 2).max(1)),
+            Action::HalfPageDown => current + (page / 2).max(1),
+            Action::PageUp => current.saturating_sub(page),
+            Action::PageDown => current + page,
+            Action::Top => 0,
+            Action::Bottom => last,
+            _ => current,
+        }
+        .min(last);
+
+        match &mut self.view {
+            View::Table => self.table_state.select(Some(target)),
+            View::Detail(detail) => detail.selected = target,
+            View::ParseError(state) => state.cursor = target,
+            View::Cardinality(state) => state.selected = target,
+            View::Lint(state) => state.selected = target,
+            View::Movers(state) => state.selected = target,
+            View::Raw(state) => state.cursor = target,
+            View::Textfiles(state) => state.selected = target,
+            View::Events(state) => state.selected = target,
+        }
+    }
+
+    fn handle_search_key(&mut self, code: KeyCode) {
+        if let View::Raw(state) = &mut self.view {
+            match code {
+                KeyCode::Esc => {
+                    state.search.clear();
+                    self.input_mode = InputMode::Normal;
+                }
+                KeyCode::Enter => self.input_mode = InputMode::Normal,
+                KeyCode::Backspace => {
+                    state.search.pop();
+                }
+                KeyCode::Char(c) => {
+                    state.search.push(c);
+                    self.find_raw_match(false, true);
+                }
+                _ => {}
+            }
+            return;
+        }
+        match code {
+            KeyCode::Esc => {
+                self.keeping_selection(|app| app.filter.clear());
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => self.input_mode = InputMode::Normal,
+            KeyCode::Backspace => self.keeping_selection(|app| {
+                app.filter.pop();
+            }),
+            KeyCode::Char(c) => self.keeping_selection(|app| app.filter.push(c)),
+            _ => {}
+        }
+    }
+
+    fn handle_note_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => {
+                self.note.clear();
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let text = std::mem::take(&mut self.note);
+                if text.trim().is_empty() {
+                    return;
+                }
+                let event = events::Event {
+                    at: SystemTime::now(),
+                    kind: events::Kind::Annotation,
+                    series: self.note_series(),
+                    text: text.trim().to_string(),
+                };
+                self.log_event(event);
+                self.notify("noted in the event log (E)");
+            }
+            KeyCode::Backspace => {
+                self.note.pop();
+            }
+            KeyCode::Char(c) => self.note.push(c),
+            _ => {}
+        }
+    }
+
+    /// The series a note is attached to: the one selected in the detail view.
+    pub(crate) fn note_series(&self) -> Option<String> {
+        self.selected_series_key().map(|key| key.to_string())
+    }
+
+    fn cycle_event_kind(&mut self) {
+        if let View::Events(state) = &mut self.view {
+            state.kind = match state.kind {
+                None => Some(events::Kind::ALL[0]),
+                Some(current) => events::Kind::ALL.iter().skip_while(|kind| **kind != current).nth(1).copied(),
+            };
+            state.selected = 0;
+        }
+    }
+
+    /// Adds `event` to the log, and to the `--record` file if there is one.
+    fn log_event(&mut self, event: events::Event) {
+        self.fetcher.record(&event);
+        self.events.push(event);
+    }
+
+    fn handle_jump_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => {
+                self.jump.clear();
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let text = std::mem::take(&mut self.jump);
+                let Some(replay) = &self.replay else {
+                    return;
+                };
+                match replay.find(&text) {
+                    Ok(index) => self.show_scrape(index),
+                    Err(e) => self.notify(format!("can't jump to {:?}: {e}", text.trim())),
+                }
+            }
+            KeyCode::Backspace => {
+                self.jump.pop();
+            }
+            KeyCode::Char(c) => self.jump.push(c),
+            _ => {}
+        }
+    }
+
+    fn handle_find_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let name = self.finder_matches().get(self.finder_selected).map(|m| m.name.to_string());
+                if let Some(name) = name {
+                    self.show_family(&name);
+                }
+            }
+            KeyCode::Up => self.finder_selected = self.finder_selected.saturating_sub(1),
+            KeyCode::Down => {
+                let last = self.finder_matches().len().saturating_sub(1);
+                self.finder_selected = (self.finder_selected + 1).min(last);
+            }
+            KeyCode::Backspace => {
+                self.finder.pop();
+                self.finder_selected = 0;
+            }
+            KeyCode::Char(c) => {
+                self.finder.push(c);
+                self.finder_selected = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Keys typed into the `:` prompt. Enter checks that the endpoint is one
+    /// that can be fetched and leaves opening it to `run_app`; the prompt
+    /// stays open until the first scrape is in, to show why it failed.
+    fn handle_endpoint_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let prompt = &mut self.endpoint_prompt;
+        match key.code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            _ if prompt.opening => {}
+            KeyCode::Enter => {
+                let endpoint = prompt.input.trim();
+                if endpoint.is_empty() {
+                    return None;
+                }
+                match Source::from_arg(endpoint) {
+                    Ok(Source::Stdin) => prompt.error = Some(String::from("stdin can only be read at startup")),
+                    Ok(_) => {
+                        prompt.error = None;
+                        prompt.opening = true;
+                        // The ALL tab has no endpoint of its own to replace.
+                        prompt.new_tab = key.modifiers.contains(KeyModifiers::ALT) || self.aggregation.is_some();
+                        return Some(Action::OpenEndpoint);
+                    }
+                    Err(e) => prompt.error = Some(format!("{e:#}")),
+                }
+            }
+            KeyCode::Up => {
+                let index = prompt.recalled.map_or(0, |index| index + 1);
+                if let Some(endpoint) = self.endpoint_history.get(index) {
+                    prompt.input = endpoint.clone();
+                    prompt.recalled = Some(index);
+                }
+            }
+            KeyCode::Down => match prompt.recalled {
+                Some(index) if index > 0 => {
+                    prompt.input = self.endpoint_history[index - 1].clone();
+                    prompt.recalled = Some(index - 1);
+                }
+                _ => {
+                    prompt.input.clear();
+                    prompt.recalled = None;
+                }
+            },
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                prompt.error = None;
+            }
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                prompt.error = None;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// The endpoint typed into the `:` prompt is open, in this tab's place
+    /// or in a new one.
+    pub(crate) fn endpoint_opened(&mut self) {
+        self.endpoint_prompt = EndpointPrompt::default();
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// The endpoint typed into the `:` prompt couldn't be opened, which the
+    /// prompt shows, leaving this tab as it was.
+    pub(crate) fn endpoint_failed(&mut self, error: String) {
+        self.endpoint_prompt.opening = false;
+        self.endpoint_prompt.error = Some(error);
+    }
+
+    fn handle_query_key(&mut self, code: KeyCode) {
+        let Some(query) = &mut self.query else {
+            self.input_mode = InputMode::Normal;
+            return;
+        };
+        match code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let expr = query.input.trim().to_string();
+                if !expr.is_empty() && expr != query.expr {
+                    self.run_query(expr);
+                } else if !expr.is_empty() {
+                    self.refresh();
+                }
+            }
+            KeyCode::Backspace => {
+                query.input.pop();
+            }
+            KeyCode::Char(c) => query.input.push(c),
+            _ => {}
+        }
+    }
+
+    /// Switches `promtool query` to a new expression. Results of the old
+    /// one are dropped so rates never compare the two.
+    fn run_query(&mut self, expr: String) {
+        let Some(query) = &mut self.query else {
+            return;
+        };
+        query.expr = expr.clone();
+        query.error = None;
+        query.warnings.clear();
+        let source = Source::Query {
+            server: query.server.clone(),
+            expr,
+        };
+        self.fetcher = Fetcher::spawn(source, query.http.clone(), None);
+        self.reset_scrapes();
+        self.refresh();
+    }
+
+    /// Switches to browsing a recording (`promtool replay`), starting at its
+    /// first scrape.
+    pub(crate) fn start_replay(&mut self, path: &Path, recording: record::Recording, speed: u32) {
+        self.endpoint = format!("recording {}", path.display());
+        self.export_name = Source::File(path.to_path_buf()).short_name();
+        self.replay = Some(Replay::new(recording.scrapes, recording.events, speed));
+        self.show_scrape(0);
+        if recording.unreadable > 0 {
+            let plural = if recording.unreadable == 1 { "" } else { "s" };
+            self.notify(format!("skipped {} unreadable record{plural}", recording.unreadable));
+        }
+    }
+
+    /// Moves one recorded scrape forwards or backwards.
+    fn step_scrape(&mut self, forward: bool) {
+        let Some(replay) = &self.replay else {
+            self.notify("only available when replaying a recording");
+            return;
+        };
+        let position = replay.position();
+        if forward && position + 1 == replay.len() {
+            self.notify("already at the last recorded scrape");
+        } else if !forward && position == 0 {
+            self.notify("already at the first recorded scrape");
+        } else {
+            self.show_scrape(if forward { position + 1 } else { position - 1 });
+        }
+    }
+
+    /// Shows recorded scrape `index`, with rates and churn relative to the
+    /// scrape recorded before it.
+    fn show_scrape(&mut self, index: usize) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        let from = replay.position();
+        let index = replay.seek(index);
+        // Stepping forward builds on what's shown; anywhere else, start over
+        // from the scrape before the target.
+        if index != from + 1 {
+            self.reset_scrapes();
+            if index > 0 {
+                self.apply_scrape(index - 1);
+            }
+        }
+        self.apply_scrape(index);
+    }
+
+    /// Feeds a recorded scrape through the same path as a live fetch, dated
+    /// by its recorded time so rates use the recorded intervals.
+    fn apply_scrape(&mut self, index: usize) {
+        let Some(replay) = &self.replay else {
+            return;
+        };
+        let scrape = replay.scrape(index);
+        let result = FetchResult {
+            body: Ok(scrape.body.clone()),
+            duration: scrape.duration,
+        };
+        let taken_at = replay.instant(index);
+        self.events = EventLog::recorded(replay.events_until(index));
+        self.apply_fetch_at(result, taken_at);
+    }
+
+    /// Kicks off a background fetch, unless the previous one hasn't returned
+    /// yet.
+    fn refresh(&mut self) {
+        if self.replay.is_some() {
+            self.notify("replaying a recording — refresh unavailable");
+            return;
+        }
+        if !self.refreshable {
+            self.notify("stdin input — refresh unavailable");
+            return;
+        }
+        if self.fetcher.request() {
+            tracing::debug!(endpoint = %self.endpoint, "refresh requested");
+        } else {
+            tracing::debug!(endpoint = %self.endpoint, "refresh skipped, the last fetch is still in flight");
+        }
+    }
+
+    /// A refresh the `--interval` asked for. If the previous fetch hasn't
+    /// returned yet (slow endpoint, short interval) this one is skipped and
+    /// counted rather than queued; a manual `r` never is.
+    fn scheduled_refresh(&mut self) {
+        if self.refreshable && self.fetcher.in_flight_since().is_some() {
+            self.skipped_refreshes += 1;
+        }
+        self.refresh();
+    }
+
+    /// A sample value as the table and detail views show it.
+    pub(crate) fn display_value(&self, name: &str, fam: &Family, value: &PrometheusValue) -> String {
+        if self.raw_values {
+            model::format_raw_value(value)
+        } else {
+            humanize::value(value, name, &fam.unit)
+        }
+    }
+
+    /// A single number of the series `name` as every view shows it, from
+    /// sample values to sums and chart labels: exact or humanized, with NaN
+    /// and the infinities spelled the same everywhere.
+    pub(crate) fn display_number(&self, value: f64, name: &str, fam: &Family) -> String {
+        if self.raw_values {
+            model::format_raw(value)
+        } else {
+            humanize::number(value, name, &fam.unit)
+        }
+    }
+
+    pub(crate) fn theme(&self) -> &'static Theme {
+        self.theme.theme()
+    }
+
+    /// The endpoint without its scheme or a `/metrics` path, for tab titles.
+    pub(crate) fn tab_name(&self) -> &str {
+        if self.aggregation.is_some() {
+            return "ALL";
+        }
+        let name = self.endpoint.split_once("://").map_or(self.endpoint.as_str(), |(_, rest)| rest);
+        name.strip_suffix("/metrics").unwrap_or(name)
+    }
+
+    fn notify(&mut self, message: impl Into<String>) {
+        self.notice = Some((message.into(), Instant::now()));
+    }
+
+    pub(crate) fn current_notice(&self) -> Option<&str> {
+        const NOTICE_TTL: Duration = Duration::from_secs(5);
+        match &self.notice {
+            Some((message, at)) if at.elapsed() < NOTICE_TTL => Some(message),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply_fetch(&mut self, result: FetchResult) {
+        self.apply_fetch_at(result, Instant::now());
+    }
+
+    /// `apply_fetch` for a scrape taken at `taken_at`, which replays take
+    /// from the recording.
+    fn apply_fetch_at(&mut self, result: FetchResult, taken_at: Instant) {
+        self.last_fetch_duration = result.duration;
+        self.unchanged = None;
+
+        let (metric_text, content_type) = match result.body {
+            Ok(body) if body.status == Some(reqwest::StatusCode::NOT_MODIFIED) => {
+                self.last_status = body.status;
+                self.exchange = body.exchange;
+                self.keep_scrape(Unchanged::NotModified, taken_at);
+                return;
+            }
+            Ok(body) => {
+                self.last_body_size = Some((body.transferred, body.text.len()));
+                self.last_status = body.status;
+                self.fetch_warnings = body.warnings;
+                self.exchange = body.exchange;
+                if let Some(files) = &body.textfiles {
+                    self.apply_textfiles(files, body.text, taken_at);
+                    return;
+                }
+                (body.text, body.content_type)
+            }
+            Err(e) => {
+                self.exchange = e.downcast_ref::<fetch::Failed>().map(|failed| failed.exchange.clone());
+                self.last_error = Some(e.context(format!("couldn't fetch metrics from {}", self.endpoint)));
+                self.record_history(None, taken_at);
+                return;
+            }
+        };
+
+        let body_hash = {
+            let mut hasher = DefaultHasher::new();
+            (&metric_text, &content_type).hash(&mut hasher);
+            hasher.finish()
+        };
+        if self.query.is_none() && self.current.is_some() && self.body_hash == Some(body_hash) {
+            self.keep_scrape(Unchanged::SameContent, taken_at);
+            return;
+        }
+
+        let parse_started = Instant::now();
+        if let Some(state) = &mut self.query {
+            let decoded = query::decode(&metric_text, self.last_status, &state.expr);
+            self.last_parse_duration = parse_started.elapsed();
+            match decoded {
+                Ok((metrics, warnings)) => {
+                    state.error = None;
+                    state.warnings = warnings;
+                    self.accept(metrics, taken_at, None);
+                }
+                Err(e) => {
+                    state.error = Some(format!("{e:#}"));
+                    self.last_error = Some(e.context(format!("{} couldn't evaluate the query", self.endpoint)));
+                    self.record_history(None, taken_at);
+                }
+            }
+            return;
+        }
+        let parsed = if self.lenient {
+            model::parse_lenient(&metric_text, content_type.as_deref(), self.format).map(|(mut metrics, skipped)| {
+                let truncated = self.max_series.and_then(|max| model::truncate(&mut metrics, &metric_text, max));
+                (metrics, skipped, truncated)
+            })
+        } else {
+            model::parse_by_family(&metric_text, content_type.as_deref(), self.format, self.max_series)
+                .map(|(metrics, truncated)| (metrics, Vec::new(), truncated))
+        };
+        self.last_parse_duration = parse_started.elapsed();
+        self.duplicates = lint::duplicates(&metric_text);
+        match parsed {
+            Ok((metrics, skipped_lines, truncated)) => {
+                tracing::info!(
+                    endpoint = %self.endpoint,
+                    families = metrics.families.len(),
+                    series = metrics.families.values().map(|fam| fam.iter_samples().count()).sum::<usize>(),
+                    skipped = skipped_lines.len(),
+                    elapsed = ?self.last_parse_duration,
+                    "parsed"
+                );
+                for skipped in &skipped_lines {
+                    tracing::debug!(endpoint = %self.endpoint, line = skipped.line, text = %skipped.text, "skipped");
+                }
+                if let Some(truncated) = &truncated {
+                    tracing::warn!(
+                        endpoint = %self.endpoint,
+                        max_series = truncated.max_series,
+                        families_left = truncated.families_left,
+                        "truncated"
+                    );
+                }
+                self.truncated = truncated;
+                self.body_hash = Some(body_hash);
+                self.skipped_lines = skipped_lines;
+                self.openmetrics = self.format.resolve(&metric_text, content_type.as_deref()) == Format::Openmetrics;
+                self.declared_label_families = if self.openmetrics {
+                    model::declared_label_families(&metric_text)
+                } else {
+                    HashMap::new()
+                };
+                let raw = RawScrape {
+                    lines: metric_text.lines().count(),
+                    families: model::family_lines(&metric_text),
+                    text: metric_text,
+                };
+                self.accept(metrics, taken_at, Some(raw));
+            }
+            Err(e) => {
+                self.body_hash = None;
+                let message = e.to_string();
+                tracing::warn!(endpoint = %self.endpoint, error = %message, "parse failed");
+                self.parse_failure = Some(ParseFailure {
+                    line: model::error_line(&message),
+                    message,
+                    text: metric_text,
+                });
+                self.last_error = Some(anyhow::anyhow!(
+                    "metrics from {} could not be parsed: {e} (press e to inspect)",
+                    self.endpoint
+                ));
+                if self.current.is_none() && matches!(self.view, View::Table) {
+                    self.open_parse_error();
+                }
+                self.record_history(None, taken_at);
+            }
+        }
+    }
+
+    /// A `--textfile-dir` refresh: each file is parsed on its own, so a
+    /// broken one only loses its own series and is listed in the `I` view.
+    fn apply_textfiles(&mut self, files: &[textfile::Textfile], text: String, taken_at: Instant) {
+        let parse_started = Instant::now();
+        let scan = textfile::scan(files, textfile::parser(self.format, self.lenient));
+        self.last_parse_duration = parse_started.elapsed();
+        self.textfiles = Some(scan.files);
+        self.merge_warnings = scan.warnings;
+        // Line numbers of the concatenated text wouldn't point into any one file.
+        self.duplicates = Vec::new();
+        self.skipped_lines = Vec::new();
+        self.openmetrics = false;
+        self.declared_label_families = HashMap::new();
+        let raw = RawScrape {
+            lines: text.lines().count(),
+            families: model::family_lines(&text),
+            text,
+        };
+        self.accept(scan.metrics, taken_at, Some(raw));
+    }
+
+    /// Takes in a successfully parsed scrape, and makes it the current one
+    /// unless the display is paused.
+    fn accept(&mut self, mut metrics: Exposition, taken_at: Instant, raw: Option<RawScrape>) {
+        metrics.families.retain(|name, fam| self.family_filter.matches(name, fam));
+        if !self.target_labels.is_empty() {
+            match model::with_labels(metrics, &self.target_labels) {
+                Ok(labelled) => metrics = labelled,
+                Err(e) => {
+                    self.last_error = Some(anyhow::anyhow!("couldn't add the target labels: {e}"));
+                    return;
+                }
+            }
+        }
+
+        // Counters, the baseline and history follow every scrape, shown or not.
+        let wall_time = match &self.replay {
+            Some(replay) => replay.wall_time(taken_at),
+            None => SystemTime::now(),
+        };
+        let went_back = self.counter_resets.update(&metrics, wall_time);
+        // A replay shows the events that were recorded instead.
+        if self.replay.is_none() {
+            for key in went_back {
+                if let Some(event) = self.counter_resets.events[&key].last() {
+                    let event = events::counter_reset(&key, event);
+                    self.log_event(event);
+                }
+            }
+        }
+        if let Some(baseline) = &mut self.baseline {
+            baseline.update(&metrics);
+        }
+        self.record_history(Some(&metrics), taken_at);
+        self.parse_failure = None;
+        self.last_error = None;
+
+        let previous = match self.paused.as_mut().and_then(|paused| paused.latest.take()) {
+            Some(pending) => Some(Snapshot::capture(&pending.metrics, pending.taken_at)),
+            None => self.current.as_ref().zip(self.last_success_at).map(|(old, at)| Snapshot::capture(old, at)),
+        };
+        if let Some(paused) = &mut self.paused {
+            paused.behind += 1;
+            paused.latest = Some(Pending {
+                metrics,
+                taken_at,
+                raw,
+                previous,
+            });
+            return;
+        }
+        self.show(metrics, taken_at, raw, previous);
+    }
+
+    /// A refresh that brought nothing new: the scrape shown stays as it is,
+    /// without being parsed or compared again, and charts get the point.
+    fn keep_scrape(&mut self, unchanged: Unchanged, taken_at: Instant) {
+        tracing::debug!(endpoint = %self.endpoint, reason = unchanged.text(), "unchanged");
+        let current = self.current.take();
+        self.record_history(current.as_ref(), taken_at);
+        self.current = current;
+        if self.current.is_some() {
+            self.last_error = None;
+            self.unchanged = Some((unchanged, taken_at));
+        }
+    }
+
+    /// Makes a scrape the current one, comparing it against the one it
+    /// replaces and rates against `previous`.
+    fn show(&mut self, metrics: Exposition, taken_at: Instant, raw: Option<RawScrape>, previous: Option<Snapshot>) {
+        self.cardinality = model::cardinality(&metrics);
+        self.summaries = model::family_summaries(&metrics);
+        self.steadiness.update(&metrics);
+        self.quiet = self.steadiness.quiet(&metrics, self.unchanged_after);
+        // Query results have no TYPE or HELP to hold to the conventions.
+        self.lints = match self.query {
+            Some(_) => Vec::new(),
+            None => lint::lint(&metrics, self.openmetrics),
+        };
+        let scraped_at = match &self.replay {
+            Some(replay) => replay.wall_time(taken_at),
+            None => SystemTime::now() - taken_at.elapsed(),
+        };
+        self.skewed = model::skewed_timestamps(&metrics, scraped_at, self.max_timestamp_skew);
+
+        let mut churned = Vec::new();
+        if let (Some(old), Some(previous)) = (&self.current, previous) {
+            self.churn.update(old, &metrics, self.keep_disappeared);
+            if self.replay.is_none() {
+                churned = events::churn(old, &metrics, scraped_at);
+            }
+            self.movements = model::family_movements(&metrics, &previous);
+            self.movers = model::top_movers(&metrics, &previous, taken_at, self.top);
+            self.previous = Some(previous);
+        }
+        for event in churned {
+            self.log_event(event);
+        }
+
+        // The families map is re-built on every parse, so find the
+        // previously selected family and series again by name.
+        let selected_series = self.selected_series_key();
+        self.keeping_selection(|app| {
+            app.label_index = search::LabelIndex::new(&metrics);
+            // Searched again over the new scrape.
+            app.label_matches = None;
+            app.current = Some(metrics);
+            app.sort_families();
+        });
+        if let Some(key) = selected_series {
+            self.reselect_series(&key);
+        }
+        if raw.is_some() {
+            self.raw = raw;
+        }
+        if matches!(self.view, View::ParseError(_)) {
+            self.view = View::Table;
+        }
+        self.last_success_at = Some(taken_at);
+        self.evaluate_highlights();
+    }
+
+    /// Freezes the display on the current scrape, or shows the latest one
+    /// again.
+    fn toggle_pause(&mut self) {
+        match self.paused.take() {
+            None => self.paused = Some(Paused::default()),
+            Some(Paused {
+                latest: Some(pending), ..
+            }) => self.show(pending.metrics, pending.taken_at, pending.raw, pending.previous),
+            Some(_) => {}
+        }
+    }
+
+    /// Re-checks the highlight rules against the current scrape, with rates
+    /// against the previous one.
+    fn evaluate_highlights(&mut self) {
+        let Some(metrics) = &self.current else {
+            return;
+        };
+        let highlights = highlight::evaluate(&self.highlight_rules, metrics, |name, fam, sample| {
+            match self.series_change(name, fam, sample) {
+                Some(Change::Rate(rate)) => Some(rate),
+                _ => None,
+            }
+        });
+        let previous = std::mem::replace(&mut self.highlights, highlights);
+        if self.replay.is_some() {
+            return;
+        }
+        let was_firing: HashSet<(usize, &SeriesKey)> =
+            previous.firing.iter().map(|firing| (firing.rule, &firing.key)).collect();
+        let fired: Vec<events::Event> = self
+            .highlights
+            .firing
+            .iter()
+            .filter(|firing| !was_firing.contains(&(firing.rule, &firing.key)))
+            .map(|firing| events::rule_fired(&self.highlight_rules[firing.rule], firing, SystemTime::now()))
+            .collect();
+        for event in fired {
+            self.log_event(event);
+        }
+    }
+
+    /// Forgets the current scrape and everything compared against it, for
+    /// when the next one comes from somewhere else entirely.
+    fn reset_scrapes(&mut self) {
+        self.current = None;
+        self.order.clear();
+        self.paused = None;
+        self.baseline = None;
+        self.label_index = search::LabelIndex::default();
+        self.label_matches = None;
+        self.summaries.clear();
+        self.raw = None;
+        self.previous = None;
+        self.last_success_at = None;
+        self.unchanged = None;
+        self.body_hash = None;
+        self.churn = model::Churn::default();
+        self.steadiness = model::Steadiness::default();
+        self.quiet.clear();
+        self.counter_resets = model::CounterResets::default();
+        self.movements.clear();
+        self.movers.clear();
+        self.highlights = highlight::Highlights::default();
+        self.history.clear();
+    }
+
+    /// Adds the latest scrape, or a gap if it failed, to the history of
+    /// pinned families and of the family open in the detail view.
+    fn record_history(&mut self, metrics: Option<&Exposition>, taken_at: Instant) {
+        let detail = match &self.view {
+            View::Detail(detail) => Some(detail.family.as_str()),
+            _ => None,
+        };
+        self.history.record(metrics, |name| self.pinned.contains(name) || detail == Some(name), taken_at);
+    }
+
+    /// Takes the current scrape as the baseline, replacing any earlier one.
+    fn set_baseline(&mut self) {
+        let Some(metrics) = &self.current else {
+            self.notify("nothing scraped yet to take a baseline of");
+            return;
+        };
+        let taken_at = self.last_success_at.unwrap_or_else(Instant::now);
+        let message = match self.baseline {
+            Some(_) => "baseline taken again",
+            None => "baseline taken, b hides the change since it",
+        };
+        self.baseline = Some(model::Baseline::capture(metrics, taken_at));
+        self.show_baseline = true;
+        self.notify(message);
+    }
+
+    /// What a series did since the baseline, while that's being shown.
+    pub(crate) fn since_baseline(&self, name: &str, sample: &Series) -> Option<model::SinceBaseline> {
+        self.baseline.as_ref().filter(|_| self.show_baseline)?.since(name, sample)
+    }
+
+    /// Rate or delta of one series since the previous scrape, if there was one.
+    pub(crate) fn series_change(&self, name: &str, fam: &Family, sample: &Series) -> Option<Change> {
+        let previous = self.previous.as_ref()?;
+        let current = model::scalar_value(&sample.value)?;
+        let elapsed = self.last_success_at?.saturating_duration_since(previous.taken_at);
+        let before = previous.values.get(&SeriesKey::new(name, sample)).copied();
+        model::change(&fam.family_type, before, current, elapsed)
+    }
+
+    /// Cumulative buckets of the histogram series `samples` summed by bound:
+    /// all they've counted, or while rates are shown what they counted since
+    /// the previous scrape, which is `None` until there is one.
+    pub(crate) fn histogram_buckets<'a>(
+        &self,
+        name: &str,
+        samples: impl IntoIterator<Item = &'a Series>,
+    ) -> Option<Vec<(f64, f64)>> {
+        if self.show_rates && self.previous.is_none() {
+            return None;
+        }
+        let buckets: Vec<Vec<(f64, f64)>> = samples
+            .into_iter()
+            .filter_map(|sample| {
+                let PrometheusValue::Histogram(histogram) = &sample.value else {
+                    return None;
+                };
+                let current = model::cumulative_buckets(histogram);
+                if !self.show_rates {
+                    return Some(current);
+                }
+                let before = self.previous.as_ref()?.buckets.get(&SeriesKey::new(name, sample))?;
+                Some(model::bucket_increase(&current, before))
+            })
+            .collect();
+        Some(model::sum_buckets(buckets.iter().map(Vec::as_slice)))
+    }
+
+    /// Quantile `q` of cumulative `buckets` as the views show it, e.g.
+    /// "p99 ≈ 250ms", or "p99 > 10s" when it's past the last finite bound.
+    pub(crate) fn quantile_text(&self, name: &str, fam: &Family, buckets: Option<&[(f64, f64)]>, q: f64) -> String {
+        match buckets.and_then(|buckets| model::histogram_quantile(q, buckets)) {
+            Some(QuantileEstimate::Within(value)) => {
+                format!("{} ≈ {}", model::format_percentile(q), self.display_number(value, name, fam))
+            }
+            Some(QuantileEstimate::Above(bound)) => {
+                format!("{} > {}", model::format_percentile(q), self.display_number(bound, name, fam))
+            }
+            None => format!("{} -", model::format_percentile(q)),
+        }
+    }
+
+    fn time_until_refresh(&self) -> Option<Duration> {
+        self.schedule.as_ref().map(|schedule| schedule.until_due(Instant::now()))
+    }
+}
+
+/// Runs the viewer over one `App` per ENDPOINT, showing the `active` one.
+/// Every app keeps fetching and refreshing in the background. With
+/// `targets`, `F` re-reads the targets file and adds or removes tabs; with
+/// `opener`, `:` opens other endpoints.
+pub(crate) fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    mut apps: Vec<App>,
+    mut targets: Option<TargetsFile>,
+    mut opener: Option<Opener>,
+    mut mouse: bool,
+) -> Result<()> {
+    // Wake up regularly even without input so fetch results get picked up
+    // and the spinner keeps moving.
+    const TICK: Duration = Duration::from_millis(100);
+    // Frames slower than this make typing feel sluggish.
+    const SLOW_FRAME: Duration = Duration::from_millis(50);
+    let mut active = 0;
+    let mut opening: Option<Opening> = None;
+    if let Some(opener) = &mut opener {
+        let endpoints = match opener.history_path.as_deref().map(state::load_endpoints) {
+            Some(Ok(endpoints)) => endpoints,
+            Some(Err(warning)) => {
+                apps[active].notify(warning);
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        for app in &mut apps {
+            app.opens_endpoints = true;
+            app.endpoint_history = endpoints.clone();
+        }
+    }
+
+    loop {
+        let mut merge = false;
+        for app in &mut apps {
+            if let Some(result) = app.fetcher.try_recv() {
+                app.apply_fetch(result);
+                merge = true;
+            }
+        }
+        if let Some(opener) = &opener {
+            merge |= finish_opening(&mut apps, &mut opening, &mut targets, opener, &mut active);
+        }
+        if merge {
+            merge_tabs(&mut apps);
+        }
+
+        let drawing = Instant::now();
+        terminal.draw(|f| ui(f, &mut apps, active))?;
+        let drawn = drawing.elapsed();
+        if drawn >= SLOW_FRAME {
+            tracing::warn!(elapsed = ?drawn, "slow frame");
+        } else {
+            tracing::trace!(elapsed = ?drawn, "frame");
+        }
+
+        let next = apps
+            .iter()
+            .filter_map(|app| app.time_until_refresh().or(app.replay.as_ref().and_then(Replay::time_until_due)))
+            .min();
+        let timeout = next.map_or(TICK, |t| t.min(TICK));
+        let mut refresh_all = false;
+        let mut reload = false;
+        if event::poll(timeout)? {
+            let event = event::read()?;
+            // Drawn again at the top of the loop; resizing first means the
+            // layout is worked out for the new size rather than on the draw
+            // after.
+            if let Event::Resize(..) = event {
+                terminal.autoresize()?;
+            }
+            if let Event::Mouse(mouse_event) = event {
+                let app = &mut apps[active];
+                if app.takes_mouse() {
+                    app.handle_mouse(mouse_event);
+                }
+            }
+            if let Event::Key(key) = event {
+                let tabs = apps.len();
+                let app = &mut apps[active];
+                match app.handle_key(key) {
+                    Some(Action::Quit) => {
+                        for app in &mut apps {
+                            app.save_state();
+                        }
+                        return Ok(());
+                    }
+                    Some(Action::NextTab) => active = (active + 1) % tabs,
+                    Some(Action::PreviousTab) => active = (active + tabs - 1) % tabs,
+                    Some(Action::SelectTab) => {
+                        let index = match key.code {
+                            KeyCode::Char(c) => c.to_digit(10).map_or(0, |digit| digit as usize),
+                            _ => 0,
+                        };
+                        if (1..=tabs).contains(&index) {
+                            active = index - 1;
+                        }
+                    }
+                    Some(Action::Refresh) => refresh_all = true,
+                    Some(Action::ReloadTargets) => reload = true,
+                    Some(Action::OpenEndpoint) => match &opener {
+                        Some(opener) => match (opener.open)(app.endpoint_prompt.input.trim()) {
+                            Ok(new) => {
+                                opening = Some(Opening {
+                                    app: new,
+                                    origin: active,
+                                    new_tab: app.endpoint_prompt.new_tab,
+                                });
+                            }
+                            Err(e) => app.endpoint_failed(format!("{e:#}")),
+                        },
+                        None => app.endpoint_failed(String::from("only the viewer of live endpoints can open another")),
+                    },
+                    Some(action @ (Action::OpenInPager | Action::OpenInEditor)) => {
+                        let program = match action {
+                            Action::OpenInPager => external::Program::Pager,
+                            _ => external::Program::Editor,
+                        };
+                        app.open_external(terminal, program, mouse);
+                    }
+                    Some(Action::ToggleMouse) => {
+                        mouse = !mouse;
+                        let toggled = if mouse {
+                            execute!(io::stdout(), EnableMouseCapture)
+                        } else {
+                            execute!(io::stdout(), DisableMouseCapture)
+                        };
+                        match toggled {
+                            Ok(()) if mouse => app.notify("mouse on: the wheel scrolls and clicks select"),
+                            Ok(()) => app.notify("mouse off: the terminal selects text again"),
+                            Err(e) => app.notify(format!("couldn't switch mouse capture: {e}")),
+                        }
+                    }
+                    Some(Action::CycleAggregation) => match &mut app.aggregation {
+                        Some(aggregation) => {
+                            *aggregation = aggregation.next();
+                            let message = format!("aggregation: {}", aggregation.name());
+                            // Aggregated series aren't the ones the last merge had.
+                            app.reset_scrapes();
+                            app.notify(message);
+                            merge_tabs(&mut apps);
+                        }
+                        None => app.notify("aggregation is only available in the ALL tab"),
+                    },
+                    Some(_) | None => {}
+                }
+                // Saved as soon as they change, so a crash loses nothing.
+                for app in &mut apps {
+                    app.save_state();
+                }
+            }
+        }
+
+        if reload {
+            match &mut targets {
+                Some(targets) => {
+                    reload_targets(&mut apps, targets, &mut active);
+                    merge_tabs(&mut apps);
+                }
+                None => apps[active].notify("only available with --targets-file"),
+            }
+        }
+
+        for app in &mut apps {
+            let due = app.schedule.as_mut().is_some_and(|schedule| schedule.tick(Instant::now()));
+            if due {
+                app.scheduled_refresh();
+            } else if refresh_all && app.refreshable {
+                app.refresh();
+            }
+            if app.replay.as_ref().is_some_and(Replay::is_due) {
+                app.step_scrape(true);
+            }
+        }
+    }
+}
+
+/// How `:` opens an endpoint typed into it, configured like the ones on
+/// the command line, and where the endpoints opened are remembered.
+pub(crate) struct Opener<'a> {
+    pub(crate) open: Box<dyn Fn(&str) -> Result<App> + 'a>,
+    /// `None` with `--no-state`.
+    pub(crate) history_path: Option<PathBuf>,
+}
+
+/// An endpoint typed into the `:` prompt of tab `origin`, waiting for its
+/// first scrape.
+struct Opening {
+    app: App,
+    origin: usize,
+    new_tab: bool,
+}
+
+/// Puts the endpoint being opened in place once its first scrape is in,
+/// replacing the tab it was typed in or in a tab of its own, and remembers
+/// it. A failed scrape is shown in the prompt and leaves the tabs as they
+/// were; closing the prompt gives up on the endpoint. Returns whether the
+/// tabs changed.
+fn finish_opening(
+    apps: &mut Vec<App>,
+    opening: &mut Option<Opening>,
+    targets: &mut Option<TargetsFile>,
+    opener: &Opener,
+    active: &mut usize,
+) -> bool {
+    let Some(pending) = opening else {
+        return false;
+    };
+    if apps.get(pending.origin).is_none_or(|app| app.input_mode != InputMode::Endpoint) {
+        *opening = None;
+        return false;
+    }
+    let Some(result) = pending.app.fetcher.try_recv() else {
+        return false;
+    };
+    pending.app.apply_fetch(result);
+    let Some(Opening {
+        mut app,
+        origin,
+        new_tab,
+    }) = opening.take()
+    else {
+        return false;
+    };
+    if let Some(e) = &app.last_error {
+        apps[origin].endpoint_failed(format!("{e:#}"));
+        return false;
+    }
+
+    let mut endpoints = apps[origin].endpoint_history.clone();
+    state::remember(&mut endpoints, &app.endpoint);
+    if let Some(path) = &opener.history_path {
+        if let Err(e) = state::save_endpoints(path, &endpoints) {
+            app.notify(format!("{e:#}"));
+        }
+    }
+    apps[origin].endpoint_opened();
+    for app in apps.iter_mut() {
+        app.endpoint_history = endpoints.clone();
+    }
+    app.endpoint_history = endpoints;
+    app.opens_endpoints = true;
+    let target = targets::Target {
+        url: app.endpoint.clone(),
+        labels: Vec::new(),
+    };
+    if new_tab {
+        // New tabs go before the ALL tab, when there is one.
+        let index = match apps.last() {
+            Some(last) if last.aggregation.is_some() => apps.len() - 1,
+            _ => apps.len(),
+        };
+        if let Some(targets) = targets {
+            targets.current.insert(index.min(targets.current.len()), target);
+        }
+        apps.insert(index, app);
+        *active = index;
+    } else {
+        if let Some(current) = targets.as_mut().and_then(|targets| targets.current.get_mut(origin)) {
+            *current = target;
+        }
+        apps[origin].save_state();
+        apps[origin] = app;
+        *active = origin;
+    }
+    true
+}
+
+/// What `F` re-reads: the `--targets-file`, the targets behind the tabs
+/// (in tab order) and how to open a tab for a new one.
+pub(crate) struct TargetsFile<'a> {
+    pub(crate) path: PathBuf,
+    pub(crate) current: Vec<targets::Target>,
+    pub(crate) open: Box<dyn Fn(&targets::Target) -> Result<App> + 'a>,
+}
+
+/// Re-reads the targets file. Tabs of targets that are still listed are
+/// kept with their state, new targets get a tab before the ALL tab, and
+/// targets that are gone lose theirs.
+fn reload_targets(apps: &mut Vec<App>, targets: &mut TargetsFile, active: &mut usize) {
+    let loaded = match targets::load(&targets.path) {
+        Ok(loaded) if loaded.targets.is_empty() => {
+            let message = format!("{} lists no valid targets; keeping the current tabs", targets.path.display());
+            apps[*active].notify(message);
+            return;
+        }
+        Ok(loaded) => loaded,
+        Err(e) => {
+            apps[*active].notify(format!("{e:#}"));
+            return;
+        }
+    };
+    let Some(mut all) = apps.pop() else {
+        return;
+    };
+    let mut old: Vec<(targets::Target, App)> = targets.current.drain(..).zip(apps.drain(..)).collect();
+    let mut invalid = loaded.invalid;
+    let mut added = 0;
+    for target in loaded.targets {
+        let app = match old.iter().position(|(current, _)| *current == target) {
+            Some(index) => old.remove(index).1,
+            None => match (targets.open)(&target) {
+                Ok(app) => {
+                    added += 1;
+                    app
+                }
+                Err(e) => {
+                    invalid.push(format!("{:?}: {e:#}", target.url));
+                    continue;
+                }
+            },
+        };
+        targets.current.push(target);
+        apps.push(app);
+    }
+    all.invalid_targets = invalid;
+    all.notify(format!(
+        "reloaded {}: {} targets, {added} added, {} removed",
+        targets.path.display(),
+        targets.current.len(),
+        old.len()
+    ));
+    apps.push(all);
+    *active = (*active).min(apps.len() - 1);
+}
+
+/// Rebuilds the ALL tab, the last one when there is one, from every other
+/// tab's latest scrape.
+fn merge_tabs(apps: &mut [App]) {
+    let Some((all, endpoints)) = apps.split_last_mut() else {
+        return;
+    };
+    let Some(aggregation) = all.aggregation else {
+        return;
+    };
+    let scrapes: Vec<(&str, &Exposition)> =
+        endpoints.iter().filter_map(|app| Some((app.tab_name(), app.current.as_ref()?))).collect();
+    if scrapes.is_empty() {
+        return;
+    }
+    let (merged, warnings) = model::merge(&scrapes, aggregation);
+    all.merge_warnings = warnings;
+    all.accept(merged, Instant::now(), None);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::fetch::{Body, HttpConfig};
+
+    pub(crate) fn app() -> App {
+        // Stdin isn't refreshable, so the app never starts a fetch of its own.
+        app_reading(Source::Stdin)
+    }
+
+    fn app_reading(source: Source) -> App {
+        let http = HttpFetcher::new(HttpConfig::default()).unwrap();
+        let filter = FamilyFilter {
+            include: None,
+            exclude: None,
+            types: Vec::new(),
+        };
+        let fetcher = Fetcher::spawn(source.clone(), http, None);
+        App::new(source, fetcher, None, filter, Format::Prometheus, false, 2)
+    }
+
+    /// A press of `code` without modifiers.
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    pub(crate) fn scrape(body: Result<&str>) -> FetchResult {
+        FetchResult {
+            body: body.map(|text| Body::plain(text.to_string())),
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn only_scheduled_refreshes_are_counted_as_skipped() {
+        // The first fetch starts here and stays in flight, as nothing collects it.
+        let mut app = app_reading(Source::File(std::env::temp_dir().join("promtool-skipped-refresh-test.prom")));
+
+        app.perform(Action::Refresh);
+        assert_eq!(app.skipped_refreshes, 0);
+        app.scheduled_refresh();
+        assert_eq!(app.skipped_refreshes, 1);
+    }
+
+    #[test]
+    fn pins_filter_and_sort_are_restored_from_the_saved_state() {
+        let path = std::env::temp_dir().join(format!("promtool-app-state-test-{}.json", std::process::id()));
+        let mut first = app();
+        first.restore_state(path.clone(), false, false);
+        first.pinned.insert(String::from("up"));
+        first.filter = String::from("http_");
+        first.sort_reversed = true;
+        first.perform(Action::CycleTheme);
+        first.perform(Action::PickColumns);
+        first.handle_key(key(KeyCode::Char('j')));
+        first.handle_key(key(KeyCode::Char(' ')));
+        first.save_state();
+
+        let mut second = app();
+        second.restore_state(path.clone(), true, false);
+        assert!(second.pinned.contains("up"));
+        assert_eq!(second.filter, "http_");
+        assert!(second.sort_reversed);
+        // --theme on the command line wins, but the chosen theme stays saved.
+        assert!(second.theme == ThemeName::Dark);
+        assert_eq!(second.state().theme, Some(ThemeName::Light));
+        assert_eq!(second.columns, Columns::parse("name,value").unwrap());
+
+        second.perform(Action::ForgetState);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn columns_are_shown_hidden_and_moved_in_the_picker() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n")));
+        app.handle_key(key(KeyCode::Char('|')));
+        assert_eq!(app.column_picker, Some(0));
+        app.handle_key(key(KeyCode::Char(' ')));
+        assert_eq!(app.current_notice(), Some("the name column is always shown"));
+
+        // Rate is listed last, after the hidden count.
+        for _ in 0..4 {
+            app.handle_key(key(KeyCode::Down));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.columns, Columns::parse("name,type,value,rate").unwrap());
+        app.handle_key(key(KeyCode::Char('K')));
+        app.handle_key(key(KeyCode::Char('K')));
+        assert_eq!(app.columns, Columns::parse("name,rate,type,value").unwrap());
+        assert_eq!(app.column_picker, Some(1), "the cursor follows the column it moved");
+        assert_eq!(app.state().columns, Some(app.columns.clone()));
+
+        // The picker takes the keys until it's closed.
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), None);
+        assert_eq!(app.column_picker, None);
+        assert!(matches!(app.view, View::Table));
+    }
+
+    #[test]
+    fn keys_go_to_the_overlay_or_prompt_before_the_keybindings() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n# TYPE b gauge\nb 2\n")));
+        assert_eq!(app.handle_key(key(KeyCode::Char('j'))), None);
+        assert_eq!(app.selected_family_name().as_deref(), Some("b"));
+
+        app.handle_key(key(KeyCode::Char('?')));
+        assert_eq!(app.handle_key(key(KeyCode::Char('k'))), None);
+        assert_eq!(app.selected_family_name().as_deref(), Some("b"));
+        app.handle_key(key(KeyCode::Esc));
+
+        app.handle_key(key(KeyCode::Char('/')));
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), None);
+        assert_eq!(app.filter, "q");
+        app.handle_key(key(KeyCode::Esc));
+
+        // Quitting and switching tabs are left to the event loop.
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), Some(Action::Quit));
+        assert_eq!(app.handle_key(key(KeyCode::Tab)), Some(Action::NextTab));
+    }
+
+    #[test]
+    fn table_keys_sort_pin_and_tell_modifiers_apart() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE b_total counter\nb_total 1\n# TYPE a gauge\na 1\n# TYPE c gauge\nc 1\n")));
+        let names = |app: &App| -> Vec<String> {
+            app.visible_families().iter().map(|(name, _)| name.to_string()).collect()
+        };
+        assert_eq!(names(&app), vec!["a", "b_total", "c"]);
+
+        app.handle_key(key(KeyCode::Char('S')));
+        assert_eq!(names(&app), vec!["c", "b_total", "a"]);
+        app.handle_key(key(KeyCode::Char('s')));
+        assert_eq!(app.sort_key, SortKey::Type);
+        app.handle_key(key(KeyCode::Char('S')));
+        app.handle_key(key(KeyCode::Char('g')));
+        assert_eq!(app.selected_family_name().as_deref(), Some("b_total"), "counters sort before gauges");
+
+        app.handle_key(key(KeyCode::Char('G')));
+        app.handle_key(key(KeyCode::Char('p')));
+        assert_eq!(names(&app)[0], "c", "pinned families go on top");
+        assert_eq!(app.input_mode, InputMode::Normal);
+        // Ctrl-p is the family finder, not another pin.
+        app.handle_key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL));
+        assert_eq!(app.input_mode, InputMode::Find);
+        assert!(app.pinned.contains("c"));
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(app.input_mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn filter_keys_narrow_the_table_until_it_is_cleared() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE http_up gauge\nhttp_up 1\n# TYPE grpc_up gauge\ngrpc_up 1\n")));
+        let shown = |app: &App| app.visible_families().len();
+
+        app.handle_key(key(KeyCode::Char('/')));
+        assert_eq!(app.input_mode, InputMode::Search);
+        for c in "http".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(shown(&app), 1);
+        for _ in 0..4 {
+            app.handle_key(key(KeyCode::Backspace));
+        }
+        assert_eq!(shown(&app), 2);
+        app.handle_key(key(KeyCode::Char('g')));
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!((app.filter.as_str(), shown(&app)), ("g", 1), "Enter keeps the filter");
+
+        app.handle_key(key(KeyCode::Char('/')));
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!((app.filter.as_str(), shown(&app)), ("", 2), "Esc clears it");
+    }
+
+    #[test]
+    fn detail_keys_move_through_the_series_and_back() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE queue_depth gauge\n",
+            "queue_depth{queue=\"a\"} 1\n",
+            "queue_depth{queue=\"b\"} 2\n",
+            "queue_depth{queue=\"c\"} 3\n",
+        ))));
+        app.handle_key(key(KeyCode::Enter));
+        assert!(matches!(&app.view, View::Detail(detail) if detail.family == "queue_depth"));
+        let selected = |app: &App| app.selected_series_key().map(|key| key.to_string());
+
+        app.handle_key(key(KeyCode::Char('j')));
+        assert_eq!(selected(&app).as_deref(), Some("queue_depth{queue=\"b\"}"));
+        app.handle_key(key(KeyCode::Char('G')));
+        assert_eq!(selected(&app).as_deref(), Some("queue_depth{queue=\"c\"}"));
+        app.handle_key(key(KeyCode::Char('k')));
+        assert_eq!(selected(&app).as_deref(), Some("queue_depth{queue=\"b\"}"));
+
+        // q goes back to the table rather than quitting.
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), None);
+        assert!(matches!(app.view, View::Table));
+        assert_eq!(app.selected_family_name().as_deref(), Some("queue_depth"));
+    }
+
+    #[test]
+    fn the_label_breakdown_ranks_labels_and_copies_a_rule_dropping_one() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE inflight gauge\n",
+            "inflight{method=\"GET\",route=\"/a\"} 1\n",
+            "inflight{method=\"GET\",route=\"/b\"} 1\n",
+            "inflight{method=\"GET\",route=\"/c\"} 1\n",
+            "inflight{method=\"POST\",route=\"/a\"} 1\n",
+        ))));
+        let breakdown = |app: &App| match &app.view {
+            View::Detail(detail) => detail
+                .breakdown
+                .as_ref()
+                .map(|breakdown| (breakdown.series, breakdown.labels[breakdown.cursor].label.clone())),
+            _ => None,
+        };
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Char('C')));
+        assert_eq!(breakdown(&app), Some((4, String::from("route"))));
+        app.handle_key(key(KeyCode::Char('j')));
+        assert_eq!(breakdown(&app), Some((4, String::from("method"))));
+
+        // What `y` would copy, without putting it on the system clipboard.
+        let rule = app.breakdown_rule().unwrap_or_default();
+        assert!(rule.contains("regex: inflight\n  target_label: method\n"), "{rule}");
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(breakdown(&app), None);
+        assert!(matches!(app.view, View::Detail(_)));
+    }
+
+    #[test]
+    fn the_mouse_leaves_the_series_under_the_label_breakdown_alone() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE inflight gauge\n",
+            "inflight{route=\"/a\"} 1\n",
+            "inflight{route=\"/b\"} 1\n",
+        ))));
+        app.handle_key(key(KeyCode::Enter));
+        assert!(app.takes_mouse());
+        app.handle_key(key(KeyCode::Char('C')));
+        // The pane covers the whole screen, so the wheel would move a
+        // selection that can't be seen.
+        assert!(!app.takes_mouse());
+        app.handle_key(key(KeyCode::Esc));
+        assert!(app.takes_mouse());
+    }
+
+    #[test]
+    fn w_graphs_the_selected_series_as_a_rate_of_counters() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE requests_total counter\n",
+            "requests_total{code=\"200\",path=\"/api/v1\"} 10\n",
+            "requests_total{code=\"500\",path=\"/api/v1\"} 1\n",
+        ))));
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Char('W')));
+        assert_eq!(app.current_notice(), Some("set --prometheus-url or --grafana-url to graph series"));
+
+        // The URLs are checked without pressing W again, which would open a
+        // browser or copy to the system clipboard.
+        app.prometheus_url = Some(String::from("prom:9090"));
+        app.handle_key(key(KeyCode::Char('j')));
+        let expr = r#"rate(requests_total{code="500",path="/api/v1"}[5m])"#;
+        assert_eq!(app.graph_expression().as_deref(), Some(expr));
+        let url = app.graph_url().unwrap().unwrap();
+        assert_eq!(url, query::graph_url("prom:9090", expr).unwrap());
+
+        app.grafana_url = Some(String::from("https://grafana.example.com"));
+        let url = app.graph_url().unwrap().unwrap();
+        assert_eq!(url, query::explore_url("https://grafana.example.com", expr).unwrap());
+    }
+
+    #[test]
+    fn failed_refresh_keeps_the_last_good_metrics() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 1\n")));
+        assert!(app.family("up").is_some());
+        assert!(!app.is_stale());
+
+        app.apply_fetch(scrape(Err(anyhow::anyhow!("connection refused"))));
+        assert!(app.family("up").is_some(), "stale data should stay visible");
+        assert!(app.is_stale());
+        let error = format!("{:#}", app.last_error.as_ref().unwrap());
+        assert!(error.contains("connection refused"), "{error}");
+
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 2\n# TYPE other gauge\nother 1\n")));
+        assert!(!app.is_stale());
+        assert!(app.last_error.is_none());
+        assert!(app.family("other").is_some());
+    }
+
+    #[test]
+    fn selection_follows_the_family_through_refreshes_filters_and_sorts() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE b gauge\nb 1\n# TYPE c gauge\nc 1\n")));
+        app.reselect("c");
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n# TYPE b gauge\nb 1\n# TYPE c gauge\nc 1\n")));
+        assert_eq!((app.selected(), app.selected_family_name().as_deref()), (2, Some("c")));
+
+        app.handle_search_key(KeyCode::Char('c'));
+        assert_eq!((app.selected(), app.selected_family_name().as_deref()), (0, Some("c")));
+        app.handle_search_key(KeyCode::Esc);
+        assert_eq!((app.selected(), app.selected_family_name().as_deref()), (2, Some("c")));
+
+        app.set_sort(app.sort_key, true);
+        assert_eq!((app.selected(), app.selected_family_name().as_deref()), (0, Some("c")));
+
+        // Once it's gone, the cursor stays where it was.
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n# TYPE b gauge\nb 1\n")));
+        assert_eq!((app.selected(), app.selected_family_name().as_deref()), (0, Some("b")));
+    }
+
+    #[test]
+    fn detail_selection_follows_the_series_through_refreshes() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup{job=\"b\"} 1\nup{job=\"c\"} 1\n")));
+        app.show_detail(String::from("up"), 1);
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup{job=\"a\"} 1\nup{job=\"b\"} 1\nup{job=\"c\"} 1\n")));
+        let View::Detail(detail) = &app.view else {
+            panic!("the detail view should stay open");
+        };
+        assert_eq!(detail.selected, 2);
+    }
+
+    #[test]
+    fn zero_and_unchanged_series_are_hidden_until_shown_again() {
+        let mut app = app();
+        app.unchanged_after = 2;
+        let names = |app: &App| -> Vec<String> {
+            app.visible_families()
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        let refresh = |app: &mut App, requests: u32| {
+            app.apply_fetch(scrape(Ok(&format!(
+                "# TYPE errors_total counter\n\
+                 errors_total{{code=\"500\"}} 0\n\
+                 errors_total{{code=\"502\"}} 0\n\
+                 # TYPE queue_length gauge\n\
+                 queue_length{{queue=\"a\"}} 0\n\
+                 queue_length{{queue=\"b\"}} {requests}\n\
+                 # TYPE temperature_celsius gauge\n\
+                 temperature_celsius 21\n"
+            ))))
+        };
+        refresh(&mut app, 1);
+        app.handle_key(key(KeyCode::Char('z')));
+        assert_eq!(names(&app), vec!["queue_length", "temperature_celsius"]);
+        assert_eq!(app.hidden_series(), (3, 0));
+
+        // One refresh isn't enough to tell that a series doesn't move.
+        app.handle_key(key(KeyCode::Char('z')));
+        app.handle_key(key(KeyCode::Char('Z')));
+        refresh(&mut app, 2);
+        assert_eq!(app.steadiness.refreshes_to_go(2), 1);
+        assert_eq!(names(&app).len(), 3);
+        refresh(&mut app, 3);
+        assert_eq!(names(&app), vec!["queue_length"]);
+        assert_eq!(app.hidden_series(), (0, 4));
+
+        app.handle_key(key(KeyCode::Enter));
+        let View::Detail(detail) = &app.view else {
+            panic!("the detail view should be open");
+        };
+        let fam = app.family("queue_length").unwrap();
+        let shown: Vec<Vec<(String, String)>> =
+            app.detail_series(fam, detail).iter().map(|(_, sample)| model::label_pairs(sample)).collect();
+        assert_eq!(shown, vec![vec![(String::from("queue"), String::from("b"))]]);
+    }
+
+    #[test]
+    fn the_endpoint_prompt_recalls_history_and_rejects_bad_urls() {
+        let mut app = app();
+        app.handle_key(key(KeyCode::Char(':')));
+        assert_eq!(app.input_mode, InputMode::Normal, "nothing could open it");
+
+        app.opens_endpoints = true;
+        app.endpoint_history = vec![
+            String::from("http://pod-b:9100/metrics"),
+            String::from("http://pod-a:9100/metrics"),
+        ];
+        app.handle_key(key(KeyCode::Char(':')));
+        assert_eq!(app.input_mode, InputMode::Endpoint);
+        for _ in 0..3 {
+            app.handle_key(key(KeyCode::Up));
+        }
+        assert_eq!(app.endpoint_prompt.input, "http://pod-a:9100/metrics");
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.endpoint_prompt.input, "http://pod-b:9100/metrics");
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.endpoint_prompt.input, "");
+
+        for c in "ftp://pod-c".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), None);
+        assert!(
+            app.endpoint_prompt.error.as_deref().is_some_and(|e| e.contains("not an http:// or https:// URL")),
+            "{:?}",
+            app.endpoint_prompt.error
+        );
+
+        app.endpoint_prompt.input = String::from(":9100");
+        let alt_enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT);
+        assert_eq!(app.handle_key(alt_enter), Some(Action::OpenEndpoint));
+        assert!(app.endpoint_prompt.opening && app.endpoint_prompt.new_tab);
+        // Until the scrape is in, only Esc does anything.
+        app.handle_key(key(KeyCode::Char('x')));
+        assert_eq!(app.endpoint_prompt.input, ":9100");
+        app.endpoint_failed(String::from("couldn't fetch metrics from http://localhost:9100/metrics"));
+        assert!(!app.endpoint_prompt.opening);
+        assert_eq!(app.input_mode, InputMode::Endpoint);
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(app.input_mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn detail_series_sort_numeric_label_values_by_value() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE cpu_busy gauge\n",
+            "cpu_busy{cpu=\"10\",code=\"503\"} 1\n",
+            "cpu_busy{cpu=\"2\",code=\"200\"} 1\n",
+            "cpu_busy{cpu=\"1\",code=\"503\"} 1\n",
+        ))));
+        let fam = app.family("cpu_busy").unwrap();
+        let cpus = |group_by| -> Vec<String> {
+            grouped_series(fam, group_by, None)
+                .iter()
+                .flat_map(|(_, sample)| model::label_pairs(sample))
+                .filter(|(name, _)| name == "cpu")
+                .map(|(_, value)| value)
+                .collect()
+        };
+        assert_eq!(cpus(None), vec!["1", "2", "10"]);
+        assert_eq!(cpus(Some("code")), vec!["2", "1", "10"]);
+    }
+
+    #[test]
+    fn unparseable_refresh_keeps_the_last_good_metrics() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 1\n")));
+
+        app.apply_fetch(scrape(Ok("up{ 1\n")));
+        assert!(app.family("up").is_some());
+        assert!(app.is_stale());
+        assert!(app.parse_failure.is_some());
+        assert!(matches!(app.view, View::Table), "the stale table stays on screen");
+
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 1\n")));
+        assert!(!app.is_stale());
+        assert!(app.parse_failure.is_none());
+    }
+
+    #[test]
+    fn refreshes_without_anything_new_keep_the_scrape() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 1\n")));
+        assert_eq!(app.unchanged, None);
+        let parsed_at = app.last_success_at;
+
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 1\n")));
+        assert!(matches!(app.unchanged, Some((Unchanged::SameContent, _))));
+        assert_eq!(app.last_success_at, parsed_at);
+
+        app.apply_fetch(FetchResult {
+            body: Ok(Body {
+                status: Some(reqwest::StatusCode::NOT_MODIFIED),
+                ..Body::plain(String::new())
+            }),
+            duration: Duration::ZERO,
+        });
+        assert!(matches!(app.unchanged, Some((Unchanged::NotModified, _))));
+        assert!(app.family("up").is_some());
+        assert!(app.last_error.is_none());
+
+        app.apply_fetch(scrape(Ok("# TYPE up gauge\nup 0\n")));
+        assert_eq!(app.unchanged, None);
+        assert!(app.body_hash.is_some());
+    }
+
+    #[test]
+    fn first_failure_has_nothing_to_fall_back_to() {
+        let mut app = app();
+        app.apply_fetch(scrape(Err(anyhow::anyhow!("connection refused"))));
+        assert!(app.current.is_none());
+        assert!(app.last_error.is_some());
+        assert!(!app.is_stale());
+    }
+
+    #[test]
+    fn resets_churn_rules_and_notes_go_into_the_event_log() {
+        let mut app = app();
+        app.highlight_rules = vec![highlight::Rule::parse("queue_depth > 10").unwrap()];
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE jobs_total counter\n",
+            "jobs_total 100\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth 3\n",
+        ))));
+        assert!(app.events.is_empty());
+        app.apply_fetch(scrape(Ok(concat!(
+            "# TYPE jobs_total counter\n",
+            "jobs_total 5\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth 20\n",
+            "queue_depth{queue=\"sms\"} 1\n",
+        ))));
+
+        app.handle_key(key(KeyCode::Char('m')));
+        assert_eq!(app.input_mode, InputMode::Note);
+        for c in "deployed v2 ".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.current_notice(), Some("noted in the event log (E)"));
+
+        app.handle_key(key(KeyCode::Char('E')));
+        assert!(matches!(app.view, View::Events(_)));
+        let listed: Vec<(events::Kind, Option<&str>, &str)> = app
+            .listed_events()
+            .into_iter()
+            .map(|event| (event.kind, event.series.as_deref(), event.text.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (events::Kind::Annotation, None, "deployed v2"),
+                (events::Kind::RuleFired, Some("queue_depth"), "queue_depth > 10 at 20"),
+                (events::Kind::Appeared, Some("queue_depth{queue=\"sms\"}"), ""),
+                (events::Kind::CounterReset, Some("jobs_total"), "reset from 100 to 5"),
+            ]
+        );
+        app.handle_key(key(KeyCode::Char('t')));
+        assert_eq!(app.listed_events().len(), 1, "only notes");
+        app.handle_key(key(KeyCode::Char('E')));
+        assert!(matches!(app.view, View::Table));
+    }
+}
@@ -0,0 +1,232 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::humanize;
+
+/// `promtool bench`: scrape an endpoint over and over and report how long
+/// it takes, e.g. to tell whether an exporter got slow enough to hit the
+/// Prometheus scrape timeout.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// The endpoint to scrape
+    #[arg(value_name = "ENDPOINT")]
+    pub endpoint: String,
+
+    /// How many scrapes to make in all
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub count: usize,
+
+    /// Keep scraping for this long instead of making --count scrapes (e.g. "30s")
+    #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "count")]
+    pub duration: Option<Duration>,
+
+    /// How many scrapes are in flight at once, each on its own thread
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub concurrency: u32,
+
+    /// Print the report as JSON, for tracking it over time
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// One successful scrape.
+pub struct Sample {
+    /// Until the response headers arrived.
+    pub first_byte: Duration,
+    /// Until the body was read.
+    pub fetch: Duration,
+    pub parse: Duration,
+    /// As transferred, so compressed if the endpoint gzips.
+    pub size: usize,
+}
+
+/// Scrapes with `scrape` on `--concurrency` threads until `--count`
+/// scrapes were started or `--duration` is up. Failures are counted by
+/// their message.
+pub fn run(args: &BenchArgs, scrape: impl Fn() -> Result<Sample, String> + Sync) -> Report {
+    let started = Instant::now();
+    let deadline = args.duration.map(|duration| started + duration);
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..args.concurrency {
+            scope.spawn(|| loop {
+                let more = match deadline {
+                    Some(deadline) => Instant::now() < deadline,
+                    None => next.fetch_add(1, Ordering::Relaxed) < args.count,
+                };
+                if !more {
+                    break;
+                }
+                let outcome = scrape();
+                outcomes
+                    .lock()
+                    .expect("no scrape panics holding the lock")
+                    .push(outcome);
+            });
+        }
+    });
+    let outcomes = outcomes.into_inner().expect("no scrape panics holding the lock");
+    Report::new(&args.endpoint, outcomes, started.elapsed())
+}
+
+/// How one measurement was spread over the successful scrapes.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    /// Nearest-rank percentiles, so every figure is one that was measured.
+    /// `None` when there's nothing to summarize.
+    fn of(mut values: Vec<f64>) -> Option<Stats> {
+        values.sort_by(f64::total_cmp);
+        let rank = |p: f64| values[((p * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+        Some(Stats {
+            min: *values.first()?,
+            p50: rank(0.5),
+            p90: rank(0.9),
+            p99: rank(0.99),
+            max: *values.last()?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub endpoint: String,
+    pub scrapes: usize,
+    pub failed: usize,
+    pub elapsed_seconds: f64,
+    pub first_byte_seconds: Option<Stats>,
+    pub fetch_seconds: Option<Stats>,
+    pub parse_seconds: Option<Stats>,
+    pub size_bytes: Option<Stats>,
+    /// How many scrapes failed with each message.
+    pub errors: BTreeMap<String, usize>,
+}
+
+impl Report {
+    fn new(endpoint: &str, outcomes: Vec<Result<Sample, String>>, elapsed: Duration) -> Report {
+        let scrapes = outcomes.len();
+        let mut samples = Vec::new();
+        let mut errors = BTreeMap::new();
+        for outcome in outcomes {
+            match outcome {
+                Ok(sample) => samples.push(sample),
+                Err(message) => *errors.entry(message).or_default() += 1,
+            }
+        }
+        let stats = |measure: fn(&Sample) -> f64| Stats::of(samples.iter().map(measure).collect());
+        Report {
+            endpoint: endpoint.to_string(),
+            scrapes,
+            failed: scrapes - samples.len(),
+            elapsed_seconds: elapsed.as_secs_f64(),
+            first_byte_seconds: stats(|sample| sample.first_byte.as_secs_f64()),
+            fetch_seconds: stats(|sample| sample.fetch.as_secs_f64()),
+            parse_seconds: stats(|sample| sample.parse.as_secs_f64()),
+            size_bytes: stats(|sample| sample.size as f64),
+            errors,
+        }
+    }
+
+    /// A table of the measurements, one per line, then the errors.
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "{}: {} scrapes in {}, {} failed\n",
+            self.endpoint,
+            self.scrapes,
+            humanize::number(self.elapsed_seconds, "", "seconds"),
+            self.failed
+        );
+        if self.failed < self.scrapes {
+            text.push_str(&format!(
+                "{:<12}{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+                "", "min", "p50", "p90", "p99", "max"
+            ));
+        }
+        let rows = [
+            ("first byte", &self.first_byte_seconds, "seconds"),
+            ("fetch", &self.fetch_seconds, "seconds"),
+            ("parse", &self.parse_seconds, "seconds"),
+            ("size", &self.size_bytes, "bytes"),
+        ];
+        for (label, stats, unit) in rows {
+            let Some(stats) = stats else { continue };
+            let cells: String = [stats.min, stats.p50, stats.p90, stats.p99, stats.max]
+                .iter()
+                .map(|value| format!("{:>10}", humanize::number(*value, "", unit)))
+                .collect();
+            text.push_str(&format!("{label:<12}{cells}\n"));
+        }
+        for (message, count) in &self.errors {
+            text.push_str(&format!("{count}× {message}\n"));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        let stats = Stats::of((1..=100).rev().map(f64::from).collect()).unwrap();
+        assert_eq!(
+            stats,
+            Stats {
+                min: 1.0,
+                p50: 50.0,
+                p90: 90.0,
+                p99: 99.0,
+                max: 100.0
+            }
+        );
+        let one = Stats::of(vec![7.0]).unwrap();
+        assert_eq!((one.min, one.p50, one.p99, one.max), (7.0, 7.0, 7.0, 7.0));
+        assert_eq!(Stats::of(Vec::new()), None);
+    }
+
+    #[test]
+    fn every_counted_scrape_is_made_once_across_threads() {
+        let args = BenchArgs {
+            endpoint: String::from("localhost:9100"),
+            count: 10,
+            duration: None,
+            concurrency: 3,
+            json: false,
+        };
+        let made = AtomicUsize::new(0);
+        let report = run(&args, || {
+            let n = made.fetch_add(1, Ordering::Relaxed);
+            if n % 5 == 4 {
+                return Err(String::from("connection refused"));
+            }
+            Ok(Sample {
+                first_byte: Duration::from_millis(10),
+                fetch: Duration::from_millis(20),
+                parse: Duration::from_millis(5),
+                size: 1024,
+            })
+        });
+        assert_eq!(made.load(Ordering::Relaxed), 10);
+        assert_eq!((report.scrapes, report.failed), (10, 2));
+        assert_eq!(report.errors.get("connection refused"), Some(&2));
+        assert_eq!(report.size_bytes.as_ref().map(|stats| stats.max), Some(1024.0));
+
+        let text = report.text();
+        assert!(text.starts_with("localhost:9100: 10 scrapes in "), "{text}");
+        assert!(text.contains("first byte"), "{text}");
+        assert!(text.ends_with("2× connection refused\n"), "{text}");
+    }
+}
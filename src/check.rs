@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::model::Exposition;
+
+/// `promtool check`: scrape once and exit with whether the exposition is
+/// usable, for scripts, CI and post-deploy smoke tests.
+#[derive(clap::Args, Debug, Clone)]
+pub struct CheckArgs {
+    /// The endpoint, saved scrape, or `-` for stdin to check
+    #[arg(value_name = "ENDPOINT")]
+    pub endpoint: String,
+
+    /// Also fail unless this family is exposed (repeatable)
+    #[arg(long, value_name = "FAMILY")]
+    pub require: Vec<String>,
+
+    /// Treat a repeated series or HELP/TYPE line as invalid, even where the
+    /// parser lets it through
+    #[arg(long)]
+    pub fail_on_duplicates: bool,
+}
+
+/// Exit code when the scrape couldn't be fetched.
+pub const FETCH_FAILED: i32 = 1;
+/// Exit code when the scrape was fetched but isn't a valid exposition, or
+/// repeats lines under `--fail-on-duplicates`.
+pub const PARSE_FAILED: i32 = 2;
+/// Exit code when the exposition is valid but a `--require`d family is absent.
+pub const MISSING_FAMILY: i32 = 3;
+
+/// The `--require`d families the scrape doesn't expose. A counter can be
+/// required by its sample name too, `_total` included.
+pub fn missing<'a>(metrics: &Exposition, require: &'a [String]) -> Vec<&'a str> {
+    require
+        .iter()
+        .map(String::as_str)
+        .filter(|name| {
+            let family = name.strip_suffix("_total").unwrap_or(name);
+            !metrics.families.contains_key(*name) && !metrics.families.contains_key(family)
+        })
+        .collect()
+}
+
+/// `312 families, 14890 series, 84ms`
+pub fn summary(metrics: &Exposition, took: Duration) -> String {
+    let series: usize = metrics.families.values().map(|fam| fam.iter_samples().count()).sum();
+    format!(
+        "{} families, {series} series, {}ms",
+        metrics.families.len(),
+        took.as_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model;
+
+    #[test]
+    fn required_families_match_by_family_or_sample_name() {
+        let text = concat!(
+            "# TYPE requests counter\n",
+            "requests_total{code=\"200\"} 7\n",
+            "requests_total{code=\"500\"} 1\n",
+            "# TYPE up gauge\n",
+            "up 1\n",
+            "# EOF\n",
+        );
+        let metrics = model::parse(text, None, model::Format::Openmetrics).unwrap_or_else(|e| panic!("{e}"));
+        let require: Vec<String> = ["up", "requests_total", "requests", "build_info"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(missing(&metrics, &require), vec!["build_info"]);
+        assert_eq!(
+            summary(&metrics, Duration::from_millis(84)),
+            "2 families, 3 series, 84ms"
+        );
+    }
+}
@@ -0,0 +1,817 @@
+use anyhow::{Context as _, Result};
+use clap::builder::StyledStr;
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, Parser};
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::bench;
+use crate::check;
+use crate::columns::Columns;
+use crate::config;
+use crate::dashboard;
+use crate::diff;
+use crate::fetch::{Auth, HttpConfig, HttpFetcher, TlsConfig};
+use crate::highlight;
+use crate::history;
+use crate::lint;
+use crate::logging::LogFormat;
+use crate::metadata;
+use crate::model::{Family, FamilyType, Format};
+use crate::output::OutputFormat;
+use crate::query;
+use crate::remote_write;
+use crate::replay;
+use crate::theme::ThemeName;
+use crate::verify;
+
+/// promtool's command line: a subcommand, or the viewer's ENDPOINTs and
+/// options on their own, as `promtool ENDPOINT` predates `promtool tui`.
+#[derive(Parser, Debug, Clone)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    #[command(flatten)]
+    pub(crate) tui: Args,
+
+    #[command(flatten)]
+    pub(crate) global: GlobalArgs,
+}
+
+/// `promtool tui`: browse ENDPOINTs in the viewer, or print one and exit.
+#[derive(clap::Args, Debug, Clone)]
+pub struct Args {
+    /// The Prometheus metrics endpoint URL, a path to a saved exposition file, or `-` for stdin.
+    /// Several open side by side in tabs
+    #[arg(
+        value_name = "ENDPOINT",
+        required_unless_present_any = ["file", "targets_file", "textfile_dir", "unix_socket"]
+    )]
+    pub(crate) endpoints: Vec<String>,
+
+    /// Read metrics from a local file instead of an HTTP endpoint
+    #[arg(long, value_name = "PATH", conflicts_with = "endpoints")]
+    pub(crate) file: Option<PathBuf>,
+
+    /// Open a tab per target of a Prometheus file_sd/http_sd list (JSON or
+    /// YAML), adding its labels to the series; `F` re-reads it
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["endpoints", "file"])]
+    pub(crate) targets_file: Option<PathBuf>,
+
+    /// Read every `*.prom` file of a node_exporter textfile-collector
+    /// directory, labelling each series with its file; `I` lists the files
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["endpoints", "file", "targets_file"])]
+    pub(crate) textfile_dir: Option<PathBuf>,
+
+    /// Fetch over this unix domain socket instead of TCP, e.g. "/run/app/metrics.sock"
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["endpoints", "file", "targets_file", "textfile_dir"]
+    )]
+    pub(crate) unix_socket: Option<PathBuf>,
+
+    /// The path to request over --unix-socket
+    #[arg(long, value_name = "PATH", default_value = "/metrics", requires = "unix_socket")]
+    pub(crate) path: String,
+
+    /// Re-fetch the endpoint on this interval while the viewer is open (e.g. "5s", "1m")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub(crate) interval: Option<Duration>,
+
+    /// Print every series to stdout and exit instead of opening the viewer
+    #[arg(long)]
+    pub(crate) no_tui: bool,
+
+    /// Append every successful scrape to this file as JSON lines (timestamp, duration, body)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["no_tui", "output"])]
+    pub(crate) record: Option<PathBuf>,
+
+    /// Gzip the --record file; every record is its own gzip member, so `zcat` reads it whole
+    #[arg(long, requires = "record")]
+    pub(crate) record_compress: bool,
+
+    /// Move the --record file to PATH.1 and start a new one before it grows past this size
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "record")]
+    pub(crate) record_max_size: Option<u64>,
+
+    /// Output format for the non-interactive mode; implies --no-tui
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub(crate) output: Option<OutputFormat>,
+
+    /// Neither restore nor save each endpoint's pins, `/` filter, sort, theme and columns
+    #[arg(long)]
+    pub(crate) no_state: bool,
+
+    #[command(flatten)]
+    pub(crate) viewer: ViewerArgs,
+
+    #[command(flatten)]
+    pub(crate) parse: ParseArgs,
+
+    #[command(flatten)]
+    pub(crate) http: HttpArgs,
+}
+
+/// How the viewer shows a scrape, for `tui`, `replay` and `query`.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct ViewerArgs {
+    /// Show exact sample values instead of humanized ones ("1.5 KiB", "2m 5s")
+    #[arg(long)]
+    pub(crate) raw_values: bool,
+
+    /// Never start other programs from the viewer, such as $PAGER for `o` or $EDITOR for `O`
+    #[arg(long)]
+    pub(crate) no_external: bool,
+
+    /// Leave the mouse to the terminal, for its own text selection, instead of scrolling and
+    /// clicking in the viewer; `M` toggles it while running
+    #[arg(long)]
+    pub(crate) no_mouse: bool,
+
+    /// Where `X` in the detail view opens an exemplar's trace, with {trace_id} replaced,
+    /// e.g. "https://tempo.example.com/trace/{trace_id}"
+    #[arg(long, value_name = "URL")]
+    pub(crate) trace_url_template: Option<String>,
+
+    /// The Prometheus that `W` in the detail view graphs the selected series on, e.g. "http://prom:9090"
+    #[arg(long, value_name = "URL")]
+    pub(crate) prometheus_url: Option<String>,
+
+    /// Have `W` open Grafana's Explore here instead, e.g. "https://grafana.example.com"
+    #[arg(long, value_name = "URL")]
+    pub(crate) grafana_url: Option<String>,
+
+    /// Keep showing series and families that disappeared for this many refreshes (0 to turn off)
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub(crate) keep_disappeared: u32,
+
+    /// Scrapes to keep for the detail view's chart of pinned families and
+    /// the open family's series (0 to turn off)
+    #[arg(long, value_name = "N", default_value_t = history::DEFAULT_CAPACITY)]
+    pub(crate) history: usize,
+
+    /// Warn about series whose own timestamp is further than this from the
+    /// scrape time (e.g. "5m")
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, default_value = "5m")]
+    pub(crate) max_timestamp_skew: Duration,
+
+    /// Read families only until the scrape has this many series, keeping
+    /// giant payloads from using up the memory of the machine
+    #[arg(long, value_name = "N")]
+    pub(crate) max_series: Option<usize>,
+
+    /// Color series red while a rule holds, e.g. "node_filesystem_avail_bytes < 1e9",
+    /// "rate(http_requests_total) > 100" for a counter's rate, or a "warn:" prefix
+    /// for yellow (repeatable)
+    #[arg(long, value_name = "RULE", value_parser = highlight::Rule::parse)]
+    pub(crate) highlight: Vec<highlight::Rule>,
+
+    /// How many of the fastest-changing series the `H` view lists
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub(crate) top: usize,
+
+    /// Quantiles the detail view estimates from a histogram's buckets
+    #[arg(
+        long,
+        value_name = "Q",
+        value_delimiter = ',',
+        value_parser = parse_quantile,
+        default_value = "0.5,0.9,0.99"
+    )]
+    pub(crate) quantiles: Vec<f64>,
+
+    /// Show this quantile of each histogram family, e.g. 0.99, as its value in
+    /// the table instead of how many observations it has
+    #[arg(long, value_name = "Q", value_parser = parse_quantile)]
+    pub(crate) table_quantile: Option<f64>,
+
+    /// The table's columns, in order, from name, type, count, value and rate;
+    /// the name can't be left out. Otherwise those picked with `|` last time,
+    /// or name,type,value
+    #[arg(long, value_name = "COLUMNS", value_parser = Columns::parse)]
+    pub(crate) columns: Option<Columns>,
+
+    /// Refreshes a series has to keep its value through for `Z` to hide it
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) unchanged_after: u32,
+
+    /// Color theme; NO_COLOR in the environment forces "mono"
+    #[arg(long, value_enum, default_value_t = ThemeName::Dark)]
+    pub(crate) theme: ThemeName,
+
+    /// Whether --theme was given on the command line, which a saved theme
+    /// doesn't override.
+    #[arg(skip)]
+    pub(crate) theme_from_cli: bool,
+}
+
+/// Which families of a scrape are kept, and how it's parsed.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct ParseArgs {
+    /// Parse responses as this format instead of trusting their Content-Type
+    #[arg(long, value_enum, default_value_t = Format::Auto)]
+    pub(crate) format: Format,
+
+    /// Drop lines the parser rejects instead of discarding the whole scrape
+    #[arg(long)]
+    pub(crate) lenient: bool,
+
+    /// Only keep metric families whose name matches this regex
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    pub(crate) filter: Option<Regex>,
+
+    /// Drop metric families whose name matches this regex
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    pub(crate) exclude: Option<Regex>,
+
+    /// Only keep families of these types, e.g. "histogram,summary"
+    #[arg(long = "type", value_enum, value_name = "TYPES", value_delimiter = ',')]
+    pub(crate) types: Vec<FamilyType>,
+}
+
+/// How requests are made, for every subcommand that fetches.
+#[derive(clap::Args, Debug, Clone)]
+#[command(next_help_heading = "Connection options")]
+pub(crate) struct HttpArgs {
+    /// Give up on a single request after this long
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub(crate) timeout: Duration,
+
+    /// Retry connection errors, timeouts and 5xx responses this many times, with exponential backoff
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Abort responses larger than this, before or after decompression (e.g. "512KiB", "64MiB")
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "256MiB")]
+    max_body_size: u64,
+
+    /// When an endpoint returns a page instead of metrics, scrape /metrics on
+    /// the same host instead
+    #[arg(long)]
+    auto_path: bool,
+
+    /// Send requests through this HTTP proxy, e.g. "http://bastion:3128"
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Ignore the HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY environment variables
+    #[arg(long)]
+    no_env_proxy: bool,
+
+    /// Follow at most this many redirects; 0 reports where the first one leads instead
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    max_redirects: usize,
+
+    /// HTTP basic auth credentials
+    #[arg(long, value_name = "USER:PASS", value_parser = parse_basic_auth, group = "auth")]
+    basic_auth: Option<(String, String)>,
+
+    /// HTTP basic auth username; the password is read from $PROMTOOL_PASSWORD
+    #[arg(long, value_name = "USER", group = "auth")]
+    basic_auth_user: Option<String>,
+
+    /// Send this bearer token in the Authorization header
+    #[arg(long, value_name = "TOKEN", group = "auth")]
+    bearer_token: Option<String>,
+
+    /// Read the bearer token from a file
+    #[arg(long, value_name = "PATH", group = "auth")]
+    bearer_token_file: Option<PathBuf>,
+
+    /// Extra request header, e.g. "X-Scope-OrgID: tenant1" (repeatable)
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Skip TLS certificate verification
+    #[arg(long)]
+    pub(crate) insecure: bool,
+
+    /// Trust this additional PEM root certificate
+    #[arg(long, value_name = "PEM")]
+    ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for mutual TLS (uses the platform's native TLS stack)
+    #[arg(long, value_name = "PEM", requires = "client_key")]
+    client_cert: Option<PathBuf>,
+
+    /// PKCS#8 PEM private key for --client-cert
+    #[arg(long, value_name = "PEM", requires = "client_cert")]
+    client_key: Option<PathBuf>,
+}
+
+/// Options every subcommand takes, after its name.
+#[derive(clap::Args, Debug, Clone)]
+#[command(next_help_heading = "Global options")]
+pub(crate) struct GlobalArgs {
+    /// Read defaults and endpoint profiles from this file instead of ~/.config/promtool/config.toml
+    #[arg(long, value_name = "PATH", global = true)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Ignore the config file
+    #[arg(long, conflicts_with = "config", global = true)]
+    pub(crate) no_config: bool,
+
+    /// Append what promtool does (fetches, parses, keys, refreshes) to this file, for troubleshooting
+    #[arg(long, value_name = "PATH", global = true)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// How --log-file lines are written
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = LogFormat::Json,
+        requires = "log_file",
+        global = true
+    )]
+    pub(crate) log_format: LogFormat,
+
+    /// Log more to --log-file: debug events with -v, trace events with -vv
+    #[arg(short, long, action = clap::ArgAction::Count, requires = "log_file", global = true)]
+    pub(crate) verbose: u8,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub(crate) enum Command {
+    /// Browse endpoints in the viewer, or print one with --no-tui; the same
+    /// as leaving out the subcommand.
+    Tui(Args),
+    /// Scrape once and print every series, as a table, CSV or JSON.
+    Get {
+        /// The endpoint, config profile, saved scrape, or `-` for stdin to print
+        #[arg(value_name = "ENDPOINT")]
+        endpoint: String,
+
+        /// How to print the series
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+
+        /// Print exact sample values instead of humanized ones ("1.5 KiB", "2m 5s")
+        #[arg(long)]
+        raw_values: bool,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Compare two endpoints series by series, e.g. a canary against its baseline.
+    Diff {
+        #[command(flatten)]
+        args: diff::DiffArgs,
+
+        /// Color theme; NO_COLOR in the environment forces "mono"
+        #[arg(long, value_enum, default_value_t = ThemeName::Dark)]
+        theme: ThemeName,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Compare two saved scrapes offline, exiting 1 when series were added or
+    /// removed, values changed by more than --threshold, or TYPE or HELP
+    /// changed.
+    DiffFiles {
+        #[command(flatten)]
+        args: diff::DiffFilesArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+    },
+    /// Browse a file written by --record, stepping or playing through its
+    /// scrapes.
+    Replay {
+        #[command(flatten)]
+        args: replay::ReplayArgs,
+
+        #[command(flatten)]
+        viewer: ViewerArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+    },
+    /// Evaluate PromQL on a Prometheus server and browse the result,
+    /// editing and re-running the expression in the viewer.
+    Query {
+        #[command(flatten)]
+        args: query::QueryArgs,
+
+        /// Re-evaluate the expression on this interval (e.g. "15s")
+        #[arg(long, value_parser = humantime::parse_duration)]
+        interval: Option<Duration>,
+
+        #[command(flatten)]
+        viewer: ViewerArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Send a scrape, or every scrape of a recording, to a remote-write
+    /// receiver. Connection options apply to both ends.
+    Push {
+        #[command(flatten)]
+        args: remote_write::PushArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Scrape once and print a one-line summary, exiting 0 when the exposition
+    /// is valid, 1 when the fetch failed, 2 when parsing failed and 3 when a
+    /// --require'd family is missing.
+    Check {
+        #[command(flatten)]
+        args: check::CheckArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Check a scrape against the Prometheus naming conventions, exiting 1
+    /// when there are more findings than --max-warnings.
+    Lint {
+        #[command(flatten)]
+        args: lint::LintArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Scrape an endpoint repeatedly, without the viewer, and print how
+    /// time to first byte, fetch and parse time and size were spread.
+    Bench {
+        #[command(flatten)]
+        args: bench::BenchArgs,
+
+        /// Parse responses as this format instead of trusting their Content-Type
+        #[arg(long, value_enum, default_value_t = Format::Auto)]
+        format: Format,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Scrape once and compare the metrics, their types and labels against
+    /// a spec file, exiting 1 when they don't match.
+    Verify {
+        #[command(flatten)]
+        args: verify::VerifyArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Compare the types and HELP a Prometheus server has for a job's
+    /// metrics with what its exporter serves now, exiting 1 when they
+    /// disagree. Connection options apply to both.
+    MetadataCheck {
+        #[command(flatten)]
+        args: metadata::MetadataArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Scrape once and write a Grafana dashboard with a panel per family:
+    /// rates of counters, gauges, and quantiles of histograms and summaries.
+    ScaffoldDashboard {
+        #[command(flatten)]
+        args: dashboard::DashboardArgs,
+
+        #[command(flatten)]
+        parse: ParseArgs,
+
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+}
+
+/// Parses the command line, exiting on errors like clap does, but with a
+/// tip saying where an option belongs when it was given to the wrong
+/// subcommand or before one.
+pub fn get_matches() -> ArgMatches {
+    let mut cmd = Cli::command();
+    let matches = cmd.try_get_matches_from_mut(std::env::args_os()).unwrap_or_else(|e| with_tip(e).exit());
+    if matches.subcommand().is_none() {
+        let first = matches.get_many::<String>("endpoints").and_then(|mut endpoints| endpoints.next());
+        if let Some(name) = first.filter(|name| cmd.find_subcommand(name).is_some()) {
+            cmd.error(ErrorKind::ArgumentConflict, misplaced(name)).exit();
+        }
+    }
+    matches
+}
+
+fn with_tip(mut error: clap::Error) -> clap::Error {
+    let tip = match (error.kind(), error.get(ContextKind::InvalidArg), error.get(ContextKind::InvalidSubcommand)) {
+        (ErrorKind::UnknownArgument, Some(ContextValue::String(arg)), _) => taken_by(arg),
+        (ErrorKind::ArgumentConflict, _, Some(ContextValue::String(name))) => Some(misplaced(name)),
+        _ => None,
+    };
+    if let Some(tip) = tip {
+        let mut tips = match error.get(ContextKind::Suggested) {
+            Some(ContextValue::StyledStrs(tips)) => tips.clone(),
+            _ => Vec::new(),
+        };
+        tips.push(StyledStr::from(tip));
+        error.insert(ContextKind::Suggested, ContextValue::StyledStrs(tips));
+    }
+    error
+}
+
+/// Which subcommands take `arg`, e.g. `--interval` given to `check`.
+fn taken_by(arg: &str) -> Option<String> {
+    let long = arg.strip_prefix("--")?.split('=').next()?;
+    let cmd = Cli::command();
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .filter(|sub| sub.get_arguments().any(|option| option.get_long() == Some(long)))
+        .map(|sub| format!("`promtool {}`", sub.get_name()))
+        .collect();
+    (!names.is_empty()).then(|| format!("--{long} is an option of {}", names.join(", ")))
+}
+
+/// Options before a subcommand are taken as the viewer's, so a subcommand
+/// only gets the ones after its name.
+fn misplaced(subcommand: &str) -> String {
+    format!("options of `promtool {subcommand}` go after `{subcommand}`")
+}
+
+pub(crate) fn parse_header(value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = value
+        .split_once(':')
+        .ok_or_else(|| String::from("expected \"Name: value\""))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("invalid header name {name:?}: {e}"))?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|e| format!("invalid header value {value:?}: {e}"))?;
+    Ok((name, value))
+}
+
+/// A quantile for `--quantiles` and `--table-quantile`, between 0 and 1.
+pub(crate) fn parse_quantile(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+        Ok(_) => Err(format!("quantile {value} isn't between 0 and 1")),
+        Err(_) => Err(format!("invalid quantile {value:?}")),
+    }
+}
+
+/// A `--threshold` for `diff-files`: relative like `5%`, or absolute like `0.5`.
+pub(crate) fn parse_threshold(value: &str) -> Result<diff::Threshold, String> {
+    let (number, percent) = match value.trim().strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (value.trim(), false),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n >= 0.0 && percent => Ok(diff::Threshold::Percent(n)),
+        Ok(n) if n >= 0.0 => Ok(diff::Threshold::Absolute(n)),
+        Ok(n) if n < 0.0 => Err(format!("threshold {value} is negative")),
+        _ => Err(format!("invalid threshold {value:?}, e.g. 0.5 or 5%")),
+    }
+}
+
+/// Parses a byte count with an optional binary suffix: `4096`, `512KiB`, `64M`.
+pub(crate) fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, suffix) = value.split_at(split);
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        _ => return Err(format!("unknown size suffix {suffix:?}, expected KiB, MiB or GiB")),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size {value:?}"))
+}
+
+fn parse_basic_auth(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once(':')
+        .map(|(user, pass)| (user.to_string(), pass.to_string()))
+        .ok_or_else(|| String::from("expected USER:PASS"))
+}
+
+impl HttpArgs {
+    fn auth(&self) -> Result<Option<Auth>> {
+        if let Some((username, password)) = &self.basic_auth {
+            return Ok(Some(Auth::Basic {
+                username: username.clone(),
+                password: Some(password.clone()),
+            }));
+        }
+        if let Some(username) = &self.basic_auth_user {
+            return Ok(Some(Auth::Basic {
+                username: username.clone(),
+                password: std::env::var("PROMTOOL_PASSWORD").ok(),
+            }));
+        }
+        if let Some(token) = &self.bearer_token {
+            return Ok(Some(Auth::Bearer(token.clone())));
+        }
+        if let Some(path) = &self.bearer_token_file {
+            let token = std::fs::read_to_string(path)
+                .with_context(|| format!("couldn't read bearer token from {}", path.display()))?;
+            return Ok(Some(Auth::Bearer(token.trim_end_matches(['\r', '\n']).to_string())));
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn http(&self) -> Result<HttpFetcher> {
+        HttpFetcher::new(self.http_config()?)
+    }
+
+    pub(crate) fn http_config(&self) -> Result<HttpConfig> {
+        Ok(HttpConfig {
+            auth: self.auth()?,
+            headers: self.headers.clone(),
+            tls: TlsConfig {
+                insecure: self.insecure,
+                ca_cert: self.ca_cert.clone(),
+                client_identity: self.client_cert.clone().zip(self.client_key.clone()),
+            },
+            timeout: Some(self.timeout),
+            retries: self.retries,
+            retry_backoff: RETRY_BACKOFF,
+            max_body_size: Some(self.max_body_size),
+            auto_path: self.auto_path,
+            proxy: self.proxy.clone(),
+            no_env_proxy: self.no_env_proxy,
+            max_redirects: Some(self.max_redirects),
+            conditional: false,
+        })
+    }
+
+}
+
+impl ParseArgs {
+    pub(crate) fn family_filter(&self) -> FamilyFilter {
+        FamilyFilter {
+            include: self.filter.clone(),
+            exclude: self.exclude.clone(),
+            types: self.types.clone(),
+        }
+    }
+}
+
+/// Startup `--filter`/`--exclude` patterns, applied to every parsed exposition
+/// before it's stored so the rest of the app never sees excluded families.
+pub(crate) struct FamilyFilter {
+    pub(crate) include: Option<Regex>,
+    pub(crate) exclude: Option<Regex>,
+    /// Empty keeps every type.
+    pub(crate) types: Vec<FamilyType>,
+}
+
+impl FamilyFilter {
+    pub(crate) fn matches(&self, name: &str, fam: &Family) -> bool {
+        self.matches_type(name, FamilyType::of(fam))
+    }
+
+    /// Like [`matches`](Self::matches), for a family known only by its name
+    /// and type, as in a Prometheus server's metadata.
+    pub(crate) fn matches_type(&self, name: &str, family_type: FamilyType) -> bool {
+        self.include.as_ref().is_none_or(|re| re.is_match(name))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(name))
+            && (self.types.is_empty() || self.types.contains(&family_type))
+    }
+}
+
+/// Wait before the first retry; each later retry waits twice as long.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+
+/// Whether `id` was given on the command line rather than left at its
+/// default, which a config file value doesn't override.
+pub(crate) fn from_cli(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+impl Args {
+    /// Fills in whatever wasn't given on the command line from the config
+    /// file. `matches` tells flags left at their default apart from ones the
+    /// user actually passed.
+    fn apply(&mut self, matches: &ArgMatches, settings: &config::Settings) {
+        self.interval = self.interval.or(settings.interval);
+        self.viewer.apply(matches, settings);
+        self.parse.apply(matches, settings);
+        self.http.apply(matches, settings);
+    }
+}
+
+impl ViewerArgs {
+    pub(crate) fn apply(&mut self, matches: &ArgMatches, settings: &config::Settings) {
+        if let Some(keep) = settings.keep_disappeared.filter(|_| !from_cli(matches, "keep_disappeared")) {
+            self.keep_disappeared = keep;
+        }
+        if let Some(history) = settings.history.filter(|_| !from_cli(matches, "history")) {
+            self.history = history;
+        }
+        if let Some(skew) = settings.max_timestamp_skew.filter(|_| !from_cli(matches, "max_timestamp_skew")) {
+            self.max_timestamp_skew = skew;
+        }
+        if let Some(theme) = settings.theme.filter(|_| !from_cli(matches, "theme")) {
+            self.theme = theme;
+        }
+        self.theme_from_cli = from_cli(matches, "theme");
+        self.trace_url_template = self.trace_url_template.take().or(settings.trace_url_template.clone());
+        self.prometheus_url = self.prometheus_url.take().or(settings.prometheus_url.clone());
+        self.grafana_url = self.grafana_url.take().or(settings.grafana_url.clone());
+        let mut highlight = settings.highlight.clone();
+        highlight.append(&mut self.highlight);
+        self.highlight = highlight;
+    }
+}
+
+impl ParseArgs {
+    pub(crate) fn apply(&mut self, matches: &ArgMatches, settings: &config::Settings) {
+        if let Some(format) = settings.format.filter(|_| !from_cli(matches, "format")) {
+            self.format = format;
+        }
+        self.lenient |= settings.lenient.unwrap_or(false);
+        self.filter = self.filter.take().or(settings.filter.clone());
+        self.exclude = self.exclude.take().or(settings.exclude.clone());
+    }
+}
+
+impl HttpArgs {
+    pub(crate) fn apply(&mut self, matches: &ArgMatches, settings: &config::Settings) {
+        if let Some(timeout) = settings.timeout.filter(|_| !from_cli(matches, "timeout")) {
+            self.timeout = timeout;
+        }
+        if let Some(retries) = settings.retries.filter(|_| !from_cli(matches, "retries")) {
+            self.retries = retries;
+        }
+        if let Some(size) = settings.max_body_size.filter(|_| !from_cli(matches, "max_body_size")) {
+            self.max_body_size = size;
+        }
+        self.insecure |= settings.insecure.unwrap_or(false);
+        self.auto_path |= settings.auto_path.unwrap_or(false);
+        self.ca_cert = self.ca_cert.take().or(settings.ca_cert.clone());
+        if self.client_cert.is_none() {
+            self.client_cert = settings.client_cert.clone();
+            self.client_key = settings.client_key.clone();
+        }
+        let mut headers: Vec<_> = settings
+            .headers
+            .iter()
+            .filter(|(name, _)| !self.headers.iter().any(|(own, _)| own == name))
+            .cloned()
+            .collect();
+        headers.append(&mut self.headers);
+        self.headers = headers;
+        let cli_auth = self.basic_auth.is_some()
+            || self.basic_auth_user.is_some()
+            || self.bearer_token.is_some()
+            || self.bearer_token_file.is_some();
+        if !cli_auth {
+            self.basic_auth_user = settings.basic_auth_user.clone();
+            self.bearer_token = settings.bearer_token.clone();
+            self.bearer_token_file = settings.bearer_token_file.clone();
+        }
+    }
+}
+
+/// A copy of `args` for one ENDPOINT, filled in from the config profile
+/// matching it. Returns the endpoint to fetch (a profile's URL in place of
+/// its name) and the profile's pins alongside.
+pub(crate) fn configure(
+    args: &Args,
+    matches: &ArgMatches,
+    config: &config::Config,
+    endpoint: Option<&str>,
+) -> (Args, Option<String>, Vec<String>) {
+    let (settings, profile_url) = config.resolve(endpoint);
+    let mut args = args.clone();
+    args.apply(matches, &settings);
+    (args, profile_url.or(endpoint.map(String::from)), settings.pins)
+}
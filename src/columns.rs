@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+
+/// A column of the main table. Each is drawn by a function of its own in
+/// the ui, so another one only needs a variant here and that function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Name,
+    Type,
+    /// How many series the family has.
+    Count,
+    /// The value, or what `R`, `B` or `--table-quantile` make of it.
+    Value,
+    /// A single series' rate or delta, or the summed rate of a family of
+    /// counters, whether `R` is on or not.
+    Rate,
+}
+
+impl Column {
+    pub const ALL: [Column; 5] = [Column::Name, Column::Type, Column::Count, Column::Value, Column::Rate];
+
+    /// As `--columns` and the state file spell it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Type => "type",
+            Column::Count => "count",
+            Column::Value => "value",
+            Column::Rate => "rate",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Type => "Type",
+            Column::Count => "Series",
+            Column::Value => "Value",
+            Column::Rate => "Rate",
+        }
+    }
+
+    /// The cells a column gets at the least; what's left of the table's
+    /// width is shared out by [`weight`](Self::weight).
+    fn min_width(self) -> u16 {
+        match self {
+            Column::Name => 16,
+            Column::Type => 9,
+            Column::Count => 6,
+            Column::Value => 12,
+            Column::Rate => 10,
+        }
+    }
+
+    fn weight(self) -> u16 {
+        match self {
+            Column::Name => 6,
+            Column::Type | Column::Count => 1,
+            Column::Value | Column::Rate => 2,
+        }
+    }
+}
+
+/// The main table's columns, in order. The name is always one of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Column>", into = "Vec<Column>")]
+pub struct Columns(Vec<Column>);
+
+/// Name, type and value.
+impl Default for Columns {
+    fn default() -> Columns {
+        Columns(vec![Column::Name, Column::Type, Column::Value])
+    }
+}
+
+impl TryFrom<Vec<Column>> for Columns {
+    type Error = String;
+
+    fn try_from(columns: Vec<Column>) -> Result<Columns, String> {
+        if !columns.contains(&Column::Name) {
+            return Err(String::from("the name column can't be left out"));
+        }
+        if let Some(column) = columns.iter().enumerate().find_map(|(i, c)| columns[..i].contains(c).then_some(c)) {
+            return Err(format!("the {} column is listed twice", column.name()));
+        }
+        Ok(Columns(columns))
+    }
+}
+
+impl From<Columns> for Vec<Column> {
+    fn from(columns: Columns) -> Vec<Column> {
+        columns.0
+    }
+}
+
+impl Columns {
+    /// `--columns name,type,count,value,rate`.
+    pub fn parse(text: &str) -> Result<Columns, String> {
+        let columns = text
+            .split(',')
+            .map(str::trim)
+            .map(|name| {
+                Column::ALL.into_iter().find(|column| column.name() == name).ok_or_else(|| {
+                    let names: Vec<&str> = Column::ALL.iter().map(|column| column.name()).collect();
+                    format!("unknown column {name:?}, expected some of {}", names.join(", "))
+                })
+            })
+            .collect::<Result<Vec<Column>, String>>()?;
+        Columns::try_from(columns)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Column> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn contains(&self, column: Column) -> bool {
+        self.0.contains(&column)
+    }
+
+    /// Every column as the `|` picker lists them: the shown ones in order,
+    /// then the hidden ones.
+    pub fn listed(&self) -> Vec<(Column, bool)> {
+        let hidden = Column::ALL.into_iter().filter(|column| !self.contains(*column));
+        self.iter().map(|column| (column, true)).chain(hidden.map(|column| (column, false))).collect()
+    }
+
+    /// Hides `column`, or shows it after the others. The name can't be
+    /// hidden; returns whether anything changed.
+    pub fn toggle(&mut self, column: Column) -> bool {
+        match self.0.iter().position(|shown| *shown == column) {
+            Some(_) if column == Column::Name => false,
+            Some(index) => {
+                self.0.remove(index);
+                true
+            }
+            None => {
+                self.0.push(column);
+                true
+            }
+        }
+    }
+
+    /// Moves a shown `column` one place left, or right when `right`;
+    /// returns whether it moved.
+    pub fn shift(&mut self, column: Column, right: bool) -> bool {
+        let Some(index) = self.0.iter().position(|shown| *shown == column) else {
+            return false;
+        };
+        let target = if right { index + 1 } else { index.wrapping_sub(1) };
+        if target >= self.0.len() {
+            return false;
+        }
+        self.0.swap(index, target);
+        true
+    }
+}
+
+/// How many cells each of `columns` gets of `width`: its minimum, plus a
+/// share of the rest by weight, the name taking what rounding leaves. Too
+/// narrow for the minimums, they shrink in proportion to them instead.
+pub fn widths(columns: &[Column], width: u16) -> Vec<u16> {
+    let total = u32::from(width);
+    let minimum: u32 = columns.iter().map(|column| u32::from(column.min_width())).sum();
+    let weights: u32 = columns.iter().map(|column| u32::from(column.weight())).sum();
+    let mut widths: Vec<u32> = if total < minimum {
+        columns
+            .iter()
+            .map(|column| u32::from(column.min_width()) * total / minimum)
+            .collect()
+    } else {
+        let spare = total - minimum;
+        columns
+            .iter()
+            .map(|column| u32::from(column.min_width()) + spare * u32::from(column.weight()) / weights.max(1))
+            .collect()
+    };
+    let left = total.saturating_sub(widths.iter().sum());
+    if let Some(name) = columns.iter().position(|column| *column == Column::Name).or(widths.len().checked_sub(1)) {
+        widths[name] += left;
+    }
+    widths.into_iter().map(|width| width as u16).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_parsed_with_the_name_required_and_each_once() {
+        let columns = Columns::parse("name, value,rate").unwrap();
+        assert_eq!(columns.iter().collect::<Vec<_>>(), vec![Column::Name, Column::Value, Column::Rate]);
+        assert_eq!(Columns::parse("value").unwrap_err(), "the name column can't be left out");
+        assert_eq!(Columns::parse("name,type,type").unwrap_err(), "the type column is listed twice");
+        assert_eq!(
+            Columns::parse("name,help").unwrap_err(),
+            "unknown column \"help\", expected some of name, type, count, value, rate"
+        );
+
+        let saved: Columns = serde_json::from_str(r#"["value","name"]"#).unwrap();
+        assert_eq!(serde_json::to_string(&saved).unwrap(), r#"["value","name"]"#);
+        assert!(serde_json::from_str::<Columns>(r#"["value"]"#).is_err());
+    }
+
+    #[test]
+    fn columns_are_toggled_and_moved_but_the_name_stays() {
+        let mut columns = Columns::default();
+        assert!(!columns.toggle(Column::Name));
+        assert!(columns.toggle(Column::Type));
+        assert!(columns.toggle(Column::Rate));
+        assert!(columns.shift(Column::Rate, false));
+        assert!(!columns.shift(Column::Name, false));
+        assert!(!columns.shift(Column::Count, true), "hidden columns stay where they are");
+        assert_eq!(
+            columns.listed(),
+            vec![
+                (Column::Name, true),
+                (Column::Rate, true),
+                (Column::Value, true),
+                (Column::Type, false),
+                (Column::Count, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn widths_start_from_the_minimums_and_share_the_rest_by_weight() {
+        let all = Column::ALL;
+        assert_eq!(widths(&[Column::Name, Column::Type, Column::Value], 113), vec![68, 17, 28]);
+        assert_eq!(widths(&all, 113), vec![46, 14, 11, 22, 20]);
+        // Narrower than the 53 cells of minimums.
+        assert_eq!(widths(&all, 40), vec![14, 6, 4, 9, 7]);
+        assert_eq!(widths(&[Column::Name], 30), vec![30]);
+    }
+}
@@ -0,0 +1,807 @@
+use anyhow::{Context as _, Result};
+use clap::ArgMatches;
+use std::io;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::app::{run_app, App, Opener, QueryState, TargetsFile};
+use crate::bench;
+use crate::check;
+use crate::cli::{configure, from_cli, Args, Cli, Command, FamilyFilter, ParseArgs, ViewerArgs};
+use crate::config;
+use crate::dashboard;
+use crate::diff;
+use crate::fetch::{self, Body, FetchResult, Fetcher, HttpConfig, HttpFetcher, Source};
+use crate::lint;
+use crate::logging;
+use crate::metadata;
+use crate::model::{self, Aggregation, Exposition, Family, Format};
+use crate::output::{self, OutputFormat, ScrapeInfo};
+use crate::record;
+use crate::remote_write;
+use crate::state;
+use crate::targets;
+use crate::terminal::with_terminal;
+use crate::textfile;
+use crate::theme::ThemeName;
+use crate::verify;
+
+/// Fetches and parses a single scrape for the non-interactive modes,
+/// reporting retries and skipped lines on stderr. Returns how long the
+/// fetch took.
+fn scrape_once(
+    source: &Source,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<(Exposition, Duration)> {
+    let started = Instant::now();
+    let body = fetch_once(source, http)?;
+    let duration = started.elapsed();
+    let metrics = parse_once(&body, source, family_filter, format, lenient)?;
+    Ok((metrics, duration))
+}
+
+fn fetch_once(source: &Source, http: &HttpFetcher) -> Result<Body> {
+    match source {
+        Source::Stdin => Ok(Body::plain(io::read_to_string(io::stdin())?)),
+        source => fetch::fetch_source(source, http, |retry| {
+            eprintln!("retrying (attempt {}/{}) after: {}", retry.attempt, retry.attempts, retry.error);
+        })
+        .with_context(|| format!("couldn't fetch metrics from {source}")),
+    }
+}
+
+/// Parses a scrape for the non-interactive commands, reporting skipped
+/// lines on stderr.
+fn parse_once(
+    body: &Body,
+    source: &dyn std::fmt::Display,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<Exposition> {
+    if let Some(files) = &body.textfiles {
+        let scan = textfile::scan(files, textfile::parser(format, lenient));
+        for status in &scan.files {
+            if let Some(error) = &status.error {
+                eprintln!("skipped {}: {error}", status.name);
+            }
+        }
+        for warning in &scan.warnings {
+            eprintln!("{warning}");
+        }
+        let mut metrics = scan.metrics;
+        metrics.families.retain(|name, fam| family_filter.matches(name, fam));
+        return Ok(metrics);
+    }
+    for warning in &body.warnings {
+        eprintln!("{warning}");
+    }
+    let content_type = body.content_type.as_deref();
+    let (mut metrics, skipped) = if lenient {
+        model::parse_lenient(&body.text, content_type, format)
+    } else {
+        model::parse(&body.text, content_type, format).map(|metrics| (metrics, Vec::new()))
+    }
+    .map_err(|e| anyhow::anyhow!("metrics from {source} could not be parsed: {e}"))?;
+    for line in &skipped {
+        eprintln!("skipped line {}: {}", line.line, line.text);
+    }
+    metrics.families.retain(|name, fam| family_filter.matches(name, fam));
+    Ok(metrics)
+}
+
+/// `promtool push`: converts one scrape, or every scrape of a recording at
+/// its recorded time, to remote-write requests.
+fn run_push(
+    args: &remote_write::PushArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let (name, scrapes) = match (&args.recording, &args.source) {
+        (Some(path), _) => {
+            let recording = record::load(path)?;
+            if recording.unreadable > 0 {
+                eprintln!("skipped {} unreadable records", recording.unreadable);
+            }
+            let scrapes: Vec<(Body, SystemTime)> =
+                recording.scrapes.into_iter().map(|scrape| (scrape.body, scrape.at)).collect();
+            (path.display().to_string(), scrapes)
+        }
+        (None, Some(source)) => {
+            let source = Source::from_arg(source)?;
+            let at = SystemTime::now();
+            (source.to_string(), vec![(fetch_once(&source, http)?, at)])
+        }
+        (None, None) => unreachable!("clap requires SOURCE unless --recording is given"),
+    };
+
+    let (mut series, mut requests) = (0, 0);
+    for (body, at) in &scrapes {
+        let metrics = parse_once(body, &name, family_filter, format, lenient)?;
+        let openmetrics = format.resolve(&body.text, body.content_type.as_deref()) == Format::Openmetrics;
+        let (batch, metadata) = remote_write::time_series(&metrics, openmetrics, *at, &args.extra_label);
+        requests += remote_write::push(http, &args.remote_write_url, &batch, &metadata)?;
+        series += batch.len();
+    }
+    let plural = if requests == 1 { "" } else { "s" };
+    println!("pushed {series} samples from {name} in {requests} request{plural} to {}", args.remote_write_url);
+    Ok(())
+}
+
+/// `promtool check`: the summary goes to stdout and retries and skipped
+/// lines to stderr; the outcome is in the exit code.
+fn run_check(
+    args: &check::CheckArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let source = Source::from_arg(&args.endpoint)?;
+    let started = Instant::now();
+    fn fail(e: anyhow::Error, code: i32) -> ! {
+        println!("FAIL: {e:#}");
+        std::process::exit(code);
+    }
+    let body = fetch_once(&source, http).unwrap_or_else(|e| fail(e, check::FETCH_FAILED));
+    if args.fail_on_duplicates {
+        let duplicates = lint::duplicates(&body.text);
+        if let Some(first) = duplicates.first() {
+            let more = match duplicates.len() - 1 {
+                0 => String::new(),
+                more => format!(" (+{more} more)"),
+            };
+            let e = anyhow::anyhow!("{source} repeats lines: {}: {}{more}", first.family, first.message);
+            fail(e, check::PARSE_FAILED);
+        }
+    }
+    let metrics =
+        parse_once(&body, &source, family_filter, format, lenient).unwrap_or_else(|e| fail(e, check::PARSE_FAILED));
+    let took = started.elapsed();
+
+    let missing = check::missing(&metrics, &args.require);
+    if !missing.is_empty() {
+        println!("FAIL: {source} doesn't expose {} ({})", missing.join(", "), check::summary(&metrics, took));
+        std::process::exit(check::MISSING_FAMILY);
+    }
+    println!("OK: {}", check::summary(&metrics, took));
+    Ok(())
+}
+
+/// `promtool verify`: the report goes to stdout, and any failure makes the
+/// exit code 1. A spec, fetch or parse error is an error like anywhere else.
+fn run_verify(
+    args: &verify::VerifyArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let spec = verify::Spec::load(&args.spec)?;
+    let source = Source::from_arg(&args.endpoint)?;
+    let body = fetch_once(&source, http)?;
+    let metrics = parse_once(&body, &source, family_filter, format, lenient)?;
+    let failures = verify::compare(&spec, &metrics, args.strict);
+    let report = verify::Report::new(&source.to_string(), spec.metrics.len(), failures);
+    match args.output {
+        verify::ReportFormat::Text => print!("{}", report.text()),
+        verify::ReportFormat::Json => println!("{}", report.json()),
+    }
+    if !report.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `promtool bench`: every scrape is parsed too, since a slow parse is as
+/// much the exporter's doing as a slow response. Retries are left out so
+/// each failure is counted.
+fn run_bench(args: &bench::BenchArgs, http: &HttpFetcher, format: Format) -> Result<()> {
+    let Source::Http(url) = Source::from_arg(&args.endpoint)? else {
+        anyhow::bail!("promtool bench needs an HTTP endpoint, not {}", args.endpoint);
+    };
+    let report = bench::run(args, || {
+        let timed = http.fetch_timed(&url).map_err(|e| format!("{e:#}"))?;
+        let started = Instant::now();
+        model::parse(&timed.body.text, timed.body.content_type.as_deref(), format)
+            .map_err(|e| format!("couldn't parse: {e}"))?;
+        Ok(bench::Sample {
+            first_byte: timed.first_byte,
+            fetch: timed.total,
+            parse: started.elapsed(),
+            size: timed.body.transferred,
+        })
+    });
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.text());
+    }
+    Ok(())
+}
+
+/// `promtool lint`: prints one finding per line to stdout, worst first, and
+/// exits 1 when there are more than `--max-warnings`.
+fn run_lint(
+    args: &lint::LintArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    use io::Write;
+
+    let source = Source::from_arg(&args.endpoint)?;
+    let body = fetch_once(&source, http)?;
+    // Duplicates are found in the raw text, so they're reported even when
+    // they're what the parser rejected.
+    let mut findings = lint::duplicates(&body.text);
+    let parsed = match parse_once(&body, &source, family_filter, format, lenient) {
+        Ok(metrics) => {
+            let openmetrics = format.resolve(&body.text, body.content_type.as_deref()) == Format::Openmetrics;
+            findings.extend(lint::lint(&metrics, openmetrics));
+            true
+        }
+        Err(e) if findings.is_empty() => return Err(e),
+        Err(e) => {
+            eprintln!("{e:#}");
+            false
+        }
+    };
+
+    let mut out = io::stdout().lock();
+    for finding in &findings {
+        let line = format!("{}: {}: {} [{}]", finding.severity.name(), finding.family, finding.message, finding.rule);
+        match writeln!(out, "{line}") {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            written => written?,
+        }
+    }
+    eprintln!("{} findings in {source}", findings.len());
+    if !parsed || findings.len() > args.max_warnings {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `promtool metadata-check`: scrapes the job's first healthy target, or
+/// `--target`, and prints where the server's metadata disagrees with it,
+/// exiting 1 when it does.
+fn run_metadata_check(
+    args: &metadata::MetadataArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let (endpoint, instance) = match &args.target {
+        Some(target) => (target.clone(), None),
+        None => {
+            let body = http
+                .fetch_api(&args.server, "targets", &[("state", "active")])
+                .with_context(|| format!("couldn't list the targets of {}", args.server))?;
+            let targets = metadata::job_targets(&body, &args.job)?;
+            let Some(first) = targets.first() else {
+                anyhow::bail!("{} has no active targets of job {:?}", args.server, args.job);
+            };
+            if targets.len() > 1 {
+                eprintln!(
+                    "checking {}, one of the {} targets of job {}; --target picks another",
+                    first.scrape_url,
+                    targets.len(),
+                    args.job
+                );
+            }
+            (first.scrape_url.clone(), first.instance.clone())
+        }
+    };
+    // Only a target found on the server is known by its instance label.
+    let mut matcher = vec![(String::from("job"), args.job.clone())];
+    matcher.extend(instance.map(|instance| (String::from("instance"), instance)));
+    let mut server = metadata::fetch(http, &args.server, &model::promql_selector("", &matcher), args.page_size)?;
+    // Metrics filtered out of the scrape aren't missing from it.
+    server.targets.retain(|name, known| {
+        known
+            .iter()
+            .any(|entry| family_filter.matches_type(name, metadata::family_type(&entry.kind)))
+    });
+
+    let source = Source::from_arg(&endpoint)?;
+    let body = fetch_once(&source, http)?;
+    let metrics = parse_once(&body, &source, family_filter, format, lenient)?;
+    let mismatches = metadata::compare(&metrics, &server);
+    let report = metadata::Report::new(args, &source.to_string(), metrics.families.len(), mismatches);
+    match args.output {
+        verify::ReportFormat::Text => print!("{}", report.text()),
+        verify::ReportFormat::Json => println!("{}", report.json()),
+    }
+    if !report.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `promtool scaffold-dashboard`: writes the dashboard to `--out`, or to
+/// stdout, and says on stderr how many panels it has.
+fn run_scaffold(
+    args: &dashboard::DashboardArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let source = Source::from_arg(&args.endpoint)?;
+    let body = fetch_once(&source, http)?;
+    let metrics = parse_once(&body, &source, family_filter, format, lenient)?;
+    let openmetrics = format.resolve(&body.text, body.content_type.as_deref()) == Format::Openmetrics;
+    let title = args.title.clone().unwrap_or_else(|| source.to_string());
+    let dashboard = dashboard::scaffold(&metrics, &title, &args.quantiles, openmetrics);
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, dashboard.json() + "\n")
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+            eprintln!("wrote {} panels to {}", dashboard.panels.len(), path.display());
+        }
+        None => println!("{}", dashboard.json()),
+    }
+    Ok(())
+}
+
+/// `promtool diff`: scrapes both endpoints at once, then prints the
+/// differences or opens them in a table.
+fn run_diff(
+    args: &diff::DiffArgs,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+    theme: ThemeName,
+) -> Result<()> {
+    let (a, b) = (Source::from_arg(&args.a)?, Source::from_arg(&args.b)?);
+    if matches!((&a, &b), (Source::Stdin, Source::Stdin)) {
+        anyhow::bail!("only one side of the diff can be read from stdin");
+    }
+    let (scrape_a, scrape_b) = std::thread::scope(|scope| {
+        let scrape_a = scope.spawn(|| scrape_once(&a, http, family_filter, format, lenient));
+        let scrape_b = scrape_once(&b, http, family_filter, format, lenient);
+        (scrape_a.join().expect("scrape thread panicked"), scrape_b)
+    });
+    let rows = diff::diff(&scrape_a?.0, &scrape_b?.0, &args.ignore_label);
+
+    if args.no_tui {
+        let differing = match diff::print(&rows, args.threshold, &mut io::stdout().lock()) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            written => written?,
+        };
+        if differing > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let names = (a.to_string(), b.to_string());
+    // The diff has nothing to click, so the terminal keeps its selection.
+    with_terminal(false, |terminal| {
+        diff::run_tui(terminal, &rows, (&names.0, &names.1), args.threshold, ThemeName::from_env(theme).theme())
+    })
+}
+
+/// `promtool diff-files`: compares two saved scrapes without fetching
+/// anything, and exits 1 when they differ.
+fn run_diff_files(
+    args: &diff::DiffFilesArgs,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+) -> Result<()> {
+    let read = |path: &std::path::Path| -> Result<Exposition> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+        parse_once(&Body::plain(text), &path.display(), family_filter, format, lenient)
+    };
+    let (before, after) = (read(&args.before)?, read(&args.after)?);
+    let names = (args.before.display().to_string(), args.after.display().to_string());
+    let report = diff::FilesReport::new((&names.0, &names.1), &before, &after, &args.ignore_label, args.threshold);
+    match args.output {
+        verify::ReportFormat::Text => print!("{}", report.text()),
+        verify::ReportFormat::Json => println!("{}", report.json()),
+    }
+    if !report.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn print_once(
+    source: &Source,
+    http: &HttpFetcher,
+    family_filter: &FamilyFilter,
+    format: Format,
+    lenient: bool,
+    output: OutputFormat,
+    raw_values: bool,
+) -> Result<()> {
+    let fetched_at = SystemTime::now();
+    let (metrics, duration) = scrape_once(source, http, family_filter, format, lenient)?;
+
+    let mut stdout = io::stdout().lock();
+    let endpoint = source.to_string();
+    let written = match output {
+        OutputFormat::Table => output::print_table(&metrics, raw_values, &mut stdout),
+        OutputFormat::Csv => {
+            let mut families: Vec<(&str, &Family)> =
+                metrics.families.iter().map(|(name, fam)| (name.as_str(), fam)).collect();
+            families.sort_by(|a, b| a.0.cmp(b.0));
+            output::write_csv(families, &mut stdout).map(|_| ())
+        }
+        OutputFormat::Json => {
+            let info = ScrapeInfo {
+                endpoint: &endpoint,
+                fetched_at,
+                duration,
+            };
+            output::print_json(&metrics, &info, &mut stdout)
+        }
+    };
+    match written {
+        // `| head` closing the pipe early isn't a failure.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// A viewer on `fetcher`'s scrapes, shown and parsed the way the options say.
+fn viewer_app(
+    source: Source,
+    fetcher: Fetcher,
+    interval: Option<Duration>,
+    viewer: &ViewerArgs,
+    parse: &ParseArgs,
+    pins: Vec<String>,
+) -> App {
+    let mut app = App::new(
+        source,
+        fetcher,
+        interval,
+        parse.family_filter(),
+        parse.format,
+        parse.lenient,
+        viewer.keep_disappeared,
+    );
+    app.apply_args(viewer, pins);
+    app
+}
+
+/// Starts fetching `source` in the background and sets up a viewer for it.
+fn open_app(args: Args, source: Source, pins: Vec<String>) -> Result<App> {
+    // Piped input has to be consumed before the terminal is taken over;
+    // crossterm then reads keys from the controlling terminal instead.
+    let stdin_text = match source {
+        Source::Stdin => {
+            #[cfg(unix)]
+            std::fs::File::open("/dev/tty")
+                .map_err(|e| anyhow::anyhow!("reading metrics from stdin needs a controlling terminal for the viewer: {e}"))?;
+            let started = Instant::now();
+            let text = io::read_to_string(io::stdin())?;
+            Some(FetchResult {
+                body: Ok(Body::plain(text)),
+                duration: started.elapsed(),
+            })
+        }
+        _ => None,
+    };
+
+    let recorder = args
+        .record
+        .clone()
+        .map(|path| record::Recorder::open(path, args.record_compress, args.record_max_size))
+        .transpose()?;
+    let interval = args.interval.filter(|_| source.is_refreshable());
+    let keeps_state = matches!(
+        source,
+        Source::Http(_) | Source::File(_) | Source::TextfileDir(_) | Source::UnixSocket { .. }
+    );
+    // Refreshes can skip unchanged bodies, except where every scrape is
+    // to be recorded.
+    let http = HttpFetcher::new(HttpConfig {
+        conditional: recorder.is_none(),
+        ..args.http.http_config()?
+    })?;
+    let fetcher = Fetcher::spawn(source.clone(), http, recorder);
+    let mut app = viewer_app(source, fetcher, interval, &args.viewer, &args.parse, pins);
+    if let Some(path) = state::path(&app.endpoint).filter(|_| keeps_state && !args.no_state) {
+        app.restore_state(path, args.viewer.theme_from_cli, args.viewer.columns.is_some());
+    }
+    if let Some(result) = stdin_text {
+        app.apply_fetch(result);
+    }
+    Ok(app)
+}
+
+/// Several ENDPOINTs: a tab each, every one with its own config profile,
+/// fetcher and refresh timer, plus an ALL tab merging them.
+fn run_tabs(args: &Args, matches: &ArgMatches, config: &config::Config) -> Result<()> {
+    if args.no_tui || args.output.is_some() || args.record.is_some() {
+        anyhow::bail!("--no-tui, --output and --record take a single ENDPOINT");
+    }
+    if args.endpoints.iter().filter(|endpoint| *endpoint == "-").count() > 1 {
+        anyhow::bail!("only one ENDPOINT can be read from stdin");
+    }
+    let open = |endpoint: &str, labels: &[(String, String)]| -> Result<App> {
+        let (args, endpoint, pins) = configure(args, matches, config, Some(endpoint));
+        let source = Source::from_arg(&endpoint.expect("given on the command line or in the targets file"))?;
+        let mut app = open_app(args, source, pins)?;
+        app.target_labels = labels.to_vec();
+        Ok(app)
+    };
+    let (mut apps, targets_file, invalid) = match &args.targets_file {
+        Some(path) => {
+            let loaded = targets::load(path)?;
+            if loaded.targets.is_empty() {
+                let reason = loaded.invalid.first().map(|e| format!(" ({e})")).unwrap_or_default();
+                anyhow::bail!("{} lists no valid targets{reason}", path.display());
+            }
+            let apps = loaded
+                .targets
+                .iter()
+                .map(|target| open(&target.url, &target.labels))
+                .collect::<Result<Vec<App>>>()?;
+            let targets_file = TargetsFile {
+                path: path.clone(),
+                current: loaded.targets,
+                open: Box::new(move |target| open(&target.url, &target.labels)),
+            };
+            (apps, Some(targets_file), loaded.invalid)
+        }
+        None => {
+            let apps = args
+                .endpoints
+                .iter()
+                .map(|endpoint| open(endpoint, &[]))
+                .collect::<Result<Vec<App>>>()?;
+            (apps, None, Vec::new())
+        }
+    };
+
+    // The ALL tab fetches nothing itself; run_app fills it in from the others.
+    let (defaults, _, pins) = configure(args, matches, config, None);
+    let fetcher = Fetcher::spawn(Source::Stdin, defaults.http.http()?, None);
+    let mut all = viewer_app(Source::Stdin, fetcher, None, &defaults.viewer, &defaults.parse, pins);
+    all.endpoint = String::from("all endpoints");
+    all.aggregation = Some(Aggregation::Each);
+    all.invalid_targets = invalid;
+    apps.push(all);
+    let mouse = !args.viewer.no_mouse;
+    let opener = Opener {
+        open: Box::new(move |endpoint| open(endpoint, &[])),
+        history_path: state::endpoints_path().filter(|_| !args.no_state),
+    };
+    with_terminal(mouse, |terminal| run_app(terminal, apps, targets_file, Some(opener), mouse))
+}
+
+/// Opens an endpoint typed into the viewer's `:` prompt with the options
+/// and config profile it would have had on the command line, less what
+/// only suits the first one, like `--record`.
+fn open_typed(args: &Args, matches: &ArgMatches, config: &config::Config, endpoint: &str) -> Result<App> {
+    let (mut args, endpoint, pins) = configure(args, matches, config, Some(endpoint));
+    args.record = None;
+    let source = Source::from_arg(&endpoint.expect("typed into the prompt"))?;
+    open_app(args, source, pins)
+}
+
+/// `promtool tui`, or no subcommand: opens the viewer on the ENDPOINTs, or
+/// prints the one ENDPOINT for `--no-tui`.
+fn run_tui(args: Args, matches: &ArgMatches, config: &config::Config) -> Result<()> {
+    if args.endpoints.len() > 1 || args.targets_file.is_some() {
+        return run_tabs(&args, matches, config);
+    }
+    let given = args.clone();
+    let (args, endpoint, pins) = configure(&args, matches, config, args.endpoints.first().map(String::as_str));
+    let source = match (&args.file, &args.textfile_dir, &args.unix_socket, endpoint) {
+        (Some(path), _, _, _) => Source::File(path.clone()),
+        (None, Some(dir), _, _) => Source::TextfileDir(dir.clone()),
+        (None, None, Some(socket), _) => Source::UnixSocket {
+            socket: socket.clone(),
+            path: args.path.clone(),
+        },
+        (None, None, None, Some(endpoint)) => Source::from_arg(&endpoint)?,
+        (None, None, None, None) => {
+            unreachable!("clap requires ENDPOINT unless --file, --textfile-dir or --unix-socket is given")
+        }
+    };
+
+    if args.no_tui || args.output.is_some() {
+        let output = args.output.unwrap_or(OutputFormat::Table);
+        let (parse, raw_values) = (&args.parse, args.viewer.raw_values);
+        let http = args.http.http()?;
+        return print_once(&source, &http, &parse.family_filter(), parse.format, parse.lenient, output, raw_values);
+    }
+
+    let mouse = !args.viewer.no_mouse;
+    let opener = Opener {
+        open: Box::new(|endpoint| open_typed(&given, matches, config, endpoint)),
+        history_path: state::endpoints_path().filter(|_| !args.no_state),
+    };
+    let app = open_app(args, source, pins)?;
+    with_terminal(mouse, |terminal| run_app(terminal, vec![app], None, Some(opener), mouse))
+}
+
+/// Everything after parsing the arguments: runs the subcommand, or the
+/// viewer when there's none.
+pub fn run(cli: Cli, matches: &ArgMatches) -> Result<()> {
+    let global = &cli.global;
+    if let Some(path) = &global.log_file {
+        logging::init(path, global.log_format, global.verbose)?;
+        tracing::info!(version = env!("CARGO_PKG_VERSION"), "starting");
+    }
+    let config = match (&global.config, global.no_config) {
+        (_, true) => config::Config::default(),
+        (Some(path), false) => config::Config::load(path, true)?,
+        (None, false) => match config::default_path() {
+            Some(path) => config::Config::load(&path, false)?,
+            None => config::Config::default(),
+        },
+    };
+    // Each subcommand's options are in its own matches.
+    let (command, matches) = match (cli.command, matches.subcommand()) {
+        (Some(command), Some((_, matches))) => (command, matches),
+        (None, _) => (Command::Tui(cli.tui), matches),
+        (Some(_), None) => unreachable!("clap parsed a subcommand without its matches"),
+    };
+    // The subcommands other than the viewer use the config file's defaults,
+    // not an endpoint profile.
+    let (defaults, _) = config.resolve(None);
+
+    match command {
+        Command::Tui(args) => run_tui(args, matches, &config),
+        Command::Get {
+            endpoint,
+            output,
+            raw_values,
+            mut parse,
+            mut http,
+        } => {
+            let (settings, profile_url) = config.resolve(Some(&endpoint));
+            parse.apply(matches, &settings);
+            http.apply(matches, &settings);
+            let source = Source::from_arg(&profile_url.unwrap_or(endpoint))?;
+            print_once(&source, &http.http()?, &parse.family_filter(), parse.format, parse.lenient, output, raw_values)
+        }
+        Command::Diff {
+            args,
+            mut theme,
+            mut parse,
+            mut http,
+        } => {
+            if let Some(configured) = defaults.theme.filter(|_| !from_cli(matches, "theme")) {
+                theme = configured;
+            }
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_diff(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient, theme)
+        }
+        Command::DiffFiles { args, mut parse } => {
+            parse.apply(matches, &defaults);
+            run_diff_files(&args, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::Replay {
+            args,
+            mut viewer,
+            mut parse,
+        } => {
+            viewer.apply(matches, &defaults);
+            parse.apply(matches, &defaults);
+            let recording = record::load(&args.file)?;
+            if recording.scrapes.is_empty() {
+                anyhow::bail!("{} has no readable records", args.file.display());
+            }
+            let fetcher = Fetcher::spawn(Source::Stdin, HttpFetcher::new(HttpConfig::default())?, None);
+            let mut app = viewer_app(Source::Stdin, fetcher, None, &viewer, &parse, defaults.pins);
+            app.start_replay(&args.file, recording, args.speed);
+            let mouse = !viewer.no_mouse;
+            with_terminal(mouse, |terminal| run_app(terminal, vec![app], None, None, mouse))
+        }
+        Command::Query {
+            args,
+            interval,
+            mut viewer,
+            mut parse,
+            mut http,
+        } => {
+            viewer.apply(matches, &defaults);
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            let source = Source::Query {
+                server: args.server.clone(),
+                expr: args.expr.clone(),
+            };
+            let fetcher = Fetcher::spawn(
+                source.clone(),
+                HttpFetcher::new(HttpConfig {
+                    conditional: true,
+                    ..http.http_config()?
+                })?,
+                None,
+            );
+            let interval = interval.or(defaults.interval);
+            let mut app = viewer_app(source, fetcher, interval, &viewer, &parse, defaults.pins);
+            app.query = Some(QueryState {
+                server: args.server,
+                expr: args.expr,
+                input: String::new(),
+                error: None,
+                warnings: Vec::new(),
+                http: http.http()?,
+            });
+            let mouse = !viewer.no_mouse;
+            with_terminal(mouse, |terminal| run_app(terminal, vec![app], None, None, mouse))
+        }
+        Command::Push {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_push(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::Check {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_check(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::Lint {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_lint(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::Bench {
+            args,
+            mut format,
+            mut http,
+        } => {
+            if let Some(configured) = defaults.format.filter(|_| !from_cli(matches, "format")) {
+                format = configured;
+            }
+            http.apply(matches, &defaults);
+            run_bench(&args, &http.http()?, format)
+        }
+        Command::Verify {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_verify(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::MetadataCheck {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_metadata_check(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+        Command::ScaffoldDashboard {
+            args,
+            mut parse,
+            mut http,
+        } => {
+            parse.apply(matches, &defaults);
+            http.apply(matches, &defaults);
+            run_scaffold(&args, &http.http()?, &parse.family_filter(), parse.format, parse.lenient)
+        }
+    }
+}
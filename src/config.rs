@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::highlight;
+use crate::model::Format;
+use crate::theme::ThemeName;
+
+/// `~/.config/promtool/config.toml`, honoring `$XDG_CONFIG_HOME`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("promtool").join("config.toml"))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Settings,
+    /// `[[endpoint]]` tables, selected by passing a profile's `name` as
+    /// ENDPOINT or any ENDPOINT that starts with its `url`.
+    #[serde(default, rename = "endpoint")]
+    pub endpoints: Vec<Settings>,
+}
+
+/// Everything a config file can set. All optional; anything given on the
+/// command line wins.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Only used in `[[endpoint]]` tables.
+    pub name: Option<String>,
+    /// Required in `[[endpoint]]` tables, not allowed in `[defaults]`.
+    pub url: Option<String>,
+    #[serde(default, deserialize_with = "duration")]
+    pub interval: Option<Duration>,
+    #[serde(default, deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    #[serde(default, deserialize_with = "size")]
+    pub max_body_size: Option<u64>,
+    pub format: Option<Format>,
+    pub lenient: Option<bool>,
+    pub keep_disappeared: Option<u32>,
+    pub history: Option<usize>,
+    #[serde(default, deserialize_with = "duration")]
+    pub max_timestamp_skew: Option<Duration>,
+    pub theme: Option<ThemeName>,
+    /// `--trace-url-template`, e.g. `"https://tempo.example.com/trace/{trace_id}"`.
+    pub trace_url_template: Option<String>,
+    /// `--prometheus-url`, e.g. `"http://prom:9090"`.
+    pub prometheus_url: Option<String>,
+    /// `--grafana-url`, e.g. `"https://grafana.example.com"`.
+    pub grafana_url: Option<String>,
+    #[serde(default, deserialize_with = "regex")]
+    pub filter: Option<Regex>,
+    #[serde(default, deserialize_with = "regex")]
+    pub exclude: Option<Regex>,
+    #[serde(default, deserialize_with = "headers")]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub basic_auth_user: Option<String>,
+    pub bearer_token: Option<String>,
+    pub bearer_token_file: Option<PathBuf>,
+    pub insecure: Option<bool>,
+    pub auto_path: Option<bool>,
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Families to pin when the viewer opens.
+    #[serde(default)]
+    pub pins: Vec<String>,
+    /// `--highlight` rules, e.g. `"node_filesystem_avail_bytes < 1e9"`.
+    #[serde(default, deserialize_with = "highlights")]
+    pub highlight: Vec<highlight::Rule>,
+}
+
+impl Settings {
+    /// `self` with every unset value taken from `fallback`. Headers, pins and
+    /// highlight rules are combined, with `self`'s headers replacing
+    /// same-named ones.
+    fn or(self, fallback: &Settings) -> Settings {
+        let mut headers: Vec<(HeaderName, HeaderValue)> = fallback
+            .headers
+            .iter()
+            .filter(|(name, _)| !self.headers.iter().any(|(own, _)| own == name))
+            .cloned()
+            .collect();
+        headers.extend(self.headers);
+        let mut pins = fallback.pins.clone();
+        pins.extend(self.pins);
+        let mut highlight = fallback.highlight.clone();
+        highlight.extend(self.highlight);
+
+        // Auth is all-or-nothing, so a profile's token isn't mixed with a
+        // username from the defaults.
+        let has_auth =
+            self.basic_auth_user.is_some() || self.bearer_token.is_some() || self.bearer_token_file.is_some();
+        let auth = if has_auth { &self } else { fallback };
+        let (basic_auth_user, bearer_token, bearer_token_file) = (
+            auth.basic_auth_user.clone(),
+            auth.bearer_token.clone(),
+            auth.bearer_token_file.clone(),
+        );
+
+        Settings {
+            name: self.name,
+            url: self.url,
+            interval: self.interval.or(fallback.interval),
+            timeout: self.timeout.or(fallback.timeout),
+            retries: self.retries.or(fallback.retries),
+            max_body_size: self.max_body_size.or(fallback.max_body_size),
+            format: self.format.or(fallback.format),
+            lenient: self.lenient.or(fallback.lenient),
+            keep_disappeared: self.keep_disappeared.or(fallback.keep_disappeared),
+            history: self.history.or(fallback.history),
+            max_timestamp_skew: self.max_timestamp_skew.or(fallback.max_timestamp_skew),
+            theme: self.theme.or(fallback.theme),
+            trace_url_template: self.trace_url_template.or_else(|| fallback.trace_url_template.clone()),
+            prometheus_url: self.prometheus_url.or_else(|| fallback.prometheus_url.clone()),
+            grafana_url: self.grafana_url.or_else(|| fallback.grafana_url.clone()),
+            filter: self.filter.or_else(|| fallback.filter.clone()),
+            exclude: self.exclude.or_else(|| fallback.exclude.clone()),
+            headers,
+            basic_auth_user,
+            bearer_token,
+            bearer_token_file,
+            insecure: self.insecure.or(fallback.insecure),
+            auto_path: self.auto_path.or(fallback.auto_path),
+            ca_cert: self.ca_cert.or_else(|| fallback.ca_cert.clone()),
+            client_cert: self.client_cert.or_else(|| fallback.client_cert.clone()),
+            client_key: self.client_key.or_else(|| fallback.client_key.clone()),
+            pins,
+            highlight,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `path`. A missing file is only an error when `required`, i.e.
+    /// it was named with `--config`.
+    pub fn load(path: &Path, required: bool) -> Result<Config> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+            Err(e) => return Err(e).with_context(|| format!("couldn't read config file {}", path.display())),
+        };
+        let config: Config =
+            toml::from_str(&text).with_context(|| format!("invalid config file {}", path.display()))?;
+        if config.defaults.url.is_some() || config.defaults.name.is_some() {
+            anyhow::bail!(
+                "invalid config file {}: `name` and `url` belong in [[endpoint]] tables, not [defaults]",
+                path.display()
+            );
+        }
+        if let Some(index) = config.endpoints.iter().position(|p| p.url.is_none()) {
+            anyhow::bail!(
+                "invalid config file {}: [[endpoint]] #{} has no `url`",
+                path.display(),
+                index + 1
+            );
+        }
+        Ok(config)
+    }
+
+    /// The settings for `endpoint` and, if it named a profile, the URL it
+    /// stands for.
+    pub fn resolve(&self, endpoint: Option<&str>) -> (Settings, Option<String>) {
+        let profile = endpoint.and_then(|endpoint| {
+            self.endpoints
+                .iter()
+                .find(|p| p.name.as_deref() == Some(endpoint))
+                .map(|p| (p, p.url.clone()))
+                .or_else(|| {
+                    self.endpoints
+                        .iter()
+                        .find(|p| p.url.as_deref().is_some_and(|url| endpoint.starts_with(url)))
+                        .map(|p| (p, None))
+                })
+        });
+        match profile {
+            Some((profile, url)) => (profile.clone().or(&self.defaults), url),
+            None => (self.defaults.clone(), None),
+        }
+    }
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    humantime::parse_duration(&value)
+        .map(Some)
+        .map_err(|e| serde::de::Error::custom(format!("invalid duration {value:?}: {e}")))
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    crate::cli::parse_size(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Regex::new(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn headers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(HeaderName, HeaderValue)>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|header| crate::cli::parse_header(header).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn highlights<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<highlight::Rule>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|rule| highlight::Rule::parse(rule).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` loaded as a config file, and the file's path.
+    fn load(name: &str, text: &str) -> (Result<Config>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("promtool-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.toml"));
+        std::fs::write(&path, text).unwrap();
+        (Config::load(&path, true), path)
+    }
+
+    fn error(name: &str, text: &str) -> String {
+        let (config, path) = load(name, text);
+        let message = format!("{:#}", config.err().expect("the config should be rejected"));
+        assert!(message.contains(&path.display().to_string()), "{message}");
+        message
+    }
+
+    #[test]
+    fn unknown_keys_are_named_with_their_line() {
+        let message = error("unknown", "[defaults]\ninterval = \"5s\"\ncolour = \"dark\"\n");
+        assert!(message.contains("line 3"), "{message}");
+        assert!(message.contains("unknown field `colour`"), "{message}");
+
+        let message = error("endpoint-in-defaults", "[defaults]\nurl = \"http://localhost:9100\"\n");
+        assert!(message.contains("`name` and `url` belong in [[endpoint]] tables"), "{message}");
+        let message = error("no-url", "[[endpoint]]\nname = \"a\"\nurl = \"http://a\"\n\n[[endpoint]]\nname = \"b\"\n");
+        assert!(message.contains("[[endpoint]] #2 has no `url`"), "{message}");
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_named_with_their_line() {
+        let message = error("retries", "[defaults]\nretries = \"three\"\n");
+        assert!(message.contains("line 2"), "{message}");
+        assert!(message.contains("invalid type: string \"three\""), "{message}");
+
+        let message = error("interval", "[defaults]\ntimeout = \"1s\"\ninterval = \"soon\"\n");
+        assert!(message.contains("line 3"), "{message}");
+        assert!(message.contains("invalid duration \"soon\""), "{message}");
+
+        let message = error("size", "[defaults]\nmax_body_size = \"12 parsecs\"\n");
+        assert!(message.contains("unknown size suffix"), "{message}");
+    }
+
+    #[test]
+    fn profiles_are_found_by_name_or_url_prefix_over_the_defaults() {
+        let (config, path) = load(
+            "profiles",
+            concat!(
+                "[defaults]\n",
+                "timeout = \"2s\"\n",
+                "retries = 1\n",
+                "headers = [\"X-Team: infra\", \"X-Env: dev\"]\n",
+                "basic_auth_user = \"admin\"\n",
+                "\n",
+                "[[endpoint]]\n",
+                "name = \"prod\"\n",
+                "url = \"https://prod.example.com:9100/metrics\"\n",
+                "retries = 5\n",
+                "headers = [\"X-Env: prod\"]\n",
+                "bearer_token = \"s3cret\"\n",
+            ),
+        );
+        let config = config.unwrap();
+
+        let (prod, url) = config.resolve(Some("prod"));
+        assert_eq!(url.as_deref(), Some("https://prod.example.com:9100/metrics"));
+        assert_eq!(prod.retries, Some(5));
+        assert_eq!(prod.timeout, Some(Duration::from_secs(2)));
+        let headers: Vec<String> =
+            prod.headers.iter().map(|(name, value)| format!("{name}: {}", value.to_str().unwrap())).collect();
+        assert_eq!(headers, vec!["x-team: infra", "x-env: prod"]);
+        // The profile's token isn't mixed with the defaults' username.
+        assert_eq!(prod.bearer_token.as_deref(), Some("s3cret"));
+        assert_eq!(prod.basic_auth_user, None);
+
+        let (by_url, url) = config.resolve(Some("https://prod.example.com:9100/metrics?debug=1"));
+        assert_eq!(url, None, "an endpoint given as a URL is fetched as given");
+        assert_eq!(by_url.retries, Some(5));
+
+        let (other, url) = config.resolve(Some("localhost:9100"));
+        assert_eq!(url, None);
+        assert_eq!(other.retries, Some(1));
+        assert_eq!(other.basic_auth_user.as_deref(), Some("admin"));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Config::load(&path, false).unwrap().endpoints.is_empty());
+        let missing = format!("{:#}", Config::load(&path, true).err().unwrap());
+        assert!(missing.contains("couldn't read config file"), "{missing}");
+    }
+}
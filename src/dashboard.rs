@@ -0,0 +1,480 @@
+use openmetrics_parser::PrometheusType;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::cli::parse_quantile;
+use crate::model::{self, Exposition, Family};
+
+/// `promtool scaffold-dashboard`: scrape once and write a Grafana dashboard
+/// with a panel per family, to start from rather than click together.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DashboardArgs {
+    /// The endpoint, saved scrape, or `-` for stdin to scaffold a dashboard for
+    #[arg(value_name = "ENDPOINT")]
+    pub endpoint: String,
+
+    /// Write the dashboard here instead of to stdout
+    #[arg(long, value_name = "FILE")]
+    pub out: Option<PathBuf>,
+
+    /// The dashboard's title; the endpoint by default
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Quantiles to chart for each histogram, e.g. "0.5,0.9,0.99"
+    #[arg(
+        long,
+        value_name = "Q",
+        value_delimiter = ',',
+        value_parser = parse_quantile,
+        default_value = "0.5,0.9,0.99"
+    )]
+    pub quantiles: Vec<f64>,
+}
+
+/// The Grafana dashboard schema version written.
+const SCHEMA_VERSION: u32 = 39;
+/// Panels are laid out two to a row of Grafana's 24 columns.
+const PANEL_WIDTH: u32 = 12;
+const PANEL_HEIGHT: u32 = 8;
+/// Every query picks its data source from the `$datasource` variable.
+const DATASOURCE: &str = "${datasource}";
+
+/// The subset of Grafana's dashboard JSON that scaffolds use.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Dashboard {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub editable: bool,
+    pub schema_version: u32,
+    pub time: TimeRange,
+    pub templating: Templating,
+    pub panels: Vec<Panel>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TimeRange {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Templating {
+    pub list: Vec<Variable>,
+}
+
+/// A template variable: the Prometheus data source, or the instances to show.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Variable {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datasource: Option<DatasourceRef>,
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub definition: Option<String>,
+    /// 1 re-queries the values when the dashboard loads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<u32>,
+    pub multi: bool,
+    pub include_all: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DatasourceRef {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub uid: String,
+}
+
+impl DatasourceRef {
+    fn prometheus() -> DatasourceRef {
+        DatasourceRef {
+            kind: String::from("prometheus"),
+            uid: String::from(DATASOURCE),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Panel {
+    pub id: u32,
+    /// `timeseries` or `gauge`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub description: String,
+    pub grid_pos: GridPos,
+    pub datasource: DatasourceRef,
+    pub targets: Vec<Target>,
+    pub field_config: FieldConfig,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GridPos {
+    pub h: u32,
+    pub w: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// One query of a panel.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    pub ref_id: String,
+    pub datasource: DatasourceRef,
+    pub expr: String,
+    pub legend_format: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FieldConfig {
+    pub defaults: FieldDefaults,
+    pub overrides: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FieldDefaults {
+    /// A Grafana unit id such as `s`, `bytes` or `Bps`; `short` when the
+    /// name doesn't say.
+    pub unit: String,
+}
+
+impl Dashboard {
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("dashboards always serialize")
+    }
+}
+
+/// A dashboard with a panel per family of `metrics`, in name order: a rate
+/// graph for counters, a gauge for gauges, `quantiles` estimated from the
+/// buckets of histograms and the quantiles a summary exposes. Counters of
+/// an OpenMetrics scrape are queried by their `_total` series.
+pub fn scaffold(metrics: &Exposition, title: &str, quantiles: &[f64], openmetrics: bool) -> Dashboard {
+    let mut names: Vec<&String> = metrics.families.keys().collect();
+    names.sort();
+    let panels = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let fam = &metrics.families[*name];
+            let (kind, targets, unit) = queries(name, fam, quantiles, openmetrics);
+            let index = index as u32;
+            Panel {
+                id: index + 1,
+                kind: String::from(kind),
+                title: name.to_string(),
+                description: fam.help.trim().to_string(),
+                grid_pos: GridPos {
+                    h: PANEL_HEIGHT,
+                    w: PANEL_WIDTH,
+                    x: index % 2 * PANEL_WIDTH,
+                    y: index / 2 * PANEL_HEIGHT,
+                },
+                datasource: DatasourceRef::prometheus(),
+                targets,
+                field_config: FieldConfig {
+                    defaults: FieldDefaults {
+                        unit: String::from(unit),
+                    },
+                    overrides: Vec::new(),
+                },
+            }
+        })
+        .collect();
+    // The instances are whatever Prometheus scraped the first family from,
+    // so the list is this exporter's rather than every target's.
+    let instances = names.first().map_or_else(
+        || String::from("label_values(instance)"),
+        |name| {
+            format!(
+                "label_values({}, instance)",
+                sample_name(name, &metrics.families[*name], openmetrics)
+            )
+        },
+    );
+    Dashboard {
+        title: title.to_string(),
+        tags: vec![String::from("promtool")],
+        editable: true,
+        schema_version: SCHEMA_VERSION,
+        time: TimeRange {
+            from: String::from("now-1h"),
+            to: String::from("now"),
+        },
+        templating: Templating {
+            list: vec![
+                Variable {
+                    kind: String::from("datasource"),
+                    name: String::from("datasource"),
+                    label: String::from("Data source"),
+                    datasource: None,
+                    query: String::from("prometheus"),
+                    definition: None,
+                    refresh: None,
+                    multi: false,
+                    include_all: false,
+                },
+                Variable {
+                    kind: String::from("query"),
+                    name: String::from("instance"),
+                    label: String::from("Instance"),
+                    datasource: Some(DatasourceRef::prometheus()),
+                    query: instances.clone(),
+                    definition: Some(instances),
+                    refresh: Some(1),
+                    multi: true,
+                    include_all: true,
+                },
+            ],
+        },
+        panels,
+    }
+}
+
+/// The panel type, queries and unit for the family `name`.
+fn queries(
+    name: &str,
+    fam: &Family,
+    quantiles: &[f64],
+    openmetrics: bool,
+) -> (&'static str, Vec<Target>, &'static str) {
+    let selector = |metric: &str| format!("{metric}{{instance=~\"$instance\"}}");
+    let legend = legend(fam);
+    let unit = unit(name, &fam.unit);
+    match fam.family_type {
+        PrometheusType::Counter => {
+            let expr = format!(
+                "rate({}[$__rate_interval])",
+                selector(&sample_name(name, fam, openmetrics))
+            );
+            // A rate of seconds, like CPU time, is a ratio with no unit of its own.
+            let unit = if unit == "bytes" { "Bps" } else { "short" };
+            ("timeseries", vec![target(0, expr, legend)], unit)
+        }
+        PrometheusType::Gauge => ("gauge", vec![target(0, selector(name), legend)], unit),
+        PrometheusType::Histogram => {
+            let targets = quantiles
+                .iter()
+                .enumerate()
+                .map(|(index, q)| {
+                    let expr = format!(
+                        "histogram_quantile({q}, sum by (le) (rate({}[$__rate_interval])))",
+                        selector(&format!("{name}_bucket"))
+                    );
+                    target(index, expr, model::format_percentile(*q))
+                })
+                .collect();
+            ("timeseries", targets, unit)
+        }
+        PrometheusType::Summary => {
+            let legend = format!("{legend} {{{{quantile}}}}");
+            ("timeseries", vec![target(0, selector(name), legend)], unit)
+        }
+        PrometheusType::Unknown => ("timeseries", vec![target(0, selector(name), legend)], unit),
+    }
+}
+
+/// The series a query selects for the family `name`: its own name, bar
+/// OpenMetrics counters, whose samples add `_total`.
+fn sample_name(name: &str, fam: &Family, openmetrics: bool) -> String {
+    match fam.family_type {
+        PrometheusType::Counter if openmetrics && !name.ends_with("_total") => format!("{name}_total"),
+        PrometheusType::Histogram => format!("{name}_bucket"),
+        _ => name.to_string(),
+    }
+}
+
+/// `{{instance}}` and then each label the family's series carry, in name
+/// order, so every line of a panel is told apart.
+fn legend(fam: &Family) -> String {
+    let labels: BTreeSet<String> = fam
+        .iter_samples()
+        .flat_map(model::label_pairs)
+        .map(|(label, _)| label)
+        .filter(|label| label != "instance")
+        .collect();
+    std::iter::once(String::from("instance"))
+        .chain(labels)
+        .map(|label| format!("{{{{{label}}}}}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The Grafana unit for a family, going by its UNIT or name suffix the way
+/// the viewer humanizes values.
+fn unit(name: &str, unit: &str) -> &'static str {
+    let base = name.strip_suffix("_total").unwrap_or(name);
+    if unit == "bytes" || base.ends_with("_bytes") {
+        "bytes"
+    } else if unit == "seconds" || base.ends_with("_seconds") {
+        "s"
+    } else {
+        "short"
+    }
+}
+
+fn target(index: usize, expr: String, legend_format: String) -> Target {
+    Target {
+        ref_id: ref_id(index),
+        datasource: DatasourceRef::prometheus(),
+        expr,
+        legend_format,
+    }
+}
+
+/// `A`, `B`, ... `Z`, then `AA` and on, like Grafana names queries.
+fn ref_id(index: usize) -> String {
+    let letter = |n: usize| char::from(b'A' + (n % 26) as u8);
+    if index < 26 {
+        letter(index).to_string()
+    } else {
+        format!("{}{}", letter(index / 26 - 1), letter(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRAPE: &str = concat!(
+        "# HELP jobs_processed_total Jobs the worker finished.\n",
+        "# TYPE jobs_processed_total counter\n",
+        "jobs_processed_total{queue=\"email\"} 1027\n",
+        "jobs_processed_total{queue=\"sms\"} 88\n",
+        "# HELP queue_depth Jobs waiting to be picked up.\n",
+        "# TYPE queue_depth gauge\n",
+        "queue_depth{queue=\"email\"} 3\n",
+        "# HELP job_duration_seconds How long jobs took.\n",
+        "# TYPE job_duration_seconds histogram\n",
+        "job_duration_seconds_bucket{le=\"0.1\"} 12\n",
+        "job_duration_seconds_bucket{le=\"1\"} 40\n",
+        "job_duration_seconds_bucket{le=\"+Inf\"} 41\n",
+        "job_duration_seconds_sum 17.5\n",
+        "job_duration_seconds_count 41\n",
+        "# HELP payload_bytes Size of the payloads sent.\n",
+        "# TYPE payload_bytes summary\n",
+        "payload_bytes{quantile=\"0.5\"} 512\n",
+        "payload_bytes{quantile=\"0.99\"} 4096\n",
+        "payload_bytes_sum 81920\n",
+        "payload_bytes_count 120\n",
+    );
+
+    fn dashboard() -> Dashboard {
+        let metrics = model::parse(SCRAPE, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        scaffold(&metrics, "worker", &[0.5, 0.99], false)
+    }
+
+    #[test]
+    fn each_family_gets_a_panel_suiting_its_type() {
+        let dashboard = dashboard();
+        let panels: Vec<(&str, &str, &str)> = dashboard
+            .panels
+            .iter()
+            .map(|panel| {
+                (
+                    panel.title.as_str(),
+                    panel.kind.as_str(),
+                    panel.field_config.defaults.unit.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            panels,
+            vec![
+                ("job_duration_seconds", "timeseries", "s"),
+                ("jobs_processed_total", "timeseries", "short"),
+                ("payload_bytes", "timeseries", "bytes"),
+                ("queue_depth", "gauge", "short"),
+            ]
+        );
+        let exprs: Vec<&str> = dashboard.panels[0]
+            .targets
+            .iter()
+            .map(|target| target.expr.as_str())
+            .collect();
+        assert_eq!(
+            exprs,
+            vec![
+                concat!(
+                    "histogram_quantile(0.5, sum by (le) ",
+                    "(rate(job_duration_seconds_bucket{instance=~\"$instance\"}[$__rate_interval])))"
+                ),
+                concat!(
+                    "histogram_quantile(0.99, sum by (le) ",
+                    "(rate(job_duration_seconds_bucket{instance=~\"$instance\"}[$__rate_interval])))"
+                ),
+            ]
+        );
+        let counter = &dashboard.panels[1];
+        assert_eq!(counter.description, "Jobs the worker finished.");
+        assert_eq!(counter.targets[0].legend_format, "{{instance}} {{queue}}");
+        assert_eq!(
+            (
+                counter.grid_pos.x,
+                counter.grid_pos.y,
+                dashboard.panels[2].grid_pos.x,
+                dashboard.panels[2].grid_pos.y
+            ),
+            (12, 0, 0, 8)
+        );
+    }
+
+    #[test]
+    fn openmetrics_counters_are_queried_by_their_total() {
+        let metrics = model::parse(
+            "# TYPE jobs_processed counter\njobs_processed_total 3\n# EOF\n",
+            None,
+            model::Format::Openmetrics,
+        )
+        .unwrap_or_else(|e| panic!("{e}"));
+        let dashboard = scaffold(&metrics, "worker", &[0.5], true);
+        assert_eq!(
+            dashboard.panels[0].targets[0].expr,
+            "rate(jobs_processed_total{instance=~\"$instance\"}[$__rate_interval])"
+        );
+        assert_eq!(
+            dashboard.templating.list[1].query,
+            "label_values(jobs_processed_total, instance)"
+        );
+    }
+
+    /// The whole dashboard against `tests/snapshots/dashboard.json`, which
+    /// `UPDATE_SNAPSHOTS=1` records again, like the screen snapshots. A
+    /// missing file fails rather than being recorded.
+    #[test]
+    fn dashboards_match_the_golden_file() {
+        let actual = dashboard().json() + "\n";
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/dashboard.json");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) => assert!(
+                actual == expected,
+                "the dashboard no longer matches {}; rerun with UPDATE_SNAPSHOTS=1 if that's intended.\n\
+                 expected:\n{expected}\nactual:\n{actual}",
+                path.display()
+            ),
+            Err(err) => panic!(
+                "can't read {}: {err}; record it with UPDATE_SNAPSHOTS=1.\nactual:\n{actual}",
+                path.display()
+            ),
+        }
+    }
+
+    #[test]
+    fn queries_are_lettered_like_grafana_does() {
+        let ids: Vec<String> = [0, 1, 25, 26, 27, 51, 52].into_iter().map(ref_id).collect();
+        assert_eq!(ids, vec!["A", "B", "Z", "AA", "AB", "AZ", "BA"]);
+    }
+}
@@ -0,0 +1,572 @@
+use anyhow::Result;
+use crossterm::event::{self, Event};
+use openmetrics_parser::PrometheusValue;
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Constraint},
+    prelude::Stylize,
+    text::{Line, Text},
+    widgets::{Block, Borders, Row, Table, TableState},
+    Terminal,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::cli::parse_threshold;
+use crate::keys::{self, Action, Context};
+use crate::model::{self, Exposition, FamilyType};
+use crate::theme::Theme;
+use crate::verify::ReportFormat;
+
+/// `promtool diff`: compare two endpoints series by series.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// The baseline endpoint or saved scrape
+    #[arg(value_name = "A")]
+    pub a: String,
+
+    /// The endpoint or saved scrape to compare against A
+    #[arg(value_name = "B")]
+    pub b: String,
+
+    /// Labels to drop before aligning series, e.g. "instance,pod"; series
+    /// that then collide are summed
+    #[arg(long, value_name = "LABELS", value_delimiter = ',')]
+    pub ignore_label: Vec<String>,
+
+    /// Only count differences larger than this many percent; with --no-tui
+    /// the exit code is 1 if any series exceeds it
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+    pub threshold: f64,
+
+    /// Print the differing series and exit instead of opening the viewer
+    #[arg(long)]
+    pub no_tui: bool,
+}
+
+/// `promtool diff-files`: compare two saved scrapes offline, e.g. captured
+/// before and after a deploy, for CI.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffFilesArgs {
+    /// The saved scrape from before the change
+    #[arg(value_name = "BEFORE")]
+    pub before: PathBuf,
+
+    /// The saved scrape from after it
+    #[arg(value_name = "AFTER")]
+    pub after: PathBuf,
+
+    /// Labels to drop before aligning series, e.g. "instance,pod"; series
+    /// that then collide are summed
+    #[arg(long, value_name = "LABELS", value_delimiter = ',')]
+    pub ignore_label: Vec<String>,
+
+    /// Only report values that changed by more than this, either absolute
+    /// ("0.5") or relative to BEFORE ("5%")
+    #[arg(long, value_parser = parse_threshold, default_value = "0")]
+    pub threshold: Threshold,
+
+    /// How to print the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+}
+
+/// How much a value has to change for `diff-files` to report it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    Absolute(f64),
+    /// Percent of the value before.
+    Percent(f64),
+}
+
+impl Threshold {
+    fn exceeded_by(self, row: &DiffRow) -> bool {
+        match self {
+            Threshold::Percent(percent) => row.exceeds(percent),
+            Threshold::Absolute(_) if row.is_equal() => false,
+            Threshold::Absolute(limit) => row.delta().is_none_or(|delta| delta.abs() > limit || delta.is_nan()),
+        }
+    }
+}
+
+/// One series aligned across both scrapes. Histograms and summaries are
+/// compared by their `_count` and `_sum`.
+pub struct DiffRow {
+    pub series: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl DiffRow {
+    pub fn delta(&self) -> Option<f64> {
+        Some(self.b? - self.a?)
+    }
+
+    /// Change relative to A, or `None` when A is zero and B isn't.
+    pub fn percent(&self) -> Option<f64> {
+        let (a, b) = (self.a?, self.b?);
+        if a == b {
+            Some(0.0)
+        } else if a == 0.0 {
+            None
+        } else {
+            Some((b - a) / a.abs() * 100.0)
+        }
+    }
+
+    fn is_equal(&self) -> bool {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) => a == b || (a.is_nan() && b.is_nan()),
+            _ => false,
+        }
+    }
+
+    /// Whether this series differs by more than `threshold` percent. Series
+    /// on only one side always do.
+    pub fn exceeds(&self, threshold: f64) -> bool {
+        if self.is_equal() {
+            return false;
+        }
+        self.percent().is_none_or(|percent| percent.abs() > threshold || percent.is_nan())
+    }
+}
+
+/// Aligns the series of `a` and `b` by family and labels, minus `ignore`d
+/// labels, sorted by series.
+pub fn diff(a: &Exposition, b: &Exposition, ignore: &[String]) -> Vec<DiffRow> {
+    let a = series_values(a, ignore);
+    let mut b = series_values(b, ignore);
+    let mut rows: Vec<DiffRow> = a
+        .into_iter()
+        .map(|(series, a)| DiffRow {
+            b: b.remove(&series),
+            a: Some(a),
+            series,
+        })
+        .collect();
+    rows.extend(b.into_iter().map(|(series, b)| DiffRow {
+        series,
+        a: None,
+        b: Some(b),
+    }));
+    rows.sort_by(|x, y| x.series.cmp(&y.series));
+    rows
+}
+
+fn series_values(exposition: &Exposition, ignore: &[String]) -> BTreeMap<String, f64> {
+    let mut values = BTreeMap::new();
+    for (name, fam) in &exposition.families {
+        for sample in fam.iter_samples() {
+            let mut pairs: Vec<(String, String)> = model::label_pairs(sample)
+                .into_iter()
+                .filter(|(label, _)| !ignore.contains(label))
+                .collect();
+            // Like `SeriesKey`, so the order labels were written in doesn't matter.
+            pairs.sort();
+            let labels = if pairs.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", model::format_labels(&pairs))
+            };
+            let mut add = |suffix: &str, value: f64| {
+                *values.entry(format!("{name}{suffix}{labels}")).or_insert(0.0) += value;
+            };
+            match &sample.value {
+                PrometheusValue::Histogram(h) => {
+                    if let Some(count) = h.count {
+                        add("_count", count as f64);
+                    }
+                    if let Some(sum) = h.sum {
+                        add("_sum", sum.as_f64());
+                    }
+                }
+                PrometheusValue::Summary(s) => {
+                    if let Some(count) = s.count {
+                        add("_count", count as f64);
+                    }
+                    if let Some(sum) = s.sum {
+                        add("_sum", sum.as_f64());
+                    }
+                }
+                value => {
+                    if let Some(value) = model::scalar_value(value) {
+                        add("", value);
+                    }
+                }
+            }
+        }
+    }
+    values
+}
+
+fn format_side(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), model::format_number)
+}
+
+/// The delta column: absolute and relative change, or which side the series
+/// is missing from.
+fn format_delta(row: &DiffRow) -> String {
+    match (row.a, row.b) {
+        (Some(_), None) => String::from("only in A"),
+        (None, Some(_)) => String::from("only in B"),
+        _ if row.is_equal() => String::from("="),
+        _ => format_change(row.delta().unwrap_or(f64::NAN), row.percent()),
+    }
+}
+
+/// `+10 (+10.0%)`, or just `+10` when there's nothing to be a percent of.
+fn format_change(delta: f64, percent: Option<f64>) -> String {
+    let sign = if delta > 0.0 { "+" } else { "" };
+    match percent {
+        Some(percent) => format!("{sign}{} ({percent:+.1}%)", model::format_number(delta)),
+        None => format!("{sign}{}", model::format_number(delta)),
+    }
+}
+
+/// Prints the series that differ by more than `threshold` as aligned
+/// columns and returns how many there were.
+pub fn print(rows: &[DiffRow], threshold: f64, out: &mut impl Write) -> io::Result<usize> {
+    let differing: Vec<&DiffRow> = rows.iter().filter(|row| row.exceeds(threshold)).collect();
+    let lines: Vec<[String; 4]> = differing
+        .iter()
+        .map(|row| [row.series.clone(), format_side(row.a), format_side(row.b), format_delta(row)])
+        .collect();
+    let width = |column: usize| lines.iter().map(|l| l[column].chars().count()).max().unwrap_or(0);
+    let (series_width, a_width, b_width) = (width(0), width(1), width(2));
+    for [series, a, b, delta] in &lines {
+        writeln!(out, "{series:series_width$}  {a:>a_width$}  {b:>b_width$}  {delta}")?;
+    }
+    Ok(differing.len())
+}
+
+/// What `diff-files` found: series on one side only, values that changed by
+/// more than the threshold, and families whose TYPE or HELP changed.
+#[derive(Serialize, Debug)]
+pub struct FilesReport {
+    pub before: String,
+    pub after: String,
+    pub added: Vec<SeriesValue>,
+    pub removed: Vec<SeriesValue>,
+    pub changed: Vec<ValueChange>,
+    pub families: Vec<FamilyChange>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SeriesValue {
+    pub series: String,
+    pub value: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ValueChange {
+    pub series: String,
+    pub before: f64,
+    pub after: f64,
+    pub delta: f64,
+    /// `None` when the value before was zero.
+    pub percent: Option<f64>,
+}
+
+/// A family on both sides whose `field`, `type` or `help`, differs.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FamilyChange {
+    pub family: String,
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+impl FilesReport {
+    /// Compares `before` with `after`, aligned like [`diff`] does.
+    pub fn new(
+        names: (&str, &str),
+        before: &Exposition,
+        after: &Exposition,
+        ignore: &[String],
+        threshold: Threshold,
+    ) -> FilesReport {
+        let mut report = FilesReport {
+            before: names.0.to_string(),
+            after: names.1.to_string(),
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            families: family_changes(before, after),
+        };
+        for row in diff(before, after, ignore) {
+            match (row.a, row.b) {
+                (Some(value), None) => report.removed.push(SeriesValue {
+                    series: row.series,
+                    value,
+                }),
+                (None, Some(value)) => report.added.push(SeriesValue {
+                    series: row.series,
+                    value,
+                }),
+                (Some(a), Some(b)) if threshold.exceeded_by(&row) => report.changed.push(ValueChange {
+                    delta: b - a,
+                    percent: row.percent(),
+                    series: row.series,
+                    before: a,
+                    after: b,
+                }),
+                _ => {}
+            }
+        }
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.families.is_empty()
+    }
+
+    /// A line per difference, `~` for changes, `-` for removed series and
+    /// `+` for added ones, then a summary. Values are written out exactly.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for change in &self.families {
+            let (before, after) = match change.field {
+                "help" => (format!("{:?}", change.before), format!("{:?}", change.after)),
+                _ => (change.before.clone(), change.after.clone()),
+            };
+            let field = change.field.to_uppercase();
+            text.push_str(&format!("~ {} {field} {before} -> {after}\n", change.family));
+        }
+        for series in &self.removed {
+            text.push_str(&format!("- {} {}\n", series.series, model::format_raw(series.value)));
+        }
+        for series in &self.added {
+            text.push_str(&format!("+ {} {}\n", series.series, model::format_raw(series.value)));
+        }
+        for change in &self.changed {
+            text.push_str(&format!(
+                "~ {} {} -> {} {}\n",
+                change.series,
+                model::format_raw(change.before),
+                model::format_raw(change.after),
+                format_change(change.delta, change.percent)
+            ));
+        }
+        if self.is_empty() {
+            text.push_str(&format!("{} and {} match\n", self.before, self.after));
+        } else {
+            text.push_str(&format!(
+                "{} -> {}: {} series added, {} removed, {} changed, {} TYPE or HELP changes\n",
+                self.before,
+                self.after,
+                self.added.len(),
+                self.removed.len(),
+                self.changed.len(),
+                self.families.len()
+            ));
+        }
+        text
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+}
+
+/// Families on both sides whose TYPE or HELP differ, by name.
+fn family_changes(before: &Exposition, after: &Exposition) -> Vec<FamilyChange> {
+    let mut names: Vec<&String> = before.families.keys().filter(|name| after.families.contains_key(*name)).collect();
+    names.sort();
+    let mut changes = Vec::new();
+    for name in names {
+        let (a, b) = (&before.families[name], &after.families[name]);
+        let (type_a, type_b) = (FamilyType::of(a).name(), FamilyType::of(b).name());
+        if type_a != type_b {
+            changes.push(FamilyChange {
+                family: name.clone(),
+                field: "type",
+                before: type_a.to_string(),
+                after: type_b.to_string(),
+            });
+        }
+        if a.help.trim() != b.help.trim() {
+            changes.push(FamilyChange {
+                family: name.clone(),
+                field: "help",
+                before: a.help.trim().to_string(),
+                after: b.help.trim().to_string(),
+            });
+        }
+    }
+    changes
+}
+
+/// The diff as a scrollable table. Equal series are dimmed and one-sided
+/// ones colored; `q` quits.
+pub fn run_tui<B: Backend>(
+    terminal: &mut Terminal<B>,
+    rows: &[DiffRow],
+    names: (&str, &str),
+    threshold: f64,
+    theme: &Theme,
+) -> Result<()> {
+    let differing = rows.iter().filter(|row| row.exceeds(threshold)).count();
+    let mut state = TableState::default().with_selected(Some(0));
+    let mut page = 1;
+    loop {
+        terminal.draw(|f| {
+            let title = format!(
+                "A: {} — B: {} — {differing} of {} series differ",
+                names.0,
+                names.1,
+                rows.len()
+            );
+            let table_rows: Vec<Row> = rows
+                .iter()
+                .map(|row| {
+                    let cells = vec![
+                        Text::from(row.series.clone()),
+                        Text::from(format_side(row.a)).alignment(Alignment::Right),
+                        Text::from(format_side(row.b)).alignment(Alignment::Right),
+                        Text::from(format_delta(row)).alignment(Alignment::Right),
+                    ];
+                    match (row.a, row.b) {
+                        (Some(_), None) => Row::new(cells).style(theme.down),
+                        (None, Some(_)) => Row::new(cells).style(theme.added),
+                        _ if !row.exceeds(threshold) => Row::new(cells).dim(),
+                        _ => Row::new(cells),
+                    }
+                })
+                .collect();
+            let widths = [
+                Constraint::Percentage(55),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ];
+            let area = f.area();
+            page = (area.height as usize).saturating_sub(3).max(1);
+            let table = Table::new(table_rows, widths)
+                .header(Row::new(vec!["Series", "A", "B", "Δ"]).bold())
+                .block(Block::default().borders(Borders::ALL).title(Line::from(title)))
+                .highlight_style(theme.selected)
+                .highlight_symbol(">> ");
+            f.render_stateful_widget(table, area, &mut state);
+        })?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        let current = state.selected().unwrap_or(0);
+        let last = rows.len().saturating_sub(1);
+        let target = match keys::lookup(Context::Diff, key) {
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Up) => current.saturating_sub(1),
+            Some(Action::Down) => current + 1,
+            Some(Action::HalfPageUp) => current.saturating_sub((page / 2).max(1)),
+            Some(Action::HalfPageDown) => current + (page / 2).max(1),
+            Some(Action::PageUp) => current.saturating_sub(page),
+            Some(Action::PageDown) => current + page,
+            Some(Action::Top) => 0,
+            Some(Action::Bottom) => last,
+            _ => current,
+        };
+        state.select(Some(target.min(last)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrape(text: &str) -> Exposition {
+        model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    #[test]
+    fn aligns_series_ignoring_identity_labels() {
+        let a = scrape("# TYPE requests counter\nrequests{pod=\"a-1\",code=\"200\"} 100\nrequests{pod=\"a-1\",code=\"500\"} 4\n");
+        let b = scrape("# TYPE requests counter\nrequests{pod=\"b-7\",code=\"200\"} 110\nrequests{pod=\"b-7\",code=\"404\"} 1\n");
+        let rows = diff(&a, &b, &[String::from("pod")]);
+        let summary: Vec<(&str, Option<f64>, Option<f64>)> =
+            rows.iter().map(|row| (row.series.as_str(), row.a, row.b)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("requests{code=\"200\"}", Some(100.0), Some(110.0)),
+                ("requests{code=\"404\"}", None, Some(1.0)),
+                ("requests{code=\"500\"}", Some(4.0), None),
+            ]
+        );
+        assert_eq!(rows[0].percent(), Some(10.0));
+    }
+
+    #[test]
+    fn colliding_series_are_summed() {
+        let a = scrape("# TYPE up gauge\nup{instance=\"x\"} 1\nup{instance=\"y\"} 1\n");
+        let b = scrape("# TYPE up gauge\nup{instance=\"z\"} 1\n");
+        let rows = diff(&a, &b, &[String::from("instance")]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].a, rows[0].b), (Some(2.0), Some(1.0)));
+    }
+
+    #[test]
+    fn file_reports_list_added_removed_and_changed_series_and_families() {
+        let before = scrape(concat!(
+            "# HELP requests_total Requests.\n",
+            "# TYPE requests_total counter\n",
+            "requests_total{code=\"200\",path=\"/\"} 100\n",
+            "requests_total{code=\"500\",path=\"/\"} 4\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth 1.0\n",
+            "# TYPE temperature gauge\n",
+            "temperature 20.0\n",
+        ));
+        let after = scrape(concat!(
+            "# HELP requests_total Requests served.\n",
+            "# TYPE requests_total counter\n",
+            "requests_total{path=\"/\",code=\"200\"} 110\n",
+            "requests_total{path=\"/\",code=\"404\"} 1\n",
+            "# TYPE queue_depth untyped\n",
+            "queue_depth 1e0\n",
+            "# TYPE temperature gauge\n",
+            "temperature 20.25\n",
+        ));
+        let names = ("before.txt", "after.txt");
+        let report = FilesReport::new(names, &before, &after, &[], Threshold::Absolute(0.5));
+        assert_eq!(
+            report.text(),
+            concat!(
+                "~ queue_depth TYPE gauge -> untyped\n",
+                "~ requests_total HELP \"Requests.\" -> \"Requests served.\"\n",
+                "- requests_total{code=\"500\", path=\"/\"} 4\n",
+                "+ requests_total{code=\"404\", path=\"/\"} 1\n",
+                "~ requests_total{code=\"200\", path=\"/\"} 100 -> 110 +10 (+10.0%)\n",
+                "before.txt -> after.txt: 1 series added, 1 removed, 1 changed, 2 TYPE or HELP changes\n",
+            )
+        );
+
+        let report = FilesReport::new(names, &before, &after, &[], Threshold::Percent(1.0));
+        let changed: Vec<&str> = report.changed.iter().map(|change| change.series.as_str()).collect();
+        assert_eq!(changed, vec!["requests_total{code=\"200\", path=\"/\"}", "temperature"]);
+        let json: serde_json::Value = serde_json::from_str(&report.json()).unwrap();
+        assert_eq!(json["changed"][1]["delta"], 0.25);
+        assert_eq!(json["families"][0]["field"], "type");
+
+        let same = FilesReport::new(names, &before, &before, &[], Threshold::Absolute(0.0));
+        assert!(same.is_empty());
+        assert_eq!(same.text(), "before.txt and after.txt match\n");
+    }
+
+    #[test]
+    fn threshold_ignores_small_changes() {
+        let row = |a, b| DiffRow {
+            series: String::from("x"),
+            a,
+            b,
+        };
+        assert!(!row(Some(100.0), Some(104.0)).exceeds(5.0));
+        assert!(row(Some(100.0), Some(106.0)).exceeds(5.0));
+        assert!(row(Some(0.0), Some(1.0)).exceeds(1000.0));
+        assert!(!row(Some(f64::NAN), Some(f64::NAN)).exceeds(0.0));
+        assert!(row(Some(1.0), None).exceeds(1000.0));
+        assert!(!row(Some(3.0), Some(3.0)).exceeds(0.0));
+    }
+}
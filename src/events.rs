@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::SystemTime;
+
+use crate::highlight::{Firing, Rule};
+use crate::model::{self, CounterEvent, Decrease, Exposition, SeriesKey};
+
+/// Events kept in the log; the oldest are dropped first.
+const MAX_EVENTS: usize = 1000;
+
+/// What an event is about, for filtering the `E` view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// A note added with `m`.
+    Annotation,
+    /// A counter went backwards, whether it reset or only went down.
+    CounterReset,
+    Appeared,
+    Disappeared,
+    /// A `--highlight` rule started holding for a series.
+    RuleFired,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 5] = [
+        Kind::Annotation,
+        Kind::CounterReset,
+        Kind::Appeared,
+        Kind::Disappeared,
+        Kind::RuleFired,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Annotation => "note",
+            Kind::CounterReset => "counter reset",
+            Kind::Appeared => "appeared",
+            Kind::Disappeared => "disappeared",
+            Kind::RuleFired => "rule fired",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub at: SystemTime,
+    pub kind: Kind,
+    /// The series it's about as `family{labels}`, or `None` for notes taken
+    /// outside the detail view and for changes to many series at once.
+    pub series: Option<String>,
+    pub text: String,
+}
+
+/// What happened while the viewer was open, oldest first.
+#[derive(Default)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+}
+
+impl EventLog {
+    /// The events of a recording up to the scrape being replayed.
+    pub fn recorded(events: &[Event]) -> EventLog {
+        let skip = events.len().saturating_sub(MAX_EVENTS);
+        EventLog {
+            events: events[skip..].iter().cloned().collect(),
+        }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Newest first, only those of `kind` if given.
+    pub fn newest_first(&self, kind: Option<Kind>) -> impl Iterator<Item = &Event> {
+        self.events
+            .iter()
+            .rev()
+            .filter(move |event| kind.is_none_or(|kind| event.kind == kind))
+    }
+
+    /// Newest first, the events attached to `series`.
+    pub fn of_series(&self, series: &str) -> Vec<&Event> {
+        self.events
+            .iter()
+            .rev()
+            .filter(|event| event.series.as_deref() == Some(series))
+            .collect()
+    }
+}
+
+/// A counter going backwards, from what [`model::CounterResets`] found.
+pub fn counter_reset(key: &SeriesKey, event: &CounterEvent) -> Event {
+    let what = match event.decrease {
+        Decrease::Reset => "reset",
+        Decrease::Partial => "went down",
+    };
+    Event {
+        at: event.at,
+        kind: Kind::CounterReset,
+        series: Some(key.to_string()),
+        text: format!(
+            "{what} from {} to {}",
+            model::format_number(event.before),
+            model::format_number(event.after)
+        ),
+    }
+}
+
+/// A `--highlight` rule that holds for a series it didn't hold for before.
+pub fn rule_fired(rule: &Rule, firing: &Firing, at: SystemTime) -> Event {
+    Event {
+        at,
+        kind: Kind::RuleFired,
+        series: Some(firing.key.to_string()),
+        text: format!("{} at {}", rule.text, model::format_number(firing.value)),
+    }
+}
+
+/// Series that appeared or disappeared between two scrapes, by family. A
+/// lone series gets an event of its own; when several of a family come or
+/// go at once, as when a pod is replaced, the family gets one for them all.
+pub fn churn(previous: &Exposition, current: &Exposition, at: SystemTime) -> Vec<Event> {
+    let keys = |exposition: &Exposition| -> HashSet<SeriesKey> {
+        exposition
+            .families
+            .iter()
+            .flat_map(|(name, fam)| fam.iter_samples().map(move |sample| SeriesKey::new(name, sample)))
+            .collect()
+    };
+    let (before, after) = (keys(previous), keys(current));
+    let mut changes: BTreeMap<(&str, bool), Vec<&SeriesKey>> = BTreeMap::new();
+    for key in after.difference(&before) {
+        changes.entry((&key.family, true)).or_default().push(key);
+    }
+    for key in before.difference(&after) {
+        changes.entry((&key.family, false)).or_default().push(key);
+    }
+    changes
+        .into_iter()
+        .map(|((family, appeared), keys)| {
+            let kind = if appeared { Kind::Appeared } else { Kind::Disappeared };
+            match keys.as_slice() {
+                [key] => Event {
+                    at,
+                    kind,
+                    series: Some(key.to_string()),
+                    text: String::new(),
+                },
+                keys => Event {
+                    at,
+                    kind,
+                    series: None,
+                    text: format!("{} series of {family} {}", keys.len(), kind.name()),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn scrape(text: &str) -> Exposition {
+        model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    #[test]
+    fn churn_is_logged_per_series_or_per_family() {
+        let before = scrape(concat!(
+            "# TYPE up gauge\n",
+            "up{pod=\"a-1\"} 1\n",
+            "up{pod=\"a-2\"} 1\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth{queue=\"mail\"} 3\n",
+        ));
+        let after = scrape(concat!(
+            "# TYPE up gauge\n",
+            "up{pod=\"b-1\"} 1\n",
+            "up{pod=\"b-2\"} 1\n",
+            "up{pod=\"b-3\"} 1\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth{queue=\"mail\"} 3\n",
+            "queue_depth{queue=\"sms\"} 0\n",
+        ));
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_432_000);
+        let churn = churn(&before, &after, at);
+        let summary: Vec<(Kind, Option<&str>, &str)> =
+            churn.iter().map(|event| (event.kind, event.series.as_deref(), event.text.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Kind::Appeared, Some("queue_depth{queue=\"sms\"}"), ""),
+                (Kind::Disappeared, None, "2 series of up disappeared"),
+                (Kind::Appeared, None, "3 series of up appeared"),
+            ]
+        );
+        assert!(churn.iter().all(|event| event.at == at));
+    }
+
+    #[test]
+    fn the_log_keeps_the_newest_events() {
+        let mut log = EventLog::default();
+        let at = SystemTime::UNIX_EPOCH;
+        for i in 0..MAX_EVENTS + 2 {
+            let kind = if i % 2 == 0 { Kind::Annotation } else { Kind::Appeared };
+            log.push(Event {
+                at: at + Duration::from_secs(i as u64),
+                kind,
+                series: (kind == Kind::Appeared).then(|| String::from("up")),
+                text: i.to_string(),
+            });
+        }
+        assert_eq!(log.len(), MAX_EVENTS);
+        let newest: Vec<&str> = log.newest_first(None).take(3).map(|event| event.text.as_str()).collect();
+        assert_eq!(newest, vec!["1001", "1000", "999"]);
+        let notes: Vec<&str> = log.newest_first(Some(Kind::Annotation)).take(2).map(|e| e.text.as_str()).collect();
+        assert_eq!(notes, vec!["1000", "998"]);
+        assert_eq!(log.of_series("up").len(), MAX_EVENTS / 2);
+        assert_eq!(log.newest_first(None).last().map(|event| event.text.as_str()), Some("2"));
+    }
+}
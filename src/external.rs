@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::{backend::Backend, Terminal};
+use std::io;
+use std::process::{Command, ExitStatus, Stdio};
+
+/// A program the viewer can hand the raw text to, opened with `o` and `O`.
+#[derive(Clone, Copy)]
+pub enum Program {
+    Pager,
+    Editor,
+}
+
+impl Program {
+    /// `$PAGER`, or `$VISUAL` then `$EDITOR`, split on whitespace so
+    /// `PAGER="less -S"` works; `less` and `vi` when they're unset.
+    fn command(self) -> Vec<String> {
+        let (variables, fallback): (&[&str], &str) = match self {
+            Program::Pager => (&["PAGER"], "less"),
+            Program::Editor => (&["VISUAL", "EDITOR"], "vi"),
+        };
+        variables
+            .iter()
+            .filter_map(|variable| std::env::var(variable).ok())
+            .map(|command| command.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .find(|words| !words.is_empty())
+            .unwrap_or_else(|| vec![fallback.to_string()])
+    }
+}
+
+/// Writes `text` to a temporary file and runs the pager or editor on it in
+/// the foreground, suspending the viewer until it exits. The terminal is
+/// taken back however the program ends, including when it can't be started
+/// or dies from a signal. Mouse capture is restored only if it was on.
+pub fn open<B: Backend>(terminal: &mut Terminal<B>, program: Program, text: &str, mouse: bool) -> Result<()> {
+    let path = std::env::temp_dir().join(format!("promtool-{}.txt", std::process::id()));
+    std::fs::write(&path, text).with_context(|| format!("couldn't write {}", path.display()))?;
+    let words = program.command();
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]).arg(&path);
+
+    let suspended = disable_raw_mode().and_then(|()| execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture));
+    let status = suspended.and_then(|()| run(command));
+    // Best effort: the file is in the temp directory either way.
+    let _ = std::fs::remove_file(&path);
+    let resumed = enable_raw_mode()
+        .and_then(|()| execute!(io::stdout(), EnterAlternateScreen))
+        .and_then(|()| if mouse { execute!(io::stdout(), EnableMouseCapture) } else { Ok(()) });
+    // The alternate screen comes back blank, so everything is redrawn.
+    terminal.clear()?;
+    resumed?;
+
+    let status = status.with_context(|| format!("couldn't run {}", words[0]))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {status}", words[0]);
+    }
+    Ok(())
+}
+
+/// Whether there's likely a browser to open URLs in: on macOS and Windows
+/// always, elsewhere only in a graphical session, which SSH usually isn't.
+pub fn has_browser() -> bool {
+    cfg!(any(target_os = "macos", windows))
+        || ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|variable| std::env::var_os(variable).is_some_and(|display| !display.is_empty()))
+}
+
+/// Opens `url` in the default browser without waiting for it, and with its
+/// output discarded so it can't scribble over the viewer.
+pub fn open_url(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let mut child = Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("couldn't run {opener} to open {url}"))?;
+    // Reaped in the background so it doesn't linger as a zombie.
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Runs the program with the controlling terminal as its stdin, which is a
+/// pipe when the scrape itself came from stdin. Ctrl-C and Ctrl-\ are the
+/// program's to handle while it runs, and whatever terminal mode it leaves
+/// behind after crashing is undone, so a later exit restores the shell's.
+#[cfg(unix)]
+fn run(mut command: Command) -> io::Result<ExitStatus> {
+    use std::os::fd::AsRawFd;
+
+    let tty = std::fs::File::open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    // SAFETY: termios is plain data, and tcgetattr only writes to it.
+    let mut mode: libc::termios = unsafe { std::mem::zeroed() };
+    let saved = unsafe { libc::tcgetattr(fd, &mut mode) } == 0;
+
+    command.stdin(tty.try_clone()?);
+    let mut child = command.spawn()?;
+    // Ignored only once the child exists, since exec keeps ignored signals
+    // ignored, which would leave the program unable to be interrupted.
+    // SAFETY: SIG_IGN and the handlers being put back are valid dispositions.
+    let (interrupt, quit) = unsafe {
+        (
+            libc::signal(libc::SIGINT, libc::SIG_IGN),
+            libc::signal(libc::SIGQUIT, libc::SIG_IGN),
+        )
+    };
+    let status = child.wait();
+    unsafe {
+        libc::signal(libc::SIGINT, interrupt);
+        libc::signal(libc::SIGQUIT, quit);
+        if saved {
+            libc::tcsetattr(fd, libc::TCSANOW, &mode);
+        }
+    }
+    status
+}
+
+#[cfg(not(unix))]
+fn run(mut command: Command) -> io::Result<ExitStatus> {
+    command.status()
+}
@@ -0,0 +1,156 @@
+/// Every matched character.
+const MATCH: i64 = 16;
+/// A match at the start of a word: the name's start or after `_`, `:`, `.`
+/// or `-`, or an uppercase letter after a lowercase one.
+const BOUNDARY: i64 = 10;
+/// On top of `BOUNDARY` for a match on the name's first character.
+const START: i64 = 8;
+/// A match right after the previous one.
+const CONSECUTIVE: i64 = 8;
+/// Skipping characters between two matches costs this, plus one per
+/// skipped character.
+const GAP: i64 = 3;
+
+/// A candidate the query matched, with where its characters matched for
+/// highlighting.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    pub name: &'a str,
+    pub score: i64,
+    /// Char indices into `name`, one per query character.
+    pub positions: Vec<usize>,
+}
+
+fn is_boundary(chars: &[char], index: usize) -> bool {
+    match index.checked_sub(1).map(|previous| chars[previous]) {
+        None => true,
+        Some('_' | ':' | '.' | '-') => true,
+        Some(previous) => previous.is_lowercase() && chars[index].is_uppercase(),
+    }
+}
+
+/// Matches `query` as a case-insensitive subsequence of `name`, like fzf:
+/// of every way to match it, the one scoring best on word starts and runs
+/// of consecutive characters. `None` when some query character is missing.
+pub fn score<'a>(query: &str, name: &'a str) -> Option<Match<'a>> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = name.chars().collect();
+    if query.is_empty() {
+        return Some(Match {
+            name,
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    // best[i][j]: the best score for the first i + 1 query characters with
+    // the last of them matched at j, and where the one before it matched.
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; chars.len()]; query.len()];
+    for (i, wanted) in query.iter().enumerate() {
+        for (j, c) in chars.iter().enumerate() {
+            if !c.to_lowercase().eq(std::iter::once(*wanted)) {
+                continue;
+            }
+            let mut own = MATCH;
+            if is_boundary(&chars, j) {
+                own += BOUNDARY;
+            }
+            if j == 0 {
+                own += START;
+            }
+            if i == 0 {
+                best[i][j] = Some((own, 0));
+                continue;
+            }
+            best[i][j] = (0..j)
+                .filter_map(|k| {
+                    let (previous, _) = best[i - 1][k]?;
+                    let gap = j - k - 1;
+                    let link = if gap == 0 { CONSECUTIVE } else { -(GAP + gap as i64) };
+                    Some((previous + own + link, k))
+                })
+                .max_by_key(|(score, _)| *score);
+        }
+    }
+
+    let last = query.len() - 1;
+    let (end, (score, _)) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| Some((j, (*cell)?)))
+        .max_by_key(|(_, (score, _))| *score)?;
+    let mut positions = vec![end];
+    for i in (1..=last).rev() {
+        let (_, previous) = best[i][positions[0]].expect("every cell on the path was filled");
+        positions.insert(0, previous);
+    }
+    Some(Match {
+        name,
+        score,
+        positions,
+    })
+}
+
+/// The `limit` best matches of `query` among `names`, ties going to the
+/// shorter name and then alphabetically.
+pub fn rank<'a>(query: &str, names: impl IntoIterator<Item = &'a str>, limit: usize) -> Vec<Match<'a>> {
+    let mut matches: Vec<Match> = names.into_iter().filter_map(|name| score(query, name)).collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.name.len().cmp(&b.name.len()))
+            .then_with(|| a.name.cmp(b.name))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &[&str] = &[
+        "go_gc_duration_seconds",
+        "go_goroutines",
+        "go_memstats_alloc_bytes",
+        "http_request_duration_seconds",
+        "http_requests_total",
+        "node_cpu_seconds_total",
+        "node_filesystem_avail_bytes",
+        "node_filesystem_files",
+        "node_filesystem_free_bytes",
+        "node_memory_MemAvailable_bytes",
+        "node_network_receive_bytes_total",
+        "process_resident_memory_bytes",
+        "promhttp_metric_handler_requests_total",
+    ];
+
+    fn top(query: &str) -> Vec<&'static str> {
+        rank(query, CORPUS.iter().copied(), 3).into_iter().map(|m| m.name).collect()
+    }
+
+    #[test]
+    fn word_starts_beat_scattered_matches() {
+        assert_eq!(top("nfab")[0], "node_filesystem_avail_bytes");
+        assert_eq!(top("hrt")[0], "http_requests_total");
+        assert_eq!(top("gor")[0], "go_goroutines");
+        assert_eq!(top("nmma")[0], "node_memory_MemAvailable_bytes");
+        assert_eq!(top("prmb")[0], "process_resident_memory_bytes");
+    }
+
+    #[test]
+    fn matching_ignores_case_and_needs_every_character_in_order() {
+        assert_eq!(
+            top("HTTP"),
+            vec!["http_requests_total", "http_request_duration_seconds", "promhttp_metric_handler_requests_total"]
+        );
+        assert!(top("zzz").is_empty());
+        assert!(score("bn", "node_bytes").is_none());
+    }
+
+    #[test]
+    fn positions_point_at_the_matched_characters() {
+        let found = score("nfab", "node_filesystem_avail_bytes").unwrap();
+        assert_eq!(found.positions, vec![0, 5, 16, 22]);
+    }
+}
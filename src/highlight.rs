@@ -0,0 +1,228 @@
+use openmetrics_parser::PrometheusType;
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::model::{self, Exposition, Family, Series, SeriesKey};
+
+/// How loudly a firing rule colors its rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Yellow, for rules written with a `warn:` prefix.
+    Warning,
+    /// Red.
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Op {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Less => value < threshold,
+            Op::LessOrEqual => value <= threshold,
+            Op::Greater => value > threshold,
+            Op::GreaterOrEqual => value >= threshold,
+            Op::Equal => value == threshold,
+            Op::NotEqual => value != threshold,
+        }
+    }
+}
+
+/// A `--highlight` rule: `[warn:] FAMILY_REGEX OP NUMBER`, or
+/// `rate(FAMILY_REGEX) OP NUMBER` to compare counters' per-second rate
+/// instead of their value. The regex must match the whole family name, as
+/// in PromQL's `=~`.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// The rule as written, for the panel listing firing rules.
+    pub text: String,
+    pub level: Level,
+    family: Regex,
+    rate: bool,
+    op: Op,
+    threshold: f64,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let syntax = Regex::new(r"^\s*(warn:\s*)?(?:rate\((.+)\)|(.+?))\s*(<=|>=|==|!=|<|>)\s*(\S+)\s*$")
+            .expect("the rule syntax is a valid regex");
+        let captures = syntax.captures(text).ok_or_else(|| {
+            format!("expected \"FAMILY_REGEX < NUMBER\" or \"rate(FAMILY_REGEX) > NUMBER\", got {text:?}")
+        })?;
+        let (pattern, rate) = match (captures.get(2), captures.get(3)) {
+            (Some(pattern), _) => (pattern.as_str(), true),
+            (None, Some(pattern)) => (pattern.as_str(), false),
+            (None, None) => unreachable!("one of the alternatives matched"),
+        };
+        let family = Regex::new(&format!("^(?:{})$", pattern.trim())).map_err(|e| e.to_string())?;
+        let op = match &captures[4] {
+            "<" => Op::Less,
+            "<=" => Op::LessOrEqual,
+            ">" => Op::Greater,
+            ">=" => Op::GreaterOrEqual,
+            "==" => Op::Equal,
+            _ => Op::NotEqual,
+        };
+        let threshold: f64 = captures[5]
+            .parse()
+            .map_err(|_| format!("{:?} isn't a number", &captures[5]))?;
+        Ok(Rule {
+            text: text.trim().to_string(),
+            level: if captures.get(1).is_some() {
+                Level::Warning
+            } else {
+                Level::Critical
+            },
+            family,
+            rate,
+            op,
+            threshold,
+        })
+    }
+}
+
+/// A series a rule holds for, with the value it was compared on.
+pub struct Firing {
+    /// Index into the rules that were evaluated.
+    pub rule: usize,
+    pub key: SeriesKey,
+    pub value: f64,
+}
+
+/// The outcome of evaluating every rule against one scrape.
+#[derive(Default)]
+pub struct Highlights {
+    pub firing: Vec<Firing>,
+    families: HashMap<String, Level>,
+    series: HashMap<SeriesKey, Level>,
+}
+
+impl Highlights {
+    /// The loudest rule firing for any series of the family.
+    pub fn family(&self, name: &str) -> Option<Level> {
+        self.families.get(name).copied()
+    }
+
+    pub fn series(&self, key: &SeriesKey) -> Option<Level> {
+        self.series.get(key).copied()
+    }
+}
+
+/// Checks every rule against every series of the families it matches.
+/// `rate` gives a counter series' per-second rate, when there is one yet.
+pub fn evaluate(
+    rules: &[Rule],
+    exposition: &Exposition,
+    rate: impl Fn(&str, &Family, &Series) -> Option<f64>,
+) -> Highlights {
+    let mut highlights = Highlights::default();
+    if rules.is_empty() {
+        return highlights;
+    }
+    let mut names: Vec<&String> = exposition.families.keys().collect();
+    names.sort();
+    for (index, rule) in rules.iter().enumerate() {
+        for name in names.iter().filter(|name| rule.family.is_match(name)) {
+            let fam = &exposition.families[*name];
+            if rule.rate && fam.family_type != PrometheusType::Counter {
+                continue;
+            }
+            for sample in fam.iter_samples() {
+                let value = if rule.rate {
+                    rate(name, fam, sample)
+                } else {
+                    model::scalar_value(&sample.value)
+                };
+                let Some(value) = value.filter(|value| rule.op.holds(*value, rule.threshold)) else {
+                    continue;
+                };
+                let key = SeriesKey::new(name, sample);
+                let family = highlights.families.entry(name.to_string()).or_insert(rule.level);
+                *family = (*family).max(rule.level);
+                let series = highlights.series.entry(key.clone()).or_insert(rule.level);
+                *series = (*series).max(rule.level);
+                highlights.firing.push(Firing {
+                    rule: index,
+                    key,
+                    value,
+                });
+            }
+        }
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_parse_with_optional_rate_and_level() {
+        let rule = Rule::parse("node_filesystem_avail_bytes < 1e9").unwrap();
+        assert_eq!(
+            (rule.level, rule.rate, rule.op, rule.threshold),
+            (Level::Critical, false, Op::Less, 1e9)
+        );
+        assert!(rule.family.is_match("node_filesystem_avail_bytes"));
+        assert!(!rule.family.is_match("node_filesystem_avail_bytes_total"));
+
+        let rule = Rule::parse("warn: rate(http_.*_total)>=100").unwrap();
+        assert_eq!(
+            (rule.level, rule.rate, rule.op),
+            (Level::Warning, true, Op::GreaterOrEqual)
+        );
+        assert!(rule.family.is_match("http_requests_total"));
+
+        assert!(Rule::parse("up").is_err());
+        assert!(Rule::parse("up < a lot").is_err());
+        assert!(Rule::parse("up( < 1").is_err());
+    }
+
+    #[test]
+    fn firing_series_color_their_family_with_the_loudest_rule() {
+        let text = concat!(
+            "# TYPE disk_free_bytes gauge\n",
+            "disk_free_bytes{mount=\"/\"} 5e8\n",
+            "disk_free_bytes{mount=\"/data\"} 5e10\n",
+            "# TYPE errors_total counter\n",
+            "errors_total 10\n",
+        );
+        let exposition = model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let rules = [
+            Rule::parse("warn: disk_free_bytes < 1e11").unwrap(),
+            Rule::parse("disk_free_bytes < 1e9").unwrap(),
+            Rule::parse("rate(errors_total) > 1").unwrap(),
+        ];
+        let highlights = evaluate(&rules, &exposition, |_, _, _| Some(2.5));
+        let firing: Vec<(usize, &str, f64)> = highlights
+            .firing
+            .iter()
+            .map(|firing| (firing.rule, firing.key.labels.as_str(), firing.value))
+            .collect();
+        assert_eq!(
+            firing,
+            vec![
+                (0, "mount=\"/\"", 5e8),
+                (0, "mount=\"/data\"", 5e10),
+                (1, "mount=\"/\"", 5e8),
+                (2, "", 2.5),
+            ]
+        );
+        assert_eq!(highlights.family("disk_free_bytes"), Some(Level::Critical));
+        let data = SeriesKey {
+            family: String::from("disk_free_bytes"),
+            labels: String::from("mount=\"/data\""),
+        };
+        assert_eq!(highlights.series(&data), Some(Level::Warning));
+        assert_eq!(highlights.family("up"), None);
+    }
+}
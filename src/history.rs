@@ -0,0 +1,150 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+use crate::model::{self, Exposition, SeriesKey};
+
+/// How many scrapes `--history` keeps per series by default.
+pub const DEFAULT_CAPACITY: usize = 120;
+
+/// One scrape's value of a series; `None` when the scrape failed or
+/// didn't include the series.
+#[derive(Clone, Copy)]
+pub struct Point {
+    pub at: Instant,
+    pub value: Option<f64>,
+}
+
+/// The last few values of the series worth charting: those of pinned
+/// families and of the family open in the detail view. Everything else is
+/// left alone so large expositions don't cost memory per refresh.
+pub struct History {
+    capacity: usize,
+    series: HashMap<SeriesKey, VecDeque<Point>>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Adds a point for every series of a `tracked` family in `exposition`,
+    /// or `None` for a failed scrape. Series that were tracked but are now
+    /// missing get a gap; buffers of untracked families, and ones holding
+    /// nothing but gaps, are dropped.
+    pub fn record(&mut self, exposition: Option<&Exposition>, tracked: impl Fn(&str) -> bool, at: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.series.retain(|key, _| tracked(&key.family));
+        let mut seen = HashSet::new();
+        for (name, fam) in exposition.into_iter().flat_map(|metrics| &metrics.families) {
+            if !tracked(name) {
+                continue;
+            }
+            for sample in fam.iter_samples() {
+                let Some(value) = model::scalar_value(&sample.value) else {
+                    continue;
+                };
+                let key = SeriesKey::new(name, sample);
+                self.push(&key, Point { at, value: Some(value) });
+                seen.insert(key);
+            }
+        }
+        let missing: Vec<SeriesKey> = self.series.keys().filter(|key| !seen.contains(key)).cloned().collect();
+        for key in missing {
+            self.push(&key, Point { at, value: None });
+        }
+        self.series.retain(|_, points| points.iter().any(|point| point.value.is_some()));
+    }
+
+    fn push(&mut self, key: &SeriesKey, point: Point) {
+        let points = self.series.entry(key.clone()).or_default();
+        if points.len() == self.capacity {
+            points.pop_front();
+        }
+        points.push_back(point);
+    }
+
+    pub fn points(&self, key: &SeriesKey) -> Option<&VecDeque<Point>> {
+        self.series.get(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+}
+
+/// Per-second increase between consecutive points, dated by the later one.
+/// Gaps and counter resets leave a gap in the rates too.
+pub fn rates(points: &VecDeque<Point>) -> Vec<Point> {
+    points
+        .iter()
+        .zip(points.iter().skip(1))
+        .map(|(before, after)| {
+            let elapsed = after.at.saturating_duration_since(before.at).as_secs_f64();
+            let value = match (before.value, after.value) {
+                (Some(before), Some(after)) if after >= before && elapsed > 0.0 => {
+                    Some((after - before) / elapsed).filter(|rate| !rate.is_nan())
+                }
+                _ => None,
+            };
+            Point { at: after.at, value }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn scrape(text: &str) -> Exposition {
+        model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn values(history: &History, family: &str) -> Vec<Option<f64>> {
+        let key = SeriesKey {
+            family: String::from(family),
+            labels: String::new(),
+        };
+        history.points(&key).map_or(Vec::new(), |points| points.iter().map(|p| p.value).collect())
+    }
+
+    #[test]
+    fn tracks_only_requested_families_with_gaps() {
+        let mut history = History::new(3);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let tracked = |name: &str| name == "requests_total";
+        let both = scrape("# TYPE requests_total counter\nrequests_total 10\n# TYPE up gauge\nup 1\n");
+
+        history.record(Some(&both), tracked, at(0));
+        history.record(None, tracked, at(10));
+        history.record(Some(&scrape("# TYPE up gauge\nup 1\n")), tracked, at(20));
+        history.record(Some(&both), tracked, at(30));
+        assert_eq!(values(&history, "requests_total"), vec![None, None, Some(10.0)]);
+        assert!(values(&history, "up").is_empty());
+
+        // Untracking a family drops its buffer at the next scrape.
+        history.record(Some(&both), |_| false, at(40));
+        assert!(values(&history, "requests_total").is_empty());
+    }
+
+    #[test]
+    fn rates_break_at_gaps_and_resets() {
+        let start = Instant::now();
+        let points: VecDeque<Point> = [Some(0.0), Some(50.0), None, Some(80.0), Some(100.0), Some(5.0)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| Point {
+                at: start + Duration::from_secs(10 * i as u64),
+                value,
+            })
+            .collect();
+        let rates: Vec<Option<f64>> = rates(&points).iter().map(|p| p.value).collect();
+        assert_eq!(rates, vec![Some(5.0), None, None, Some(2.0), None]);
+    }
+}
@@ -0,0 +1,216 @@
+use openmetrics_parser::PrometheusValue;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::model;
+
+/// Unix times in seconds that are plausible for a live exporter
+/// (2001-09-09 onwards), so a `_seconds` gauge in this range is shown as a
+/// date rather than as a 30-year duration.
+const EPOCH_RANGE: std::ops::Range<f64> = 1e9..1e10;
+
+const SI: &[(f64, &str)] = &[(1.0, ""), (1e3, "k"), (1e6, "M"), (1e9, "G"), (1e12, "T")];
+const BINARY: &[(f64, &str)] = &[
+    (1.0, " B"),
+    (1024.0, " KiB"),
+    (1048576.0, " MiB"),
+    (1073741824.0, " GiB"),
+    (1099511627776.0, " TiB"),
+];
+const SUBMINUTE: &[(f64, &str)] = &[(1e-9, "ns"), (1e-6, "µs"), (1e-3, "ms"), (1.0, "s")];
+
+/// Formats a sample value for reading rather than copying, going by the
+/// family's name and unit: `_bytes` in binary units, `_seconds` as a
+/// duration or, for timestamps, an RFC 3339 date, and anything else with SI
+/// suffixes. Everything keeps three significant digits.
+pub fn value(value: &PrometheusValue, name: &str, unit: &str) -> String {
+    match value {
+        PrometheusValue::Unknown(n) | PrometheusValue::Gauge(n) => number(n.as_f64(), name, unit),
+        PrometheusValue::Counter(c) => number(c.value.as_f64(), name, unit),
+        PrometheusValue::Histogram(h) => format!(
+            "count={} sum={}",
+            h.count.map_or(String::from("?"), |c| scaled(c as f64, SI)),
+            h.sum.map_or(String::from("?"), |s| number(s.as_f64(), name, unit))
+        ),
+        PrometheusValue::Summary(s) => format!(
+            "count={} sum={}",
+            s.count.map_or(String::from("?"), |c| scaled(c as f64, SI)),
+            s.sum.map_or(String::from("?"), |s| number(s.as_f64(), name, unit))
+        ),
+    }
+}
+
+/// [`value`] for a single number belonging to the series `name`.
+pub fn number(value: f64, name: &str, unit: &str) -> String {
+    if let Some(special) = model::special_value(value) {
+        return special.to_string();
+    }
+    // `_count` and `_bucket` series count observations whatever the unit,
+    // and `_created` is always a timestamp.
+    if name.ends_with("_count") || name.ends_with("_bucket") {
+        return scaled(value, SI);
+    }
+    if name.ends_with("_created") {
+        return timestamp(value).unwrap_or_else(|| scaled(value, SI));
+    }
+    let base = ["_total", "_sum"].iter().find_map(|suffix| name.strip_suffix(suffix)).unwrap_or(name);
+
+    if unit == "bytes" || base.ends_with("_bytes") {
+        scaled(value, BINARY)
+    } else if unit == "seconds" || base.ends_with("_seconds") {
+        if base.ends_with("_timestamp_seconds") || EPOCH_RANGE.contains(&value) {
+            timestamp(value).unwrap_or_else(|| duration(value))
+        } else {
+            duration(value)
+        }
+    } else {
+        scaled(value, SI)
+    }
+}
+
+fn timestamp(seconds: f64) -> Option<String> {
+    if !(0.0..1e11).contains(&seconds) {
+        return None;
+    }
+    let time = UNIX_EPOCH + Duration::from_secs(seconds.round() as u64);
+    Some(humantime::format_rfc3339_seconds(time).to_string())
+}
+
+/// Sub-minute durations with three significant digits (`12.3ms`), longer
+/// ones as their two largest whole units (`2m 5s`, `3d 4h`).
+fn duration(seconds: f64) -> String {
+    if seconds < 0.0 {
+        return format!("-{}", duration(-seconds));
+    }
+    if round_significant(seconds) < 60.0 {
+        return scaled(seconds, SUBMINUTE);
+    }
+    // Past a few centuries a count of days means nothing either.
+    if seconds >= 1e10 {
+        return format!("{}s", model::scientific(seconds, 3));
+    }
+    let total = seconds.round() as u64;
+    let parts = [(total / 86400, "d"), (total / 3600 % 24, "h"), (total / 60 % 60, "m"), (total % 60, "s")];
+    let first = parts.iter().position(|(n, _)| *n > 0).unwrap_or(parts.len() - 1);
+    parts[first..]
+        .iter()
+        .take(2)
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, unit)| format!("{n}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `value` in the largest of `units` (ascending) that keeps it at or above
+/// one, or in the smallest if it's below that already. Values a thousand
+/// times past either end are in scientific notation in the base unit.
+fn scaled(value: f64, units: &[(f64, &str)]) -> String {
+    let base = units.iter().find(|(factor, _)| *factor == 1.0).map_or("", |(_, s)| *s);
+    if value == 0.0 {
+        return format!("0{base}");
+    }
+    let (factor, suffix) = units
+        .iter()
+        .rev()
+        .find(|(factor, _)| round_significant(value.abs() / factor) >= 1.0)
+        .unwrap_or(&units[0]);
+    let scaled = round_significant(value.abs() / factor);
+    let past_largest = *factor == units[units.len() - 1].0 && scaled >= 1e3;
+    let past_smallest = *factor == units[0].0 && scaled < 1e-3;
+    if past_largest || past_smallest {
+        return format!("{}{base}", model::scientific(value, 3));
+    }
+    format!("{}{suffix}", significant(value / factor))
+}
+
+/// Rounded through its decimal form, which unlike scaling by a power of
+/// ten can't overflow for subnormals or values near `f64::MAX`.
+fn round_significant(value: f64) -> f64 {
+    format!("{value:.2e}").parse().unwrap_or(value)
+}
+
+/// Three significant digits with trailing zeros dropped: `1.5`, `12.3`, `700`.
+fn significant(value: f64) -> String {
+    let rounded = round_significant(value);
+    if rounded == 0.0 {
+        return String::from("0");
+    }
+    let decimals = (2 - rounded.abs().log10().floor() as i32).max(0) as usize;
+    let formatted = format!("{rounded:.decimals$}");
+    if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_by_naming_convention() {
+        let cases: &[(&str, &str, f64, &str)] = &[
+            // Plain numbers: exact below 1000, SI suffixes above.
+            ("requests_in_flight", "", 0.0, "0"),
+            ("requests_in_flight", "", 42.0, "42"),
+            ("ratio", "", 0.004512837, "0.00451"),
+            ("ratio", "", 3.14159, "3.14"),
+            ("http_requests_total", "", 999.0, "999"),
+            ("http_requests_total", "", 1234.0, "1.23k"),
+            ("http_requests_total", "", 999_600.0, "1M"),
+            ("http_requests_total", "", 5_250_000_000.0, "5.25G"),
+            ("temperature_delta", "", -12_345.0, "-12.3k"),
+            // Ratios have no unit to scale to.
+            ("cache_hit_ratio", "", 0.953, "0.953"),
+            ("cache_hit_ratio", "", 1.0, "1"),
+            ("error_budget_ratio", "", -0.25, "-0.25"),
+            // Bytes, by suffix or by OpenMetrics unit.
+            ("process_resident_memory_bytes", "", 0.0, "0 B"),
+            ("process_resident_memory_bytes", "", 512.0, "512 B"),
+            ("process_resident_memory_bytes", "", 1536.0, "1.5 KiB"),
+            ("process_resident_memory_bytes", "", 734_003_200.0, "700 MiB"),
+            ("node_network_receive_bytes_total", "", 3.5 * 1073741824.0, "3.5 GiB"),
+            ("response_size_bytes_sum", "", 1_048_576.0, "1 MiB"),
+            ("memory_delta_bytes", "", -1536.0, "-1.5 KiB"),
+            ("memory", "bytes", 2048.0, "2 KiB"),
+            // Durations.
+            ("request_duration_seconds", "", 0.0, "0s"),
+            ("request_duration_seconds", "", 0.0000005, "500ns"),
+            ("request_duration_seconds", "", 0.0123, "12.3ms"),
+            ("request_duration_seconds_sum", "", 2.5, "2.5s"),
+            ("process_cpu_seconds_total", "", 125.0, "2m 5s"),
+            ("uptime_seconds", "", 3600.0, "1h"),
+            ("uptime_seconds", "", 3.0 * 86400.0 + 4.0 * 3600.0 + 59.0, "3d 4h"),
+            ("latency", "seconds", 0.25, "250ms"),
+            ("clock_skew_seconds", "", -0.5, "-500ms"),
+            ("clock_skew_seconds", "", -125.0, "-2m 5s"),
+            // Counts of observations are never in the family's unit.
+            ("request_duration_seconds_count", "", 1500.0, "1.5k"),
+            ("response_size_bytes_bucket", "", 2500.0, "2.5k"),
+            // Timestamps, by name or by looking like one.
+            ("process_start_time_seconds", "", 1.719432e9, "2024-06-26T20:00:00Z"),
+            ("last_success_timestamp_seconds", "", 1_700_000_000.6, "2023-11-14T22:13:21Z"),
+            ("http_requests_created", "", 1.719432e9, "2024-06-26T20:00:00Z"),
+            ("cert_expiry_seconds", "", 2e9, "2033-05-18T03:33:20Z"),
+            // NaN and the infinities, whatever the unit.
+            ("ratio", "", f64::NAN, "NaN"),
+            ("ratio", "", f64::INFINITY, "∞"),
+            ("request_duration_seconds", "", f64::NEG_INFINITY, "-∞"),
+            ("process_resident_memory_bytes", "", f64::INFINITY, "∞"),
+            ("memory_delta_bytes", "", f64::NEG_INFINITY, "-∞"),
+            ("request_duration_seconds", "", f64::NAN, "NaN"),
+            // Past the ends of the suffixes, scientific in the base unit.
+            ("http_requests_total", "", 1.5e15, "1.5e15"),
+            ("http_requests_total", "", -2.5e21, "-2.5e21"),
+            ("ratio", "", 0.0000123, "1.23e-5"),
+            ("ratio", "", f64::MAX, "1.8e308"),
+            ("ratio", "", 5e-324, "4.94e-324"),
+            ("process_resident_memory_bytes", "", 2e18, "2e18 B"),
+            ("request_duration_seconds", "", 1e-13, "1e-13s"),
+            ("uptime_seconds", "", 1e20, "1e20s"),
+        ];
+        for (name, unit, value, expected) in cases {
+            assert_eq!(number(*value, name, unit), *expected, "{name} {unit} {value}");
+        }
+    }
+}
@@ -0,0 +1,908 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A key plus whether Ctrl is held. Shift is part of the character itself
+/// (`G` vs `g`), so it's not tracked separately.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+const fn key(code: KeyCode) -> Key {
+    Key { code, ctrl: false }
+}
+
+const fn plain(c: char) -> Key {
+    key(KeyCode::Char(c))
+}
+
+const fn ctrl(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: true,
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Key {
+        Key {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+}
+
+/// Which screen a keybinding applies in.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Context {
+    Table,
+    Detail,
+    /// The raw text of a scrape that failed to parse.
+    ParseError,
+    Cardinality,
+    /// Naming convention findings for the current scrape.
+    Lint,
+    /// The fastest-changing series.
+    Movers,
+    /// The raw text of the current scrape, or one family of it.
+    Raw,
+    /// The files of a `--textfile-dir`.
+    Textfiles,
+    /// The event log.
+    Events,
+    /// `promtool diff`, which only scrolls.
+    Diff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    Back,
+    Refresh,
+    ShowKeybindings,
+    ShowExchange,
+    Up,
+    Down,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    StartSearch,
+    FindFamily,
+    ScrollLeft,
+    ScrollRight,
+    ClearFilter,
+    OpenDetail,
+    ToggleHelpFooter,
+    ToggleRates,
+    SetBaseline,
+    ToggleBaseline,
+    ToggleHighlight,
+    CycleSort,
+    ReverseSort,
+    ShowParseError,
+    ToggleRawText,
+    ShowRaw,
+    ShowRawPayload,
+    OpenInPager,
+    OpenInEditor,
+    NextMatch,
+    PreviousMatch,
+    ExportCsv,
+    CopyName,
+    CopySelector,
+    CopyTraceId,
+    OpenTrace,
+    OpenGraph,
+    ToggleCardinality,
+    ToggleLint,
+    ToggleMovers,
+    ToggleTextfiles,
+    ToggleEvents,
+    AddNote,
+    PickColumns,
+    JumpToFamily,
+    ToggleDisappeared,
+    HideZero,
+    HideUnchanged,
+    ForgetState,
+    TogglePin,
+    ClearPins,
+    Expand,
+    Collapse,
+    CycleTypeFilter,
+    CycleTheme,
+    ToggleMouse,
+    ToggleRawValues,
+    OpenPrompt,
+    /// Enter in the `:` prompt, for `run_app` to open the endpoint typed.
+    OpenEndpoint,
+    PreviousScrape,
+    NextScrape,
+    TogglePause,
+    FasterPlayback,
+    SlowerPlayback,
+    JumpToTime,
+    NextTab,
+    PreviousTab,
+    /// The digit pressed picks the tab.
+    SelectTab,
+    CycleAggregation,
+    ReloadTargets,
+    PickGroupLabel,
+    DropLabels,
+    CycleDetailAggregation,
+    ShowLabelCardinality,
+}
+
+pub struct Keybinding {
+    pub keys: &'static [Key],
+    /// How the keys are spelled in the `?` overlay.
+    pub label: &'static str,
+    pub group: &'static str,
+    pub contexts: &'static [Context],
+    pub description: &'static str,
+    pub action: Action,
+}
+
+const ALL: &[Context] = &[
+    Context::Table,
+    Context::Detail,
+    Context::ParseError,
+    Context::Cardinality,
+    Context::Lint,
+    Context::Movers,
+    Context::Raw,
+    Context::Textfiles,
+    Context::Events,
+];
+const NAVIGATION: &[Context] = &[
+    Context::Table,
+    Context::Detail,
+    Context::ParseError,
+    Context::Cardinality,
+    Context::Lint,
+    Context::Movers,
+    Context::Raw,
+    Context::Textfiles,
+    Context::Events,
+    Context::Diff,
+];
+const BOTH: &[Context] = &[Context::Table, Context::Detail];
+const TABLE: &[Context] = &[Context::Table];
+const DETAIL: &[Context] = &[Context::Detail];
+const SUBVIEWS: &[Context] = &[
+    Context::Detail,
+    Context::ParseError,
+    Context::Cardinality,
+    Context::Lint,
+    Context::Movers,
+    Context::Raw,
+    Context::Textfiles,
+    Context::Events,
+];
+const PARSE_ERROR: &[Context] = &[Context::ParseError];
+const FAMILY_LISTS: &[Context] = &[Context::Cardinality, Context::Lint];
+const QUIT: &[Context] = &[Context::Table, Context::Diff];
+const TABLE_AND_CARDINALITY: &[Context] = &[Context::Table, Context::Cardinality];
+const TABLE_AND_LINT: &[Context] = &[Context::Table, Context::Lint];
+const MOVERS: &[Context] = &[Context::Movers];
+const TABLE_AND_MOVERS: &[Context] = &[Context::Table, Context::Movers];
+const TABLE_AND_TEXTFILES: &[Context] = &[Context::Table, Context::Textfiles];
+const EVENTS: &[Context] = &[Context::Events];
+const TABLE_AND_EVENTS: &[Context] = &[Context::Table, Context::Events];
+const RAW: &[Context] = &[Context::Raw];
+const RAW_TEXT: &[Context] = &[Context::Table, Context::Detail, Context::ParseError, Context::Raw];
+
+/// Every keybinding outside of text prompts. Key dispatch and the `?`
+/// overlay are both driven from this table so they can't disagree.
+pub const KEYBINDINGS: &[Keybinding] = &[
+    Keybinding {
+        keys: &[key(KeyCode::Up), plain('k')],
+        label: "↑/k",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Move up",
+        action: Action::Up,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Down), plain('j')],
+        label: "↓/j",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Move down",
+        action: Action::Down,
+    },
+    Keybinding {
+        keys: &[ctrl('u')],
+        label: "Ctrl-u",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Half page up",
+        action: Action::HalfPageUp,
+    },
+    Keybinding {
+        keys: &[ctrl('d')],
+        label: "Ctrl-d",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Half page down",
+        action: Action::HalfPageDown,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::PageUp), ctrl('b')],
+        label: "PgUp/Ctrl-b",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Page up",
+        action: Action::PageUp,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::PageDown), ctrl('f')],
+        label: "PgDn/Ctrl-f",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Page down",
+        action: Action::PageDown,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Home), plain('g')],
+        label: "Home/g",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Jump to top",
+        action: Action::Top,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::End), plain('G')],
+        label: "End/G",
+        group: "Navigation",
+        contexts: NAVIGATION,
+        description: "Jump to bottom",
+        action: Action::Bottom,
+    },
+    Keybinding {
+        keys: &[plain('/')],
+        label: "/",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Filter families by name, or with label: by label names and values",
+        action: Action::StartSearch,
+    },
+    Keybinding {
+        keys: &[plain('f'), ctrl('p')],
+        label: "f / Ctrl-p",
+        group: "Filtering",
+        contexts: BOTH,
+        description: "Jump to a family by fuzzy name",
+        action: Action::FindFamily,
+    },
+    Keybinding {
+        keys: &[plain('/')],
+        label: "/",
+        group: "Filtering",
+        contexts: RAW,
+        description: "Search the raw text",
+        action: Action::StartSearch,
+    },
+    Keybinding {
+        keys: &[plain('n')],
+        label: "n",
+        group: "Filtering",
+        contexts: RAW,
+        description: "Next match",
+        action: Action::NextMatch,
+    },
+    Keybinding {
+        keys: &[plain('N')],
+        label: "N",
+        group: "Filtering",
+        contexts: RAW,
+        description: "Previous match",
+        action: Action::PreviousMatch,
+    },
+    Keybinding {
+        keys: &[plain(':')],
+        label: ":",
+        group: "Filtering",
+        contexts: BOTH,
+        description: "Open another endpoint, or edit the PromQL of promtool query",
+        action: Action::OpenPrompt,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Esc)],
+        label: "Esc",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Clear the filter",
+        action: Action::ClearFilter,
+    },
+    Keybinding {
+        keys: &[plain('t')],
+        label: "t",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Cycle the type filter",
+        action: Action::CycleTypeFilter,
+    },
+    Keybinding {
+        keys: &[plain('t')],
+        label: "t",
+        group: "Filtering",
+        contexts: EVENTS,
+        description: "Cycle which kind of event is listed",
+        action: Action::CycleTypeFilter,
+    },
+    Keybinding {
+        keys: &[plain('s')],
+        label: "s",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Cycle sort column",
+        action: Action::CycleSort,
+    },
+    Keybinding {
+        keys: &[plain('S')],
+        label: "S",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Reverse sort direction",
+        action: Action::ReverseSort,
+    },
+    Keybinding {
+        keys: &[plain('p')],
+        label: "p",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Pin or unpin the family to the top",
+        action: Action::TogglePin,
+    },
+    Keybinding {
+        keys: &[plain('P')],
+        label: "P",
+        group: "Filtering",
+        contexts: TABLE,
+        description: "Clear all pins",
+        action: Action::ClearPins,
+    },
+    Keybinding {
+        keys: &[plain('D')],
+        label: "D",
+        group: "Filtering",
+        contexts: BOTH,
+        description: "Show or hide disappeared series",
+        action: Action::ToggleDisappeared,
+    },
+    Keybinding {
+        keys: &[plain('z')],
+        label: "z",
+        group: "Filtering",
+        contexts: BOTH,
+        description: "Hide or show series that are exactly zero",
+        action: Action::HideZero,
+    },
+    Keybinding {
+        keys: &[plain('Z')],
+        label: "Z",
+        group: "Filtering",
+        contexts: BOTH,
+        description: "Hide or show series that haven't changed in --unchanged-after refreshes",
+        action: Action::HideUnchanged,
+    },
+    Keybinding {
+        keys: &[plain('r')],
+        label: "r",
+        group: "Refresh",
+        contexts: ALL,
+        description: "Refresh now",
+        action: Action::Refresh,
+    },
+    Keybinding {
+        keys: &[plain('R')],
+        label: "R",
+        group: "Refresh",
+        contexts: BOTH,
+        description: "Toggle rates instead of values, in the table and counter charts",
+        action: Action::ToggleRates,
+    },
+    Keybinding {
+        keys: &[plain('B')],
+        label: "B",
+        group: "Refresh",
+        contexts: BOTH,
+        description: "Take the current values as a baseline, or take it again",
+        action: Action::SetBaseline,
+    },
+    Keybinding {
+        keys: &[plain('b')],
+        label: "b",
+        group: "Refresh",
+        contexts: BOTH,
+        description: "Show or hide the change since the baseline",
+        action: Action::ToggleBaseline,
+    },
+    Keybinding {
+        keys: &[plain(' ')],
+        label: "Space",
+        group: "Refresh",
+        contexts: ALL,
+        description: "Freeze the display while refreshes go on; in a replay, play or pause at the recorded cadence",
+        action: Action::TogglePause,
+    },
+    Keybinding {
+        keys: &[plain('u')],
+        label: "u",
+        group: "Refresh",
+        contexts: BOTH,
+        description: "Toggle exact or humanized values",
+        action: Action::ToggleRawValues,
+    },
+    Keybinding {
+        keys: &[plain('c')],
+        label: "c",
+        group: "Refresh",
+        contexts: BOTH,
+        description: "Toggle change highlighting",
+        action: Action::ToggleHighlight,
+    },
+    Keybinding {
+        keys: &[plain('[')],
+        label: "[",
+        group: "Replay",
+        contexts: ALL,
+        description: "Previous recorded scrape",
+        action: Action::PreviousScrape,
+    },
+    Keybinding {
+        keys: &[plain(']')],
+        label: "]",
+        group: "Replay",
+        contexts: ALL,
+        description: "Next recorded scrape",
+        action: Action::NextScrape,
+    },
+    Keybinding {
+        keys: &[plain('+')],
+        label: "+",
+        group: "Replay",
+        contexts: ALL,
+        description: "Play twice as fast",
+        action: Action::FasterPlayback,
+    },
+    Keybinding {
+        keys: &[plain('-')],
+        label: "-",
+        group: "Replay",
+        contexts: ALL,
+        description: "Play half as fast",
+        action: Action::SlowerPlayback,
+    },
+    Keybinding {
+        keys: &[plain('@')],
+        label: "@",
+        group: "Replay",
+        contexts: ALL,
+        description: "Jump to a time",
+        action: Action::JumpToTime,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Right)],
+        label: "→",
+        group: "Navigation",
+        contexts: TABLE,
+        description: "Expand the group",
+        action: Action::Expand,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Left)],
+        label: "←",
+        group: "Navigation",
+        contexts: TABLE,
+        description: "Collapse the group",
+        action: Action::Collapse,
+    },
+    Keybinding {
+        keys: &[plain('<')],
+        label: "<",
+        group: "Navigation",
+        contexts: BOTH,
+        description: "Scroll names and labels left",
+        action: Action::ScrollLeft,
+    },
+    Keybinding {
+        keys: &[plain('>')],
+        label: ">",
+        group: "Navigation",
+        contexts: BOTH,
+        description: "Scroll names and labels right",
+        action: Action::ScrollRight,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Left)],
+        label: "←",
+        group: "Navigation",
+        contexts: DETAIL,
+        description: "Scroll labels left",
+        action: Action::ScrollLeft,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Right)],
+        label: "→",
+        group: "Navigation",
+        contexts: DETAIL,
+        description: "Scroll labels right",
+        action: Action::ScrollRight,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Enter)],
+        label: "Enter",
+        group: "Views",
+        contexts: TABLE,
+        description: "Open family detail, or expand/collapse a group",
+        action: Action::OpenDetail,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Enter)],
+        label: "Enter",
+        group: "Views",
+        contexts: FAMILY_LISTS,
+        description: "Jump to the family in the table",
+        action: Action::JumpToFamily,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Enter)],
+        label: "Enter",
+        group: "Views",
+        contexts: MOVERS,
+        description: "Open the series in the family detail",
+        action: Action::OpenDetail,
+    },
+    Keybinding {
+        keys: &[plain('C')],
+        label: "C",
+        group: "Views",
+        contexts: TABLE_AND_CARDINALITY,
+        description: "Toggle the cardinality view",
+        action: Action::ToggleCardinality,
+    },
+    Keybinding {
+        keys: &[plain('L')],
+        label: "L",
+        group: "Views",
+        contexts: TABLE_AND_LINT,
+        description: "Toggle the naming lint view",
+        action: Action::ToggleLint,
+    },
+    // `T` cycles the theme, so the fastest-changing series are on H, for "hot".
+    Keybinding {
+        keys: &[plain('H')],
+        label: "H",
+        group: "Views",
+        contexts: TABLE_AND_MOVERS,
+        description: "Toggle the fastest-changing series",
+        action: Action::ToggleMovers,
+    },
+    Keybinding {
+        keys: &[plain('I')],
+        label: "I",
+        group: "Views",
+        contexts: TABLE_AND_TEXTFILES,
+        description: "Toggle the --textfile-dir files, with their age and parse errors",
+        action: Action::ToggleTextfiles,
+    },
+    Keybinding {
+        keys: &[plain('E')],
+        label: "E",
+        group: "Views",
+        contexts: TABLE_AND_EVENTS,
+        description: "Toggle the event log: notes, counter resets, series coming and going, rules firing",
+        action: Action::ToggleEvents,
+    },
+    Keybinding {
+        keys: &[plain('m')],
+        label: "m",
+        group: "Views",
+        contexts: ALL,
+        description: "Add a note to the event log, on the selected series in the detail view",
+        action: Action::AddNote,
+    },
+    Keybinding {
+        keys: &[plain('|')],
+        label: "|",
+        group: "Views",
+        contexts: TABLE,
+        description: "Pick and reorder the table's columns",
+        action: Action::PickColumns,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Esc), plain('q')],
+        label: "Esc/q",
+        group: "Views",
+        contexts: SUBVIEWS,
+        description: "Back to the table",
+        action: Action::Back,
+    },
+    Keybinding {
+        keys: &[plain('e')],
+        label: "e",
+        group: "Views",
+        contexts: TABLE,
+        description: "Inspect parse errors or skipped lines",
+        action: Action::ShowParseError,
+    },
+    Keybinding {
+        keys: &[plain('v')],
+        label: "v",
+        group: "Views",
+        contexts: PARSE_ERROR,
+        description: "Toggle between the error and the raw text",
+        action: Action::ToggleRawText,
+    },
+    Keybinding {
+        keys: &[plain('v')],
+        label: "v",
+        group: "Views",
+        contexts: BOTH,
+        description: "Show the family's lines as exposed",
+        action: Action::ShowRaw,
+    },
+    Keybinding {
+        keys: &[plain('V')],
+        label: "V",
+        group: "Views",
+        contexts: BOTH,
+        description: "Show the whole response at the family",
+        action: Action::ShowRawPayload,
+    },
+    Keybinding {
+        keys: &[plain('o')],
+        label: "o",
+        group: "Views",
+        contexts: RAW_TEXT,
+        description: "Open the raw text in $PAGER",
+        action: Action::OpenInPager,
+    },
+    Keybinding {
+        keys: &[plain('O')],
+        label: "O",
+        group: "Views",
+        contexts: RAW_TEXT,
+        description: "Open the raw text in $EDITOR",
+        action: Action::OpenInEditor,
+    },
+    Keybinding {
+        keys: &[ctrl('e')],
+        label: "Ctrl-e",
+        group: "Views",
+        contexts: BOTH,
+        description: "Export visible series to CSV",
+        action: Action::ExportCsv,
+    },
+    Keybinding {
+        keys: &[plain('y')],
+        label: "y",
+        group: "Views",
+        contexts: BOTH,
+        description: "Copy the family name",
+        action: Action::CopyName,
+    },
+    Keybinding {
+        keys: &[plain('L')],
+        label: "L",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Group series by a label",
+        action: Action::PickGroupLabel,
+    },
+    Keybinding {
+        keys: &[plain('w')],
+        label: "w",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Aggregate labels away, like sum without(...)",
+        action: Action::DropLabels,
+    },
+    Keybinding {
+        keys: &[plain('A')],
+        label: "A",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Cycle sum/avg/min/max of the aggregation",
+        action: Action::CycleDetailAggregation,
+    },
+    Keybinding {
+        keys: &[plain('C')],
+        label: "C",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Break the series count down by label; y copies a rule dropping one",
+        action: Action::ShowLabelCardinality,
+    },
+    Keybinding {
+        keys: &[plain('Y')],
+        label: "Y",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Copy a PromQL selector for the series",
+        action: Action::CopySelector,
+    },
+    Keybinding {
+        keys: &[plain('x')],
+        label: "x",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Copy the trace ID of the series' newest exemplar",
+        action: Action::CopyTraceId,
+    },
+    Keybinding {
+        keys: &[plain('X')],
+        label: "X",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Open that trace with --trace-url-template",
+        action: Action::OpenTrace,
+    },
+    Keybinding {
+        keys: &[plain('W')],
+        label: "W",
+        group: "Views",
+        contexts: DETAIL,
+        description: "Graph the series in --prometheus-url, or Grafana's Explore with --grafana-url",
+        action: Action::OpenGraph,
+    },
+    Keybinding {
+        keys: &[plain('h')],
+        label: "h",
+        group: "Views",
+        contexts: TABLE,
+        description: "Toggle HELP footer",
+        action: Action::ToggleHelpFooter,
+    },
+    Keybinding {
+        keys: &[plain('T')],
+        label: "T",
+        group: "Views",
+        contexts: ALL,
+        description: "Cycle the color theme",
+        action: Action::CycleTheme,
+    },
+    Keybinding {
+        keys: &[plain('U')],
+        label: "U",
+        group: "Views",
+        contexts: BOTH,
+        description: "Forget the pins, filter, sort and theme saved for this endpoint",
+        action: Action::ForgetState,
+    },
+    Keybinding {
+        keys: &[plain('M')],
+        label: "M",
+        group: "Views",
+        contexts: ALL,
+        description: "Toggle mouse capture, or the terminal's own text selection",
+        action: Action::ToggleMouse,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::Tab)],
+        label: "Tab",
+        group: "Views",
+        contexts: ALL,
+        description: "Next endpoint",
+        action: Action::NextTab,
+    },
+    Keybinding {
+        keys: &[key(KeyCode::BackTab)],
+        label: "S-Tab",
+        group: "Views",
+        contexts: ALL,
+        description: "Previous endpoint",
+        action: Action::PreviousTab,
+    },
+    Keybinding {
+        keys: &[
+            plain('1'),
+            plain('2'),
+            plain('3'),
+            plain('4'),
+            plain('5'),
+            plain('6'),
+            plain('7'),
+            plain('8'),
+            plain('9'),
+        ],
+        label: "1-9",
+        group: "Views",
+        contexts: ALL,
+        description: "Endpoint by number",
+        action: Action::SelectTab,
+    },
+    Keybinding {
+        keys: &[plain('F')],
+        label: "F",
+        group: "Refresh",
+        contexts: ALL,
+        description: "Re-read --targets-file, adding and removing tabs",
+        action: Action::ReloadTargets,
+    },
+    Keybinding {
+        keys: &[plain('a')],
+        label: "a",
+        group: "Views",
+        contexts: BOTH,
+        description: "Cycle how the ALL tab combines endpoints (per endpoint, sum, avg, min, max)",
+        action: Action::CycleAggregation,
+    },
+    Keybinding {
+        keys: &[plain('d')],
+        label: "d",
+        group: "Views",
+        contexts: ALL,
+        description: "Show the last request and response: URL, redirects, headers and timings",
+        action: Action::ShowExchange,
+    },
+    Keybinding {
+        keys: &[plain('?')],
+        label: "?",
+        group: "Views",
+        contexts: ALL,
+        description: "Show this help",
+        action: Action::ShowKeybindings,
+    },
+    Keybinding {
+        keys: &[plain('q')],
+        label: "q",
+        group: "Views",
+        contexts: QUIT,
+        description: "Quit",
+        action: Action::Quit,
+    },
+];
+
+pub const GROUPS: &[&str] = &["Navigation", "Filtering", "Refresh", "Replay", "Views"];
+
+pub fn lookup(context: Context, event: KeyEvent) -> Option<Action> {
+    let key = Key::from(event);
+    KEYBINDINGS
+        .iter()
+        .find(|binding| binding.contexts.contains(&context) && binding.keys.contains(&key))
+        .map(|binding| binding.action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_two_bindings_share_a_key_in_the_same_view() {
+        for (index, first) in KEYBINDINGS.iter().enumerate() {
+            for second in &KEYBINDINGS[index + 1..] {
+                let shares_key = first.keys.iter().any(|key| second.keys.contains(key));
+                let shares_context = first.contexts.iter().any(|context| second.contexts.contains(context));
+                assert!(
+                    !(shares_key && shares_context),
+                    "{} and {} share a key in the same view: {:?} and {:?}",
+                    first.label,
+                    second.label,
+                    first.description,
+                    second.description
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keys_are_looked_up_with_their_modifiers_in_their_view() {
+        let press = |code, modifiers| KeyEvent::new(code, modifiers);
+        let table = |code| lookup(Context::Table, press(code, KeyModifiers::NONE));
+        assert_eq!(table(KeyCode::Char('Z')), Some(Action::HideUnchanged));
+        assert_eq!(table(KeyCode::Char('U')), Some(Action::ForgetState));
+        assert_eq!(table(KeyCode::Char('p')), Some(Action::TogglePin));
+        assert_eq!(
+            lookup(Context::Table, press(KeyCode::Char('p'), KeyModifiers::CONTROL)),
+            Some(Action::FindFamily)
+        );
+        assert_eq!(lookup(Context::Detail, press(KeyCode::Char('p'), KeyModifiers::NONE)), None);
+    }
+}
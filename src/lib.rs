@@ -0,0 +1,39 @@
+//! The viewer and its non-interactive modes. The binary only parses the
+//! arguments and hands them to [`commands::run`].
+
+pub mod app;
+pub mod bench;
+pub mod check;
+pub mod cli;
+pub mod columns;
+pub mod commands;
+pub mod config;
+pub mod dashboard;
+pub mod diff;
+pub mod events;
+pub mod external;
+pub mod fetch;
+pub mod fuzzy;
+pub mod highlight;
+pub mod history;
+pub mod humanize;
+pub mod keys;
+pub mod lint;
+pub mod logging;
+pub mod metadata;
+pub mod model;
+pub mod natural;
+pub mod output;
+pub mod query;
+pub mod record;
+pub mod remote_write;
+pub mod replay;
+pub mod search;
+pub mod sniff;
+pub mod state;
+pub mod targets;
+pub mod terminal;
+pub mod textfile;
+pub mod theme;
+pub mod ui;
+pub mod verify;
@@ -0,0 +1,507 @@
+use openmetrics_parser::{PrometheusType, PrometheusValue};
+use std::collections::HashMap;
+
+use crate::model::{self, Exposition, Family};
+
+/// `promtool lint`: check a scrape against the Prometheus naming
+/// conventions, for CI.
+#[derive(clap::Args, Debug, Clone)]
+pub struct LintArgs {
+    /// The endpoint, saved scrape, or `-` for stdin to lint
+    #[arg(value_name = "ENDPOINT")]
+    pub endpoint: String,
+
+    /// Exit with 1 when there are more findings than this
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub max_warnings: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Unconventional, but queries still work.
+    Warning,
+    /// Breaks or confuses queries, e.g. two families fighting over one name.
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// One convention a family breaks.
+pub struct Finding {
+    pub severity: Severity,
+    pub family: String,
+    /// The rule's name, e.g. `counter-total`.
+    pub rule: &'static str,
+    pub message: String,
+}
+
+/// What a rule gets to look at: the family, and the rest of the scrape for
+/// rules about how families relate.
+struct Scope<'a> {
+    exposition: &'a Exposition,
+    openmetrics: bool,
+    name: &'a str,
+    fam: &'a Family,
+}
+
+/// A check over one family, reporting at most one problem with it.
+struct Rule {
+    name: &'static str,
+    check: fn(&Scope) -> Option<(Severity, String)>,
+}
+
+/// Every rule, in the order their findings are listed for a family. Adding
+/// one is a function and an entry here.
+const RULES: &[Rule] = &[
+    Rule {
+        name: "counter-total",
+        check: counter_total,
+    },
+    Rule {
+        name: "total-on-non-counter",
+        check: total_on_non_counter,
+    },
+    Rule {
+        name: "base-units",
+        check: base_units,
+    },
+    Rule {
+        name: "suffix-collision",
+        check: suffix_collision,
+    },
+    Rule {
+        name: "label-names",
+        check: label_names,
+    },
+    Rule {
+        name: "bucket-order",
+        check: bucket_order,
+    },
+    Rule {
+        name: "missing-help",
+        check: missing_help,
+    },
+    Rule {
+        name: "explicit-timestamps",
+        check: explicit_timestamps,
+    },
+];
+
+/// Runs every rule over every family. Errors come first, then by family.
+pub fn lint(exposition: &Exposition, openmetrics: bool) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    for (name, fam) in &exposition.families {
+        let scope = Scope {
+            exposition,
+            openmetrics,
+            name,
+            fam,
+        };
+        for rule in RULES {
+            if let Some((severity, message)) = (rule.check)(&scope) {
+                findings.push(Finding {
+                    severity,
+                    family: name.clone(),
+                    rule: rule.name,
+                    message,
+                });
+            }
+        }
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.family.cmp(&b.family)));
+    findings
+}
+
+/// OpenMetrics adds `_total` to counter samples itself, so this only applies
+/// to the Prometheus text format.
+fn counter_total(scope: &Scope) -> Option<(Severity, String)> {
+    if scope.openmetrics || scope.fam.family_type != PrometheusType::Counter || scope.name.ends_with("_total") {
+        return None;
+    }
+    Some((
+        Severity::Warning,
+        format!("counters should end in _total, e.g. {}_total", scope.name),
+    ))
+}
+
+fn total_on_non_counter(scope: &Scope) -> Option<(Severity, String)> {
+    if scope.fam.family_type == PrometheusType::Counter || !scope.name.ends_with("_total") {
+        return None;
+    }
+    let kind = model::FamilyType::of(scope.fam).name();
+    Some((
+        Severity::Warning,
+        format!("_total is for counters, but this is a {kind}"),
+    ))
+}
+
+/// Scaled units and the base unit Prometheus wants instead.
+const SCALED_UNITS: &[(&str, &str)] = &[
+    ("nanoseconds", "seconds"),
+    ("microseconds", "seconds"),
+    ("milliseconds", "seconds"),
+    ("minutes", "seconds"),
+    ("hours", "seconds"),
+    ("days", "seconds"),
+    ("kilobytes", "bytes"),
+    ("megabytes", "bytes"),
+    ("gigabytes", "bytes"),
+    ("kibibytes", "bytes"),
+    ("mebibytes", "bytes"),
+    ("gibibytes", "bytes"),
+    ("percent", "ratio"),
+    ("fahrenheit", "celsius"),
+];
+
+fn base_units(scope: &Scope) -> Option<(Severity, String)> {
+    let (unit, base) = scope
+        .name
+        .split('_')
+        .find_map(|word| SCALED_UNITS.iter().find(|(unit, _)| *unit == word))?;
+    Some((Severity::Warning, format!("use base units: {base} rather than {unit}")))
+}
+
+/// A `foo_count`, `foo_sum` or `foo_bucket` family next to a histogram or
+/// summary `foo` exposes the same series names twice.
+fn suffix_collision(scope: &Scope) -> Option<(Severity, String)> {
+    let (base, suffix) = ["_count", "_sum", "_bucket"]
+        .iter()
+        .find_map(|suffix| Some((scope.name.strip_suffix(suffix)?, suffix)))?;
+    let owner = scope.exposition.families.get(base)?;
+    if !matches!(owner.family_type, PrometheusType::Histogram | PrometheusType::Summary) {
+        return None;
+    }
+    let kind = model::FamilyType::of(owner).name();
+    Some((
+        Severity::Error,
+        format!("collides with the {suffix} series of the {kind} {base}"),
+    ))
+}
+
+/// Names the parser accepts but Prometheus reserves or rejects.
+fn label_names(scope: &Scope) -> Option<(Severity, String)> {
+    let valid = |label: &str| {
+        let mut chars = label.chars();
+        chars.next().is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+            && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+    };
+    let labels = scope.fam.get_label_names();
+    if let Some(label) = labels.iter().find(|label| !valid(label.as_str())) {
+        return Some((Severity::Error, format!("{label:?} isn't a valid label name")));
+    }
+    let label = labels.iter().find(|label| label.starts_with("__"))?;
+    Some((
+        Severity::Error,
+        format!("label names starting with __ are reserved, but {label} is exposed"),
+    ))
+}
+
+/// Buckets should be listed by increasing `le`, end in `+Inf`, and only ever
+/// grow, since they're cumulative.
+fn bucket_order(scope: &Scope) -> Option<(Severity, String)> {
+    scope.fam.iter_samples().find_map(|sample| {
+        let PrometheusValue::Histogram(h) = &sample.value else {
+            return None;
+        };
+        let unordered = h
+            .buckets
+            .windows(2)
+            .any(|pair| pair[0].upper_bound >= pair[1].upper_bound);
+        let shrinking = h.buckets.windows(2).any(|pair| pair[0].count > pair[1].count);
+        let problem = if unordered {
+            "buckets aren't in increasing le order"
+        } else if h
+            .buckets
+            .last()
+            .is_none_or(|bucket| bucket.upper_bound != f64::INFINITY)
+        {
+            "there is no le=\"+Inf\" bucket"
+        } else if shrinking {
+            "bucket counts decrease with le, but buckets are cumulative"
+        } else {
+            return None;
+        };
+        let labels = model::format_labels(&model::label_pairs(sample));
+        Some((Severity::Error, format!("{problem} in {{{labels}}}")))
+    })
+}
+
+fn missing_help(scope: &Scope) -> Option<(Severity, String)> {
+    if !scope.fam.help.trim().is_empty() {
+        return None;
+    }
+    Some((Severity::Warning, String::from("no HELP text")))
+}
+
+/// Prometheus stores an exposed timestamp instead of the scrape's time, so a
+/// late or frozen one silently breaks `rate()`. It's rarely meant, outside of
+/// federation and pushed metrics.
+fn explicit_timestamps(scope: &Scope) -> Option<(Severity, String)> {
+    let series = scope.fam.iter_samples().count();
+    let stamped = scope.fam.iter_samples().filter(|sample| sample.timestamp.is_some()).count();
+    if stamped == 0 {
+        return None;
+    }
+    Some((
+        Severity::Warning,
+        format!("{stamped} of {series} series carry their own timestamp, used instead of the scrape time"),
+    ))
+}
+
+/// Finds series and HELP/TYPE/UNIT lines the scrape repeats, by scanning the
+/// raw text rather than trusting the parser, which may keep either value or
+/// reject the scrape without saying which lines clash. Line numbers are 1-based.
+pub fn duplicates(text: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut metadata: HashMap<(&str, &str), usize> = HashMap::new();
+    let mut series: HashMap<String, (usize, &str)> = HashMap::new();
+    let mut family = "";
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut words = comment.split_whitespace();
+            if let (Some(kind @ ("HELP" | "TYPE" | "UNIT")), Some(name)) = (words.next(), words.next()) {
+                family = name;
+                if let Some(first) = metadata.insert((kind, name), number) {
+                    findings.push(Finding {
+                        severity: Severity::Error,
+                        family: name.to_string(),
+                        rule: "duplicate-metadata",
+                        message: format!("# {kind} is repeated on line {number}; the first is on line {first}"),
+                    });
+                }
+            }
+            continue;
+        }
+        let Some((name, labels, value)) = parse_sample(line) else {
+            continue;
+        };
+        let key = if labels.is_empty() {
+            name.to_string()
+        } else {
+            format!("{name}{{{}}}", model::format_labels(&labels))
+        };
+        if let Some(&(first, first_value)) = series.get(&key) {
+            let in_family = model::SAMPLE_SUFFIXES
+                .iter()
+                .any(|suffix| name.strip_suffix(suffix) == Some(family));
+            let owner = if in_family { family } else { name };
+            findings.push(Finding {
+                severity: Severity::Error,
+                family: owner.to_string(),
+                rule: "duplicate-series",
+                message: format!("{key} is on line {first} ({first_value}) and again on line {number} ({value})"),
+            });
+        } else {
+            series.insert(key, (number, value));
+        }
+    }
+    findings
+}
+
+/// Splits a sample line into its name, labels sorted by name, and value.
+fn parse_sample(line: &str) -> Option<(&str, Vec<(String, String)>, &str)> {
+    let end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let (name, mut rest) = line.split_at(end);
+    let mut labels = Vec::new();
+    if let Some(inner) = rest.strip_prefix('{') {
+        rest = inner;
+        loop {
+            rest = rest.trim_start_matches([' ', ',']);
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (label, after) = rest.split_once('=')?;
+            let after = after.trim_start().strip_prefix('"')?;
+            let mut value = String::new();
+            let mut escaped = false;
+            let mut close = None;
+            for (i, c) in after.char_indices() {
+                match (escaped, c) {
+                    (true, 'n') => value.push('\n'),
+                    (true, c) => value.push(c),
+                    (false, '\\') => {}
+                    (false, '"') => {
+                        close = Some(i);
+                        break;
+                    }
+                    (false, c) => value.push(c),
+                }
+                escaped = !escaped && c == '\\';
+            }
+            labels.push((label.trim().to_string(), value));
+            rest = &after[close? + 1..];
+        }
+    }
+    labels.sort();
+    let value = rest.split_whitespace().next()?;
+    Some((name, labels, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(text: &str, rule: &str) -> Vec<(Severity, String, String)> {
+        let format = if text.ends_with("# EOF\n") {
+            model::Format::Openmetrics
+        } else {
+            model::Format::Prometheus
+        };
+        let exposition = model::parse(text, None, format).unwrap_or_else(|e| panic!("{e}"));
+        lint(&exposition, format == model::Format::Openmetrics)
+            .into_iter()
+            .filter(|finding| finding.rule == rule)
+            .map(|finding| (finding.severity, finding.family, finding.message))
+            .collect()
+    }
+
+    fn families(text: &str, rule: &str) -> Vec<String> {
+        findings(text, rule).into_iter().map(|(_, family, _)| family).collect()
+    }
+
+    #[test]
+    fn counters_need_total_in_the_text_format() {
+        let text = "# TYPE requests counter\nrequests 1\n# TYPE errors_total counter\nerrors_total 1\n";
+        assert_eq!(families(text, "counter-total"), vec!["requests"]);
+        let text = "# TYPE requests counter\nrequests_total 1\n# EOF\n";
+        assert!(families(text, "counter-total").is_empty());
+    }
+
+    #[test]
+    fn total_is_only_for_counters() {
+        let text = "# TYPE queued_total gauge\nqueued_total 3\n# TYPE sent_total counter\nsent_total 1\n";
+        assert_eq!(
+            findings(text, "total-on-non-counter"),
+            vec![(
+                Severity::Warning,
+                String::from("queued_total"),
+                String::from("_total is for counters, but this is a gauge")
+            )]
+        );
+    }
+
+    #[test]
+    fn scaled_units_are_flagged() {
+        let text = "# TYPE latency_milliseconds gauge\nlatency_milliseconds 3\n# TYPE size_bytes gauge\nsize_bytes 1\n";
+        assert_eq!(
+            findings(text, "base-units"),
+            vec![(
+                Severity::Warning,
+                String::from("latency_milliseconds"),
+                String::from("use base units: seconds rather than milliseconds")
+            )]
+        );
+    }
+
+    #[test]
+    fn families_colliding_with_histogram_series_are_errors() {
+        let text = concat!(
+            "# TYPE latency histogram\n",
+            "latency_bucket{le=\"+Inf\"} 1\n",
+            "latency_sum 1\n",
+            "latency_count 1\n",
+            "# TYPE latency_count gauge\n",
+            "latency_count{path=\"/\"} 1\n",
+            "# TYPE jobs_count gauge\n",
+            "jobs_count 1\n",
+        );
+        assert_eq!(families(text, "suffix-collision"), vec!["latency_count"]);
+    }
+
+    #[test]
+    fn reserved_label_names_are_errors() {
+        let text = "# TYPE up gauge\nup{__meta_pod=\"a\"} 1\n# TYPE ok gauge\nok{pod=\"a\"} 1\n";
+        assert_eq!(families(text, "label-names"), vec!["up"]);
+    }
+
+    #[test]
+    fn buckets_must_be_ordered_cumulative_and_end_in_inf() {
+        let text = concat!(
+            "# TYPE shrinking histogram\n",
+            "shrinking_bucket{le=\"1\"} 5\n",
+            "shrinking_bucket{le=\"+Inf\"} 3\n",
+            "shrinking_sum 1\n",
+            "shrinking_count 3\n",
+            "# TYPE fine histogram\n",
+            "fine_bucket{le=\"1\"} 1\n",
+            "fine_bucket{le=\"+Inf\"} 3\n",
+            "fine_sum 1\n",
+            "fine_count 3\n",
+        );
+        let found = findings(text, "bucket-order");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1, "shrinking");
+        assert!(found[0].2.starts_with("bucket counts decrease"));
+    }
+
+    #[test]
+    fn families_need_help() {
+        let text = "# HELP up Whether the target is up.\n# TYPE up gauge\nup 1\n# TYPE down gauge\ndown 0\n";
+        assert_eq!(families(text, "missing-help"), vec!["down"]);
+    }
+
+    #[test]
+    fn explicit_timestamps_are_flagged() {
+        let text = "# TYPE up gauge\nup{job=\"a\"} 1 1719432000000\nup{job=\"b\"} 1\n# TYPE ok gauge\nok 1\n";
+        assert_eq!(
+            findings(text, "explicit-timestamps"),
+            vec![(
+                Severity::Warning,
+                String::from("up"),
+                String::from("1 of 2 series carry their own timestamp, used instead of the scrape time")
+            )]
+        );
+    }
+
+    #[test]
+    fn errors_are_listed_first() {
+        let text = "# TYPE a_milliseconds gauge\na_milliseconds 1\n# TYPE b gauge\nb{__x=\"1\"} 1\n";
+        let exposition = model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let all = lint(&exposition, false);
+        assert_eq!(all[0].severity, Severity::Error);
+        assert_eq!(all[0].family, "b");
+    }
+
+    #[test]
+    fn repeated_series_and_metadata_are_found_in_the_raw_text() {
+        let text = concat!(
+            "# HELP latency Request latency.\n",
+            "# TYPE latency histogram\n",
+            "latency_bucket{le=\"1\",path=\"/a\\\"b\"} 1\n",
+            "latency_bucket{path=\"/a\\\"b\", le=\"1\"} 2\n",
+            "latency_bucket{le=\"+Inf\",path=\"/a\\\"b\"} 2\n",
+            "# TYPE latency histogram\n",
+            "up 1\n",
+            "up{job=\"x\"} 1\n",
+        );
+        let found: Vec<(&str, String, String)> = duplicates(text)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.family, finding.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "duplicate-series",
+                    String::from("latency"),
+                    String::from("latency_bucket{le=\"1\", path=\"/a\\\"b\"} is on line 3 (1) and again on line 4 (2)")
+                ),
+                (
+                    "duplicate-metadata",
+                    String::from("latency"),
+                    String::from("# TYPE is repeated on line 6; the first is on line 2")
+                ),
+            ]
+        );
+    }
+}
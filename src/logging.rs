@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How `--log-file` writes its lines.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// One JSON object per line, for `jq`
+    #[default]
+    Json,
+    /// For reading as is
+    Text,
+}
+
+/// Info by default, then debug for `-v` and trace for `-vv`.
+fn level(verbosity: u8) -> Level {
+    match verbosity {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+fn subscriber<W>(writer: W, format: LogFormat, level: Level) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_max_level(level)
+        .with_ansi(false)
+        .with_thread_names(true);
+    match format {
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+        LogFormat::Text => Box::new(builder.finish()),
+    }
+}
+
+/// Appends the events of the whole run to `path`. Without this nothing
+/// subscribes, and every event is skipped where it would be recorded.
+pub fn init(path: &Path, format: LogFormat, verbosity: u8) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("couldn't open {}", path.display()))?;
+    tracing::subscriber::set_global_default(subscriber(Mutex::new(file), format, level(verbosity)))
+        .context("couldn't start logging")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn logged(format: LogFormat, verbosity: u8) -> String {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        tracing::subscriber::with_default(subscriber(move || writer.clone(), format, level(verbosity)), || {
+            tracing::info!(url = "http://localhost:9100/metrics", status = 200, "fetched");
+            tracing::debug!(action = "Refresh", "key");
+        });
+        let bytes = buffer.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn json_lines_carry_the_fields_and_verbosity_adds_debug() {
+        let log = logged(LogFormat::Json, 0);
+        let lines: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1, "{log}");
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "fetched");
+        assert_eq!(lines[0]["url"], "http://localhost:9100/metrics");
+        assert_eq!(lines[0]["status"], 200);
+
+        assert_eq!(logged(LogFormat::Json, 1).lines().count(), 2);
+        let text = logged(LogFormat::Text, 2);
+        assert!(
+            text.contains("DEBUG") && text.contains("key action=\"Refresh\""),
+            "{text}"
+        );
+    }
+}
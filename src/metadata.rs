@@ -0,0 +1,493 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::fetch::{Body, HttpFetcher};
+use crate::model::{Exposition, FamilyType};
+use crate::query;
+use crate::verify::ReportFormat;
+
+/// `promtool metadata-check`: compare the types and HELP a Prometheus
+/// server has for a job's metrics with what its exporter serves now, e.g.
+/// after a counter was changed to a gauge.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MetadataArgs {
+    /// The Prometheus server, e.g. http://prometheus:9090
+    #[arg(long, value_name = "URL")]
+    pub server: String,
+
+    /// The job to check, as in its targets' `job` label
+    #[arg(long)]
+    pub job: String,
+
+    /// The exporter to scrape instead of the job's first healthy target on
+    /// the server's targets page, e.g. when that address isn't reachable
+    #[arg(long, value_name = "ENDPOINT")]
+    pub target: Option<String>,
+
+    /// Metrics, or for the targets' metadata targets, to ask for in one
+    /// request; a full page is asked for again with twice the limit
+    #[arg(long, value_name = "N", default_value_t = 10_000)]
+    pub page_size: usize,
+
+    /// How to print the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+}
+
+/// A type and HELP the server has for a metric.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Metadata {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub help: String,
+}
+
+/// What the server knows about the job's metrics, by metric name.
+#[derive(Default, Debug)]
+pub struct ServerMetadata {
+    /// From `/api/v1/targets/metadata`: what the job's targets exposed.
+    pub targets: BTreeMap<String, Vec<Metadata>>,
+    /// From `/api/v1/metadata`: everything ingested, whichever job it
+    /// came from.
+    pub ingested: BTreeMap<String, Vec<Metadata>>,
+}
+
+/// An active target of the job on the server's targets page.
+#[derive(Debug, PartialEq)]
+pub struct Target {
+    pub scrape_url: String,
+    pub instance: Option<String>,
+    pub healthy: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Targets {
+    active_targets: Vec<ActiveTarget>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActiveTarget {
+    labels: BTreeMap<String, String>,
+    scrape_url: String,
+    health: String,
+}
+
+/// One entry of `/api/v1/targets/metadata`, for one metric of one target.
+#[derive(Deserialize)]
+struct TargetMetadata {
+    /// The target's labels.
+    #[serde(default)]
+    target: BTreeMap<String, String>,
+    metric: String,
+    #[serde(flatten)]
+    metadata: Metadata,
+}
+
+/// The active targets of `job` in an `/api/v1/targets` response, healthy
+/// ones first.
+pub fn job_targets(body: &Body, job: &str) -> Result<Vec<Target>> {
+    let (targets, _) = query::api_data::<Targets>(&body.text, body.status)?;
+    let mut targets: Vec<Target> = targets
+        .active_targets
+        .into_iter()
+        .filter(|target| target.labels.get("job").map(String::as_str) == Some(job))
+        .map(|mut target| Target {
+            instance: target.labels.remove("instance"),
+            healthy: target.health == "up",
+            scrape_url: target.scrape_url,
+        })
+        .collect();
+    targets.sort_by_key(|target| !target.healthy);
+    Ok(targets)
+}
+
+/// Asks `server` for the metadata of the targets `matcher` selects, e.g.
+/// `{job="node"}`, and for everything it has ingested.
+pub fn fetch(http: &HttpFetcher, server: &str, matcher: &str, page_size: usize) -> Result<ServerMetadata> {
+    let entries = paged(page_size, |limit| {
+        let limit = limit.to_string();
+        let body = http.fetch_api(server, "targets/metadata", &[("match_target", matcher), ("limit", limit.as_str())])?;
+        let (entries, _) = query::api_data::<Vec<TargetMetadata>>(&body.text, body.status)?;
+        Ok((targets_in(&entries), entries))
+    })
+    .with_context(|| format!("couldn't get the metadata of {matcher} from {server}"))?;
+    let ingested = paged(page_size, |limit| {
+        let limit = limit.to_string();
+        let body = http.fetch_api(server, "metadata", &[("limit", limit.as_str())])?;
+        let (metrics, _) = query::api_data::<BTreeMap<String, Vec<Metadata>>>(&body.text, body.status)?;
+        Ok((metrics.len(), metrics))
+    })
+    .with_context(|| format!("couldn't get the ingested metadata from {server}"))?;
+
+    let mut metadata = ServerMetadata {
+        ingested,
+        ..ServerMetadata::default()
+    };
+    // Each target repeats what it exposes; only the distinct ones count.
+    for entry in entries {
+        let known = metadata.targets.entry(entry.metric).or_default();
+        if !known.contains(&entry.metadata) {
+            known.push(entry.metadata);
+        }
+    }
+    Ok(metadata)
+}
+
+/// How many targets a `targets/metadata` page is about. Its `limit` counts
+/// targets, each listed once per metric, rather than entries.
+fn targets_in(entries: &[TargetMetadata]) -> usize {
+    entries.iter().map(|entry| &entry.target).collect::<BTreeSet<_>>().len()
+}
+
+/// The metadata endpoints have no cursor, only a `limit` on what they
+/// count: metrics for `metadata`, targets for `targets/metadata`. A page
+/// with as many of those as the limit may have been cut short, so it's
+/// asked for again with twice the limit until one has room to spare.
+/// `get` returns that count with the page.
+fn paged<T>(page_size: usize, mut get: impl FnMut(usize) -> Result<(usize, T)>) -> Result<T> {
+    let mut limit = page_size.max(1);
+    loop {
+        let (len, page) = get(limit)?;
+        if len < limit {
+            return Ok(page);
+        }
+        limit = limit.saturating_mul(2);
+    }
+}
+
+/// The viewer's type for a metadata type. OpenMetrics info and state sets
+/// are parsed as gauges, and gauge histograms as histograms, so they're
+/// taken as those.
+pub fn family_type(kind: &str) -> FamilyType {
+    match kind {
+        "counter" => FamilyType::Counter,
+        "gauge" | "info" | "stateset" => FamilyType::Gauge,
+        "histogram" | "gaugehistogram" => FamilyType::Histogram,
+        "summary" => FamilyType::Summary,
+        _ => FamilyType::Untyped,
+    }
+}
+
+/// Where the server and the exporter disagree about a family.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// The server has other types for it than the exporter's TYPE.
+    Type {
+        family: String,
+        exposed: String,
+        server: Vec<String>,
+    },
+    Help {
+        family: String,
+        exposed: String,
+        server: Vec<String>,
+    },
+    /// Exposed, but the server has no metadata for it.
+    Unknown { family: String },
+    /// In the job's targets' metadata, but not exposed anymore.
+    Gone { family: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Type {
+                family,
+                exposed,
+                server,
+            } => write!(f, "{family}: exposed as {exposed}, but Prometheus has it as {}", server.join(" and ")),
+            Mismatch::Help {
+                family,
+                exposed,
+                server,
+            } => {
+                let server: Vec<String> = server.iter().map(|help| format!("{help:?}")).collect();
+                write!(f, "{family}: HELP is {exposed:?}, but Prometheus has {}", server.join(" and "))
+            }
+            Mismatch::Unknown { family } => write!(f, "{family} is exposed, but Prometheus has no metadata for it"),
+            Mismatch::Gone { family } => {
+                write!(f, "{family} isn't exposed anymore, but the job's targets have metadata for it")
+            }
+        }
+    }
+}
+
+/// Compares every exposed family with what the server has for it, the
+/// job's targets' metadata before whatever else was ingested, then lists
+/// what the targets had that isn't exposed anymore.
+pub fn compare(metrics: &Exposition, server: &ServerMetadata) -> Vec<Mismatch> {
+    let mut names: Vec<&String> = metrics.families.keys().collect();
+    names.sort();
+    let mut mismatches = Vec::new();
+    for name in names {
+        let fam = &metrics.families[name];
+        let Some(known) = lookup(&server.targets, name).or_else(|| lookup(&server.ingested, name)) else {
+            mismatches.push(Mismatch::Unknown { family: name.clone() });
+            continue;
+        };
+        let exposed = FamilyType::of(fam).name();
+        let types = distinct(known.iter().map(|metadata| family_type(&metadata.kind).name()), exposed);
+        if !types.is_empty() {
+            mismatches.push(Mismatch::Type {
+                family: name.clone(),
+                exposed: exposed.to_string(),
+                server: types,
+            });
+        }
+        let help = distinct(known.iter().map(|metadata| metadata.help.trim()), fam.help.trim());
+        if !help.is_empty() {
+            mismatches.push(Mismatch::Help {
+                family: name.clone(),
+                exposed: fam.help.trim().to_string(),
+                server: help,
+            });
+        }
+    }
+    for metric in server.targets.keys() {
+        if !metrics.families.contains_key(metric) && !metrics.families.contains_key(&toggle_total(metric)) {
+            mismatches.push(Mismatch::Gone { family: metric.clone() });
+        }
+    }
+    mismatches
+}
+
+/// The server's entries for `family`. A counter is named with `_total` or
+/// without it depending on the format it was scraped in, so either is taken.
+fn lookup<'a>(metadata: &'a BTreeMap<String, Vec<Metadata>>, family: &str) -> Option<&'a Vec<Metadata>> {
+    metadata.get(family).or_else(|| metadata.get(&toggle_total(family)))
+}
+
+fn toggle_total(name: &str) -> String {
+    match name.strip_suffix("_total") {
+        Some(base) => base.to_string(),
+        None => format!("{name}_total"),
+    }
+}
+
+/// The values other than `exposed`, each once, in the server's order.
+fn distinct<'a>(values: impl Iterator<Item = &'a str>, exposed: &str) -> Vec<String> {
+    let mut other: Vec<String> = Vec::new();
+    for value in values.filter(|value| *value != exposed) {
+        if !other.iter().any(|seen| seen == value) {
+            other.push(value.to_string());
+        }
+    }
+    other
+}
+
+/// The outcome of one `promtool metadata-check`.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub server: String,
+    pub job: String,
+    /// The exporter that was scraped.
+    pub target: String,
+    /// How many families it exposes.
+    pub checked: usize,
+    pub passed: bool,
+    pub mismatches: Vec<Entry>,
+}
+
+/// A mismatch with its message spelled out, like `promtool verify`'s
+/// failures.
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub mismatch: Mismatch,
+    pub message: String,
+}
+
+impl Report {
+    pub fn new(args: &MetadataArgs, target: &str, checked: usize, mismatches: Vec<Mismatch>) -> Report {
+        Report {
+            server: args.server.clone(),
+            job: args.job.clone(),
+            target: target.to_string(),
+            checked,
+            passed: mismatches.is_empty(),
+            mismatches: mismatches
+                .into_iter()
+                .map(|mismatch| Entry {
+                    message: mismatch.to_string(),
+                    mismatch,
+                })
+                .collect(),
+        }
+    }
+
+    /// `FAIL: <message>` per mismatch, then an `OK:` or `FAIL:` summary.
+    pub fn text(&self) -> String {
+        let mut text: String = self
+            .mismatches
+            .iter()
+            .map(|entry| format!("FAIL: {}\n", entry.message))
+            .collect();
+        let families = if self.checked == 1 { "family" } else { "families" };
+        if self.passed {
+            text.push_str(&format!(
+                "OK: {} agrees with {} about job {} ({} {families})\n",
+                self.target, self.server, self.job, self.checked
+            ));
+        } else {
+            let mismatches = if self.mismatches.len() == 1 {
+                "mismatch"
+            } else {
+                "mismatches"
+            };
+            text.push_str(&format!(
+                "FAIL: {} disagrees with {} about job {} ({} {families}, {} {mismatches})\n",
+                self.target,
+                self.server,
+                self.job,
+                self.checked,
+                self.mismatches.len()
+            ));
+        }
+        text
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model;
+
+    fn metadata(kind: &str, help: &str) -> Metadata {
+        Metadata {
+            kind: kind.to_string(),
+            help: help.to_string(),
+        }
+    }
+
+    #[test]
+    fn targets_of_the_job_are_listed_healthy_first() {
+        let text = r#"{"status":"success","data":{"activeTargets":[
+            {"labels":{"job":"node","instance":"a:9100"},"scrapeUrl":"http://a:9100/metrics","health":"down"},
+            {"labels":{"job":"app","instance":"b:8080"},"scrapeUrl":"http://b:8080/metrics","health":"up"},
+            {"labels":{"job":"node","instance":"c:9100"},"scrapeUrl":"http://c:9100/metrics","health":"up"}
+        ],"droppedTargets":[]}}"#;
+        let targets = job_targets(&Body::plain(text.to_string()), "node").unwrap();
+        let urls: Vec<(&str, Option<&str>, bool)> = targets
+            .iter()
+            .map(|target| (target.scrape_url.as_str(), target.instance.as_deref(), target.healthy))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                ("http://c:9100/metrics", Some("c:9100"), true),
+                ("http://a:9100/metrics", Some("a:9100"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_pages_are_asked_for_again_with_a_larger_limit() {
+        let mut asked = Vec::new();
+        let page = paged(2, |limit| {
+            asked.push(limit);
+            let len = 5.min(limit);
+            Ok((len, len))
+        })
+        .unwrap();
+        assert_eq!((page, asked), (5, vec![2, 4, 8]));
+    }
+
+    #[test]
+    fn target_metadata_pages_are_full_by_targets_rather_than_entries() {
+        let entries: Vec<TargetMetadata> = (0..500)
+            .map(|i| TargetMetadata {
+                target: BTreeMap::from([(String::from("instance"), String::from("a:9100"))]),
+                metric: format!("metric_{i}"),
+                metadata: metadata("gauge", ""),
+            })
+            .collect();
+        let mut asked = Vec::new();
+        let page = paged(100, |limit| {
+            asked.push(limit);
+            Ok((targets_in(&entries), entries.len()))
+        })
+        .unwrap();
+        assert_eq!((page, asked), (500, vec![100]));
+    }
+
+    #[test]
+    fn types_help_and_presence_are_compared_with_the_servers() {
+        let text = concat!(
+            "# HELP jobs_running Jobs being worked on.\n",
+            "# TYPE jobs_running gauge\n",
+            "jobs_running 3\n",
+            "# HELP queue_depth Jobs waiting in the queue.\n",
+            "# TYPE queue_depth gauge\n",
+            "queue_depth 7\n",
+            "# HELP requests_total Requests served.\n",
+            "# TYPE requests_total counter\n",
+            "requests_total 12\n",
+            "# HELP cache_hits_total Cache hits.\n",
+            "# TYPE cache_hits_total counter\n",
+            "cache_hits_total 4\n",
+        );
+        let metrics = model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let server = ServerMetadata {
+            targets: BTreeMap::from([
+                (String::from("jobs_running"), vec![metadata("counter", "Jobs being worked on.")]),
+                (String::from("requests"), vec![metadata("counter", "Requests handled.")]),
+                (String::from("legacy_errors_total"), vec![metadata("counter", "Errors.")]),
+            ]),
+            ingested: BTreeMap::from([(
+                String::from("queue_depth"),
+                vec![metadata("gauge", "Jobs waiting in the queue.")],
+            )]),
+        };
+        let messages: Vec<String> = compare(&metrics, &server).iter().map(Mismatch::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "cache_hits_total is exposed, but Prometheus has no metadata for it",
+                "jobs_running: exposed as gauge, but Prometheus has it as counter",
+                "requests_total: HELP is \"Requests served.\", but Prometheus has \"Requests handled.\"",
+                "legacy_errors_total isn't exposed anymore, but the job's targets have metadata for it",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_tag_each_mismatch_with_its_kind() {
+        let args = MetadataArgs {
+            server: String::from("http://prometheus:9090"),
+            job: String::from("node"),
+            target: None,
+            page_size: 10_000,
+            output: ReportFormat::Json,
+        };
+        let mismatches = vec![Mismatch::Gone {
+            family: String::from("legacy_errors_total"),
+        }];
+        let report = Report::new(&args, "http://c:9100/metrics", 3, mismatches);
+        assert_eq!(
+            report.text(),
+            concat!(
+                "FAIL: legacy_errors_total isn't exposed anymore, but the job's targets have metadata for it\n",
+                "FAIL: http://c:9100/metrics disagrees with http://prometheus:9090 about job node ",
+                "(3 families, 1 mismatch)\n",
+            )
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.json()).unwrap();
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["mismatches"][0]["kind"], "gone");
+        assert_eq!(json["mismatches"][0]["family"], "legacy_errors_total");
+        assert_eq!(
+            Report::new(&args, "http://c:9100/metrics", 1, Vec::new()).text(),
+            "OK: http://c:9100/metrics agrees with http://prometheus:9090 about job node (1 family)\n"
+        );
+    }
+}
@@ -0,0 +1,2557 @@
+use openmetrics_parser::{
+    Exemplar, HistogramValue, MetricNumber, OpenMetricsType, OpenMetricsValue, ParseError, PrometheusType, PrometheusValue,
+    Sample, SummaryValue,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::natural;
+
+pub type Exposition = openmetrics_parser::MetricsExposition<PrometheusType, PrometheusValue>;
+pub type Family = openmetrics_parser::MetricFamily<PrometheusType, PrometheusValue>;
+pub type Series = Sample<PrometheusValue>;
+
+/// Which text format to parse a scrape as.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Go by the response's Content-Type, or by a trailing `# EOF` when there is none.
+    Auto,
+    Prometheus,
+    Openmetrics,
+}
+
+impl Format {
+    /// `Auto` settled by the response: `Prometheus` or `Openmetrics`.
+    pub fn resolve(self, text: &str, content_type: Option<&str>) -> Format {
+        match (self, content_type) {
+            (Format::Auto, Some(content_type)) if content_type.starts_with("application/openmetrics-text") => {
+                Format::Openmetrics
+            }
+            (Format::Auto, Some(_)) => Format::Prometheus,
+            (Format::Auto, None) if text.trim_end().ends_with("# EOF") => Format::Openmetrics,
+            (Format::Auto, None) => Format::Prometheus,
+            (format, _) => format,
+        }
+    }
+}
+
+/// The family types `t` and `--type` filter on. Families without a TYPE
+/// line are `Untyped` rather than being lumped in with gauges.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FamilyType {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+    Untyped,
+}
+
+impl FamilyType {
+    pub const ALL: [FamilyType; 5] = [
+        FamilyType::Counter,
+        FamilyType::Gauge,
+        FamilyType::Histogram,
+        FamilyType::Summary,
+        FamilyType::Untyped,
+    ];
+
+    pub fn of(fam: &Family) -> FamilyType {
+        match fam.family_type {
+            PrometheusType::Counter => FamilyType::Counter,
+            PrometheusType::Gauge => FamilyType::Gauge,
+            PrometheusType::Histogram => FamilyType::Histogram,
+            PrometheusType::Summary => FamilyType::Summary,
+            PrometheusType::Unknown => FamilyType::Untyped,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FamilyType::Counter => "counter",
+            FamilyType::Gauge => "gauge",
+            FamilyType::Histogram => "histogram",
+            FamilyType::Summary => "summary",
+            FamilyType::Untyped => "untyped",
+        }
+    }
+}
+
+/// Parses a scrape in whichever format `format` resolves to. OpenMetrics
+/// scrapes are converted to the Prometheus model so the rest of the viewer
+/// only deals with one representation.
+pub fn parse(text: &str, content_type: Option<&str>, format: Format) -> Result<Exposition, ParseError> {
+    match format.resolve(text, content_type) {
+        Format::Openmetrics => from_openmetrics(openmetrics_parser::openmetrics::parse_openmetrics(text)?),
+        _ => openmetrics_parser::prometheus::parse_prometheus(text),
+    }
+}
+
+/// `text` cut before every `# HELP`, `# TYPE` or `# UNIT` line naming
+/// another family than the one before it, so each piece holds a family's
+/// metadata and samples, along with any samples without metadata after them.
+fn family_blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut family: Option<&str> = None;
+    let (mut start, mut offset) = (0, 0);
+    for line in text.split_inclusive('\n') {
+        if let Some(comment) = line.trim_start().strip_prefix('#') {
+            let mut words = comment.split_whitespace();
+            if let (Some("HELP" | "TYPE" | "UNIT"), Some(name)) = (words.next(), words.next()) {
+                if family.is_some_and(|family| family != name) {
+                    blocks.push(&text[start..offset]);
+                    start = offset;
+                }
+                family = Some(name);
+            }
+        }
+        offset += line.len();
+    }
+    if start < text.len() {
+        blocks.push(&text[start..]);
+    }
+    blocks
+}
+
+/// What `--max-series` left out of a scrape.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Truncated {
+    pub max_series: usize,
+    /// Families that weren't read, the one that would have gone past the
+    /// limit included.
+    pub families_left: usize,
+}
+
+/// Like [`parse`], but a family at a time and with at most `max_series`
+/// series: the parser's own structures are several times the size of the
+/// text, and this way only hold one family at once. Scrapes that don't cut
+/// cleanly into families, e.g. with one declared twice, are parsed whole,
+/// so errors name the same lines as [`parse`] would.
+pub fn parse_by_family(
+    text: &str,
+    content_type: Option<&str>,
+    format: Format,
+    max_series: Option<usize>,
+) -> Result<(Exposition, Option<Truncated>), ParseError> {
+    let format = format.resolve(text, content_type);
+    let whole = || {
+        let mut exposition = parse(text, None, format)?;
+        let truncated = max_series.and_then(|max| truncate(&mut exposition, text, max));
+        Ok((exposition, truncated))
+    };
+    let blocks = family_blocks(text);
+    let openmetrics = format == Format::Openmetrics;
+    if blocks.is_empty() || (openmetrics && !text.trim_end().ends_with("# EOF")) {
+        return whole();
+    }
+
+    let mut families = HashMap::new();
+    let mut series = 0;
+    for (index, block) in blocks.iter().enumerate() {
+        // Only the last block has the `# EOF` OpenMetrics ends on.
+        let parsed = if openmetrics && index + 1 < blocks.len() {
+            parse(&format!("{block}# EOF\n"), None, format)
+        } else {
+            parse(block, None, format)
+        };
+        let Ok(parsed) = parsed else {
+            return whole();
+        };
+        let count: usize = parsed.families.values().map(|fam| fam.iter_samples().count()).sum();
+        if let Some(max_series) = max_series.filter(|&max| series + count > max) {
+            let truncated = Truncated {
+                max_series,
+                families_left: blocks.len() - index,
+            };
+            return Ok((Exposition { families }, Some(truncated)));
+        }
+        series += count;
+        for (name, fam) in parsed.families {
+            if families.contains_key(&name) {
+                return whole();
+            }
+            families.insert(name, fam);
+        }
+    }
+    Ok((Exposition { families }, None))
+}
+
+/// Drops the families past the first `max_series` series, in the order
+/// `text` has them, for scrapes that had to be parsed whole.
+pub fn truncate(exposition: &mut Exposition, text: &str, max_series: usize) -> Option<Truncated> {
+    let lines = family_lines(text);
+    let mut names: Vec<String> = exposition.families.keys().cloned().collect();
+    names.sort_by_key(|name| lines.get(name).map_or(usize::MAX, |lines| lines.start));
+    let mut series = 0;
+    let mut left = 0;
+    for name in names {
+        let count = exposition.families[&name].iter_samples().count();
+        if left > 0 || series + count > max_series {
+            exposition.families.remove(&name);
+            left += 1;
+        } else {
+            series += count;
+        }
+    }
+    (left > 0).then_some(Truncated {
+        max_series,
+        families_left: left,
+    })
+}
+
+/// A line dropped by lenient parsing, numbered as in the original scrape.
+pub struct SkippedLine {
+    pub line: usize,
+    pub text: String,
+}
+
+/// Like [`parse`], but when the scrape is rejected, drops exact-duplicate
+/// `# TYPE`/`# HELP` lines and then every line the parser points at until the
+/// rest parses. Fails with the parser's error once it stops naming a line.
+pub fn parse_lenient(
+    text: &str,
+    content_type: Option<&str>,
+    format: Format,
+) -> Result<(Exposition, Vec<SkippedLine>), ParseError> {
+    // Each dropped line costs a full re-parse, so don't grind through a
+    // payload that is garbage throughout.
+    const MAX_SKIPPED: usize = 1000;
+
+    let first_error = match parse_by_family(text, content_type, format, None) {
+        Ok((exposition, _)) => return Ok((exposition, Vec::new())),
+        Err(e) => e,
+    };
+
+    let mut lines: Vec<(usize, &str)> = text.lines().enumerate().map(|(i, line)| (i + 1, line)).collect();
+    let mut skipped = Vec::new();
+    let mut declarations = HashSet::new();
+    lines.retain(|&(number, line)| {
+        let declaration = line.starts_with("# TYPE ") || line.starts_with("# HELP ");
+        if declaration && !declarations.insert(line) {
+            skipped.push(SkippedLine {
+                line: number,
+                text: line.to_string(),
+            });
+            return false;
+        }
+        true
+    });
+
+    loop {
+        let mut remainder = lines.iter().map(|(_, line)| *line).collect::<Vec<_>>().join("\n");
+        remainder.push('\n');
+        let error = match parse(&remainder, content_type, format) {
+            Ok(exposition) => {
+                skipped.sort_by_key(|s| s.line);
+                return Ok((exposition, skipped));
+            }
+            Err(e) => e,
+        };
+        let index = error_line(&error.to_string())
+            .and_then(|line| line.checked_sub(1))
+            .filter(|&index| index < lines.len() && skipped.len() < MAX_SKIPPED);
+        let Some(index) = index else {
+            return Err(if skipped.is_empty() { first_error } else { error });
+        };
+        let (number, line) = lines.remove(index);
+        skipped.push(SkippedLine {
+            line: number,
+            text: line.to_string(),
+        });
+    }
+}
+
+/// The 1-based line a parse error points at, if its message says. The
+/// parser's grammar errors render as `--> line:column`.
+pub fn error_line(message: &str) -> Option<usize> {
+    let pattern = regex::Regex::new(r"(?:-->\s*|line\s+)(\d+)").expect("valid regex");
+    pattern.captures(message)?.get(1)?.as_str().parse().ok()
+}
+
+/// Sample suffixes that still belong to the family of the last `# TYPE`.
+pub const SAMPLE_SUFFIXES: &[&str] = &["_bucket", "_count", "_sum", "_total", "_created", "_info"];
+
+/// The 0-based lines each family takes up in the raw text, from its first
+/// HELP/TYPE/UNIT line or sample to its last sample. A family exposed in
+/// more than one block keeps its first.
+pub fn family_lines(text: &str) -> HashMap<String, Range<usize>> {
+    let mut ranges = HashMap::new();
+    let mut current: Option<(&str, Range<usize>)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let name = match line.strip_prefix('#') {
+            Some(comment) => {
+                let mut words = comment.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some("HELP" | "TYPE" | "UNIT"), Some(name)) => name,
+                    // Other comments and `# EOF` don't start a family.
+                    _ => continue,
+                }
+            }
+            None => match line.split(|c: char| c == '{' || c.is_whitespace()).next() {
+                Some(name) if !name.is_empty() => name,
+                _ => continue,
+            },
+        };
+        if let Some((family, lines)) = &mut current {
+            let same = name == *family || SAMPLE_SUFFIXES.iter().any(|suffix| name.strip_suffix(suffix) == Some(*family));
+            if same {
+                lines.end = index + 1;
+                continue;
+            }
+        }
+        if let Some((family, lines)) = current.replace((name, index..index + 1)) {
+            ranges.entry(family.to_string()).or_insert(lines);
+        }
+    }
+    if let Some((family, lines)) = current {
+        ranges.entry(family.to_string()).or_insert(lines);
+    }
+    ranges
+}
+
+type OpenMetricsExposition = openmetrics_parser::MetricsExposition<OpenMetricsType, OpenMetricsValue>;
+
+/// Maps OpenMetrics-only types onto their closest Prometheus equivalent:
+/// gauge histograms become histograms, state sets and info metrics become
+/// gauges. Exemplars are kept since both models share the value structs.
+fn from_openmetrics(exposition: OpenMetricsExposition) -> Result<Exposition, ParseError> {
+    let mut families = HashMap::new();
+    for (name, fam) in exposition.families {
+        let family_type = match fam.family_type {
+            OpenMetricsType::Counter => PrometheusType::Counter,
+            OpenMetricsType::Gauge | OpenMetricsType::StateSet | OpenMetricsType::Info => PrometheusType::Gauge,
+            OpenMetricsType::Histogram | OpenMetricsType::GaugeHistogram => PrometheusType::Histogram,
+            OpenMetricsType::Summary => PrometheusType::Summary,
+            OpenMetricsType::Unknown => PrometheusType::Unknown,
+        };
+        let label_names = fam.get_label_names().to_vec();
+        let samples: Vec<Series> = fam
+            .iter_samples()
+            .map(|sample| {
+                let pairs = label_pairs(sample);
+                let label_values = label_names
+                    .iter()
+                    .map(|label| {
+                        pairs
+                            .iter()
+                            .find(|(name, _)| name == label)
+                            .map_or_else(String::new, |(_, value)| value.clone())
+                    })
+                    .collect();
+                let value = match &sample.value {
+                    OpenMetricsValue::Unknown(n) => PrometheusValue::Unknown(n.clone()),
+                    OpenMetricsValue::Gauge(n) | OpenMetricsValue::StateSet(n) => PrometheusValue::Gauge(n.clone()),
+                    OpenMetricsValue::Info => PrometheusValue::Gauge(MetricNumber::Int(1)),
+                    OpenMetricsValue::Counter(c) => PrometheusValue::Counter(c.clone()),
+                    OpenMetricsValue::Histogram(h) | OpenMetricsValue::GaugeHistogram(h) => {
+                        PrometheusValue::Histogram(h.clone())
+                    }
+                    OpenMetricsValue::Summary(s) => PrometheusValue::Summary(s.clone()),
+                };
+                Sample::new(label_values, sample.timestamp, value)
+            })
+            .collect();
+        let converted = Family::new(
+            fam.family_name.clone(),
+            label_names,
+            family_type,
+            fam.help.clone(),
+            fam.unit.clone(),
+        )
+        .with_samples(samples)?;
+        families.insert(name, converted);
+    }
+    Ok(Exposition { families })
+}
+
+/// Label names and values of a single series, in exposition order.
+pub fn label_pairs<V>(sample: &Sample<V>) -> Vec<(String, String)> {
+    match sample.get_labelset() {
+        Ok(labelset) => labelset
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Renders labels the way they appear in the exposition: `key="value", ...`
+pub fn format_labels(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A PromQL selector matching exactly this labelset, e.g.
+/// `http_requests_total{method="POST",code="500"}`.
+pub fn promql_selector(name: &str, pairs: &[(String, String)]) -> String {
+    if pairs.is_empty() {
+        return name.to_string();
+    }
+    let matchers: Vec<String> = pairs
+        .iter()
+        .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+        .collect();
+    format!("{name}{{{}}}", matchers.join(","))
+}
+
+/// Escapes backslashes, double quotes and newlines, which is what both the
+/// exposition format and PromQL double-quoted strings require.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A sample value exactly as exposed, for exports that are read back.
+pub fn format_value(value: &PrometheusValue) -> String {
+    format_value_with(value, |number| number.to_string())
+}
+
+/// A sample value for `--raw-values`, with [`format_raw`] numbers.
+pub fn format_raw_value(value: &PrometheusValue) -> String {
+    format_value_with(value, format_raw)
+}
+
+fn format_value_with(value: &PrometheusValue, number: fn(f64) -> String) -> String {
+    match value {
+        PrometheusValue::Unknown(n) | PrometheusValue::Gauge(n) => number(n.as_f64()),
+        PrometheusValue::Counter(c) => number(c.value.as_f64()),
+        PrometheusValue::Histogram(h) => format!(
+            "count={} sum={}",
+            h.count.map_or(String::from("?"), |c| c.to_string()),
+            h.sum.map_or(String::from("?"), |s| number(s.as_f64()))
+        ),
+        PrometheusValue::Summary(s) => format!(
+            "count={} sum={}",
+            s.count.map_or(String::from("?"), |c| c.to_string()),
+            s.sum.map_or(String::from("?"), |s| number(s.as_f64()))
+        ),
+    }
+}
+
+/// One histogram bucket with its own observations, rather than the
+/// cumulative `le` count from the exposition.
+pub struct Bucket {
+    pub upper_bound: f64,
+    pub count: f64,
+}
+
+/// Sorts buckets numerically by upper bound (`+Inf` last) and converts the
+/// cumulative counts into per-bucket counts.
+pub fn decumulate_buckets(histogram: &HistogramValue) -> Vec<Bucket> {
+    let mut cumulative: Vec<(f64, f64)> = histogram
+        .buckets
+        .iter()
+        .map(|b| (b.upper_bound, b.count))
+        .collect();
+    cumulative.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut previous = 0.0;
+    cumulative
+        .into_iter()
+        .map(|(upper_bound, count)| {
+            // A misbehaving exporter can emit non-monotonic buckets; clamp
+            // rather than charting negative counts.
+            let own = (count - previous).max(0.0);
+            previous = count;
+            Bucket { upper_bound, count: own }
+        })
+        .collect()
+}
+
+pub fn format_bound(upper_bound: f64) -> String {
+    format!("≤{}", format_raw(upper_bound))
+}
+
+/// A histogram's `(upper bound, cumulative count)` pairs, `+Inf` last.
+pub fn cumulative_buckets(histogram: &HistogramValue) -> Vec<(f64, f64)> {
+    let mut buckets: Vec<(f64, f64)> = histogram.buckets.iter().map(|b| (b.upper_bound, b.count)).collect();
+    buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    buckets
+}
+
+/// Cumulative buckets of several series added up bound by bound, like
+/// `sum by (le)`.
+pub fn sum_buckets<'a>(series: impl IntoIterator<Item = &'a [(f64, f64)]>) -> Vec<(f64, f64)> {
+    let mut total: Vec<(f64, f64)> = Vec::new();
+    for buckets in series {
+        for &(upper_bound, count) in buckets {
+            match total.binary_search_by(|(bound, _)| bound.total_cmp(&upper_bound)) {
+                Ok(i) => total[i].1 += count,
+                Err(i) => total.insert(i, (upper_bound, count)),
+            }
+        }
+    }
+    total
+}
+
+/// What was observed between two scrapes of the same histogram. A count
+/// going backwards means the process restarted, so everything it has
+/// counted since is the increase, as `rate()` would take it.
+pub fn bucket_increase(current: &[(f64, f64)], previous: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let same_bounds = current.len() == previous.len() && current.iter().zip(previous).all(|(c, p)| c.0 == p.0);
+    if !same_bounds || current.iter().zip(previous).any(|(c, p)| c.1 < p.1) {
+        return current.to_vec();
+    }
+    current.iter().zip(previous).map(|(c, p)| (c.0, c.1 - p.1)).collect()
+}
+
+/// Where a quantile of a histogram's observations lies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuantileEstimate {
+    /// Interpolated linearly within the bucket the quantile falls in.
+    Within(f64),
+    /// The quantile falls in the `+Inf` bucket, so all that's known is
+    /// that it's above the highest finite bound.
+    Above(f64),
+}
+
+/// Estimates quantile `q` (0 to 1) from cumulative buckets the way
+/// Prometheus' `histogram_quantile()` does: observations are assumed to
+/// spread evenly within a bucket, and the lowest bucket to start at 0 when
+/// its bound is positive. `None` when there's nothing to estimate from: no
+/// `+Inf` bucket, no finite one, or no observations.
+pub fn histogram_quantile(q: f64, buckets: &[(f64, f64)]) -> Option<QuantileEstimate> {
+    let mut buckets = buckets.to_vec();
+    buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if buckets.len() < 2 || buckets.last()?.0 != f64::INFINITY {
+        return None;
+    }
+    // Counts that go down between bounds can't be right; Prometheus evens
+    // them out the same way.
+    for i in 1..buckets.len() {
+        buckets[i].1 = buckets[i].1.max(buckets[i - 1].1);
+    }
+    let observations = buckets.last()?.1;
+    if observations.is_nan() || observations <= 0.0 {
+        return None;
+    }
+    let rank = q.clamp(0.0, 1.0) * observations;
+    let b = buckets.iter().position(|&(_, count)| count >= rank)?;
+    if b == buckets.len() - 1 {
+        return Some(QuantileEstimate::Above(buckets[b - 1].0));
+    }
+    let (upper_bound, count) = buckets[b];
+    if b == 0 && upper_bound <= 0.0 {
+        return Some(QuantileEstimate::Within(upper_bound));
+    }
+    let (lower_bound, below) = if b == 0 { (0.0, 0.0) } else { buckets[b - 1] };
+    let in_bucket = count - below;
+    if in_bucket <= 0.0 {
+        return Some(QuantileEstimate::Within(upper_bound));
+    }
+    Some(QuantileEstimate::Within(
+        lower_bound + (upper_bound - lower_bound) * ((rank - below) / in_bucket),
+    ))
+}
+
+/// A quantile as a percentile, e.g. 0.99 as "p99" and 0.999 as "p99.9".
+pub fn format_percentile(q: f64) -> String {
+    format!("p{}", (q * 100_000.0).round() / 1000.0)
+}
+
+/// How NaN and the infinities read wherever a value is shown.
+pub fn special_value(value: f64) -> Option<&'static str> {
+    if value.is_nan() {
+        Some("NaN")
+    } else if value == f64::INFINITY {
+        Some("∞")
+    } else if value == f64::NEG_INFINITY {
+        Some("-∞")
+    } else {
+        None
+    }
+}
+
+/// `significant` digits in scientific notation with trailing zeros dropped,
+/// e.g. `1.5e-7` or `3e21`, for magnitudes no suffix reads well at.
+pub fn scientific(value: f64, significant: usize) -> String {
+    let formatted = format!("{value:.precision$e}", precision = significant.saturating_sub(1));
+    match formatted.split_once('e') {
+        Some((mantissa, exponent)) if mantissa.contains('.') => {
+            format!("{}e{exponent}", mantissa.trim_end_matches('0').trim_end_matches('.'))
+        }
+        _ => formatted,
+    }
+}
+
+/// Whether a finite, non-zero value is too large or too small to write out
+/// in full.
+fn is_extreme(value: f64) -> bool {
+    value != 0.0 && !(1e-4..1e15).contains(&value.abs())
+}
+
+/// Formats a float with four significant digits, dropping trailing zeros,
+/// so quantiles like `0.004512837` read as `0.004513`.
+pub fn format_number(value: f64) -> String {
+    if let Some(special) = special_value(value) {
+        return special.to_string();
+    }
+    if value == 0.0 {
+        return String::from("0");
+    }
+    if is_extreme(value) {
+        return scientific(value, 4);
+    }
+    let magnitude = value.abs().log10().floor() as i32;
+    let decimals = (3 - magnitude).clamp(0, 12) as usize;
+    let formatted = format!("{value:.decimals$}");
+    if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        formatted
+    }
+}
+
+/// A float exactly as exposed, for `--raw-values`: every digit, but in
+/// scientific notation once writing it out would take dozens of zeros.
+pub fn format_raw(value: f64) -> String {
+    match special_value(value) {
+        Some(special) => special.to_string(),
+        None if is_extreme(value) => format!("{value:e}"),
+        None => value.to_string(),
+    }
+}
+
+/// Orders values numerically with NaN last whatever its sign, which
+/// `f64::total_cmp` would put at either end.
+pub fn compare_values(a: f64, b: f64) -> std::cmp::Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.total_cmp(&b),
+        (a, b) => a.cmp(&b),
+    }
+}
+
+/// Quantiles of a summary sorted numerically, with NaN (no observations in
+/// the current window) kept as `None`.
+pub fn sorted_quantiles(summary: &SummaryValue) -> Vec<(f64, Option<f64>)> {
+    let mut quantiles: Vec<(f64, Option<f64>)> = summary
+        .quantiles
+        .iter()
+        .map(|q| {
+            let value = q.value.as_f64();
+            (q.quantile, (!value.is_nan()).then_some(value))
+        })
+        .collect();
+    quantiles.sort_by(|a, b| compare_values(a.0, b.0));
+    quantiles
+}
+
+/// Identifies a series across scrapes: the family name plus its labels
+/// sorted by name, so label order in the exposition doesn't matter.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SeriesKey {
+    pub family: String,
+    pub labels: String,
+}
+
+impl SeriesKey {
+    pub fn new(family: &str, sample: &Series) -> SeriesKey {
+        let mut pairs = label_pairs(sample);
+        pairs.sort();
+        SeriesKey {
+            family: family.to_string(),
+            labels: format_labels(&pairs),
+        }
+    }
+}
+
+/// `family{labels}`, or just the family when there are no labels.
+impl std::fmt::Display for SeriesKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.labels.is_empty() {
+            f.write_str(&self.family)
+        } else {
+            write!(f, "{}{{{}}}", self.family, self.labels)
+        }
+    }
+}
+
+/// The single number behind a counter, gauge, or untyped sample.
+pub fn scalar_value(value: &PrometheusValue) -> Option<f64> {
+    match value {
+        PrometheusValue::Unknown(n) | PrometheusValue::Gauge(n) => Some(n.as_f64()),
+        PrometheusValue::Counter(c) => Some(c.value.as_f64()),
+        PrometheusValue::Histogram(_) | PrometheusValue::Summary(_) => None,
+    }
+}
+
+/// Families whose content is in their labels rather than their value, which
+/// is shown as the labels instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LabelFamily {
+    /// `_info` families like `go_info` and OpenMetrics `info` ones, always 1.
+    Info,
+    /// OpenMetrics state sets: a series per state, labelled with the family
+    /// name, that is 1 for the states it's in.
+    StateSet,
+}
+
+/// The `info` and `stateset` families an OpenMetrics scrape declares, which
+/// the parser turns into plain gauges.
+pub fn declared_label_families(text: &str) -> HashMap<String, LabelFamily> {
+    text.lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix("# TYPE ")?.split_whitespace();
+            let name = words.next()?;
+            let kind = match words.next()? {
+                "info" => LabelFamily::Info,
+                "stateset" => LabelFamily::StateSet,
+                _ => return None,
+            };
+            Some((name.to_string(), kind))
+        })
+        .collect()
+}
+
+/// Whether `fam` was declared as one of those or, by the Prometheus
+/// convention, is a gauge or untyped `_info` family whose series are all 1.
+pub fn label_family(name: &str, fam: &Family, declared: &HashMap<String, LabelFamily>) -> Option<LabelFamily> {
+    if let Some(kind) = declared.get(name) {
+        return Some(*kind);
+    }
+    let info = name.ends_with("_info")
+        && matches!(fam.family_type, PrometheusType::Gauge | PrometheusType::Unknown)
+        && !fam.get_label_names().is_empty()
+        && fam.iter_samples().all(|sample| scalar_value(&sample.value) == Some(1.0));
+    info.then_some(LabelFamily::Info)
+}
+
+/// A line per labelset of a label family: an info family's labels, or a
+/// state set's other labels with the states it's in.
+pub fn label_lines(name: &str, fam: &Family, kind: LabelFamily) -> Vec<String> {
+    match kind {
+        LabelFamily::Info => fam.iter_samples().map(|sample| format_labels(&label_pairs(sample))).collect(),
+        LabelFamily::StateSet => {
+            let mut sets: Vec<(String, Vec<String>)> = Vec::new();
+            for sample in fam.iter_samples() {
+                let (states, others): (Vec<_>, Vec<_>) =
+                    label_pairs(sample).into_iter().partition(|(label, _)| label == name);
+                let others = format_labels(&others);
+                let index = match sets.iter().position(|(labels, _)| *labels == others) {
+                    Some(index) => index,
+                    None => {
+                        sets.push((others, Vec::new()));
+                        sets.len() - 1
+                    }
+                };
+                if scalar_value(&sample.value).is_some_and(|value| value != 0.0) {
+                    sets[index].1.extend(states.into_iter().map(|(_, state)| state));
+                }
+            }
+            sets.into_iter()
+                .map(|(labels, states)| {
+                    let states = if states.is_empty() { String::from("(none)") } else { states.join(", ") };
+                    if labels.is_empty() {
+                        states
+                    } else {
+                        format!("{labels}: {states}")
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// The exemplars of a series, each with the upper bound of the bucket it's
+/// attached to for histograms. Only OpenMetrics scrapes carry them.
+pub fn exemplars(value: &PrometheusValue) -> Vec<(Option<f64>, &Exemplar)> {
+    match value {
+        PrometheusValue::Counter(counter) => counter.exemplar.iter().map(|exemplar| (None, exemplar)).collect(),
+        PrometheusValue::Histogram(histogram) => histogram
+            .buckets
+            .iter()
+            .filter_map(|bucket| Some((Some(bucket.upper_bound), bucket.exemplar.as_ref()?)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Label names tracing libraries put an exemplar's trace ID under.
+const TRACE_ID_LABELS: &[&str] = &["trace_id", "traceid", "trace-id"];
+
+/// The trace ID of an exemplar: the value of a label named like one, or
+/// failing that one shaped like a W3C (32 hex digits) or 64-bit (16) ID.
+pub fn trace_id(exemplar: &Exemplar) -> Option<&str> {
+    let mut labels: Vec<(&String, &String)> = exemplar.labels.iter().collect();
+    labels.sort();
+    let by_name = labels
+        .iter()
+        .find(|(name, _)| TRACE_ID_LABELS.contains(&name.to_ascii_lowercase().as_str()));
+    let by_shape = || {
+        labels
+            .iter()
+            .find(|(_, value)| matches!(value.len(), 16 | 32) && value.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+    by_name.or_else(by_shape).map(|&(_, value)| value.as_str())
+}
+
+/// Scalar values of every series in a scrape, kept around to compare the
+/// next scrape against.
+pub struct Snapshot {
+    pub taken_at: Instant,
+    pub values: HashMap<SeriesKey, f64>,
+    /// Cumulative buckets of every histogram series, for quantiles of what
+    /// was observed in between.
+    pub buckets: HashMap<SeriesKey, Vec<(f64, f64)>>,
+}
+
+impl Snapshot {
+    pub fn capture(exposition: &Exposition, taken_at: Instant) -> Snapshot {
+        let values = exposition
+            .families
+            .iter()
+            .flat_map(|(name, fam)| {
+                fam.iter_samples()
+                    .filter_map(move |sample| Some((SeriesKey::new(name, sample), scalar_value(&sample.value)?)))
+            })
+            .collect();
+        let buckets = exposition
+            .families
+            .iter()
+            .flat_map(|(name, fam)| {
+                fam.iter_samples().filter_map(move |sample| match &sample.value {
+                    PrometheusValue::Histogram(histogram) => {
+                        Some((SeriesKey::new(name, sample), cumulative_buckets(histogram)))
+                    }
+                    _ => None,
+                })
+            })
+            .collect();
+        Snapshot {
+            taken_at,
+            values,
+            buckets,
+        }
+    }
+}
+
+/// How a series moved since the previous scrape.
+pub enum Change {
+    /// Per-second increase of a counter.
+    Rate(f64),
+    /// Signed difference of a gauge.
+    Delta(f64),
+    /// The counter went backwards, i.e. the process restarted.
+    Reset,
+    /// The series wasn't in the previous scrape.
+    New,
+}
+
+pub fn change(family_type: &PrometheusType, previous: Option<f64>, current: f64, elapsed: Duration) -> Option<Change> {
+    let Some(previous) = previous else {
+        return Some(Change::New);
+    };
+    // A NaN on either side (or ∞ minus ∞) says nothing about how fast the
+    // series moved.
+    if previous.is_nan() || current.is_nan() || (previous.is_infinite() && previous == current) {
+        return None;
+    }
+    match family_type {
+        PrometheusType::Counter if current < previous => Some(Change::Reset),
+        PrometheusType::Counter if elapsed.is_zero() => None,
+        PrometheusType::Counter => Some(Change::Rate((current - previous) / elapsed.as_secs_f64())),
+        PrometheusType::Gauge => Some(Change::Delta(current - previous)),
+        _ => None,
+    }
+}
+
+pub fn format_change(change: &Change) -> String {
+    match change {
+        Change::Rate(rate) => format!("{}/s", format_number(*rate)),
+        Change::Delta(delta) if *delta > 0.0 => format!("+{}", format_number(*delta)),
+        Change::Delta(delta) => format_number(*delta),
+        Change::Reset => String::from("↺ reset"),
+        Change::New => String::from("new"),
+    }
+}
+
+/// Values to compare later scrapes against, taken with `B`, so a load test
+/// can be read as what changed since it started rather than since the last
+/// refresh.
+pub struct Baseline {
+    pub taken_at: Instant,
+    values: HashMap<SeriesKey, f64>,
+    /// Every counter's increase so far and the value it was last seen at.
+    /// A counter going backwards restarted from zero, so what it counts
+    /// since adds on, as with PromQL's `increase()`.
+    counters: HashMap<SeriesKey, (f64, f64)>,
+}
+
+/// How a series moved since the baseline.
+#[derive(Debug, PartialEq)]
+pub enum SinceBaseline {
+    /// What a counter counted, resets included.
+    Increase(f64),
+    /// Signed difference of a gauge or untyped series.
+    Delta(f64),
+    /// The series wasn't in the baseline; its whole current value.
+    New(f64),
+}
+
+impl Baseline {
+    pub fn capture(exposition: &Exposition, taken_at: Instant) -> Baseline {
+        let values = Snapshot::capture(exposition, taken_at).values;
+        let counters = values
+            .iter()
+            .filter(|(key, _)| {
+                exposition.families.get(&key.family).is_some_and(|fam| fam.family_type == PrometheusType::Counter)
+            })
+            .map(|(key, value)| (key.clone(), (0.0, *value)))
+            .collect();
+        Baseline {
+            taken_at,
+            values,
+            counters,
+        }
+    }
+
+    /// Adds what counters counted since the previous scrape.
+    pub fn update(&mut self, exposition: &Exposition) {
+        for (name, fam) in &exposition.families {
+            if fam.family_type != PrometheusType::Counter {
+                continue;
+            }
+            for sample in fam.iter_samples() {
+                let Some(current) = scalar_value(&sample.value) else {
+                    continue;
+                };
+                if let Some((increase, last)) = self.counters.get_mut(&SeriesKey::new(name, sample)) {
+                    *increase += if current < *last { current } else { current - *last };
+                    *last = current;
+                }
+            }
+        }
+    }
+
+    /// `None` for histograms and summaries, which have no single value.
+    pub fn since(&self, name: &str, sample: &Series) -> Option<SinceBaseline> {
+        let current = scalar_value(&sample.value)?;
+        let key = SeriesKey::new(name, sample);
+        let Some(before) = self.values.get(&key) else {
+            return Some(SinceBaseline::New(current));
+        };
+        Some(match self.counters.get(&key) {
+            Some((increase, _)) => SinceBaseline::Increase(*increase),
+            None => SinceBaseline::Delta(current - before),
+        })
+    }
+}
+
+pub fn format_since_baseline(since: &SinceBaseline) -> String {
+    match since {
+        SinceBaseline::Increase(increase) | SinceBaseline::Delta(increase) if *increase > 0.0 => {
+            format!("+{}", format_number(*increase))
+        }
+        SinceBaseline::Increase(increase) | SinceBaseline::Delta(increase) => format_number(*increase),
+        SinceBaseline::New(value) => format!("{} new", format_number(*value)),
+    }
+}
+
+/// A sample's own timestamp as a time. The text format says milliseconds and
+/// OpenMetrics seconds, but exporters mix them up, so the unit is guessed from
+/// the magnitude instead: 1e11 is the year 5138 in seconds but 1973 in
+/// milliseconds, and each further factor of 1000 likewise separates
+/// milliseconds from microseconds and those from nanoseconds. `None` for
+/// negative or non-finite timestamps.
+pub fn sample_time(timestamp: f64) -> Option<SystemTime> {
+    if !timestamp.is_finite() || timestamp < 0.0 {
+        return None;
+    }
+    let per_second = match timestamp {
+        t if t < 1e11 => 1.0,
+        t if t < 1e14 => 1e3,
+        t if t < 1e17 => 1e6,
+        _ => 1e9,
+    };
+    SystemTime::UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(timestamp / per_second).ok()?)
+}
+
+/// `3m12s`: the two largest units of a duration, to the second.
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let units = [(86_400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let Some(first) = units.iter().position(|(size, _)| seconds >= *size) else {
+        return String::from("0s");
+    };
+    units[first..]
+        .iter()
+        .take(2)
+        .scan(seconds, |left, (size, unit)| {
+            let count = *left / size;
+            *left %= size;
+            Some((count, unit))
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{count}{unit}"))
+        .collect()
+}
+
+/// `2024-06-26T20:00:00Z, 3m12s ago` or `…, in 5s`, for a sample timestamp
+/// relative to `now`; the number itself when it isn't a plausible time.
+pub fn format_sample_time(timestamp: f64, now: SystemTime) -> String {
+    let Some(at) = sample_time(timestamp) else {
+        return format_number(timestamp);
+    };
+    let absolute = humantime::format_rfc3339_seconds(at);
+    match now.duration_since(at) {
+        Ok(age) => format!("{absolute}, {} ago", format_age(age)),
+        Err(ahead) => format!("{absolute}, in {}", format_age(ahead.duration())),
+    }
+}
+
+/// A series whose own timestamp is further from the time it was scraped
+/// than `--max-timestamp-skew`.
+#[derive(Debug, PartialEq)]
+pub struct Skewed {
+    pub family: String,
+    pub labels: String,
+    /// Seconds the timestamp is behind the scrape; negative when ahead.
+    pub behind: f64,
+}
+
+/// Every series with a timestamp more than `max` away from `scraped_at`,
+/// furthest off first.
+pub fn skewed_timestamps(exposition: &Exposition, scraped_at: SystemTime, max: Duration) -> Vec<Skewed> {
+    let mut skewed: Vec<Skewed> = exposition
+        .families
+        .iter()
+        .flat_map(|(name, fam)| fam.iter_samples().map(move |sample| (name, sample)))
+        .filter_map(|(name, sample)| {
+            let at = sample_time(sample.timestamp?)?;
+            let behind = match scraped_at.duration_since(at) {
+                Ok(behind) => behind.as_secs_f64(),
+                Err(ahead) => -ahead.duration().as_secs_f64(),
+            };
+            (behind.abs() > max.as_secs_f64()).then(|| Skewed {
+                family: name.clone(),
+                labels: format_labels(&label_pairs(sample)),
+                behind,
+            })
+        })
+        .collect();
+    skewed.sort_by(|a, b| {
+        b.behind
+            .abs()
+            .total_cmp(&a.behind.abs())
+            .then_with(|| a.family.cmp(&b.family))
+            .then_with(|| a.labels.cmp(&b.labels))
+    });
+    skewed
+}
+
+/// `up{job="a"} is 3m12s behind` for the status bar.
+pub fn format_skew(skewed: &Skewed) -> String {
+    let series = if skewed.labels.is_empty() {
+        skewed.family.clone()
+    } else {
+        format!("{}{{{}}}", skewed.family, skewed.labels)
+    };
+    let by = format_age(Duration::from_secs_f64(skewed.behind.abs()));
+    if skewed.behind >= 0.0 {
+        format!("{series} is {by} behind")
+    } else {
+        format!("{series} is {by} ahead")
+    }
+}
+
+/// A series ranked by how fast it's changing.
+pub struct Mover {
+    pub key: SeriesKey,
+    pub previous: f64,
+    pub current: f64,
+    /// A counter's rate or a gauge's delta.
+    pub change: Change,
+}
+
+impl Mover {
+    fn magnitude(&self) -> f64 {
+        match self.change {
+            Change::Rate(value) | Change::Delta(value) => value.abs(),
+            Change::Reset | Change::New => 0.0,
+        }
+    }
+}
+
+/// The `n` series changing fastest since `previous`: counters by rate and
+/// gauges by absolute delta, ties broken by name so the order only moves
+/// when the values do. Resets and unchanged series don't rank.
+pub fn top_movers(exposition: &Exposition, previous: &Snapshot, taken_at: Instant, n: usize) -> Vec<Mover> {
+    let elapsed = taken_at.saturating_duration_since(previous.taken_at);
+    let mut movers: Vec<Mover> = exposition
+        .families
+        .iter()
+        .flat_map(|(name, fam)| fam.iter_samples().map(move |sample| (name, fam, sample)))
+        .filter_map(|(name, fam, sample)| {
+            let current = scalar_value(&sample.value)?;
+            let key = SeriesKey::new(name, sample);
+            let before = *previous.values.get(&key)?;
+            let change = change(&fam.family_type, Some(before), current, elapsed)?;
+            let mover = Mover {
+                key,
+                previous: before,
+                current,
+                change,
+            };
+            (mover.magnitude() > 0.0).then_some(mover)
+        })
+        .collect();
+    movers.sort_by(|a, b| {
+        b.magnitude()
+            .total_cmp(&a.magnitude())
+            .then_with(|| a.key.family.cmp(&b.key.family))
+            .then_with(|| a.key.labels.cmp(&b.key.labels))
+    });
+    movers.truncate(n);
+    movers
+}
+
+/// A family or series that was in an earlier scrape but not the latest.
+pub struct Gone {
+    pub family_type: String,
+    /// The value it had in the last scrape it appeared in.
+    pub last_value: String,
+    /// 1 right after it disappeared.
+    pub scrapes_ago: u32,
+}
+
+/// What appeared and disappeared between consecutive scrapes. Appearances
+/// last one scrape; disappearances are kept for `retention` scrapes so they
+/// can be shown rather than silently dropped.
+#[derive(Default)]
+pub struct Churn {
+    pub new_families: HashSet<String>,
+    pub new_series: HashSet<SeriesKey>,
+    pub gone_families: HashMap<String, Gone>,
+    pub gone_series: HashMap<SeriesKey, Gone>,
+}
+
+impl Churn {
+    pub fn update(&mut self, previous: &Exposition, current: &Exposition, retention: u32) {
+        let current_series: HashSet<SeriesKey> = series_keys(current).collect();
+        let previous_series: HashSet<SeriesKey> = series_keys(previous).collect();
+
+        for gone in self.gone_families.values_mut().chain(self.gone_series.values_mut()) {
+            gone.scrapes_ago += 1;
+        }
+        self.gone_families
+            .retain(|name, gone| gone.scrapes_ago <= retention && !current.families.contains_key(name));
+        self.gone_series
+            .retain(|key, gone| gone.scrapes_ago <= retention && !current_series.contains(key));
+
+        self.new_families = current
+            .families
+            .keys()
+            .filter(|name| !previous.families.contains_key(*name))
+            .cloned()
+            .collect();
+        self.new_series = current_series.difference(&previous_series).cloned().collect();
+
+        if retention == 0 {
+            return;
+        }
+        for (name, fam) in &previous.families {
+            if !current.families.contains_key(name) {
+                let series = fam.iter_samples().count();
+                let last_value = match fam.iter_samples().next() {
+                    Some(sample) if series == 1 => format_raw_value(&sample.value),
+                    _ => format!("{series} series"),
+                };
+                self.gone_families.insert(
+                    name.clone(),
+                    Gone {
+                        family_type: fam.family_type.to_string(),
+                        last_value,
+                        scrapes_ago: 1,
+                    },
+                );
+            }
+            for sample in fam.iter_samples() {
+                let key = SeriesKey::new(name, sample);
+                if !current_series.contains(&key) {
+                    let gone = Gone {
+                        family_type: fam.family_type.to_string(),
+                        last_value: format_raw_value(&sample.value),
+                        scrapes_ago: 1,
+                    };
+                    self.gone_series.insert(key, gone);
+                }
+            }
+        }
+    }
+}
+
+fn series_keys(exposition: &Exposition) -> impl Iterator<Item = SeriesKey> + '_ {
+    exposition
+        .families
+        .iter()
+        .flat_map(|(name, fam)| fam.iter_samples().map(move |sample| SeriesKey::new(name, sample)))
+}
+
+/// The number `z` and `Z` look at: a scalar's value, or how many
+/// observations a histogram or summary has.
+pub fn series_number(value: &PrometheusValue) -> Option<f64> {
+    match value {
+        PrometheusValue::Histogram(h) => h.count.map(|count| count as f64),
+        PrometheusValue::Summary(s) => s.count.map(|count| count as f64),
+        _ => scalar_value(value),
+    }
+}
+
+/// How many of a family's series `z` and `Z` would hide.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quiet {
+    pub series: usize,
+    /// Exactly zero.
+    pub zero: usize,
+    /// Unchanged for the last so many refreshes.
+    pub unchanged: usize,
+    /// Zero, unchanged or both.
+    pub either: usize,
+}
+
+/// How many refreshes in a row each series has kept its value, for hiding
+/// the ones that never move.
+#[derive(Default)]
+pub struct Steadiness {
+    scrapes: u32,
+    series: HashMap<SeriesKey, (f64, u32)>,
+}
+
+impl Steadiness {
+    pub fn update(&mut self, exposition: &Exposition) {
+        self.scrapes = self.scrapes.saturating_add(1);
+        let mut series = HashMap::new();
+        for (name, fam) in &exposition.families {
+            for sample in fam.iter_samples() {
+                let Some(value) = series_number(&sample.value) else {
+                    continue;
+                };
+                let key = SeriesKey::new(name, sample);
+                let repeats = match self.series.get(&key) {
+                    Some(&(before, repeats)) if before == value || (before.is_nan() && value.is_nan()) => repeats + 1,
+                    _ => 0,
+                };
+                series.insert(key, (value, repeats));
+            }
+        }
+        self.series = series;
+    }
+
+    /// Whether `key` kept its value through the last `refreshes` refreshes.
+    /// Until there have been that many, nothing is known to be unchanged.
+    pub fn unchanged(&self, key: &SeriesKey, refreshes: u32) -> bool {
+        self.series.get(key).is_some_and(|&(_, repeats)| repeats >= refreshes)
+    }
+
+    /// Refreshes still to come before a series can be told unchanged.
+    pub fn refreshes_to_go(&self, refreshes: u32) -> u32 {
+        (refreshes + 1).saturating_sub(self.scrapes)
+    }
+
+    /// Per family, how many series `z` and `Z` would hide.
+    pub fn quiet(&self, exposition: &Exposition, refreshes: u32) -> HashMap<String, Quiet> {
+        exposition
+            .families
+            .iter()
+            .map(|(name, fam)| {
+                let mut quiet = Quiet::default();
+                for sample in fam.iter_samples() {
+                    let zero = series_number(&sample.value) == Some(0.0);
+                    let unchanged = self.unchanged(&SeriesKey::new(name, sample), refreshes);
+                    quiet.series += 1;
+                    quiet.zero += usize::from(zero);
+                    quiet.unchanged += usize::from(unchanged);
+                    quiet.either += usize::from(zero || unchanged);
+                }
+                (name.clone(), quiet)
+            })
+            .collect()
+    }
+}
+
+/// How a counter went backwards between two scrapes.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Decrease {
+    /// Back to near zero: the process restarted, which rates cope with.
+    Reset,
+    /// Down, but nowhere near zero. Usually an exporter bug, or several
+    /// processes answering behind one endpoint.
+    Partial,
+}
+
+/// A drop to at most this fraction of the previous value counts as a reset.
+const RESET_FRACTION: f64 = 0.1;
+
+/// Events kept per series; older ones are dropped but still counted.
+const MAX_COUNTER_EVENTS: usize = 50;
+
+pub struct CounterEvent {
+    pub at: SystemTime,
+    pub decrease: Decrease,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// Every time a counter series went backwards since launch, found by
+/// comparing each scrape with the last value of every counter series.
+#[derive(Default)]
+pub struct CounterResets {
+    last: HashMap<SeriesKey, f64>,
+    pub events: HashMap<SeriesKey, Vec<CounterEvent>>,
+    pub resets: usize,
+    pub partial: usize,
+}
+
+impl CounterResets {
+    /// Compares `exposition` with the last values seen and returns the
+    /// series that went backwards this time, each with a new event.
+    pub fn update(&mut self, exposition: &Exposition, at: SystemTime) -> Vec<SeriesKey> {
+        let mut last = HashMap::new();
+        let mut went_back = Vec::new();
+        for (name, fam) in &exposition.families {
+            if fam.family_type != PrometheusType::Counter {
+                continue;
+            }
+            for sample in fam.iter_samples() {
+                let Some(after) = scalar_value(&sample.value) else {
+                    continue;
+                };
+                let key = SeriesKey::new(name, sample);
+                if let Some(&before) = self.last.get(&key).filter(|before| after < **before) {
+                    let decrease = if after <= before * RESET_FRACTION {
+                        self.resets += 1;
+                        Decrease::Reset
+                    } else {
+                        self.partial += 1;
+                        Decrease::Partial
+                    };
+                    let events = self.events.entry(key.clone()).or_default();
+                    if events.len() == MAX_COUNTER_EVENTS {
+                        events.remove(0);
+                    }
+                    events.push(CounterEvent {
+                        at,
+                        decrease,
+                        before,
+                        after,
+                    });
+                    went_back.push(key.clone());
+                }
+                last.insert(key, after);
+            }
+        }
+        self.last = last;
+        went_back
+    }
+
+    /// Whether the series ever went down without resetting.
+    pub fn has_partial(&self, key: &SeriesKey) -> bool {
+        self.events
+            .get(key)
+            .is_some_and(|events| events.iter().any(|event| event.decrease == Decrease::Partial))
+    }
+
+    /// Whether any series of the family ever went down without resetting.
+    pub fn family_has_partial(&self, family: &str) -> bool {
+        self.events.keys().any(|key| key.family == family && self.has_partial(key))
+    }
+}
+
+/// How many series a family has and which label drives that number.
+pub struct Cardinality {
+    pub family: String,
+    pub series: usize,
+    /// Exposition lines, counting every bucket and quantile of histograms
+    /// and summaries along with their `_sum` and `_count`.
+    pub samples: usize,
+    /// The label name with the most distinct values, and how many.
+    pub top_label: Option<(String, usize)>,
+}
+
+/// Walks every labelset once. Sorted by series count, highest first.
+pub fn cardinality(exposition: &Exposition) -> Vec<Cardinality> {
+    let mut families: Vec<Cardinality> = exposition
+        .families
+        .iter()
+        .map(|(name, fam)| {
+            let mut distinct: HashMap<String, HashSet<String>> = HashMap::new();
+            let mut series = 0;
+            let mut samples = 0;
+            for sample in fam.iter_samples() {
+                series += 1;
+                samples += match &sample.value {
+                    PrometheusValue::Histogram(h) => h.buckets.len() + 2,
+                    PrometheusValue::Summary(s) => s.quantiles.len() + 2,
+                    _ => 1,
+                };
+                for (label, value) in label_pairs(sample) {
+                    distinct.entry(label).or_default().insert(value);
+                }
+            }
+            let top_label = distinct
+                .into_iter()
+                .map(|(label, values)| (label, values.len()))
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)));
+            Cardinality {
+                family: name.clone(),
+                series,
+                samples,
+                top_label,
+            }
+        })
+        .collect();
+    families.sort_by(|a, b| b.series.cmp(&a.series).then_with(|| a.family.cmp(&b.family)));
+    families
+}
+
+/// How many of a label's most common values [`label_cardinality`] keeps.
+pub const TOP_VALUES: usize = 10;
+
+/// How much one label of a family adds to its series count.
+#[derive(Debug, PartialEq)]
+pub struct LabelCardinality {
+    pub label: String,
+    /// Distinct values among the series that have the label.
+    pub distinct: usize,
+    /// The most common values and how many series have each, most first.
+    pub top_values: Vec<(String, usize)>,
+    /// How many times fewer series there'd be without the label: the
+    /// series count over the labelsets left once it's dropped.
+    pub factor: f64,
+}
+
+/// Breaks a family's series down by label, the label multiplying the
+/// series count most first. Every label takes a pass over the series, so
+/// this is worked out when asked for rather than per scrape.
+pub fn label_cardinality(fam: &Family) -> Vec<LabelCardinality> {
+    let series: Vec<Vec<(String, String)>> = fam.iter_samples().map(label_pairs).collect();
+    let mut names: Vec<&str> = series.iter().flatten().map(|(name, _)| name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+    let mut labels: Vec<LabelCardinality> = names
+        .into_iter()
+        .map(|label| {
+            let mut values: HashMap<&str, usize> = HashMap::new();
+            let mut rest: HashSet<Vec<&(String, String)>> = HashSet::new();
+            for labels in &series {
+                if let Some((_, value)) = labels.iter().find(|(name, _)| name == label) {
+                    *values.entry(value).or_default() += 1;
+                }
+                rest.insert(labels.iter().filter(|(name, _)| name != label).collect());
+            }
+            let distinct = values.len();
+            let mut top_values: Vec<(String, usize)> =
+                values.into_iter().map(|(value, count)| (value.to_string(), count)).collect();
+            top_values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| natural::compare(&a.0, &b.0)));
+            top_values.truncate(TOP_VALUES);
+            LabelCardinality {
+                label: label.to_string(),
+                distinct,
+                top_values,
+                factor: series.len() as f64 / rest.len().max(1) as f64,
+            }
+        })
+        .collect();
+    labels.sort_by(|a, b| {
+        b.factor
+            .total_cmp(&a.factor)
+            .then_with(|| b.distinct.cmp(&a.distinct))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    labels
+}
+
+/// A `metric_relabel_configs` entry that blanks `label` on the family's
+/// samples, which Prometheus takes as dropping it, merging the series it
+/// told apart.
+pub fn drop_label_rule(family: &str, fam: &Family, label: &str) -> String {
+    let suffixes = match fam.family_type {
+        PrometheusType::Histogram => "(_bucket|_count|_sum|_created)?",
+        PrometheusType::Summary => "(_count|_sum|_created)?",
+        PrometheusType::Counter => "(_total|_created)?",
+        _ => "",
+    };
+    format!(
+        "- source_labels: [__name__]\n  regex: {family}{suffixes}\n  target_label: {label}\n  replacement: \"\"\n"
+    )
+}
+
+/// What the main table shows for a family of several series, worked out
+/// once per scrape rather than on every frame.
+#[derive(Debug, PartialEq)]
+pub struct FamilySummary {
+    pub series: usize,
+    pub stats: SummaryStats,
+}
+
+/// The aggregate that reads best for a family's type.
+#[derive(Debug, PartialEq)]
+pub enum SummaryStats {
+    /// Gauges and untyped families, whose values can't be added up.
+    Range { min: f64, avg: f64, max: f64 },
+    /// Counters, which add up to the family's total.
+    Sum(f64),
+    /// Histograms and summaries: observations across every series.
+    Observations(u64),
+    /// No series had a number to summarize, e.g. they're all NaN.
+    Empty,
+}
+
+pub fn family_summaries(exposition: &Exposition) -> HashMap<String, FamilySummary> {
+    exposition
+        .families
+        .iter()
+        .map(|(name, fam)| {
+            let series = fam.iter_samples().count();
+            let stats = match &fam.family_type {
+                PrometheusType::Histogram | PrometheusType::Summary => {
+                    let counts = fam.iter_samples().filter_map(|sample| match &sample.value {
+                        PrometheusValue::Histogram(h) => h.count,
+                        PrometheusValue::Summary(s) => s.count,
+                        _ => None,
+                    });
+                    SummaryStats::Observations(counts.sum())
+                }
+                _ => {
+                    let values: Vec<f64> = fam
+                        .iter_samples()
+                        .filter_map(|sample| scalar_value(&sample.value))
+                        .filter(|value| !value.is_nan())
+                        .collect();
+                    if values.is_empty() {
+                        SummaryStats::Empty
+                    } else if fam.family_type == PrometheusType::Counter {
+                        SummaryStats::Sum(values.iter().sum())
+                    } else {
+                        SummaryStats::Range {
+                            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+                            avg: values.iter().sum::<f64>() / values.len() as f64,
+                            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                        }
+                    }
+                }
+            };
+            (name.clone(), FamilySummary { series, stats })
+        })
+        .collect()
+}
+
+/// Direction a series (or a whole family) moved between two scrapes.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Movement {
+    Up,
+    Down,
+    /// Some series went up and others down, or appeared.
+    Changed,
+    Unchanged,
+}
+
+impl Movement {
+    pub fn between(previous: Option<f64>, current: f64) -> Movement {
+        match previous {
+            None => Movement::Changed,
+            Some(previous) if current > previous => Movement::Up,
+            Some(previous) if current < previous => Movement::Down,
+            // NaN never equals itself, so compare bit patterns for "unchanged".
+            Some(previous) if current == previous || current.to_bits() == previous.to_bits() => Movement::Unchanged,
+            Some(_) => Movement::Changed,
+        }
+    }
+
+    fn combine(self, other: Movement) -> Movement {
+        match (self, other) {
+            (a, Movement::Unchanged) => a,
+            (Movement::Unchanged, b) => b,
+            (a, b) if a == b => a,
+            _ => Movement::Changed,
+        }
+    }
+}
+
+/// Overall movement of every family in `exposition` relative to `previous`.
+pub fn family_movements(exposition: &Exposition, previous: &Snapshot) -> HashMap<String, Movement> {
+    exposition
+        .families
+        .iter()
+        .map(|(name, fam)| {
+            let movement = fam
+                .iter_samples()
+                .filter_map(|sample| {
+                    let current = scalar_value(&sample.value)?;
+                    let before = previous.values.get(&SeriesKey::new(name, sample)).copied();
+                    Some(Movement::between(before, current))
+                })
+                .fold(Movement::Unchanged, Movement::combine);
+            (name.clone(), movement)
+        })
+        .collect()
+}
+
+/// The label the ALL tab adds to tell the same series from different
+/// endpoints apart.
+pub const ENDPOINT_LABEL: &str = "__endpoint__";
+
+/// How the ALL tab combines series that only differ by endpoint, and how
+/// the detail view combines those that only differ by the labels `w` drops.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// Every endpoint's series side by side, labelled with `__endpoint__`.
+    Each,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregation {
+    pub fn next(self) -> Aggregation {
+        match self {
+            Aggregation::Each => Aggregation::Sum,
+            Aggregation::Sum => Aggregation::Avg,
+            Aggregation::Avg => Aggregation::Min,
+            Aggregation::Min => Aggregation::Max,
+            Aggregation::Max => Aggregation::Each,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregation::Each => "per endpoint",
+            Aggregation::Sum => "sum",
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+        }
+    }
+
+    /// NaN members are skipped rather than poisoning the result, which is
+    /// only NaN when every member is.
+    fn apply(self, values: &[f64]) -> f64 {
+        let numbers: Vec<f64> = values.iter().copied().filter(|value| !value.is_nan()).collect();
+        if numbers.is_empty() && !values.is_empty() {
+            return f64::NAN;
+        }
+        let values = &numbers;
+        match self {
+            Aggregation::Each | Aggregation::Sum => values.iter().sum(),
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Merges the latest scrape of every endpoint into one exposition, like
+/// federation would. Histograms and summaries are always kept per
+/// endpoint. A family whose TYPE differs between endpoints keeps the first
+/// endpoint's series, and each disagreement is returned as a warning.
+pub fn merge(scrapes: &[(&str, &Exposition)], aggregation: Aggregation) -> (Exposition, Vec<String>) {
+    merge_as(scrapes, aggregation, ENDPOINT_LABEL)
+}
+
+/// [`merge`], with the series kept per scrape labelled `label` instead of
+/// `__endpoint__`.
+pub fn merge_as(scrapes: &[(&str, &Exposition)], aggregation: Aggregation, label: &str) -> (Exposition, Vec<String>) {
+    let mut merged: BTreeMap<&str, (&Family, &str, Vec<(&str, &Series)>)> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for &(endpoint, exposition) in scrapes {
+        for (name, fam) in &exposition.families {
+            let (first, first_endpoint, samples) = merged.entry(name).or_insert((fam, endpoint, Vec::new()));
+            if FamilyType::of(first) != FamilyType::of(fam) {
+                warnings.push(format!(
+                    "{name} is a {} on {first_endpoint} but a {} on {endpoint}",
+                    FamilyType::of(first).name(),
+                    FamilyType::of(fam).name()
+                ));
+                continue;
+            }
+            samples.extend(fam.iter_samples().map(|sample| (endpoint, sample)));
+        }
+    }
+
+    let mut families = HashMap::new();
+    for (name, (first, _, samples)) in merged {
+        match merge_family(first, samples, aggregation, label) {
+            Ok(fam) => {
+                families.insert(name.to_string(), fam);
+            }
+            Err(e) => warnings.push(format!("{name} couldn't be merged: {e}")),
+        }
+    }
+    (Exposition { families }, warnings)
+}
+
+fn merge_family(
+    first: &Family,
+    samples: Vec<(&str, &Series)>,
+    aggregation: Aggregation,
+    label: &str,
+) -> Result<Family, ParseError> {
+    let per_endpoint =
+        aggregation == Aggregation::Each || samples.first().is_some_and(|(_, s)| scalar_value(&s.value).is_none());
+
+    // Label names in order of first appearance; series sorted by labels so
+    // the same series from each endpoint end up next to each other.
+    let mut names: Vec<String> = Vec::new();
+    if per_endpoint {
+        names.push(label.to_string());
+    }
+    let mut groups: BTreeMap<Vec<(String, String)>, Vec<(&str, &Series)>> = BTreeMap::new();
+    for (endpoint, sample) in samples {
+        let mut pairs = label_pairs(sample);
+        for (name, _) in &pairs {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        pairs.sort();
+        groups.entry(pairs).or_default().push((endpoint, sample));
+    }
+    let label_values = |pairs: &[(String, String)], endpoint: &str| -> Vec<String> {
+        names
+            .iter()
+            .map(|name| {
+                if name == label {
+                    return endpoint.to_string();
+                }
+                pairs
+                    .iter()
+                    .find(|(label, _)| label == name)
+                    .map_or_else(String::new, |(_, value)| value.clone())
+            })
+            .collect()
+    };
+
+    let mut merged = Vec::new();
+    for (pairs, group) in &groups {
+        if per_endpoint {
+            merged.extend(group.iter().map(|(endpoint, sample)| {
+                Sample::new(label_values(pairs, endpoint), sample.timestamp, sample.value.clone())
+            }));
+            continue;
+        }
+        let values: Vec<f64> = group.iter().filter_map(|(_, sample)| scalar_value(&sample.value)).collect();
+        let combined = MetricNumber::Float(aggregation.apply(&values));
+        let value = match &group[0].1.value {
+            PrometheusValue::Counter(counter) => {
+                let mut counter = counter.clone();
+                counter.value = combined;
+                PrometheusValue::Counter(counter)
+            }
+            PrometheusValue::Gauge(_) => PrometheusValue::Gauge(combined),
+            _ => PrometheusValue::Unknown(combined),
+        };
+        merged.push(Sample::new(label_values(pairs, ""), None, value));
+    }
+    Family::new(
+        first.family_name.clone(),
+        names,
+        first.family_type.clone(),
+        first.help.clone(),
+        first.unit.clone(),
+    )
+    .with_samples(merged)
+}
+
+/// Adds `labels` to every series, the way Prometheus attaches target labels:
+/// an exposed label of the same name is kept as `exported_<name>`.
+pub fn with_labels(exposition: Exposition, labels: &[(String, String)]) -> Result<Exposition, ParseError> {
+    let mut families = HashMap::new();
+    for (name, fam) in exposition.families {
+        let exposed: Vec<String> = fam
+            .get_label_names()
+            .iter()
+            .map(|label| {
+                if labels.iter().any(|(target, _)| target == label) {
+                    format!("exported_{label}")
+                } else {
+                    label.clone()
+                }
+            })
+            .collect();
+        let names: Vec<String> = labels.iter().map(|(label, _)| label.clone()).chain(exposed).collect();
+        let samples: Vec<Series> = fam
+            .iter_samples()
+            .map(|sample| {
+                let pairs = label_pairs(sample);
+                let values = labels
+                    .iter()
+                    .map(|(_, value)| value.clone())
+                    .chain(fam.get_label_names().iter().map(|label| {
+                        pairs
+                            .iter()
+                            .find(|(exposed, _)| exposed == label)
+                            .map_or_else(String::new, |(_, value)| value.clone())
+                    }))
+                    .collect();
+                Sample::new(values, sample.timestamp, sample.value.clone())
+            })
+            .collect();
+        let labelled = Family::new(
+            fam.family_name.clone(),
+            names,
+            fam.family_type.clone(),
+            fam.help.clone(),
+            fam.unit.clone(),
+        )
+        .with_samples(samples)?;
+        families.insert(name, labelled);
+    }
+    Ok(Exposition { families })
+}
+
+/// One series of [`aggregate_without`]: the labels that are left, how many
+/// series went into it and their combined value, if any had one.
+pub struct Aggregate {
+    pub labels: Vec<(String, String)>,
+    pub series: usize,
+    pub value: Option<PrometheusValue>,
+}
+
+/// `<aggregation> without (<labels>)` over the series of `fam`, sorted by
+/// the remaining labels, numbers by value. Scalars are combined from `scalar`, so callers can
+/// pass rates instead of raw values. Histograms are combined bucket by
+/// bucket on the first series' bounds, summaries by count and sum only
+/// (quantiles don't aggregate).
+pub fn aggregate_without(
+    fam: &Family,
+    without: &[String],
+    aggregation: Aggregation,
+    scalar: impl Fn(&Series) -> Option<f64>,
+) -> Vec<Aggregate> {
+    let mut groups: BTreeMap<Vec<(String, String)>, (Vec<(String, String)>, Vec<&Series>)> = BTreeMap::new();
+    for sample in fam.iter_samples() {
+        let labels: Vec<(String, String)> =
+            label_pairs(sample).into_iter().filter(|(name, _)| !without.contains(name)).collect();
+        let mut key = labels.clone();
+        key.sort();
+        groups.entry(key).or_insert_with(|| (labels, Vec::new())).1.push(sample);
+    }
+    let mut aggregates: Vec<Aggregate> = groups
+        .into_values()
+        .map(|(labels, members)| Aggregate {
+            labels,
+            series: members.len(),
+            value: combine(&members, aggregation, &scalar),
+        })
+        .collect();
+    aggregates.sort_by(|a, b| natural::compare_labels(&a.labels, &b.labels));
+    aggregates
+}
+
+/// Whether `sample` is one of the series `aggregate_without` combined into
+/// the aggregate with `labels`.
+pub fn in_aggregate(sample: &Series, without: &[String], labels: &[(String, String)]) -> bool {
+    let mut own: Vec<(String, String)> =
+        label_pairs(sample).into_iter().filter(|(name, _)| !without.contains(name)).collect();
+    own.sort();
+    let mut labels = labels.to_vec();
+    labels.sort();
+    own == labels
+}
+
+fn combine(members: &[&Series], aggregation: Aggregation, scalar: &impl Fn(&Series) -> Option<f64>) -> Option<PrometheusValue> {
+    let apply = |values: Vec<f64>| (!values.is_empty()).then(|| aggregation.apply(&values));
+    match &members.first()?.value {
+        PrometheusValue::Histogram(first) => {
+            let histograms: Vec<&HistogramValue> = members
+                .iter()
+                .filter_map(|sample| match &sample.value {
+                    PrometheusValue::Histogram(h) => Some(h),
+                    _ => None,
+                })
+                .collect();
+            let mut combined = first.clone();
+            for bucket in &mut combined.buckets {
+                // Cumulative counts at the same bound add up to the
+                // cumulative count of the summed per-bucket counts.
+                let counts = histograms.iter().map(|h| cumulative_count(h, bucket.upper_bound)).collect();
+                bucket.count = apply(counts).unwrap_or(0.0);
+            }
+            combined.count = apply(histograms.iter().filter_map(|h| h.count.map(|c| c as f64)).collect())
+                .map(|count| count.round() as u64);
+            combined.sum = apply(histograms.iter().filter_map(|h| h.sum.as_ref().map(|sum| sum.as_f64())).collect())
+                .map(MetricNumber::Float);
+            Some(PrometheusValue::Histogram(combined))
+        }
+        PrometheusValue::Summary(first) => {
+            let summaries: Vec<&SummaryValue> = members
+                .iter()
+                .filter_map(|sample| match &sample.value {
+                    PrometheusValue::Summary(s) => Some(s),
+                    _ => None,
+                })
+                .collect();
+            let mut combined = first.clone();
+            combined.quantiles.clear();
+            combined.count = apply(summaries.iter().filter_map(|s| s.count.map(|c| c as f64)).collect())
+                .map(|count| count.round() as u64);
+            combined.sum = apply(summaries.iter().filter_map(|s| s.sum.as_ref().map(|sum| sum.as_f64())).collect())
+                .map(MetricNumber::Float);
+            Some(PrometheusValue::Summary(combined))
+        }
+        _ => apply(members.iter().filter_map(|sample| scalar(sample)).collect())
+            .map(|value| PrometheusValue::Unknown(MetricNumber::Float(value))),
+    }
+}
+
+/// Observations at or below `bound`, from the largest bucket of `histogram`
+/// that fits under it.
+fn cumulative_count(histogram: &HistogramValue, bound: f64) -> f64 {
+    histogram
+        .buckets
+        .iter()
+        .filter(|bucket| bucket.upper_bound <= bound)
+        .max_by(|a, b| a.upper_bound.total_cmp(&b.upper_bound))
+        .map_or(0.0, |bucket| bucket.count)
+}
+
+/// The PromQL for an aggregation, e.g. `sum without (cpu, mode) (rate(node_cpu_seconds_total[5m]))`.
+/// The range is a placeholder; pick one that suits the scrape interval.
+pub fn aggregation_expression(aggregation: Aggregation, without: &[String], selector: &str, rate: bool) -> String {
+    let inner = if rate {
+        format!("rate({selector}[5m])")
+    } else {
+        selector.to_string()
+    };
+    format!("{} without ({}) ({inner})", aggregation.name(), without.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn family_summaries_fit_the_family_type() {
+        let text = concat!(
+            "# TYPE temperature_celsius gauge\n",
+            "temperature_celsius{room=\"a\"} 18\n",
+            "temperature_celsius{room=\"b\"} 21\n",
+            "temperature_celsius{room=\"c\"} NaN\n",
+            "temperature_celsius{room=\"d\"} 24\n",
+            "# TYPE requests_total counter\n",
+            "requests_total{code=\"200\"} 90\n",
+            "requests_total{code=\"500\"} 10\n",
+            "# TYPE latency_seconds histogram\n",
+            "latency_seconds_bucket{path=\"/\",le=\"+Inf\"} 3\n",
+            "latency_seconds_sum{path=\"/\"} 1\n",
+            "latency_seconds_count{path=\"/\"} 3\n",
+            "latency_seconds_bucket{path=\"/a\",le=\"+Inf\"} 4\n",
+            "latency_seconds_sum{path=\"/a\"} 2\n",
+            "latency_seconds_count{path=\"/a\"} 4\n",
+        );
+        let exposition = parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let summaries = family_summaries(&exposition);
+        assert_eq!(
+            summaries["temperature_celsius"],
+            FamilySummary {
+                series: 4,
+                stats: SummaryStats::Range {
+                    min: 18.0,
+                    avg: 21.0,
+                    max: 24.0
+                },
+            }
+        );
+        assert_eq!(summaries["requests_total"].stats, SummaryStats::Sum(100.0));
+        assert_eq!(
+            summaries["latency_seconds"],
+            FamilySummary {
+                series: 2,
+                stats: SummaryStats::Observations(7),
+            }
+        );
+    }
+
+    #[test]
+    fn info_families_are_recognized_by_suffix_or_declared_type() {
+        let text = concat!(
+            "# TYPE go_info gauge\n",
+            "go_info{version=\"go1.22.3\"} 1\n",
+            "# TYPE build_info gauge\n",
+            "build_info{version=\"1.2\"} 1\n",
+            "build_info{version=\"1.3\"} 0\n",
+            "# TYPE up gauge\n",
+            "up{job=\"node\"} 1\n",
+        );
+        let exposition = parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let kind = |name: &str| label_family(name, &exposition.families[name], &HashMap::new());
+        assert_eq!(kind("go_info"), Some(LabelFamily::Info));
+        // A 0 means it isn't the info convention after all.
+        assert_eq!(kind("build_info"), None);
+        assert_eq!(kind("up"), None);
+        assert_eq!(
+            label_lines("go_info", &exposition.families["go_info"], LabelFamily::Info),
+            vec![String::from("version=\"go1.22.3\"")]
+        );
+    }
+
+    #[test]
+    fn state_sets_list_the_states_each_labelset_is_in() {
+        let text = concat!(
+            "# TYPE feature stateset\n",
+            "feature{region=\"eu\",feature=\"a\"} 1\n",
+            "feature{region=\"eu\",feature=\"b\"} 0\n",
+            "feature{region=\"us\",feature=\"a\"} 1\n",
+            "feature{region=\"us\",feature=\"b\"} 1\n",
+            "# TYPE target info\n",
+            "target_info{env=\"prod\"} 1\n",
+            "# EOF\n",
+        );
+        let declared = declared_label_families(text);
+        let exposition = parse(text, None, Format::Openmetrics).unwrap_or_else(|e| panic!("{e}"));
+        let feature = &exposition.families["feature"];
+        assert_eq!(label_family("feature", feature, &declared), Some(LabelFamily::StateSet));
+        assert_eq!(
+            label_family("target", &exposition.families["target"], &declared),
+            Some(LabelFamily::Info)
+        );
+        assert_eq!(
+            label_lines("feature", feature, LabelFamily::StateSet),
+            vec![String::from("region=\"eu\": a"), String::from("region=\"us\": a, b")]
+        );
+    }
+
+    #[test]
+    fn exemplars_come_with_their_bucket_and_trace_id() {
+        let text = concat!(
+            "# TYPE latency histogram\n",
+            "latency_bucket{le=\"0.1\"} 1 # {trace_id=\"4bf92f3577b34da6a3ce929d0e0e4736\"} 0.05 1719432000.1\n",
+            "latency_bucket{le=\"+Inf\"} 3 # {span=\"00f067aa0ba902b7\"} 2.5\n",
+            "latency_sum 3.1\n",
+            "latency_count 3\n",
+            "# TYPE requests counter\n",
+            "requests_total 7\n",
+            "# EOF\n",
+        );
+        let exposition = parse(text, None, Format::Openmetrics).unwrap_or_else(|e| panic!("{e}"));
+        let sample = exposition.families["latency"].iter_samples().next().unwrap();
+        let found: Vec<(Option<f64>, Option<&str>, f64)> = exemplars(&sample.value)
+            .into_iter()
+            .map(|(bucket, exemplar)| (bucket, trace_id(exemplar), exemplar.id))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Some(0.1), Some("4bf92f3577b34da6a3ce929d0e0e4736"), 0.05),
+                (Some(f64::INFINITY), Some("00f067aa0ba902b7"), 2.5),
+            ]
+        );
+        let counter = exposition.families["requests"].iter_samples().next().unwrap();
+        assert!(exemplars(&counter.value).is_empty());
+    }
+
+    #[test]
+    fn family_lines_span_metadata_and_suffixed_samples() {
+        let text = concat!(
+            "# HELP requests Requests served.\n",
+            "# TYPE requests counter\n",
+            "requests_total{code=\"200\"} 7\n",
+            "requests_created{code=\"200\"} 1719432000\n",
+            "# a comment\n",
+            "up 1\n",
+            "# TYPE latency histogram\n",
+            "latency_bucket{le=\"+Inf\"} 3\n",
+            "latency_sum 0.9\n",
+            "latency_count 3\n",
+            "# EOF\n",
+        );
+        let lines = family_lines(text);
+        assert_eq!(lines["requests"], 0..4);
+        assert_eq!(lines["up"], 5..6);
+        assert_eq!(lines["latency"], 6..10);
+        assert_eq!(lines.len(), 3);
+    }
+
+    /// Every family's type, help and series, in an order that doesn't
+    /// depend on the `HashMap`s.
+    fn summary(exposition: &Exposition) -> Vec<(String, String, String, Vec<Vec<(String, String)>>)> {
+        let mut families: Vec<_> = exposition
+            .families
+            .iter()
+            .map(|(name, fam)| {
+                let mut series: Vec<_> = fam.iter_samples().map(label_pairs).collect();
+                series.sort();
+                (name.clone(), format!("{:?}", fam.family_type), fam.help.clone(), series)
+            })
+            .collect();
+        families.sort();
+        families
+    }
+
+    #[test]
+    fn scrapes_cut_into_families_parse_like_whole_ones() {
+        let fixtures = [
+            include_str!("../tests/fixtures/node_exporter.prom"),
+            include_str!("../tests/fixtures/histograms.prom"),
+        ];
+        for text in fixtures {
+            assert!(family_blocks(text).len() > 1);
+            let whole = parse(text, None, Format::Auto).unwrap();
+            let (by_family, truncated) = parse_by_family(text, None, Format::Auto, None).unwrap();
+            assert_eq!(summary(&by_family), summary(&whole));
+            assert_eq!(truncated, None);
+        }
+
+        let text = "# HELP up Whether it's up.\n# TYPE up gauge\nup 1\n# TYPE jobs counter\njobs_total 3\n# EOF\n";
+        assert_eq!(
+            family_blocks(text),
+            vec!["# HELP up Whether it's up.\n# TYPE up gauge\nup 1\n", "# TYPE jobs counter\njobs_total 3\n# EOF\n"]
+        );
+        let (exposition, _) = parse_by_family(text, None, Format::Auto, None).unwrap();
+        assert_eq!(summary(&exposition), summary(&parse(text, None, Format::Auto).unwrap()));
+
+        // A family split across blocks is parsed whole, and errors as it would.
+        let text = "# TYPE a gauge\na 1\n# TYPE b gauge\nb 1\n# TYPE a gauge\na 2\n";
+        assert_eq!(
+            parse_by_family(text, None, Format::Prometheus, None).map(|(exposition, _)| summary(&exposition)).ok(),
+            parse(text, None, Format::Prometheus).map(|exposition| summary(&exposition)).ok(),
+        );
+        let text = include_str!("../tests/fixtures/malformed.prom");
+        assert_eq!(
+            parse_by_family(text, None, Format::Auto, None).unwrap_err().to_string(),
+            parse(text, None, Format::Auto).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn max_series_stops_before_the_family_that_would_go_past_it() {
+        let text = concat!(
+            "# TYPE a gauge\na{n=\"1\"} 1\na{n=\"2\"} 2\n",
+            "# TYPE b gauge\nb 1\n",
+            "# TYPE c gauge\nc{n=\"1\"} 1\nc{n=\"2\"} 2\n",
+        );
+        let names = |exposition: &Exposition| {
+            let mut names: Vec<String> = exposition.families.keys().cloned().collect();
+            names.sort();
+            names
+        };
+        let (exposition, truncated) = parse_by_family(text, None, Format::Prometheus, Some(4)).unwrap();
+        assert_eq!(names(&exposition), vec!["a", "b"]);
+        assert_eq!(truncated, Some(Truncated { max_series: 4, families_left: 1 }));
+        let (exposition, truncated) = parse_by_family(text, None, Format::Prometheus, Some(1)).unwrap();
+        assert!(exposition.families.is_empty());
+        assert_eq!(truncated.unwrap().families_left, 3);
+        assert_eq!(parse_by_family(text, None, Format::Prometheus, Some(5)).unwrap().1, None);
+
+        // Scrapes parsed whole are cut in the order of their text.
+        let mut exposition = parse(text, None, Format::Prometheus).unwrap();
+        assert_eq!(truncate(&mut exposition, text, 3), Some(Truncated { max_series: 3, families_left: 1 }));
+        assert_eq!(names(&exposition), vec!["a", "b"]);
+    }
+
+    fn lenient(text: &str) -> (Exposition, Vec<SkippedLine>) {
+        parse_lenient(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn skipped_lines(skipped: &[SkippedLine]) -> Vec<(usize, &str)> {
+        skipped.iter().map(|s| (s.line, s.text.as_str())).collect()
+    }
+
+    #[test]
+    fn clean_scrapes_skip_nothing() {
+        let (exposition, skipped) = lenient("# TYPE up gauge\nup 1\n");
+        assert!(exposition.families.contains_key("up"));
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn duplicate_type_declarations_are_dropped() {
+        let text = "# TYPE up gauge\nup 1\n# TYPE up gauge\n# TYPE other counter\nother 2\n";
+        let (exposition, skipped) = lenient(text);
+        assert!(exposition.families.contains_key("up"));
+        assert!(exposition.families.contains_key("other"));
+        // A parser that tolerates the duplicate has nothing to skip.
+        assert!(skipped_lines(&skipped).iter().all(|s| *s == (3, "# TYPE up gauge")));
+    }
+
+    #[test]
+    fn duplicate_help_declarations_are_dropped() {
+        let text = "# HELP up Whether the target is up.\n# HELP up Whether the target is up.\n# TYPE up gauge\nup 1\n";
+        let (exposition, skipped) = lenient(text);
+        assert_eq!(exposition.families["up"].help, "Whether the target is up.");
+        assert!(skipped_lines(&skipped)
+            .iter()
+            .all(|s| *s == (2, "# HELP up Whether the target is up.")));
+    }
+
+    #[test]
+    fn malformed_lines_are_dropped_and_numbered_as_in_the_scrape() {
+        let text = "# TYPE up gauge\nup 1\nthis is { not a sample\n# TYPE other gauge\nother 2\n}}}\n";
+        let (exposition, skipped) = lenient(text);
+        assert!(exposition.families.contains_key("up"));
+        assert!(exposition.families.contains_key("other"));
+        assert_eq!(skipped_lines(&skipped), vec![(3, "this is { not a sample"), (6, "}}}")]);
+    }
+
+    #[test]
+    fn promql_selectors_escape_label_values() {
+        let pairs = vec![
+            (String::from("method"), String::from("POST")),
+            (String::from("path"), String::from(r#"C:\dir "quoted""#)),
+            (String::from("note"), String::from("a\nb")),
+        ];
+        assert_eq!(
+            promql_selector("http_requests_total", &pairs),
+            r#"http_requests_total{method="POST",path="C:\\dir \"quoted\"",note="a\nb"}"#
+        );
+        assert_eq!(promql_selector("up", &[]), "up");
+    }
+
+    #[test]
+    fn error_line_reads_parser_locations() {
+        assert_eq!(error_line(" --> 12:5\n  |\n12 | foo{"), Some(12));
+        assert_eq!(error_line("unexpected token"), None);
+    }
+
+    #[test]
+    fn tricky_floats_format_the_same_everywhere() {
+        let cases: &[(f64, &str, &str)] = &[
+            // (value, format_number, format_raw)
+            (f64::NAN, "NaN", "NaN"),
+            (-f64::NAN, "NaN", "NaN"),
+            (f64::INFINITY, "∞", "∞"),
+            (f64::NEG_INFINITY, "-∞", "-∞"),
+            (0.0, "0", "0"),
+            (-0.0, "0", "-0"),
+            (0.004512837, "0.004513", "0.004512837"),
+            (-12_345.678, "-12346", "-12345.678"),
+            (123_456_789_012.0, "123456789012", "123456789012"),
+            (1e15, "1e15", "1e15"),
+            (-2.5e21, "-2.5e21", "-2.5e21"),
+            (1.5e-7, "1.5e-7", "1.5e-7"),
+            (0.0001, "0.0001", "0.0001"),
+            (f64::MAX, "1.798e308", "1.7976931348623157e308"),
+            (f64::MIN_POSITIVE, "2.225e-308", "2.2250738585072014e-308"),
+            (5e-324, "4.941e-324", "5e-324"),
+        ];
+        for (value, number, raw) in cases {
+            assert_eq!(format_number(*value), *number, "{value:?}");
+            assert_eq!(format_raw(*value), *raw, "{value:?}");
+        }
+        assert_eq!(format_bound(f64::INFINITY), "≤∞");
+        assert_eq!(format_bound(0.25), "≤0.25");
+    }
+
+    #[test]
+    fn values_sort_with_nan_last_and_nan_skips_rates() {
+        let mut values = [f64::NAN, 3.0, f64::INFINITY, -f64::NAN, f64::NEG_INFINITY, -0.0, 0.0, -1.0];
+        values.sort_by(|a, b| compare_values(*a, *b));
+        assert_eq!(
+            values.map(format_raw),
+            ["-∞", "-1", "-0", "0", "3", "∞", "NaN", "NaN"].map(String::from)
+        );
+
+        let rate = |previous: f64, current: f64| {
+            change(&PrometheusType::Counter, Some(previous), current, Duration::from_secs(10))
+                .map(|change| format_change(&change))
+        };
+        assert_eq!(rate(10.0, 30.0).as_deref(), Some("2/s"));
+        assert_eq!(rate(f64::NAN, 30.0), None);
+        assert_eq!(rate(10.0, f64::NAN), None);
+        assert_eq!(rate(f64::INFINITY, f64::INFINITY), None);
+        assert_eq!(rate(10.0, f64::INFINITY).as_deref(), Some("∞/s"));
+
+        assert_eq!(Aggregation::Sum.apply(&[1.0, f64::NAN, 2.0]), 3.0);
+        assert_eq!(Aggregation::Avg.apply(&[1.0, f64::NAN, 2.0]), 1.5);
+        assert!(Aggregation::Max.apply(&[f64::NAN, f64::NAN]).is_nan());
+    }
+
+    #[test]
+    fn timestamp_units_are_guessed_from_their_magnitude() {
+        let at = |seconds: u64| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        // 2024-06-26T20:00:00Z in seconds, milli-, micro- and nanoseconds.
+        assert_eq!(sample_time(1_719_432_000.0), at(1_719_432_000));
+        assert_eq!(sample_time(1_719_432_000_000.0), at(1_719_432_000));
+        assert_eq!(sample_time(1_719_432_000_000_000.0), at(1_719_432_000));
+        assert_eq!(sample_time(1_719_432_000_000_000_000.0), at(1_719_432_000));
+        // Either side of the seconds/milliseconds boundary.
+        assert_eq!(sample_time(99_999_999_999.0), at(99_999_999_999));
+        assert_eq!(sample_time(100_000_000_000.0), at(100_000_000));
+        assert_eq!(sample_time(0.0), at(0));
+        assert_eq!(sample_time(-1.0), None);
+        assert_eq!(sample_time(f64::NAN), None);
+        assert_eq!(sample_time(f64::INFINITY), None);
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_432_192);
+        assert_eq!(format_sample_time(1_719_432_000_000.0, now), "2024-06-26T20:00:00Z, 3m12s ago");
+        assert_eq!(format_sample_time(1_719_432_200.0, now), "2024-06-26T20:03:20Z, in 8s");
+        assert_eq!(format_sample_time(-5.0, now), "-5");
+        assert_eq!(format_age(Duration::from_secs(90_061)), "1d1h");
+        assert_eq!(format_age(Duration::from_secs(3600)), "1h");
+        assert_eq!(format_age(Duration::from_millis(400)), "0s");
+    }
+
+    #[test]
+    fn series_with_skewed_timestamps_are_listed_furthest_first() {
+        let text = concat!(
+            "# TYPE backlog gauge\n",
+            "backlog{queue=\"a\"} 1 1719431808000\n",
+            "backlog{queue=\"b\"} 1 1719432190000\n",
+            "backlog{queue=\"c\"} 1\n",
+            "# TYPE clock gauge\n",
+            "clock 1 1719433000000\n",
+        );
+        let exposition = parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let scraped_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_432_192);
+        let skewed = skewed_timestamps(&exposition, scraped_at, Duration::from_secs(60));
+        let shown: Vec<String> = skewed.iter().map(format_skew).collect();
+        assert_eq!(shown, vec!["clock is 13m28s ahead", "backlog{queue=\"a\"} is 6m24s behind"]);
+    }
+
+    #[test]
+    fn merge_labels_series_by_endpoint_or_aggregates_them() {
+        let scrape = |text: &str| parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let a = scrape("# TYPE requests_total counter\nrequests_total{code=\"200\"} 10\n# TYPE up gauge\nup 1\n");
+        let b = scrape("# TYPE requests_total counter\nrequests_total{code=\"200\"} 30\n# TYPE up counter\nup 1\n");
+        let scrapes = [("app:8080", &a), ("sidecar:9100", &b)];
+        let series = |exposition: &Exposition| -> Vec<(String, f64)> {
+            exposition.families["requests_total"]
+                .iter_samples()
+                .map(|sample| (format_labels(&label_pairs(sample)), scalar_value(&sample.value).unwrap()))
+                .collect()
+        };
+
+        let (each, warnings) = merge(&scrapes, Aggregation::Each);
+        assert_eq!(
+            series(&each),
+            vec![
+                (String::from("__endpoint__=\"app:8080\", code=\"200\""), 10.0),
+                (String::from("__endpoint__=\"sidecar:9100\", code=\"200\""), 30.0),
+            ]
+        );
+        assert_eq!(warnings, vec![String::from("up is a gauge on app:8080 but a counter on sidecar:9100")]);
+        assert_eq!(each.families["up"].iter_samples().count(), 1);
+
+        let (sum, _) = merge(&scrapes, Aggregation::Sum);
+        assert_eq!(series(&sum), vec![(String::from("code=\"200\""), 40.0)]);
+        let (avg, _) = merge(&scrapes, Aggregation::Avg);
+        assert_eq!(series(&avg), vec![(String::from("code=\"200\""), 20.0)]);
+    }
+
+    #[test]
+    fn aggregate_without_sums_buckets_and_keeps_other_labels() {
+        let scrape = |text: &str| parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let cpu = scrape(concat!(
+            "# TYPE cpu_seconds_total counter\n",
+            "cpu_seconds_total{cpu=\"0\",mode=\"idle\"} 10\n",
+            "cpu_seconds_total{cpu=\"1\",mode=\"idle\"} 30\n",
+            "cpu_seconds_total{cpu=\"0\",mode=\"user\"} 5\n",
+        ));
+        let fam = &cpu.families["cpu_seconds_total"];
+        let without = [String::from("cpu")];
+        let values = |aggregation| -> Vec<(String, usize, Option<f64>)> {
+            aggregate_without(fam, &without, aggregation, |sample| scalar_value(&sample.value))
+                .into_iter()
+                .map(|a| (format_labels(&a.labels), a.series, a.value.as_ref().and_then(scalar_value)))
+                .collect()
+        };
+        assert_eq!(
+            values(Aggregation::Sum),
+            vec![(String::from("mode=\"idle\""), 2, Some(40.0)), (String::from("mode=\"user\""), 1, Some(5.0))]
+        );
+        assert_eq!(values(Aggregation::Max)[0].2, Some(30.0));
+
+        let latency = scrape(concat!(
+            "# TYPE latency histogram\n",
+            "latency_bucket{pod=\"a\",le=\"0.1\"} 1\n",
+            "latency_bucket{pod=\"a\",le=\"+Inf\"} 3\n",
+            "latency_sum{pod=\"a\"} 0.9\n",
+            "latency_count{pod=\"a\"} 3\n",
+            "latency_bucket{pod=\"b\",le=\"0.1\"} 4\n",
+            "latency_bucket{pod=\"b\",le=\"+Inf\"} 4\n",
+            "latency_sum{pod=\"b\"} 0.2\n",
+            "latency_count{pod=\"b\"} 4\n",
+        ));
+        let aggregates = aggregate_without(
+            &latency.families["latency"],
+            &[String::from("pod")],
+            Aggregation::Sum,
+            |_| None,
+        );
+        let Some(PrometheusValue::Histogram(histogram)) = &aggregates[0].value else {
+            panic!("expected a histogram");
+        };
+        let buckets: Vec<(f64, f64)> =
+            decumulate_buckets(histogram).iter().map(|b| (b.upper_bound, b.count)).collect();
+        assert_eq!(buckets, vec![(0.1, 5.0), (f64::INFINITY, 2.0)]);
+        assert_eq!(histogram.count, Some(7));
+        assert_eq!(
+            aggregation_expression(Aggregation::Sum, &without, "cpu_seconds_total", true),
+            "sum without (cpu) (rate(cpu_seconds_total[5m]))"
+        );
+    }
+
+    #[test]
+    fn target_labels_win_over_exposed_ones() {
+        let scrape = parse("# TYPE up gauge\nup{instance=\"inner\",code=\"200\"} 1\n", None, Format::Prometheus)
+            .unwrap_or_else(|e| panic!("{e}"));
+        let labels = [(String::from("job"), String::from("node")), (String::from("instance"), String::from("a:9100"))];
+        let labelled = with_labels(scrape, &labels).unwrap_or_else(|e| panic!("{e}"));
+        let sample = labelled.families["up"].iter_samples().next().unwrap();
+        assert_eq!(
+            format_labels(&label_pairs(sample)),
+            "job=\"node\", instance=\"a:9100\", exported_instance=\"inner\", code=\"200\""
+        );
+    }
+
+    #[test]
+    fn counter_decreases_are_told_apart_from_resets() {
+        let scrape = |a: f64, b: f64| {
+            let text = format!("# TYPE jobs_total counter\njobs_total{{q=\"a\"}} {a}\njobs_total{{q=\"b\"}} {b}\n");
+            parse(&text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+        };
+        let mut resets = CounterResets::default();
+        let at = SystemTime::UNIX_EPOCH;
+        assert!(resets.update(&scrape(1000.0, 1000.0), at).is_empty());
+        assert_eq!(resets.update(&scrape(2.0, 900.0), at).len(), 2);
+        assert!(resets.update(&scrape(5.0, 950.0), at).is_empty());
+        assert_eq!((resets.resets, resets.partial), (1, 1));
+
+        let key = |q: &str| SeriesKey {
+            family: String::from("jobs_total"),
+            labels: format!("q=\"{q}\""),
+        };
+        let events = &resets.events[&key("b")];
+        assert_eq!(events.len(), 1);
+        assert!(events[0].decrease == Decrease::Partial);
+        assert_eq!((events[0].before, events[0].after), (1000.0, 900.0));
+        assert!(resets.has_partial(&key("b")) && !resets.has_partial(&key("a")));
+        assert!(resets.family_has_partial("jobs_total"));
+    }
+
+    #[test]
+    fn top_movers_rank_rates_and_deltas_by_size() {
+        let start = Instant::now();
+        let scrape = |sent: u32, a: u32| {
+            let text = format!(
+                "# TYPE sent_total counter\nsent_total {sent}\n# TYPE queue gauge\nqueue{{q=\"a\"}} {a}\nqueue{{q=\"b\"}} 10\n"
+            );
+            parse(&text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+        };
+        let (before, after) = (scrape(100, 10), scrape(400, 4));
+        let previous = Snapshot::capture(&before, start);
+        let movers = top_movers(&after, &previous, start + Duration::from_secs(10), 5);
+        let ranked: Vec<(&str, &str, String)> = movers
+            .iter()
+            .map(|m| (m.key.family.as_str(), m.key.labels.as_str(), format_change(&m.change)))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![("sent_total", "", String::from("30/s")), ("queue", "q=\"a\"", String::from("-6"))]
+        );
+        assert_eq!(top_movers(&after, &previous, start + Duration::from_secs(10), 1).len(), 1);
+    }
+
+    #[test]
+    fn baselines_accumulate_counters_across_resets_and_diff_gauges() {
+        let scrape = |sent: u32, queue: u32, extra: bool| {
+            let mut text = format!("# TYPE sent_total counter\nsent_total {sent}\n# TYPE queue gauge\nqueue {queue}\n");
+            if extra {
+                text.push_str("# TYPE late gauge\nlate 7\n");
+            }
+            parse(&text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"))
+        };
+        let mut baseline = Baseline::capture(&scrape(100, 10, false), Instant::now());
+        baseline.update(&scrape(150, 4, false));
+        // The process restarted and counted 20 more since.
+        let latest = scrape(20, 4, true);
+        baseline.update(&latest);
+
+        let since = |name: &str| baseline.since(name, latest.families[name].iter_samples().next().unwrap());
+        assert_eq!(since("sent_total"), Some(SinceBaseline::Increase(70.0)));
+        assert_eq!(since("queue"), Some(SinceBaseline::Delta(-6.0)));
+        assert_eq!(since("late"), Some(SinceBaseline::New(7.0)));
+        let formatted: Vec<String> = ["sent_total", "queue", "late"]
+            .iter()
+            .map(|name| format_since_baseline(&since(name).unwrap()))
+            .collect();
+        assert_eq!(formatted, vec!["+70", "-6", "7 new"]);
+    }
+
+    #[test]
+    fn histogram_quantiles_interpolate_within_buckets_like_prometheus() {
+        let inf = f64::INFINITY;
+        let buckets = [(0.1, 10.0), (0.5, 60.0), (1.0, 90.0), (inf, 100.0)];
+        let within = |q: f64, buckets: &[(f64, f64)]| match histogram_quantile(q, buckets) {
+            Some(QuantileEstimate::Within(value)) => value,
+            other => panic!("p{q}: {other:?}"),
+        };
+        assert!((within(0.05, &buckets) - 0.05).abs() < 1e-9);
+        assert!((within(0.5, &buckets) - 0.42).abs() < 1e-9);
+        assert!((within(0.9, &buckets) - 1.0).abs() < 1e-9);
+        assert_eq!(histogram_quantile(0.99, &buckets), Some(QuantileEstimate::Above(1.0)));
+        assert_eq!(within(0.0, &[(-1.0, 2.0), (0.0, 4.0), (inf, 4.0)]), -1.0);
+        // Out of order and not quite monotonic, as some exporters write them.
+        assert!((within(0.5, &[(inf, 100.0), (1.0, 89.0), (0.5, 60.0), (0.1, 10.0), (0.7, 58.0)]) - 0.42).abs() < 1e-9);
+
+        // Everything was slower than the highest finite bound.
+        assert_eq!(histogram_quantile(0.5, &[(1.0, 0.0), (inf, 5.0)]), Some(QuantileEstimate::Above(1.0)));
+        // No observations at all.
+        assert_eq!(histogram_quantile(0.5, &[(1.0, 0.0), (inf, 0.0)]), None);
+        // Only the +Inf bucket, or no +Inf bucket.
+        assert_eq!(histogram_quantile(0.5, &[(inf, 5.0)]), None);
+        assert_eq!(histogram_quantile(0.5, &[(1.0, 5.0)]), None);
+        // A single finite bucket spreads its observations from 0 to its bound.
+        assert_eq!(within(0.5, &[(2.0, 4.0), (inf, 4.0)]), 1.0);
+
+        assert_eq!(format_percentile(0.5), "p50");
+        assert_eq!(format_percentile(0.999), "p99.9");
+    }
+
+    #[test]
+    fn bucket_increases_are_what_was_observed_between_scrapes() {
+        let inf = f64::INFINITY;
+        let before = [(1.0, 2.0), (inf, 4.0)];
+        assert_eq!(bucket_increase(&[(1.0, 5.0), (inf, 10.0)], &before), vec![(1.0, 3.0), (inf, 6.0)]);
+        // A restart starts the counts again from zero.
+        assert_eq!(bucket_increase(&[(1.0, 1.0), (inf, 1.0)], &before), vec![(1.0, 1.0), (inf, 1.0)]);
+        assert_eq!(
+            sum_buckets([&before[..], &[(0.5, 1.0), (1.0, 1.0), (inf, 1.0)][..]]),
+            vec![(0.5, 1.0), (1.0, 3.0), (inf, 5.0)]
+        );
+    }
+
+    #[test]
+    fn labels_are_ranked_by_how_many_times_they_multiply_the_series() {
+        let text = concat!(
+            "# TYPE inflight gauge\n",
+            "inflight{method=\"GET\",route=\"/a\",pod=\"p\"} 1\n",
+            "inflight{method=\"GET\",route=\"/b\",pod=\"p\"} 1\n",
+            "inflight{method=\"GET\",route=\"/c\",pod=\"p\"} 1\n",
+            "inflight{method=\"POST\",route=\"/a\",pod=\"p\"} 1\n",
+            "inflight{method=\"POST\",route=\"/b\",pod=\"p\"} 1\n",
+            "inflight{method=\"POST\",route=\"/c\",pod=\"p\"} 1\n",
+            "# TYPE latency_seconds histogram\n",
+            "latency_seconds_bucket{le=\"+Inf\"} 1\n",
+            "latency_seconds_sum 1\n",
+            "latency_seconds_count 1\n",
+        );
+        let metrics = parse(text, None, Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let inflight = &metrics.families["inflight"];
+        let labels = label_cardinality(inflight);
+        let ranked: Vec<(&str, usize, f64)> =
+            labels.iter().map(|label| (label.label.as_str(), label.distinct, label.factor)).collect();
+        assert_eq!(ranked, vec![("route", 3, 3.0), ("method", 2, 2.0), ("pod", 1, 1.0)]);
+        let top = |values: &[(&str, usize)]| -> Vec<(String, usize)> {
+            values.iter().map(|(value, count)| (value.to_string(), *count)).collect()
+        };
+        assert_eq!(labels[0].top_values, top(&[("/a", 2), ("/b", 2), ("/c", 2)]));
+        assert_eq!(labels[1].top_values, top(&[("GET", 3), ("POST", 3)]));
+
+        assert_eq!(
+            drop_label_rule("inflight", inflight, "route"),
+            "- source_labels: [__name__]\n  regex: inflight\n  target_label: route\n  replacement: \"\"\n"
+        );
+        let histogram = drop_label_rule("latency_seconds", &metrics.families["latency_seconds"], "le");
+        assert!(histogram.contains("regex: latency_seconds(_bucket|_count|_sum|_created)?\n"), "{histogram}");
+    }
+}
@@ -0,0 +1,184 @@
+use std::cmp::Ordering;
+
+/// How one label value sorts: numbers (`le`, `quantile`, `code`, `cpu`) by
+/// value and before everything else, the rest naturally, i.e. by text with
+/// runs of digits compared as numbers, so `eth10` comes after `eth2`.
+enum Key<'a> {
+    /// Never NaN, so comparing two is total; `+Inf` sorts last.
+    Number(f64, &'a str),
+    Text(Vec<Chunk<'a>>),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk<'a> {
+    /// Compared by value, then with more leading zeros last, so `007` and
+    /// `7` are next to each other but not equal.
+    Digits {
+        significant: usize,
+        value: &'a str,
+        zeros: usize,
+    },
+    Other(&'a str),
+}
+
+fn key(value: &str) -> Key {
+    match value.parse::<f64>() {
+        Ok(number) if !number.is_nan() => Key::Number(number, value),
+        _ => Key::Text(chunks(value)),
+    }
+}
+
+fn chunks(value: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut rest = value;
+    while let Some(first) = rest.chars().next() {
+        let digits = first.is_ascii_digit();
+        let end = rest.find(|c: char| c.is_ascii_digit() != digits).unwrap_or(rest.len());
+        let (run, after) = rest.split_at(end);
+        chunks.push(if digits {
+            let value = run.trim_start_matches('0');
+            Chunk::Digits {
+                significant: value.len(),
+                value,
+                zeros: run.len() - value.len(),
+            }
+        } else {
+            Chunk::Other(run)
+        });
+        rest = after;
+    }
+    chunks
+}
+
+/// Orders two label values numerically when both are numbers, and
+/// naturally otherwise. This is a total order, so it's safe to sort with.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (key(a), key(b)) {
+        (Key::Number(x, a), Key::Number(y, b)) => x.total_cmp(&y).then_with(|| a.cmp(b)),
+        (Key::Number(..), Key::Text(_)) => Ordering::Less,
+        (Key::Text(_), Key::Number(..)) => Ordering::Greater,
+        (Key::Text(a), Key::Text(b)) => a.cmp(&b),
+    }
+}
+
+/// Orders two labelsets of the same family pair by pair: by label name,
+/// then by [`compare`] on the values.
+pub fn compare_labels(a: &[(String, String)], b: &[(String, String)]) -> Ordering {
+    for ((a_name, a_value), (b_name, b_value)) in a.iter().zip(b) {
+        let order = a_name.cmp(b_name).then_with(|| compare(a_value, b_value));
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(values: &[&str]) -> Vec<String> {
+        let mut values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        values.sort_by(|a, b| compare(a, b));
+        values
+    }
+
+    #[test]
+    fn numbers_sort_by_value_with_inf_last() {
+        assert_eq!(
+            sorted(&["+Inf", "10", "2", "0.5", "-1", "1e3", "0.25", "-Inf"]),
+            vec!["-Inf", "-1", "0.25", "0.5", "2", "10", "1e3", "+Inf"]
+        );
+        assert_eq!(sorted(&["0.99", "0.5", "0.9"]), vec!["0.5", "0.9", "0.99"]);
+        // Equal values still have an order, so sorting is deterministic.
+        assert_eq!(sorted(&["1.0", "01", "1"]), vec!["01", "1", "1.0"]);
+    }
+
+    #[test]
+    fn everything_else_sorts_naturally_after_the_numbers() {
+        assert_eq!(
+            sorted(&["eth10", "total", "eth2", "NaN", "3", "eth02", "eth2a", "cpu"]),
+            vec!["3", "NaN", "cpu", "eth2", "eth2a", "eth02", "eth10", "total"]
+        );
+        assert_eq!(sorted(&["", "a", "0"]), vec!["0", "", "a"]);
+    }
+
+    #[test]
+    fn labelsets_compare_pair_by_pair() {
+        let labels = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        let mut sets = vec![
+            labels(&[("code", "503"), ("le", "10")]),
+            labels(&[("code", "200"), ("le", "+Inf")]),
+            labels(&[("code", "200"), ("le", "2")]),
+            labels(&[("code", "200")]),
+        ];
+        sets.sort_by(|a, b| compare_labels(a, b));
+        assert_eq!(
+            sets,
+            vec![
+                labels(&[("code", "200")]),
+                labels(&[("code", "200"), ("le", "2")]),
+                labels(&[("code", "200"), ("le", "+Inf")]),
+                labels(&[("code", "503"), ("le", "10")]),
+            ]
+        );
+    }
+
+    /// Label values built from the pieces that trip comparators up: signs,
+    /// leading zeros, exponents, infinities, NaN and digits inside text.
+    fn generated(count: usize) -> Vec<String> {
+        const PIECES: &[&str] = &[
+            "0", "00", "1", "007", "10", "2", "-", "+", ".", "e", "5", "Inf", "NaN", "inf", "a", "_", "z", "",
+        ];
+        // A fixed xorshift, so a failure is reproducible.
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..count)
+            .map(|_| {
+                let pieces = (next() % 4) as usize;
+                (0..pieces)
+                    .map(|_| PIECES[(next() % PIECES.len() as u64) as usize])
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_values_are_totally_ordered() {
+        let values = generated(80);
+        for a in &values {
+            assert_eq!(compare(a, a), Ordering::Equal, "{a:?}");
+            for b in &values {
+                assert_eq!(compare(a, b), compare(b, a).reverse(), "{a:?} {b:?}");
+                assert_eq!(compare(a, b) == Ordering::Equal, a == b, "{a:?} {b:?}");
+                for c in &values {
+                    if compare(a, b) != Ordering::Greater && compare(b, c) != Ordering::Greater {
+                        assert_ne!(compare(a, c), Ordering::Greater, "{a:?} {b:?} {c:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_numbers_sort_like_their_values() {
+        let mut numbers: Vec<String> = generated(500)
+            .into_iter()
+            .filter(|value| value.parse::<f64>().is_ok_and(|n| !n.is_nan()))
+            .collect();
+        assert!(numbers.len() > 20, "too few numbers generated: {numbers:?}");
+        numbers.sort_by(|a, b| compare(a, b));
+        let values: Vec<f64> = numbers.iter().map(|value| value.parse().unwrap()).collect();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "{numbers:?}");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::humanize;
+use crate::model::{self, Exposition, Family, SeriesKey};
+use openmetrics_parser::PrometheusValue;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// One line of `--no-tui` output.
+struct Row {
+    family: String,
+    labels: String,
+    family_type: String,
+    value: String,
+}
+
+/// Prints one line per series as aligned columns: family, labels, type and
+/// value. Rows are sorted by family and then labels so that consecutive
+/// runs against the same endpoint diff cleanly. Values are humanized unless
+/// `raw_values`.
+pub fn print_table(exposition: &Exposition, raw_values: bool, out: &mut impl Write) -> io::Result<()> {
+    let mut rows: Vec<Row> = exposition
+        .families
+        .iter()
+        .flat_map(|(name, fam)| {
+            fam.iter_samples().map(move |sample| {
+                let key = SeriesKey::new(name, sample);
+                Row {
+                    family: key.family,
+                    labels: if key.labels.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{{{}}}", key.labels)
+                    },
+                    family_type: fam.family_type.to_string(),
+                    value: if raw_values {
+                        model::format_raw_value(&sample.value)
+                    } else {
+                        humanize::value(&sample.value, name, &fam.unit)
+                    },
+                }
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| (&a.family, &a.labels).cmp(&(&b.family, &b.labels)));
+
+    let family_width = rows.iter().map(|r| r.family.len()).max().unwrap_or(0);
+    let labels_width = rows.iter().map(|r| r.labels.chars().count()).max().unwrap_or(0);
+    let type_width = rows.iter().map(|r| r.family_type.len()).max().unwrap_or(0);
+    for row in rows {
+        let line = format!(
+            "{:family_width$}  {:labels_width$}  {:type_width$}  {}",
+            row.family, row.labels, row.family_type, row.value
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// What `--no-tui` prints.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns, one line per series.
+    Table,
+    /// A single JSON document. NaN and ±Inf are written as the strings "NaN", "+Inf" and "-Inf".
+    Json,
+    /// One row per series: name, labels, type, value, timestamp.
+    Csv,
+}
+
+/// Scrape-level metadata for the JSON document.
+pub struct ScrapeInfo<'a> {
+    pub endpoint: &'a str,
+    pub fetched_at: SystemTime,
+    pub duration: Duration,
+}
+
+/// A float that stays valid JSON: finite values are numbers, NaN and the
+/// infinities are the strings Prometheus uses for them.
+struct JsonNumber(f64);
+
+impl Serialize for JsonNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            v if v.is_nan() => serializer.serialize_str("NaN"),
+            v if v == f64::INFINITY => serializer.serialize_str("+Inf"),
+            v if v == f64::NEG_INFINITY => serializer.serialize_str("-Inf"),
+            v => serializer.serialize_f64(v),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonScrape<'a> {
+    endpoint: &'a str,
+    fetched_at: String,
+    scrape_duration_seconds: f64,
+    families: Vec<JsonFamily<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFamily<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    family_type: String,
+    help: &'a str,
+    unit: &'a str,
+    samples: Vec<JsonSample>,
+}
+
+#[derive(Serialize)]
+struct JsonSample {
+    labels: BTreeMap<String, String>,
+    #[serde(flatten)]
+    value: JsonValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<JsonNumber>,
+}
+
+/// Flattened into the sample, so scalars read `"value": 1` and histograms
+/// `"histogram": {...}`.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonValue {
+    Value(JsonNumber),
+    Histogram {
+        sum: Option<JsonNumber>,
+        count: Option<u64>,
+        /// Cumulative, as in the exposition.
+        buckets: Vec<JsonBucket>,
+    },
+    Summary {
+        sum: Option<JsonNumber>,
+        count: Option<u64>,
+        quantiles: Vec<JsonQuantile>,
+    },
+}
+
+#[derive(Serialize)]
+struct JsonBucket {
+    le: JsonNumber,
+    count: JsonNumber,
+}
+
+#[derive(Serialize)]
+struct JsonQuantile {
+    quantile: JsonNumber,
+    value: JsonNumber,
+}
+
+fn json_value(value: &PrometheusValue) -> JsonValue {
+    match value {
+        PrometheusValue::Unknown(n) | PrometheusValue::Gauge(n) => JsonValue::Value(JsonNumber(n.as_f64())),
+        PrometheusValue::Counter(c) => JsonValue::Value(JsonNumber(c.value.as_f64())),
+        PrometheusValue::Histogram(h) => {
+            let mut buckets: Vec<JsonBucket> = h
+                .buckets
+                .iter()
+                .map(|b| JsonBucket {
+                    le: JsonNumber(b.upper_bound),
+                    count: JsonNumber(b.count),
+                })
+                .collect();
+            buckets.sort_by(|a, b| a.le.0.total_cmp(&b.le.0));
+            JsonValue::Histogram {
+                sum: h.sum.as_ref().map(|s| JsonNumber(s.as_f64())),
+                count: h.count,
+                buckets,
+            }
+        }
+        PrometheusValue::Summary(s) => JsonValue::Summary {
+            sum: s.sum.as_ref().map(|sum| JsonNumber(sum.as_f64())),
+            count: s.count,
+            quantiles: model::sorted_quantiles(s)
+                .into_iter()
+                .map(|(quantile, value)| JsonQuantile {
+                    quantile: JsonNumber(quantile),
+                    value: JsonNumber(value.unwrap_or(f64::NAN)),
+                })
+                .collect(),
+        },
+    }
+}
+
+/// Writes the whole scrape as one JSON document. Families are sorted by
+/// name and samples by labels, like the table output.
+pub fn print_json(exposition: &Exposition, info: &ScrapeInfo, out: &mut impl Write) -> io::Result<()> {
+    let mut families: Vec<JsonFamily> = exposition
+        .families
+        .iter()
+        .map(|(name, fam)| {
+            let mut samples: Vec<(String, JsonSample)> = fam
+                .iter_samples()
+                .map(|sample| {
+                    let key = SeriesKey::new(name, sample);
+                    let sample = JsonSample {
+                        labels: model::label_pairs(sample).into_iter().collect(),
+                        value: json_value(&sample.value),
+                        timestamp: sample.timestamp.map(JsonNumber),
+                    };
+                    (key.labels, sample)
+                })
+                .collect();
+            samples.sort_by(|a, b| a.0.cmp(&b.0));
+            JsonFamily {
+                name,
+                family_type: fam.family_type.to_string(),
+                help: &fam.help,
+                unit: &fam.unit,
+                samples: samples.into_iter().map(|(_, sample)| sample).collect(),
+            }
+        })
+        .collect();
+    families.sort_by(|a, b| a.name.cmp(b.name));
+
+    let scrape = JsonScrape {
+        endpoint: info.endpoint,
+        fetched_at: humantime::format_rfc3339_millis(info.fetched_at).to_string(),
+        scrape_duration_seconds: info.duration.as_secs_f64(),
+        families,
+    };
+    serde_json::to_writer_pretty(&mut *out, &scrape)?;
+    writeln!(out)
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break,
+/// doubling any embedded quotes (RFC 4180).
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Writes every series of `families`, in the order given, as CSV with a
+/// header row. Labels go in a single field, formatted as in the viewer.
+/// Returns how many series were written.
+pub fn write_csv<'a>(families: impl IntoIterator<Item = (&'a str, &'a Family)>, out: &mut impl Write) -> io::Result<usize> {
+    writeln!(out, "metric,labels,type,value,timestamp")?;
+    let mut written = 0;
+    for (name, fam) in families {
+        let family_type = fam.family_type.to_string();
+        for sample in fam.iter_samples() {
+            let labels = model::format_labels(&model::label_pairs(sample));
+            let timestamp = sample.timestamp.map(|t| t.to_string()).unwrap_or_default();
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                csv_field(name),
+                csv_field(&labels),
+                csv_field(&family_type),
+                csv_field(&model::format_value(&sample.value)),
+                csv_field(&timestamp)
+            )?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("http_requests_total"), "http_requests_total");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv_field("line1\nline2"), "\"line1\nline2\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn csv_rows_keep_awkward_labels_in_one_field() {
+        let text = r#"# TYPE requests counter
+requests{path="/a,b",agent="say \"hi\"",note="two\nlines"} 3
+"#;
+        let exposition = openmetrics_parser::prometheus::parse_prometheus(text).unwrap_or_else(|e| panic!("{e}"));
+        let fam = &exposition.families["requests"];
+        let mut out = Vec::new();
+        let written = write_csv([("requests", fam)], &mut out).unwrap();
+        assert_eq!(written, 1);
+
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("metric,labels,type,value,timestamp"));
+        let labels = model::format_labels(&model::label_pairs(fam.iter_samples().next().unwrap()));
+        let expected = format!("requests,\"{}\",counter,3,", labels.replace('"', "\"\""));
+        assert_eq!(lines.next(), Some(expected.as_str()));
+        assert_eq!(lines.next(), None);
+        assert!(labels.contains(r#"path="/a,b""#), "{labels}");
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::{Context, Result};
+use openmetrics_parser::{MetricNumber, PrometheusType, PrometheusValue, Sample};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::fetch;
+use crate::model::{Exposition, Family};
+
+/// `promtool query`: evaluate PromQL on a Prometheus server and browse the
+/// result like a scrape.
+#[derive(clap::Args, Debug, Clone)]
+pub struct QueryArgs {
+    /// The Prometheus server, e.g. http://prometheus:9090
+    #[arg(value_name = "SERVER")]
+    pub server: String,
+
+    /// The expression to evaluate; `:` in the viewer edits it
+    #[arg(value_name = "PROMQL")]
+    pub expr: String,
+}
+
+/// The envelope of every `/api/v1` response.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response<T> {
+    status: String,
+    data: Option<T>,
+    error_type: Option<String>,
+    error: Option<String>,
+    #[serde(default)]
+    warnings: Vec<String>,
+}
+
+/// A query result by `resultType`. Instant queries answer with any of
+/// these; range queries always with `Matrix`.
+#[derive(Deserialize)]
+#[serde(tag = "resultType", content = "result", rename_all = "lowercase")]
+enum Data {
+    Vector(Vec<InstantSeries>),
+    Matrix(Vec<RangeSeries>),
+    Scalar(Point),
+    String(Point),
+}
+
+#[derive(Deserialize)]
+struct InstantSeries {
+    metric: BTreeMap<String, String>,
+    /// Missing for native histograms, which have a `histogram` instead.
+    value: Option<Point>,
+}
+
+#[derive(Deserialize)]
+struct RangeSeries {
+    metric: BTreeMap<String, String>,
+    #[serde(default)]
+    values: Vec<Point>,
+}
+
+/// `[<unix seconds>, "<value>"]`. Values are strings so that NaN and ±Inf
+/// survive JSON.
+#[derive(Deserialize)]
+struct Point(f64, String);
+
+impl Point {
+    fn number(&self) -> f64 {
+        self.1.parse().unwrap_or(f64::NAN)
+    }
+}
+
+/// Decodes an `/api/v1/query` response into families the viewer can show,
+/// plus any warnings the server attached. An error response becomes an
+/// error carrying the server's explanation.
+pub fn decode(text: &str, status: Option<reqwest::StatusCode>, expr: &str) -> Result<(Exposition, Vec<String>)> {
+    let (data, warnings) = api_data(text, status)?;
+    Ok((to_exposition(data, expr)?, warnings))
+}
+
+/// The `data` of any `/api/v1` response, plus the warnings the server
+/// attached. An error response becomes an error carrying the server's
+/// explanation, and a page that isn't the API's JSON one naming the status.
+pub fn api_data<T: DeserializeOwned>(text: &str, status: Option<reqwest::StatusCode>) -> Result<(T, Vec<String>)> {
+    let response: Response<T> = match serde_json::from_str(text) {
+        Ok(response) => response,
+        Err(e) => match status.filter(|status| !status.is_success()) {
+            Some(status) => anyhow::bail!("the server returned {status}"),
+            None => return Err(e).context("the response isn't Prometheus API JSON"),
+        },
+    };
+    if response.status != "success" {
+        anyhow::bail!(
+            "{}: {}",
+            response.error_type.as_deref().unwrap_or("error"),
+            response.error.as_deref().unwrap_or("the request failed")
+        );
+    }
+    let data = response.data.context("the response has no data")?;
+    Ok((data, response.warnings))
+}
+
+/// Files series into families by `__name__`, or under the expression itself
+/// for series without one (what most functions and aggregations return).
+/// Matrices show each series' latest value, and scalars and strings a
+/// single series; a string's text goes in a `value` label.
+fn to_exposition(data: Data, expr: &str) -> Result<Exposition> {
+    let series: Vec<(BTreeMap<String, String>, f64)> = match data {
+        Data::Vector(result) => result
+            .into_iter()
+            .filter_map(|series| Some((series.metric, series.value?.number())))
+            .collect(),
+        Data::Matrix(result) => result
+            .into_iter()
+            .filter_map(|series| Some((series.metric, series.values.last()?.number())))
+            .collect(),
+        Data::Scalar(point) => vec![(BTreeMap::new(), point.number())],
+        Data::String(point) => vec![(BTreeMap::from([(String::from("value"), point.1)]), f64::NAN)],
+    };
+
+    let mut grouped: BTreeMap<String, Vec<(BTreeMap<String, String>, f64)>> = BTreeMap::new();
+    for (mut metric, value) in series {
+        let name = metric.remove("__name__").unwrap_or_else(|| expr.trim().to_string());
+        grouped.entry(name).or_default().push((metric, value));
+    }
+
+    let mut families = HashMap::new();
+    for (name, series) in grouped {
+        let mut label_names: Vec<String> = Vec::new();
+        for (metric, _) in &series {
+            for label in metric.keys() {
+                if !label_names.contains(label) {
+                    label_names.push(label.clone());
+                }
+            }
+        }
+        let samples = series
+            .into_iter()
+            .map(|(metric, value)| {
+                let values = label_names
+                    .iter()
+                    .map(|label| metric.get(label).cloned().unwrap_or_default())
+                    .collect();
+                Sample::new(values, None, PrometheusValue::Unknown(MetricNumber::Float(value)))
+            })
+            .collect();
+        let fam = Family::new(name.clone(), label_names, PrometheusType::Unknown, String::new(), String::new())
+            .with_samples(samples)
+            .map_err(|e| anyhow::anyhow!("couldn't build {name} from the result: {e}"))?;
+        families.insert(name, fam);
+    }
+    Ok(Exposition { families })
+}
+
+/// The `left` pane of a Grafana Explore URL: one query over the last hour,
+/// on Grafana's default data source.
+#[derive(Serialize)]
+struct ExplorePane<'a> {
+    queries: [ExploreQuery<'a>; 1],
+    range: ExploreRange,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExploreQuery<'a> {
+    ref_id: &'static str,
+    expr: &'a str,
+}
+
+#[derive(Serialize)]
+struct ExploreRange {
+    from: &'static str,
+    to: &'static str,
+}
+
+/// The graph page of the Prometheus at `server` showing `expr` over the
+/// last hour.
+pub fn graph_url(server: &str, expr: &str) -> Result<String> {
+    page_url(server, "graph", &[("g0.expr", expr), ("g0.tab", "0"), ("g0.range_input", "1h")])
+}
+
+/// The Explore page of the Grafana at `grafana` with `expr` in its query.
+pub fn explore_url(grafana: &str, expr: &str) -> Result<String> {
+    let pane = ExplorePane {
+        queries: [ExploreQuery { ref_id: "A", expr }],
+        range: ExploreRange {
+            from: "now-1h",
+            to: "now",
+        },
+    };
+    page_url(grafana, "explore", &[("left", &serde_json::to_string(&pane)?)])
+}
+
+/// `page` under `base`, which may have a path of its own, with `params`
+/// percent-encoded. Spaces become `%20` rather than the form encoding's
+/// `+`, which not every UI decodes.
+fn page_url(base: &str, page: &str, params: &[(&str, &str)]) -> Result<String> {
+    let base = fetch::with_scheme(base);
+    let mut url = reqwest::Url::parse(&format!("{}/{page}", base.trim_end_matches('/')))
+        .with_context(|| format!("{base} is not a valid URL"))?;
+    url.query_pairs_mut().extend_pairs(params);
+    // A literal `+` was encoded as `%2B`, so any left stand for spaces.
+    let query = url.query().unwrap_or_default().replace('+', "%20");
+    url.set_query(Some(&query));
+    Ok(url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model;
+
+    fn series(exposition: &Exposition, name: &str) -> Vec<(String, f64)> {
+        exposition.families[name]
+            .iter_samples()
+            .map(|sample| {
+                let labels = model::format_labels(&model::label_pairs(sample));
+                (labels, model::scalar_value(&sample.value).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vectors_are_grouped_by_metric_name() {
+        let text = r#"{"status":"success","data":{"resultType":"vector","result":[
+            {"metric":{"__name__":"up","job":"node"},"value":[1719432000,"1"]},
+            {"metric":{"__name__":"up","job":"app"},"value":[1719432000,"0"]},
+            {"metric":{"job":"node"},"value":[1719432000,"+Inf"]}
+        ]}}"#;
+        let (exposition, warnings) = decode(text, None, "up or sum by (job) (x)").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(
+            series(&exposition, "up"),
+            vec![(String::from("job=\"node\""), 1.0), (String::from("job=\"app\""), 0.0)]
+        );
+        assert_eq!(
+            series(&exposition, "up or sum by (job) (x)"),
+            vec![(String::from("job=\"node\""), f64::INFINITY)]
+        );
+    }
+
+    #[test]
+    fn other_result_types_render_as_single_series() {
+        let matrix = r#"{"status":"success","data":{"resultType":"matrix","result":[
+            {"metric":{"__name__":"up"},"values":[[1719432000,"1"],[1719432015,"0"]]}
+        ]}}"#;
+        assert_eq!(series(&decode(matrix, None, "up[1m]").unwrap().0, "up"), vec![(String::new(), 0.0)]);
+
+        let scalar = r#"{"status":"success","data":{"resultType":"scalar","result":[1719432000,"42"]}}"#;
+        assert_eq!(series(&decode(scalar, None, "6 * 7").unwrap().0, "6 * 7"), vec![(String::new(), 42.0)]);
+
+        let string = r#"{"status":"success","data":{"resultType":"string","result":[1719432000,"hello"]}}"#;
+        let (exposition, _) = decode(string, None, "\"hello\"").unwrap();
+        let sample = exposition.families["\"hello\""].iter_samples().next().unwrap();
+        assert_eq!(model::label_pairs(sample), vec![(String::from("value"), String::from("hello"))]);
+    }
+
+    #[test]
+    fn api_errors_carry_the_servers_explanation() {
+        let text = r#"{"status":"error","errorType":"bad_data","error":"invalid parameter \"query\": 1:5: parse error"}"#;
+        let error = decode(text, Some(reqwest::StatusCode::BAD_REQUEST), "sum(").err().unwrap();
+        assert_eq!(error.to_string(), "bad_data: invalid parameter \"query\": 1:5: parse error");
+
+        let error = decode("<html>", Some(reqwest::StatusCode::NOT_FOUND), "up").err().unwrap();
+        assert_eq!(error.to_string(), "the server returned 404 Not Found");
+    }
+
+    #[test]
+    fn graph_urls_encode_quotes_slashes_and_spaces_in_label_values() {
+        let expr = r#"rate(http_requests_total{path="/api/v1/users",msg="say \"hi\""}[5m])"#;
+        let url = graph_url("prom:9090", expr).unwrap();
+        assert_eq!(
+            url,
+            concat!(
+                "http://prom:9090/graph?g0.expr=rate%28http_requests_total%7Bpath%3D%22%2Fapi%2Fv1%2Fusers%22",
+                "%2Cmsg%3D%22say%20%5C%22hi%5C%22%22%7D%5B5m%5D%29&g0.tab=0&g0.range_input=1h"
+            )
+        );
+        let parsed = reqwest::Url::parse(&url).unwrap();
+        assert_eq!(parsed.query_pairs().next().unwrap().1, expr);
+        assert!(graph_url("http://[::1", "up").is_err());
+    }
+
+    #[test]
+    fn explore_urls_carry_the_query_as_json_under_grafanas_own_path() {
+        let url = explore_url("https://grafana.example.com/grafana/", r#"up{job="a b"}"#).unwrap();
+        assert_eq!(
+            url,
+            concat!(
+                "https://grafana.example.com/grafana/explore?left=%7B%22queries%22%3A%5B%7B%22refId%22%3A%22A%22",
+                "%2C%22expr%22%3A%22up%7Bjob%3D%5C%22a%20b%5C%22%7D%22%7D%5D%2C%22range%22%3A%7B%22from%22",
+                "%3A%22now-1h%22%2C%22to%22%3A%22now%22%7D%7D"
+            )
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::events::{self, Event};
+use crate::fetch::Body;
+
+/// Appends every successful scrape to a file (`--record`) as JSON lines:
+/// when it was taken, how long it took and the exposition text as served.
+/// Entries of the event log go in between as lines of their own. Runs on
+/// the fetch thread, so slow disks never block the UI.
+pub struct Recorder {
+    path: PathBuf,
+    compress: bool,
+    max_size: Option<u64>,
+    file: Option<File>,
+    /// Bytes in the current file, including what was there before we opened it.
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record<'a> {
+    timestamp: String,
+    duration_ms: f64,
+    status: Option<u16>,
+    content_type: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    body: Cow<'a, str>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventRecord<'a> {
+    timestamp: String,
+    event: events::Kind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    series: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    text: Cow<'a, str>,
+}
+
+/// One record read back from a recording.
+pub struct Scrape {
+    pub at: SystemTime,
+    pub duration: Duration,
+    pub body: Body,
+}
+
+/// The readable records of a recording, in file order, plus how many lines
+/// couldn't be read (typically the last one, torn by a crash).
+pub struct Recording {
+    pub scrapes: Vec<Scrape>,
+    pub events: Vec<Event>,
+    pub unreadable: usize,
+}
+
+fn open(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("couldn't open {}", path.display()))
+}
+
+impl Recorder {
+    /// Opens `path` for appending right away, so a bad path is reported at
+    /// startup rather than after the first scrape.
+    pub fn open(path: PathBuf, compress: bool, max_size: Option<u64>) -> Result<Recorder> {
+        let file = open(&path)?;
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        Ok(Recorder {
+            path,
+            compress,
+            max_size,
+            file: Some(file),
+            size,
+        })
+    }
+
+    /// Appends one record and flushes it. A failed write is cut back off
+    /// the file so a full disk never leaves half a record behind.
+    pub fn record(&mut self, at: SystemTime, duration: Duration, body: &Body) -> Result<()> {
+        let record = Record {
+            timestamp: humantime::format_rfc3339_millis(at).to_string(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            status: body.status.map(|status| status.as_u16()),
+            content_type: body.content_type.as_deref().map(Cow::Borrowed),
+            body: Cow::Borrowed(&body.text),
+        };
+        self.write(&record)
+    }
+
+    /// Appends an entry of the event log, the same way as a scrape.
+    pub fn record_event(&mut self, event: &Event) -> Result<()> {
+        let record = EventRecord {
+            timestamp: humantime::format_rfc3339_millis(event.at).to_string(),
+            event: event.kind,
+            series: event.series.as_deref().map(Cow::Borrowed),
+            text: Cow::Borrowed(&event.text),
+        };
+        self.write(&record)
+    }
+
+    fn write(&mut self, record: &impl Serialize) -> Result<()> {
+        let mut bytes = serde_json::to_vec(record)?;
+        bytes.push(b'\n');
+        if self.compress {
+            // One gzip member per record: concatenated members are still a
+            // valid gzip file, and every flushed record is complete on disk.
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&bytes)?;
+            bytes = encoder.finish()?;
+        }
+
+        if self.max_size.is_some_and(|max| self.size > 0 && self.size + bytes.len() as u64 > max) {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.file = Some(open(&self.path)?);
+        }
+        let file = self.file.as_mut().expect("opened above");
+        if let Err(e) = file.write_all(&bytes).and_then(|()| file.flush()) {
+            let _ = file.set_len(self.size);
+            return Err(e).with_context(|| format!("couldn't write to {}", self.path.display()));
+        }
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the current file to `<path>.1`, replacing any earlier one, so
+    /// at most two files' worth of `--record-max-size` is kept.
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&self.path, &rotated)
+            .with_context(|| format!("couldn't rotate {}", self.path.display()))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Reads a file written by [`Recorder`], compressed or not. A truncated
+/// gzip member or a malformed line loses only the records it covers.
+pub fn load(path: &Path) -> Result<Recording> {
+    let raw = std::fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let bytes = if raw.starts_with(&[0x1f, 0x8b]) {
+        let mut bytes = Vec::new();
+        // Whatever decoded before an error is kept; the torn tail then
+        // fails to parse below like any other bad line.
+        let _ = MultiGzDecoder::new(raw.as_slice()).read_to_end(&mut bytes);
+        bytes
+    } else {
+        raw
+    };
+
+    let mut recording = Recording {
+        scrapes: Vec::new(),
+        events: Vec::new(),
+        unreadable: 0,
+    };
+    for line in String::from_utf8_lossy(&bytes).lines().filter(|line| !line.trim().is_empty()) {
+        if let Some(scrape) = parse_record(line) {
+            recording.scrapes.push(scrape);
+        } else if let Some(event) = parse_event(line) {
+            recording.events.push(event);
+        } else {
+            recording.unreadable += 1;
+        }
+    }
+    Ok(recording)
+}
+
+fn parse_event(line: &str) -> Option<Event> {
+    let record: EventRecord = serde_json::from_str(line).ok()?;
+    Some(Event {
+        at: humantime::parse_rfc3339(&record.timestamp).ok()?,
+        kind: record.event,
+        series: record.series.map(Cow::into_owned),
+        text: record.text.into_owned(),
+    })
+}
+
+fn parse_record(line: &str) -> Option<Scrape> {
+    let record: Record = serde_json::from_str(line).ok()?;
+    let text = record.body.into_owned();
+    Some(Scrape {
+        at: humantime::parse_rfc3339(&record.timestamp).ok()?,
+        duration: Duration::from_micros((record.duration_ms * 1000.0).round() as u64),
+        body: Body {
+            transferred: text.len(),
+            text,
+            content_type: record.content_type.map(Cow::into_owned),
+            status: record.status.and_then(|status| reqwest::StatusCode::from_u16(status).ok()),
+            textfiles: None,
+            warnings: Vec::new(),
+            exchange: None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_recordings_load_back_skipping_torn_records() {
+        let path = std::env::temp_dir().join(format!("promtool-record-test-{}.jsonl.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut recorder = Recorder::open(path.clone(), true, None).unwrap();
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_719_432_000);
+        for (offset, text) in [(0, "up 1\n"), (15, "up 0\n")] {
+            let body = Body::plain(String::from(text));
+            recorder.record(at + Duration::from_secs(offset), Duration::from_millis(12), &body).unwrap();
+        }
+        let note = Event {
+            at: at + Duration::from_secs(20),
+            kind: events::Kind::Annotation,
+            series: Some(String::from("up")),
+            text: String::from("deployed v2"),
+        };
+        recorder.record_event(&note).unwrap();
+        // A crash mid-write leaves the start of a member behind.
+        let mut torn = GzEncoder::new(Vec::new(), Compression::default());
+        torn.write_all(b"{\"timestamp\":\"2024-06-26T20:00:30Z\",\"body\":\"up 1\\n\"}\n").unwrap();
+        let torn = torn.finish().unwrap();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&torn[..torn.len() / 2]).unwrap();
+
+        let recording = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let scrapes: Vec<(SystemTime, &str)> =
+            recording.scrapes.iter().map(|scrape| (scrape.at, scrape.body.text.as_str())).collect();
+        assert_eq!(scrapes, vec![(at, "up 1\n"), (at + Duration::from_secs(15), "up 0\n")]);
+        assert_eq!(recording.scrapes[0].duration, Duration::from_millis(12));
+        assert_eq!(recording.events, vec![note]);
+    }
+}
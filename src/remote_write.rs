@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use openmetrics_parser::{PrometheusType, PrometheusValue};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::fetch::HttpFetcher;
+use crate::model::{self, Exposition};
+
+/// `promtool push`: send a scrape to a remote-write receiver, e.g. a test
+/// Prometheus started with `--web.enable-remote-write-receiver`, or Mimir.
+#[derive(clap::Args, Debug, Clone)]
+pub struct PushArgs {
+    /// The endpoint, saved scrape, or `-` for stdin to push
+    #[arg(value_name = "SOURCE", required_unless_present = "recording")]
+    pub source: Option<String>,
+
+    /// Push every scrape of a file written by --record instead, each at its recorded time
+    #[arg(long, value_name = "PATH", conflicts_with = "source")]
+    pub recording: Option<PathBuf>,
+
+    /// Where to POST the samples, e.g. http://localhost:9090/api/v1/write
+    #[arg(long, value_name = "URL")]
+    pub remote_write_url: String,
+
+    /// Label to add to every series, e.g. "job=node" (repeatable); replaces
+    /// an exposed label of the same name
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_label)]
+    pub extra_label: Vec<(String, String)>,
+}
+
+fn parse_label(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value.split_once('=').ok_or_else(|| String::from("expected NAME=VALUE"))?;
+    let valid = name.chars().enumerate().all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if name.is_empty() || !valid {
+        return Err(format!("{name:?} is not a valid label name"));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Series per request, like Prometheus' own `max_samples_per_send`, so large
+/// scrapes stay under receivers' request size limits.
+const MAX_SERIES_PER_REQUEST: usize = 2000;
+
+/// One sample as remote write sends it: the full label set, `__name__`
+/// included and sorted by name as receivers require.
+pub struct TimeSeries {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    pub timestamp_ms: i64,
+}
+
+/// TYPE, HELP and UNIT of a family, sent alongside its samples.
+pub struct Metadata {
+    family: String,
+    family_type: u64,
+    help: String,
+    unit: String,
+}
+
+/// Flattens a scrape into remote-write series the way Prometheus stores
+/// them: histograms as `_bucket` (with `le`), `_sum` and `_count`,
+/// summaries as quantiles plus `_sum` and `_count`, and OpenMetrics
+/// counters with their `_total`. Samples without a timestamp get `at`.
+pub fn time_series(
+    exposition: &Exposition,
+    openmetrics: bool,
+    at: SystemTime,
+    extra_labels: &[(String, String)],
+) -> (Vec<TimeSeries>, Vec<Metadata>) {
+    let scraped_ms = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    let mut names: Vec<&String> = exposition.families.keys().collect();
+    names.sort();
+
+    let mut series = Vec::new();
+    let mut metadata = Vec::new();
+    for name in names {
+        let fam = &exposition.families[name];
+        let name = match fam.family_type {
+            PrometheusType::Counter if openmetrics && !name.ends_with("_total") => format!("{name}_total"),
+            _ => name.clone(),
+        };
+        metadata.push(Metadata {
+            family: name.clone(),
+            family_type: match fam.family_type {
+                PrometheusType::Unknown => 0,
+                PrometheusType::Counter => 1,
+                PrometheusType::Gauge => 2,
+                PrometheusType::Histogram => 3,
+                PrometheusType::Summary => 5,
+            },
+            help: fam.help.clone(),
+            unit: fam.unit.clone(),
+        });
+        for sample in fam.iter_samples() {
+            // OpenMetrics timestamps are in seconds, Prometheus ones in milliseconds.
+            let timestamp_ms = sample.timestamp.map_or(scraped_ms, |ts| {
+                let ts = ts as f64;
+                (if openmetrics { ts * 1000.0 } else { ts }).round() as i64
+            });
+            let mut labels = model::label_pairs(sample);
+            labels.retain(|(label, _)| !extra_labels.iter().any(|(extra, _)| extra == label));
+            labels.extend(extra_labels.iter().cloned());
+            let mut push = |suffix: &str, extra: Option<(&str, f64)>, value: f64| {
+                let mut labels = labels.clone();
+                labels.push((String::from("__name__"), format!("{name}{suffix}")));
+                if let Some((label, bound)) = extra {
+                    labels.push((label.to_string(), format_float(bound)));
+                }
+                labels.sort();
+                series.push(TimeSeries {
+                    labels,
+                    value,
+                    timestamp_ms,
+                });
+            };
+            match &sample.value {
+                PrometheusValue::Histogram(h) => {
+                    for bucket in &h.buckets {
+                        push("_bucket", Some(("le", bucket.upper_bound)), bucket.count);
+                    }
+                    if let Some(sum) = &h.sum {
+                        push("_sum", None, sum.as_f64());
+                    }
+                    if let Some(count) = h.count {
+                        push("_count", None, count as f64);
+                    }
+                }
+                PrometheusValue::Summary(s) => {
+                    for (quantile, value) in model::sorted_quantiles(s) {
+                        push("", Some(("quantile", quantile)), value.unwrap_or(f64::NAN));
+                    }
+                    if let Some(sum) = &s.sum {
+                        push("_sum", None, sum.as_f64());
+                    }
+                    if let Some(count) = s.count {
+                        push("_count", None, count as f64);
+                    }
+                }
+                value => {
+                    if let Some(value) = model::scalar_value(value) {
+                        push("", None, value);
+                    }
+                }
+            }
+        }
+    }
+    (series, metadata)
+}
+
+/// `le` and `quantile` values as Prometheus writes them.
+fn format_float(value: f64) -> String {
+    if value == f64::INFINITY {
+        String::from("+Inf")
+    } else if value == f64::NEG_INFINITY {
+        String::from("-Inf")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Encodes a `prometheus.WriteRequest` (remote write 1.0) by hand; the
+/// message is small enough not to need generated code.
+pub fn encode(series: &[TimeSeries], metadata: &[Metadata]) -> Vec<u8> {
+    let mut request = Vec::new();
+    for series in series {
+        let mut message = Vec::new();
+        for (name, value) in &series.labels {
+            let mut label = Vec::new();
+            bytes_field(&mut label, 1, name.as_bytes());
+            bytes_field(&mut label, 2, value.as_bytes());
+            bytes_field(&mut message, 1, &label);
+        }
+        let mut sample = Vec::new();
+        key(&mut sample, 1, 1);
+        sample.extend_from_slice(&series.value.to_le_bytes());
+        key(&mut sample, 2, 0);
+        varint(&mut sample, series.timestamp_ms as u64);
+        bytes_field(&mut message, 2, &sample);
+        bytes_field(&mut request, 1, &message);
+    }
+    for metadata in metadata {
+        let mut message = Vec::new();
+        key(&mut message, 1, 0);
+        varint(&mut message, metadata.family_type);
+        bytes_field(&mut message, 2, metadata.family.as_bytes());
+        bytes_field(&mut message, 4, metadata.help.as_bytes());
+        bytes_field(&mut message, 5, metadata.unit.as_bytes());
+        bytes_field(&mut request, 3, &message);
+    }
+    request
+}
+
+fn key(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    varint(out, field << 3 | wire_type);
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    key(out, field, 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// POSTs `series` to `url` in batches, with the metadata in the first one,
+/// and returns how many requests it took. A rejection carries the
+/// receiver's response body, which says what was wrong.
+pub fn push(http: &HttpFetcher, url: &str, series: &[TimeSeries], metadata: &[Metadata]) -> Result<usize> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-protobuf"));
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("snappy"));
+    headers.insert("X-Prometheus-Remote-Write-Version", HeaderValue::from_static("0.1.0"));
+
+    let mut requests = 0;
+    let mut metadata = metadata;
+    for batch in series.chunks(MAX_SERIES_PER_REQUEST) {
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&encode(batch, metadata))
+            .context("couldn't compress the write request")?;
+        metadata = &[];
+        let (status, text) = http.post(url, headers.clone(), body)?;
+        if !status.is_success() {
+            anyhow::bail!("{url} rejected the write with {status}: {}", text.trim());
+        }
+        requests += 1;
+    }
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_become_prometheus_series() {
+        let text = concat!(
+            "# TYPE latency_seconds histogram\n",
+            "latency_seconds_bucket{le=\"0.1\"} 1\n",
+            "latency_seconds_bucket{le=\"+Inf\"} 3\n",
+            "latency_seconds_sum 0.9\n",
+            "latency_seconds_count 3\n",
+        );
+        let exposition = model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let at = UNIX_EPOCH + std::time::Duration::from_secs(1_719_432_000);
+        let extra = [(String::from("job"), String::from("test"))];
+        let (series, metadata) = time_series(&exposition, false, at, &extra);
+        let flat: Vec<(String, f64)> = series
+            .iter()
+            .map(|s| (model::format_labels(&s.labels), s.value))
+            .collect();
+        assert_eq!(
+            flat,
+            vec![
+                (String::from("__name__=\"latency_seconds_bucket\", job=\"test\", le=\"0.1\""), 1.0),
+                (String::from("__name__=\"latency_seconds_bucket\", job=\"test\", le=\"+Inf\""), 3.0),
+                (String::from("__name__=\"latency_seconds_sum\", job=\"test\""), 0.9),
+                (String::from("__name__=\"latency_seconds_count\", job=\"test\""), 3.0),
+            ]
+        );
+        assert!(series.iter().all(|s| s.timestamp_ms == 1_719_432_000_000));
+        assert_eq!(metadata[0].family_type, 3);
+    }
+
+    #[test]
+    fn openmetrics_counters_get_their_total_suffix_and_second_timestamps() {
+        let text = "# TYPE requests counter\nrequests_total{code=\"200\"} 7 1719432000.5\n# EOF\n";
+        let exposition = model::parse(text, None, model::Format::Openmetrics).unwrap_or_else(|e| panic!("{e}"));
+        let (series, _) = time_series(&exposition, true, SystemTime::now(), &[]);
+        assert_eq!(series.len(), 1);
+        assert_eq!(
+            series[0].labels,
+            vec![
+                (String::from("__name__"), String::from("requests_total")),
+                (String::from("code"), String::from("200")),
+            ]
+        );
+        assert_eq!(series[0].timestamp_ms, 1_719_432_000_500);
+    }
+
+    #[test]
+    fn write_requests_encode_as_protobuf() {
+        let series = [TimeSeries {
+            labels: vec![(String::from("__name__"), String::from("up"))],
+            value: 1.0,
+            timestamp_ms: 1,
+        }];
+        let mut expected = vec![0x0a, 0x1d, 0x0a, 0x0e, 0x0a, 0x08];
+        expected.extend_from_slice(b"__name__");
+        expected.extend_from_slice(&[0x12, 0x02]);
+        expected.extend_from_slice(b"up");
+        expected.extend_from_slice(&[0x12, 0x0b, 0x09]);
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&[0x10, 0x01]);
+        assert_eq!(encode(&series, &[]), expected);
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::events::Event;
+use crate::record::Scrape;
+
+/// `promtool replay`: step through a file written by `--record`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReplayArgs {
+    /// The recording, gzip-compressed or not
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Playback speed as a multiple of the recorded cadence
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=MAX_SPEED as i64)
+    )]
+    pub speed: u32,
+}
+
+const MAX_SPEED: u32 = 64;
+
+/// Position and playback state within a recording.
+pub struct Replay {
+    scrapes: Vec<Scrape>,
+    /// The recorded event log, oldest first.
+    events: Vec<Event>,
+    position: usize,
+    speed: u32,
+    /// When playback moves on to the next scrape; `None` while paused.
+    due: Option<Instant>,
+    /// The first scrape's time on the monotonic clock. Later scrapes are
+    /// placed relative to it, so rates use the recorded gaps.
+    origin: Instant,
+}
+
+impl Replay {
+    /// `scrapes` must not be empty.
+    pub fn new(scrapes: Vec<Scrape>, mut events: Vec<Event>, speed: u32) -> Replay {
+        assert!(!scrapes.is_empty(), "a replay needs at least one scrape");
+        events.sort_by_key(|event| event.at);
+        Replay {
+            scrapes,
+            events,
+            position: 0,
+            speed,
+            due: None,
+            origin: Instant::now(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scrapes.len()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn scrape(&self, index: usize) -> &Scrape {
+        &self.scrapes[index]
+    }
+
+    /// The events recorded before the scrape after `index`, which includes
+    /// those about scrape `index` itself, logged once it had been parsed.
+    pub fn events_until(&self, index: usize) -> &[Event] {
+        match self.scrapes.get(index + 1) {
+            Some(next) => &self.events[..self.events.partition_point(|event| event.at < next.at)],
+            None => &self.events,
+        }
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.due.is_some()
+    }
+
+    /// When scrape `index` was taken, as an `Instant` for rate computations.
+    pub fn instant(&self, index: usize) -> Instant {
+        let offset = self.scrapes[index].at.duration_since(self.scrapes[0].at).unwrap_or_default();
+        self.origin + offset
+    }
+
+    /// The recorded wall clock time of an `Instant` from [`Replay::instant`].
+    pub fn wall_time(&self, at: Instant) -> SystemTime {
+        self.scrapes[0].at + at.saturating_duration_since(self.origin)
+    }
+
+    /// The scrape to jump to for `text`: the last one taken at or before an
+    /// RFC 3339 time, or `HH:MM[:SS]` on the current scrape's day (UTC).
+    pub fn find(&self, text: &str) -> Result<usize, humantime::TimestampError> {
+        let text = text.trim();
+        let time = if text.contains('-') {
+            text.to_string()
+        } else {
+            let day = humantime::format_rfc3339_seconds(self.scrapes[self.position].at).to_string();
+            let seconds = if text.matches(':').count() == 1 { ":00" } else { "" };
+            format!("{} {text}{seconds}", &day[..10])
+        };
+        let time: SystemTime = humantime::parse_rfc3339_weak(&time)?;
+        Ok(self.scrapes.iter().rposition(|scrape| scrape.at <= time).unwrap_or(0))
+    }
+
+    /// Moves to `index`, clamped to the recording, and returns where it ended up.
+    pub fn seek(&mut self, index: usize) -> usize {
+        self.position = index.min(self.scrapes.len() - 1);
+        if self.is_playing() {
+            self.schedule();
+        }
+        self.position
+    }
+
+    pub fn toggle_play(&mut self) {
+        if self.is_playing() {
+            self.due = None;
+        } else {
+            self.schedule();
+        }
+    }
+
+    /// Doubles or halves the speed, between 1x and 64x.
+    pub fn change_speed(&mut self, faster: bool) {
+        self.speed = if faster {
+            (self.speed * 2).min(MAX_SPEED)
+        } else {
+            (self.speed / 2).max(1)
+        };
+        if self.is_playing() {
+            self.schedule();
+        }
+    }
+
+    /// Whether playback is due to move on to the next scrape.
+    pub fn is_due(&self) -> bool {
+        self.due.is_some_and(|due| Instant::now() >= due)
+    }
+
+    /// Schedules the next scrape after its recorded gap at the current
+    /// speed. Playback stops at the end of the recording.
+    fn schedule(&mut self) {
+        self.due = self.scrapes.get(self.position + 1).map(|next| {
+            let gap = next.at.duration_since(self.scrapes[self.position].at).unwrap_or_default();
+            Instant::now() + gap / self.speed
+        });
+    }
+
+    /// How long until playback moves on, for the event loop's poll timeout.
+    pub fn time_until_due(&self) -> Option<Duration> {
+        self.due.map(|due| due.saturating_duration_since(Instant::now()))
+    }
+}
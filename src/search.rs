@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::model::{self, Exposition, Series};
+
+/// Filters starting with this search label names and values instead of
+/// family names, e.g. `label:checkout-7f9c` or `label:code="503"`.
+pub const LABEL_PREFIX: &str = "label:";
+
+/// What a `/` filter searches labels for, if it's a label search at all.
+pub fn label_needle(filter: &str) -> Option<&str> {
+    filter.strip_prefix(LABEL_PREFIX)
+}
+
+/// Every series' labels, lowercased once per scrape so each keystroke of a
+/// label search is a plain substring scan rather than re-formatting every
+/// labelset of a large endpoint.
+#[derive(Default)]
+pub struct LabelIndex {
+    /// Per family, each series' `name="value"` pairs joined by newlines, so
+    /// a needle can't match across two pairs.
+    families: Vec<(String, Vec<String>)>,
+}
+
+impl LabelIndex {
+    pub fn new(exposition: &Exposition) -> LabelIndex {
+        let families = exposition
+            .families
+            .iter()
+            .map(|(name, fam)| (name.clone(), fam.iter_samples().map(searchable).collect()))
+            .collect();
+        LabelIndex { families }
+    }
+
+    /// The families with at least one series whose labels contain `needle`,
+    /// ignoring case.
+    pub fn search(&self, needle: &str) -> LabelMatches {
+        let lowered = needle.to_lowercase();
+        let mut matches = LabelMatches {
+            needle: needle.to_string(),
+            families: HashMap::new(),
+            series: 0,
+        };
+        for (name, series) in &self.families {
+            let count = series.iter().filter(|labels| labels.contains(&lowered)).count();
+            if count > 0 {
+                matches.families.insert(name.clone(), count);
+                matches.series += count;
+            }
+        }
+        matches
+    }
+}
+
+/// The outcome of a label search over one scrape.
+pub struct LabelMatches {
+    pub needle: String,
+    /// Matching series per family.
+    pub families: HashMap<String, usize>,
+    pub series: usize,
+}
+
+fn searchable(sample: &Series) -> String {
+    model::label_pairs(sample)
+        .iter()
+        .map(|(name, value)| format!("{name}=\"{value}\"").to_lowercase())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether one of the series' labels contains `needle`, ignoring case, as
+/// the index would have found it.
+pub fn series_matches(sample: &Series, needle: &str) -> bool {
+    searchable(sample).contains(&needle.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_searches_count_matching_series_per_family() {
+        let text = concat!(
+            "# TYPE http_requests_total counter\n",
+            "http_requests_total{code=\"200\",pod=\"checkout-7f9c\"} 10\n",
+            "http_requests_total{code=\"503\",pod=\"checkout-7f9c\"} 1\n",
+            "http_requests_total{code=\"200\",pod=\"cart-2b1d\"} 4\n",
+            "# TYPE up gauge\n",
+            "up{pod=\"Checkout-7F9C\"} 1\n",
+            "# TYPE go_goroutines gauge\n",
+            "go_goroutines 12\n",
+        );
+        let exposition = model::parse(text, None, model::Format::Prometheus).unwrap_or_else(|e| panic!("{e}"));
+        let index = LabelIndex::new(&exposition);
+
+        let matches = index.search("checkout-7f9c");
+        assert_eq!(matches.series, 3);
+        assert_eq!(matches.families.get("http_requests_total"), Some(&2));
+        assert_eq!(matches.families.get("up"), Some(&1));
+        assert!(!matches.families.contains_key("go_goroutines"));
+
+        let matches = index.search("code=\"5");
+        assert_eq!(matches.series, 1);
+        // A needle spanning two labels matches neither.
+        assert_eq!(index.search("200\",pod").series, 0);
+
+        let fam = &exposition.families["http_requests_total"];
+        let matching: Vec<bool> = fam.iter_samples().map(|sample| series_matches(sample, "CART")).collect();
+        assert_eq!(matching, vec![false, false, true]);
+    }
+
+    #[test]
+    fn only_prefixed_filters_search_labels() {
+        assert_eq!(label_needle("label:pod"), Some("pod"));
+        assert_eq!(label_needle("label:"), Some(""));
+        assert_eq!(label_needle("node_label"), None);
+    }
+}
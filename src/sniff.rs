@@ -0,0 +1,136 @@
+use std::fmt;
+
+/// A response that isn't exposition text at all, typically an app's own
+/// page served from a path other than its `/metrics`.
+#[derive(Debug)]
+pub struct NotMetrics {
+    pub url: String,
+    /// The Content-Type it came with, or what its first bytes look like.
+    pub kind: String,
+    /// The conventional `/metrics` path on the same host, unless `url`
+    /// already is one.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for NotMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.suggestion {
+            Some(suggestion) => write!(f, "endpoint returned {} — did you mean {suggestion}?", self.kind),
+            None => write!(f, "endpoint returned {} rather than metrics", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for NotMetrics {}
+
+/// Rejects HTML and JSON responses before the parser chokes on their first
+/// character, and lists what's off about the Content-Type of the rest.
+pub fn check(url: &str, content_type: Option<&str>, text: &str) -> Result<Vec<String>, NotMetrics> {
+    if let Some(kind) = not_metrics(content_type, text) {
+        return Err(NotMetrics {
+            url: url.to_string(),
+            kind,
+            suggestion: metrics_url(url),
+        });
+    }
+    Ok(content_type_warning(content_type).into_iter().collect())
+}
+
+fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Exposition text never starts with `<`, `{` or `[`, so those betray a
+/// page or an API response even when the Content-Type doesn't.
+fn not_metrics(content_type: Option<&str>, text: &str) -> Option<String> {
+    if let Some(media) = content_type.map(media_type) {
+        if media == "text/html" || media == "application/xhtml+xml" || media == "application/json" {
+            return Some(media);
+        }
+    }
+    match text.trim_start_matches('\u{feff}').trim_start().chars().next() {
+        Some('<') => Some(String::from("HTML")),
+        Some('{' | '[') => Some(String::from("JSON")),
+        _ => None,
+    }
+}
+
+/// What Prometheus would make of the Content-Type: since 3.0 it refuses
+/// scrapes without one unless told which format to fall back to.
+fn content_type_warning(content_type: Option<&str>) -> Option<String> {
+    let Some(content_type) = content_type else {
+        return Some(String::from(
+            "no Content-Type header, which Prometheus 3 rejects unless fallback_scrape_protocol is set",
+        ));
+    };
+    let version = content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("version"))
+        .map(|(_, value)| value.trim().trim_matches('"'));
+    match media_type(content_type).as_str() {
+        "text/plain" if version == Some("0.0.4") => None,
+        "text/plain" => Some(format!("Content-Type {content_type:?} lacks version=0.0.4")),
+        "application/openmetrics-text" => None,
+        _ => Some(format!("unexpected Content-Type {content_type:?}")),
+    }
+}
+
+/// `url` with its path replaced by `/metrics`, or `None` when that's the
+/// path already.
+pub fn metrics_url(url: &str) -> Option<String> {
+    let mut parsed = reqwest::Url::parse(url).ok()?;
+    if parsed.path().trim_end_matches('/').ends_with("/metrics") {
+        return None;
+    }
+    parsed.set_path("/metrics");
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+    Some(parsed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &str = "\n<!DOCTYPE html>\n<html><head><title>Grafana</title></head></html>\n";
+
+    #[test]
+    fn pages_and_api_responses_are_told_apart_from_metrics() {
+        let error = check("http://localhost:3000/", Some("text/html; charset=utf-8"), HTML).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "endpoint returned text/html — did you mean http://localhost:3000/metrics?"
+        );
+        // Sniffed from the body when the Content-Type is missing or wrong.
+        let error = check("http://localhost:3000/login?next=%2F", Some("text/plain"), HTML).unwrap_err();
+        assert_eq!(error.kind, "HTML");
+        assert_eq!(error.suggestion.as_deref(), Some("http://localhost:3000/metrics"));
+        let error = check("http://localhost:9090/metrics", None, "\u{feff}{\"status\":\"error\"}").unwrap_err();
+        assert_eq!(error.to_string(), "endpoint returned JSON rather than metrics");
+
+        assert!(check("http://localhost:9100/metrics", Some("text/plain; version=0.0.4"), "up 1\n").is_ok());
+        assert!(check("http://localhost:9100/metrics", None, "# HELP up Whether it's up.\n").is_ok());
+    }
+
+    #[test]
+    fn content_types_prometheus_would_balk_at_are_warned_about() {
+        let warnings = |content_type| check("http://localhost:9100/metrics", content_type, "up 1\n").unwrap();
+        assert_eq!(warnings(Some("text/plain; version=0.0.4; charset=utf-8")), Vec::<String>::new());
+        assert_eq!(warnings(Some("Text/Plain;Version=\"0.0.4\"")), Vec::<String>::new());
+        assert_eq!(
+            warnings(Some("application/openmetrics-text; version=1.0.0; charset=utf-8")),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings(Some("text/plain; charset=utf-8")),
+            vec!["Content-Type \"text/plain; charset=utf-8\" lacks version=0.0.4"]
+        );
+        assert_eq!(
+            warnings(Some("application/octet-stream")),
+            vec!["unexpected Content-Type \"application/octet-stream\""]
+        );
+        assert!(warnings(None)[0].starts_with("no Content-Type header"));
+    }
+}
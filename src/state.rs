@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::app::SortKey;
+use crate::columns::Columns;
+use crate::config;
+use crate::theme::ThemeName;
+
+/// Bumped when a field changes meaning; files of any other version are
+/// ignored rather than misread.
+const VERSION: u32 = 1;
+
+/// What the viewer remembers about one endpoint between sessions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct State {
+    pub version: u32,
+    /// The endpoint the state belongs to, in case two of them hash alike.
+    pub endpoint: String,
+    /// Sorted, so the same pins always compare and save the same.
+    pub pinned: Vec<String>,
+    /// The `/` filter.
+    pub filter: String,
+    pub sort: SortKey,
+    pub sort_reversed: bool,
+    /// Only once one was chosen with `T`, so `--theme` and the config file
+    /// decide otherwise.
+    pub theme: Option<ThemeName>,
+    /// The table's columns, once they were picked with `|`; `--columns`
+    /// decides otherwise.
+    pub columns: Option<Columns>,
+}
+
+impl State {
+    pub fn new(endpoint: &str) -> State {
+        State {
+            version: VERSION,
+            endpoint: endpoint.to_string(),
+            pinned: Vec::new(),
+            filter: String::new(),
+            sort: SortKey::Name,
+            sort_reversed: false,
+            theme: None,
+            columns: None,
+        }
+    }
+}
+
+/// `~/.config/promtool/state/<hash>.json`, next to the config file, with
+/// the hash of `endpoint` keeping the name filesystem-safe.
+pub fn path(endpoint: &str) -> Option<PathBuf> {
+    let config = config::default_path()?;
+    Some(
+        config
+            .parent()?
+            .join("state")
+            .join(format!("{:016x}.json", fnv1a(endpoint))),
+    )
+}
+
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to give
+/// the same file name, or place in the refresh interval, on every build.
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The state saved for `endpoint` at `path`, if any. A file that can't be
+/// read, doesn't parse, or belongs to another version or endpoint is only
+/// worth a warning.
+pub fn load(path: &Path, endpoint: &str) -> Result<Option<State>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("ignoring saved state {}: {e}", path.display())),
+    };
+    let state: State = serde_json::from_str(&text)
+        .map_err(|e| format!("ignoring saved state {}, which is corrupt: {e}", path.display()))?;
+    if state.version != VERSION {
+        return Err(format!(
+            "ignoring saved state {} from another version of promtool",
+            path.display()
+        ));
+    }
+    if state.endpoint != endpoint {
+        return Err(format!(
+            "ignoring saved state {}, which is for {}",
+            path.display(),
+            state.endpoint
+        ));
+    }
+    Ok(Some(state))
+}
+
+/// Writes to a temporary file first, so a crash mid-write can't leave a
+/// truncated file behind.
+pub fn save(path: &Path, state: &State) -> Result<()> {
+    write_json(path, state)
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    }
+    let partial = path.with_extension("json.tmp");
+    std::fs::write(&partial, serde_json::to_vec_pretty(value)?)
+        .with_context(|| format!("couldn't write {}", partial.display()))?;
+    std::fs::rename(&partial, path).with_context(|| format!("couldn't write {}", path.display()))
+}
+
+/// How many endpoints the `:` prompt remembers.
+pub const MAX_ENDPOINTS: usize = 50;
+
+/// The endpoints opened from the viewer's `:` prompt, newest first. Unlike
+/// the rest of the state they're shared by every endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Endpoints {
+    pub version: u32,
+    pub endpoints: Vec<String>,
+}
+
+/// `~/.config/promtool/state/endpoints.json`, next to the state of each
+/// endpoint.
+pub fn endpoints_path() -> Option<PathBuf> {
+    Some(config::default_path()?.parent()?.join("state").join("endpoints.json"))
+}
+
+/// The endpoints saved at `path`, none if there's no file yet. As with the
+/// state, a bad file is only worth a warning.
+pub fn load_endpoints(path: &Path) -> Result<Vec<String>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("ignoring endpoint history {}: {e}", path.display())),
+    };
+    let saved: Endpoints = serde_json::from_str(&text)
+        .map_err(|e| format!("ignoring endpoint history {}, which is corrupt: {e}", path.display()))?;
+    if saved.version != VERSION {
+        return Err(format!(
+            "ignoring endpoint history {} from another version of promtool",
+            path.display()
+        ));
+    }
+    Ok(saved.endpoints)
+}
+
+pub fn save_endpoints(path: &Path, endpoints: &[String]) -> Result<()> {
+    let saved = Endpoints {
+        version: VERSION,
+        endpoints: endpoints.to_vec(),
+    };
+    write_json(path, &saved)
+}
+
+/// Moves `endpoint` to the front of `endpoints`, or adds it there, dropping
+/// the oldest past `MAX_ENDPOINTS`.
+pub fn remember(endpoints: &mut Vec<String>, endpoint: &str) {
+    endpoints.retain(|known| known != endpoint);
+    endpoints.insert(0, endpoint.to_string());
+    endpoints.truncate(MAX_ENDPOINTS);
+}
+
+/// Forgets the state saved at `path`; there being none is fine.
+pub fn clear(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("couldn't remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips_and_bad_files_are_ignored() {
+        let dir = std::env::temp_dir().join(format!("promtool-state-test-{}", std::process::id()));
+        let path = dir.join("state.json");
+        let endpoint = "http://localhost:9100/metrics";
+        assert_eq!(load(&path, endpoint), Ok(None));
+
+        let mut state = State::new(endpoint);
+        state.pinned = vec![String::from("up")];
+        state.filter = String::from("http_");
+        state.sort = SortKey::SeriesCount;
+        state.theme = Some(ThemeName::Light);
+        state.columns = Some(Columns::parse("name,rate,value").unwrap());
+        save(&path, &state).unwrap();
+        assert_eq!(load(&path, endpoint), Ok(Some(state.clone())));
+        assert!(load(&path, "http://localhost:8080/metrics")
+            .unwrap_err()
+            .contains("which is for"));
+
+        std::fs::write(&path, "{\"version\": 1, \"pinned\": [").unwrap();
+        assert!(load(&path, endpoint).unwrap_err().contains("corrupt"));
+        state.version = VERSION + 1;
+        save(&path, &state).unwrap();
+        assert!(load(&path, endpoint).unwrap_err().contains("another version"));
+
+        clear(&path).unwrap();
+        clear(&path).unwrap();
+        assert_eq!(load(&path, endpoint), Ok(None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn endpoint_history_keeps_the_newest_first_once_each() {
+        let dir = std::env::temp_dir().join(format!("promtool-endpoints-test-{}", std::process::id()));
+        let path = dir.join("endpoints.json");
+        assert_eq!(load_endpoints(&path), Ok(Vec::new()));
+
+        let mut endpoints = Vec::new();
+        remember(&mut endpoints, "http://pod-a:9100/metrics");
+        remember(&mut endpoints, "http://pod-b:9100/metrics");
+        remember(&mut endpoints, "http://pod-a:9100/metrics");
+        assert_eq!(endpoints, vec!["http://pod-a:9100/metrics", "http://pod-b:9100/metrics"]);
+        for n in 0..MAX_ENDPOINTS {
+            remember(&mut endpoints, &format!("http://pod-{n}:9100/metrics"));
+        }
+        assert_eq!(endpoints.len(), MAX_ENDPOINTS);
+        assert_eq!(endpoints[0], format!("http://pod-{}:9100/metrics", MAX_ENDPOINTS - 1));
+
+        save_endpoints(&path, &endpoints).unwrap();
+        assert_eq!(load_endpoints(&path), Ok(endpoints));
+        std::fs::write(&path, "[").unwrap();
+        assert!(load_endpoints(&path).unwrap_err().contains("corrupt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_names_are_stable_hashes_of_the_endpoint() {
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(fnv1a("localhost:9100"), fnv1a("localhost:9101"));
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One target of a `--targets-file`: the URL to scrape and the labels its
+/// series get.
+#[derive(Clone, PartialEq)]
+pub struct Target {
+    pub url: String,
+    pub labels: Vec<(String, String)>,
+}
+
+/// The valid targets of a file, in file order, plus why the others were
+/// left out.
+pub struct Targets {
+    pub targets: Vec<Target>,
+    pub invalid: Vec<String>,
+}
+
+/// One entry of the file, as Prometheus' file and HTTP service discovery
+/// write them.
+#[derive(Deserialize)]
+struct Group {
+    targets: Vec<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+/// Reads a file_sd/http_sd style list of `{"targets": [...], "labels":
+/// {...}}` groups, in JSON or YAML. `__scheme__`, `__metrics_path__` and
+/// `__param_<name>` shape the URL like they do in Prometheus; the other
+/// labels, plus `instance`, are added to every series. A malformed group or
+/// target is reported and skipped rather than failing the whole file.
+pub fn load(path: &Path) -> Result<Targets> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    // JSON is YAML too, so one parser covers both formats.
+    let groups: Vec<serde_yaml::Value> =
+        serde_yaml::from_str(&text).with_context(|| format!("{} isn't a list of target groups", path.display()))?;
+
+    let mut targets = Targets {
+        targets: Vec::new(),
+        invalid: Vec::new(),
+    };
+    for (index, group) in groups.into_iter().enumerate() {
+        let group: Group = match serde_yaml::from_value(group) {
+            Ok(group) => group,
+            Err(e) => {
+                targets.invalid.push(format!("group {}: {e}", index + 1));
+                continue;
+            }
+        };
+        for address in &group.targets {
+            match target(address, &group.labels) {
+                Ok(target) => targets.targets.push(target),
+                Err(e) => targets.invalid.push(format!("{address:?}: {e}")),
+            }
+        }
+    }
+    Ok(targets)
+}
+
+fn target(address: &str, labels: &BTreeMap<String, String>) -> Result<Target, String> {
+    check_address(address)?;
+    let scheme = labels.get("__scheme__").map_or("http", String::as_str);
+    let path = labels.get("__metrics_path__").map_or("/metrics", String::as_str);
+    let params: Vec<String> = labels
+        .iter()
+        .filter_map(|(name, value)| Some(format!("{}={value}", name.strip_prefix("__param_")?)))
+        .collect();
+    let mut url = format!("{scheme}://{address}{path}");
+    if !params.is_empty() {
+        url.push('?');
+        url.push_str(&params.join("&"));
+    }
+
+    let mut pairs: Vec<(String, String)> = labels
+        .iter()
+        .filter(|(name, _)| !name.starts_with("__"))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if !labels.contains_key("instance") {
+        pairs.push((String::from("instance"), address.to_string()));
+    }
+    Ok(Target { url, labels: pairs })
+}
+
+/// Targets are `host[:port]`, with IPv6 hosts in brackets; schemes and
+/// paths belong in `__scheme__` and `__metrics_path__`.
+fn check_address(address: &str) -> Result<(), String> {
+    if address.contains("://") {
+        return Err(String::from("targets are host:port; set the scheme with a __scheme__ label"));
+    }
+    if address.contains('/') {
+        return Err(String::from("targets are host:port; set the path with a __metrics_path__ label"));
+    }
+    if address.is_empty() || address.chars().any(char::is_whitespace) {
+        return Err(String::from("not a host:port"));
+    }
+    let port = match address.rsplit_once(']') {
+        Some((_, rest)) => rest.strip_prefix(':'),
+        None if address.matches(':').count() == 1 => address.rsplit_once(':').map(|(_, port)| port),
+        None if address.contains(':') => return Err(String::from("IPv6 hosts need brackets, e.g. [::1]:9100")),
+        None => None,
+    };
+    if port.is_some_and(|port| port.parse::<u16>().is_err()) {
+        return Err(String::from("the port isn't a number from 0 to 65535"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_resolve_to_urls_and_labels_with_bad_targets_reported() {
+        let path = std::env::temp_dir().join(format!("promtool-targets-test-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            concat!(
+                "- targets: [\"node-1:9100\", \"http://node-2:9100\", \"node-3:91OO\"]\n",
+                "  labels: {job: node}\n",
+                "- targets: [\"app:8443\"]\n",
+                "  labels: {__scheme__: https, __metrics_path__: /admin/metrics, __param_format: text}\n",
+                "- labels: {job: nothing}\n",
+            ),
+        )
+        .unwrap();
+        let targets = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let urls: Vec<&str> = targets.targets.iter().map(|target| target.url.as_str()).collect();
+        assert_eq!(urls, vec!["http://node-1:9100/metrics", "https://app:8443/admin/metrics?format=text"]);
+        assert_eq!(
+            targets.targets[0].labels,
+            vec![
+                (String::from("job"), String::from("node")),
+                (String::from("instance"), String::from("node-1:9100")),
+            ]
+        );
+        assert_eq!(targets.invalid.len(), 3);
+        assert!(targets.invalid[0].starts_with("\"http://node-2:9100\""));
+        assert!(targets.invalid[2].starts_with("group 3"));
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use std::io;
+use std::sync::Arc;
+
+/// Runs `run` when dropped, so the terminal is put back however the code
+/// holding it ends, unwinding from a panic included.
+struct Restore<F: FnMut()> {
+    restore: F,
+}
+
+impl<F: FnMut()> Drop for Restore<F> {
+    fn drop(&mut self) {
+        (self.restore)();
+    }
+}
+
+/// Leaves raw mode, the alternate screen and mouse capture. Undoing what
+/// was never done is harmless, so this is safe to call more than once.
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        crossterm::cursor::Show
+    )
+}
+
+/// Runs `run` on the alternate screen in raw mode, restoring the terminal
+/// afterwards even if it fails or panics. `mouse` captures the mouse, which
+/// takes text selection away from the terminal.
+pub(crate) fn with_terminal(
+    mouse: bool,
+    run: impl FnOnce(&mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>,
+) -> Result<()> {
+    // The guard alone would restore the terminal only after the panic
+    // message was printed to the alternate screen and lost with it, so the
+    // hook restores it first.
+    let previous = Arc::new(std::panic::take_hook());
+    let hook = previous.clone();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        hook(info);
+    }));
+    let restore = Restore {
+        restore: move || {
+            let _ = restore_terminal();
+            // Later panics go to the previous hook again. It can't be put
+            // back while unwinding, but that panic ends the viewer anyway.
+            if !std::thread::panicking() {
+                let previous = previous.clone();
+                let _ = std::panic::take_hook();
+                std::panic::set_hook(Box::new(move |info| previous(info)));
+            }
+        },
+    };
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let res = run(&mut terminal);
+    drop(restore);
+
+    if let Err(err) = res {
+        println!("{err:?}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_panic_while_drawing_still_restores_the_terminal() {
+        let restored = std::cell::Cell::new(false);
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _restore = Restore {
+                restore: || restored.set(true),
+            };
+            let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(40, 10)).unwrap();
+            terminal.draw(|_| panic!("drawing went wrong")).unwrap();
+        }));
+        assert!(outcome.is_err());
+        assert!(restored.get());
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::model::{self, Aggregation, Exposition, Format};
+
+/// The label `--textfile-dir` adds to every series, naming the file it came
+/// from.
+pub const LABEL: &str = "textfile";
+
+/// One `*.prom` file of a textfile-collector directory, as read on a refresh.
+#[derive(Clone)]
+pub struct Textfile {
+    pub name: String,
+    pub modified: Option<SystemTime>,
+    /// The file's text, or why it couldn't be read.
+    pub text: Result<String, String>,
+}
+
+/// Every `*.prom` file in `dir`, by name, the way node_exporter's textfile
+/// collector globs them. Only the directory itself being unreadable is an
+/// error; a file that can't be read is reported as that file's failure.
+pub fn read_dir(dir: &Path) -> Result<Vec<Textfile>> {
+    let entries = std::fs::read_dir(dir).with_context(|| format!("couldn't read {}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("couldn't read {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !name.ends_with(".prom") {
+            continue;
+        }
+        let metadata = std::fs::metadata(entry.path());
+        if metadata.as_ref().is_ok_and(|metadata| !metadata.is_file()) {
+            continue;
+        }
+        files.push(Textfile {
+            modified: metadata.ok().and_then(|metadata| metadata.modified().ok()),
+            text: std::fs::read_to_string(entry.path()).map_err(|e| format!("couldn't read {name}: {e}")),
+            name,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Every readable file's text, each under a comment naming it, for the raw
+/// view, the pager and recordings.
+pub fn concatenate(files: &[Textfile]) -> String {
+    files
+        .iter()
+        .filter_map(|file| Some((&file.name, file.text.as_ref().ok()?)))
+        .map(|(name, text)| {
+            let newline = if text.ends_with('\n') || text.is_empty() {
+                ""
+            } else {
+                "\n"
+            };
+            format!("# textfile: {name}\n{text}{newline}")
+        })
+        .collect()
+}
+
+/// How one file fared on the last refresh.
+#[derive(Debug, PartialEq)]
+pub struct FileStatus {
+    pub name: String,
+    pub modified: Option<SystemTime>,
+    pub series: usize,
+    /// Why the file couldn't be read or parsed, leaving its series out.
+    pub error: Option<String>,
+}
+
+/// The merged scrape of a directory, and what became of each file.
+pub struct Scan {
+    pub metrics: Exposition,
+    pub files: Vec<FileStatus>,
+    /// A family whose TYPE differs between files keeps the first file's
+    /// series; each disagreement is a warning.
+    pub warnings: Vec<String>,
+}
+
+/// Parses one file the way a whole scrape would be. Lines `--lenient`
+/// skips aren't reported, as they aren't per file.
+pub fn parser(format: Format, lenient: bool) -> impl Fn(&str) -> Result<Exposition, String> {
+    move |text| {
+        if lenient {
+            model::parse_lenient(text, None, format).map(|(metrics, _)| metrics)
+        } else {
+            model::parse(text, None, format)
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Parses every file on its own with `parse`, so one broken file only
+/// loses its own series, and merges the rest with each series labelled
+/// with its file.
+pub fn scan(files: &[Textfile], parse: impl Fn(&str) -> Result<Exposition, String>) -> Scan {
+    let mut parsed = Vec::new();
+    let mut statuses = Vec::new();
+    for file in files {
+        let outcome = file.text.as_ref().map_err(String::clone).and_then(|text| parse(text));
+        let (series, error) = match outcome {
+            Ok(exposition) => {
+                let series = exposition.families.values().map(|fam| fam.iter_samples().count()).sum();
+                parsed.push((file.name.as_str(), exposition));
+                (series, None)
+            }
+            Err(e) => (0, Some(e)),
+        };
+        statuses.push(FileStatus {
+            name: file.name.clone(),
+            modified: file.modified,
+            series,
+            error,
+        });
+    }
+    let scrapes: Vec<(&str, &Exposition)> = parsed.iter().map(|(name, exposition)| (*name, exposition)).collect();
+    let (metrics, warnings) = model::merge_as(&scrapes, Aggregation::Each, LABEL);
+    Scan {
+        metrics,
+        files: statuses,
+        warnings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn file(name: &str, text: &str) -> Textfile {
+        Textfile {
+            name: name.to_string(),
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            text: Ok(text.to_string()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Exposition, String> {
+        model::parse(text, None, model::Format::Prometheus).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn files_are_parsed_apart_and_merged_with_their_name() {
+        let files = [
+            file(
+                "backup.prom",
+                "# TYPE job_last_success_seconds gauge\njob_last_success_seconds{job=\"backup\"} 1700000000\n",
+            ),
+            file("broken.prom", "job_last_success_seconds{job=\"rotate\" 1\n"),
+            file(
+                "cleanup.prom",
+                "# TYPE job_last_success_seconds gauge\njob_last_success_seconds{job=\"cleanup\"} 1699990000\n",
+            ),
+            Textfile {
+                text: Err(String::from("couldn't read secret.prom: permission denied")),
+                ..file("secret.prom", "")
+            },
+        ];
+        let scan = scan(&files, parse);
+
+        let fam = &scan.metrics.families["job_last_success_seconds"];
+        let series: Vec<Vec<(String, String)>> = fam.iter_samples().map(model::label_pairs).collect();
+        assert_eq!(series.len(), 2);
+        assert!(series
+            .iter()
+            .any(|labels| labels.contains(&(String::from(LABEL), String::from("backup.prom")))));
+        assert!(series
+            .iter()
+            .any(|labels| labels.contains(&(String::from(LABEL), String::from("cleanup.prom")))));
+
+        let failed: Vec<(&str, usize, bool)> = scan
+            .files
+            .iter()
+            .map(|status| (status.name.as_str(), status.series, status.error.is_some()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("backup.prom", 1, false),
+                ("broken.prom", 0, true),
+                ("cleanup.prom", 1, false),
+                ("secret.prom", 0, true),
+            ]
+        );
+        assert_eq!(scan.files[0].modified, files[0].modified);
+        assert!(scan.warnings.is_empty());
+    }
+
+    #[test]
+    fn type_conflicts_between_files_keep_the_first() {
+        let files = [
+            file("a.prom", "# TYPE jobs_total counter\njobs_total 3\n"),
+            file("b.prom", "# TYPE jobs_total gauge\njobs_total 4\n"),
+        ];
+        let scan = scan(&files, parse);
+        assert_eq!(scan.metrics.families["jobs_total"].iter_samples().count(), 1);
+        assert_eq!(
+            scan.warnings,
+            vec!["jobs_total is a counter on a.prom but a gauge on b.prom"]
+        );
+    }
+
+    #[test]
+    fn only_prom_files_are_read_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("promtool-textfile-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested.prom")).unwrap();
+        std::fs::write(dir.join("b.prom"), "b 2").unwrap();
+        std::fs::write(dir.join("a.prom"), "a 1\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not metrics").unwrap();
+        std::fs::write(dir.join(".c.prom"), "being written").unwrap();
+
+        let files = read_dir(&dir).unwrap();
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, vec!["a.prom", "b.prom"]);
+        assert!(files.iter().all(|file| file.modified.is_some()));
+        assert_eq!(
+            concatenate(&files),
+            "# textfile: a.prom\na 1\n# textfile: b.prom\nb 2\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(read_dir(&dir).is_err());
+    }
+}
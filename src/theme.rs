@@ -0,0 +1,118 @@
+use ratatui::style::{Color, Modifier, Style};
+
+/// Every color the viewer uses, so terminals with light backgrounds (or no
+/// color at all) can swap the whole palette at once.
+pub struct Theme {
+    pub name: &'static str,
+    /// The selected row in tables.
+    pub selected: Style,
+    pub error: Style,
+    /// 2xx statuses and the like.
+    pub success: Style,
+    /// Retries, non-2xx statuses, stale data.
+    pub warning: Style,
+    /// Transient messages in the status bar.
+    pub notice: Style,
+    /// Keys in the help overlay, line numbers, pin markers.
+    pub accent: Style,
+    /// The part of a family name matching the `/` filter.
+    pub search_match: Style,
+    /// Badges like STALE in a block title.
+    pub badge: Style,
+    /// Families and series that are new since the last scrape.
+    pub added: Style,
+    pub up: Style,
+    pub down: Style,
+    pub changed: Style,
+    /// Histogram bars.
+    pub chart: Style,
+}
+
+pub const DARK: Theme = Theme {
+    name: "dark",
+    selected: Style::new().fg(Color::Black).bg(Color::LightGreen),
+    error: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+    success: Style::new().fg(Color::Green),
+    warning: Style::new().fg(Color::Yellow),
+    notice: Style::new().fg(Color::Cyan),
+    accent: Style::new().fg(Color::Yellow),
+    search_match: Style::new().fg(Color::Yellow).add_modifier(Modifier::UNDERLINED),
+    badge: Style::new().fg(Color::Black).bg(Color::Yellow),
+    added: Style::new().fg(Color::Green),
+    up: Style::new().fg(Color::Green),
+    down: Style::new().fg(Color::Red),
+    changed: Style::new().add_modifier(Modifier::BOLD),
+    chart: Style::new().fg(Color::Cyan),
+};
+
+/// Yellow and cyan wash out on white backgrounds, so this sticks to darker
+/// blues and magentas.
+pub const LIGHT: Theme = Theme {
+    name: "light",
+    selected: Style::new().fg(Color::White).bg(Color::Blue),
+    error: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+    success: Style::new().fg(Color::Green),
+    warning: Style::new().fg(Color::Magenta),
+    notice: Style::new().fg(Color::Blue),
+    accent: Style::new().fg(Color::Blue),
+    search_match: Style::new().fg(Color::Magenta).add_modifier(Modifier::UNDERLINED),
+    badge: Style::new().fg(Color::White).bg(Color::Magenta),
+    added: Style::new().fg(Color::Green),
+    up: Style::new().fg(Color::Green),
+    down: Style::new().fg(Color::Red),
+    changed: Style::new().add_modifier(Modifier::BOLD),
+    chart: Style::new().fg(Color::Blue),
+};
+
+/// No colors at all, only modifiers, for `NO_COLOR` and screen readers.
+pub const MONO: Theme = Theme {
+    name: "mono",
+    selected: Style::new().add_modifier(Modifier::REVERSED),
+    error: Style::new().add_modifier(Modifier::BOLD),
+    success: Style::new(),
+    warning: Style::new().add_modifier(Modifier::BOLD),
+    notice: Style::new().add_modifier(Modifier::ITALIC),
+    accent: Style::new().add_modifier(Modifier::BOLD),
+    search_match: Style::new().add_modifier(Modifier::UNDERLINED),
+    badge: Style::new().add_modifier(Modifier::REVERSED),
+    added: Style::new().add_modifier(Modifier::BOLD),
+    up: Style::new().add_modifier(Modifier::BOLD),
+    down: Style::new().add_modifier(Modifier::BOLD),
+    changed: Style::new().add_modifier(Modifier::BOLD),
+    chart: Style::new(),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    Dark,
+    Light,
+    Mono,
+}
+
+impl ThemeName {
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            ThemeName::Dark => &DARK,
+            ThemeName::Light => &LIGHT,
+            ThemeName::Mono => &MONO,
+        }
+    }
+
+    pub fn next(self) -> ThemeName {
+        match self {
+            ThemeName::Dark => ThemeName::Light,
+            ThemeName::Light => ThemeName::Mono,
+            ThemeName::Mono => ThemeName::Dark,
+        }
+    }
+
+    /// `requested`, unless `NO_COLOR` is set to anything non-empty
+    /// (https://no-color.org), which always wins.
+    pub fn from_env(requested: ThemeName) -> ThemeName {
+        match std::env::var_os("NO_COLOR") {
+            Some(value) if !value.is_empty() => ThemeName::Mono,
+            _ => requested,
+        }
+    }
+}
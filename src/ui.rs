@@ -0,0 +1,3187 @@
+use openmetrics_parser::{HistogramValue, PrometheusType, PrometheusValue, SummaryValue};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{
+    Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Clear, Dataset, GraphType, Paragraph, Row, Table, TableState,
+    Tabs, Wrap,
+};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::app::{
+    single_value, App, CardinalityState, ClickTarget, DetailState, EventsState, InputMode, LabelBreakdown, LabelPicker,
+    LintState, MoversState, QueryState, RawState, SortKey, SourceState, TableRow, TextfilesState, View,
+};
+use crate::columns::{self, Column, Columns};
+use crate::events::{self, Event};
+use crate::fetch;
+use crate::highlight;
+use crate::history::{self, Point};
+use crate::keys;
+use crate::lint;
+use crate::model::{self, Change, Family, Movement, Series, SeriesKey, SkippedLine};
+use crate::natural;
+use crate::search;
+use crate::theme::Theme;
+
+/// Byte range of the first case-insensitive occurrence of `needle`.
+/// Metric names are ASCII, so ASCII case folding keeps offsets aligned.
+pub(crate) fn find_ignore_case(haystack: &str, needle: &str) -> Option<std::ops::Range<usize>> {
+    let start = haystack.to_ascii_lowercase().find(&needle.to_ascii_lowercase())?;
+    Some(start..start + needle.len())
+}
+
+/// Renders a family name with the part matching the filter highlighted.
+/// Returns owned spans so the table doesn't hold a borrow of the app while
+/// its state is being updated.
+fn refreshes_ago(count: u32) -> String {
+    if count == 1 {
+        String::from("1 refresh ago")
+    } else {
+        format!("{count} refreshes ago")
+    }
+}
+
+fn highlighted_name(name: &str, filter: &str, theme: &Theme) -> Line<'static> {
+    match find_ignore_case(name, filter) {
+        Some(range) if !filter.is_empty() => Line::from(vec![
+            Span::raw(name[..range.start].to_string()),
+            Span::styled(name[range.clone()].to_string(), theme.search_match),
+            Span::raw(name[range.end..].to_string()),
+        ]),
+        _ => Line::from(name.to_string()),
+    }
+}
+
+/// Scrolls a cell `offset` characters to the left, then fits it in `width`
+/// by eliding its middle (`node_filesystem_…_bytes`) so both the prefix and
+/// the suffix stay readable. Works on chars, so a multibyte label value is
+/// never cut in half.
+fn fit_line(line: Line<'static>, offset: usize, width: usize) -> Line<'static> {
+    let mut chars: Vec<(char, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .skip(offset)
+        .collect();
+    if offset > 0 {
+        if let Some(first) = chars.first_mut() {
+            first.0 = '…';
+        }
+    }
+    if width > 0 && chars.len() > width {
+        let tail = (width - 1) / 2;
+        let head = width - 1 - tail;
+        let style = chars[head].1;
+        let end = chars.len() - tail;
+        chars.drain(head..end);
+        chars.insert(head, ('…', style));
+    }
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    for (c, style) in chars {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.content.to_mut().push(c),
+            _ => spans.push(Span::styled(c.to_string(), style)),
+        }
+    }
+    Line { spans, ..line }
+}
+
+/// Whether `fit_line` would change `text`, so the full text needs showing
+/// elsewhere.
+fn is_cut(text: &str, offset: usize, width: usize) -> bool {
+    offset > 0 || text.chars().count() > width
+}
+
+fn movement_style(movement: Option<Movement>, theme: &Theme) -> Style {
+    match movement {
+        Some(Movement::Up) => theme.up,
+        Some(Movement::Down) => theme.down,
+        Some(Movement::Changed) => theme.changed,
+        Some(Movement::Unchanged) | None => Style::default(),
+    }
+}
+
+/// NaN says there's no data rather than giving a number, so it's dimmed.
+fn number_style(value: Option<f64>, style: Style) -> Style {
+    if value.is_some_and(f64::is_nan) {
+        style.add_modifier(Modifier::DIM)
+    } else {
+        style
+    }
+}
+
+/// The family's type, with its OpenMetrics UNIT appended when it has one.
+fn label_family_type(kind: model::LabelFamily) -> String {
+    match kind {
+        model::LabelFamily::Info => String::from("info"),
+        model::LabelFamily::StateSet => String::from("stateset"),
+    }
+}
+
+/// A multi-series family's value cell: its series count, then the range of
+/// a gauge, the total of a counter or the observations of a histogram.
+fn summary_text(app: &App, name: &str, fam: &Family, summary: &model::FamilySummary) -> String {
+    let number = |value: f64, series_name: &str| app.display_number(value, series_name, fam);
+    let stats = match summary.stats {
+        model::SummaryStats::Range { min, avg, max } => {
+            format!("{}…{}, avg {}", number(min, name), number(max, name), number(avg, name))
+        }
+        model::SummaryStats::Sum(sum) => format!("Σ {}", number(sum, name)),
+        model::SummaryStats::Observations(count) => {
+            format!("{} observations", number(count as f64, &format!("{name}_count")))
+        }
+        model::SummaryStats::Empty => return format!("{} series", summary.series),
+    };
+    format!("{} series · {stats}", summary.series)
+}
+
+/// Labelsets a label family's table row shows before summing up the rest.
+const MAX_LABEL_LINES: usize = 3;
+
+/// The value cell of a label family: a line per labelset, up to a few.
+fn label_cell(lines: Vec<String>) -> Text<'static> {
+    let shown = if lines.len() > MAX_LABEL_LINES { MAX_LABEL_LINES - 1 } else { lines.len() };
+    let more = lines.len() - shown;
+    let mut text: Vec<Line> = lines.into_iter().take(shown).map(Line::from).collect();
+    if more > 0 {
+        text.push(Line::from(format!("+{more} more")).dim());
+    }
+    Text::from(text)
+}
+
+/// Every labelset of a label family as a table of its labels; a state set's
+/// rows are lit up for the states it's in.
+fn render_label_table(f: &mut Frame, area: Rect, app: &App, name: &str, fam: &Family, kind: model::LabelFamily) {
+    let names = fam.get_label_names();
+    let rows: Vec<Row> = fam
+        .iter_samples()
+        .map(|sample| {
+            let pairs = model::label_pairs(sample);
+            let cells: Vec<String> = names
+                .iter()
+                .map(|label| pairs.iter().find(|(pair, _)| pair == label).map_or_else(String::new, |(_, v)| v.clone()))
+                .collect();
+            let row = Row::new(cells);
+            let active = model::scalar_value(&sample.value).is_some_and(|value| value != 0.0);
+            match kind {
+                model::LabelFamily::StateSet if active => row.style(app.theme().success),
+                model::LabelFamily::StateSet => row.dim(),
+                model::LabelFamily::Info => row,
+            }
+        })
+        .collect();
+    let title = match kind {
+        model::LabelFamily::Info => format!("Labels of {name}"),
+        model::LabelFamily::StateSet => format!("States of {name}, lit where set"),
+    };
+    let widths = vec![Constraint::Fill(1); names.len()];
+    let table = Table::new(rows, widths)
+        .header(Row::new(names.iter().map(String::as_str).collect::<Vec<_>>()).bold())
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(table, area);
+}
+
+fn type_label(fam: &Family) -> String {
+    if fam.unit.is_empty() {
+        fam.family_type.to_string()
+    } else {
+        format!("{} ({})", fam.family_type, fam.unit)
+    }
+}
+
+/// Human-readable byte count in binary units: `412 KiB`, `2.3 MiB`.
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if size < 10.0 {
+        format!("{size:.1} {}", UNITS[unit])
+    } else {
+        format!("{size:.0} {}", UNITS[unit])
+    }
+}
+
+/// One piece of the status bar. Segments with the lowest priority are
+/// dropped first when the terminal is too narrow for all of them.
+struct Segment {
+    pub(crate) text: String,
+    style: Style,
+    priority: u8,
+}
+
+impl Segment {
+    pub(crate) fn new(text: impl Into<String>, priority: u8) -> Segment {
+        Segment {
+            text: text.into(),
+            style: Style::default(),
+            priority,
+        }
+    }
+
+    fn style(mut self, style: Style) -> Segment {
+        self.style = style;
+        self
+    }
+}
+
+/// Status bar contents in display order: any error, where the metrics come
+/// from, what the last scrape looked like, and how long ago it happened.
+fn status_segments(app: &App) -> Vec<Segment> {
+    let theme = app.theme();
+    let mut segments = Vec::new();
+    if let Some(err) = &app.last_error {
+        segments.push(Segment::new(format!("{err:#}"), 9).style(theme.error));
+    }
+    if let Some(err) = app.fetcher.record_error() {
+        segments.push(Segment::new(format!("recording paused: {err}"), 7).style(theme.warning));
+    }
+    segments.push(Segment::new(app.endpoint.as_str(), 1));
+    if let Some(status) = app.last_status {
+        let style = if status.is_success() || status == reqwest::StatusCode::NOT_MODIFIED {
+            theme.success
+        } else {
+            theme.warning
+        };
+        segments.push(Segment::new(status.to_string(), 3).style(style));
+    }
+    if app.last_success_at.is_some() {
+        let size = match app.last_body_size {
+            Some((transferred, decoded)) if transferred != decoded => {
+                format!("{} ({} uncompressed)", format_size(transferred), format_size(decoded))
+            }
+            Some((transferred, _)) => format_size(transferred),
+            None => String::new(),
+        };
+        if !size.is_empty() {
+            segments.push(Segment::new(size, 2));
+        }
+        segments.push(Segment::new(
+            format!(
+                "fetch {}ms, parse {}ms",
+                app.last_fetch_duration.as_millis(),
+                app.last_parse_duration.as_millis()
+            ),
+            2,
+        ));
+    }
+    if app.skipped_refreshes > 0 {
+        let text = match app.skipped_refreshes {
+            1 => String::from("1 refresh skipped, the fetch before was still running"),
+            n => format!("{n} refreshes skipped, the fetch before was still running"),
+        };
+        segments.push(Segment::new(text, 5).style(theme.warning));
+    }
+    if let Some(metrics) = &app.current {
+        let counts = if app.filter.is_empty() && app.type_filter.is_none() {
+            let series: usize = app.cardinality.iter().map(|c| c.series).sum();
+            format!("{} families, {series} series", metrics.families.len())
+        } else if let Some(matches) = &app.label_matches {
+            let visible = app.visible_families();
+            let series: usize = visible.iter().filter_map(|(name, _)| matches.families.get(*name)).sum();
+            format!("{} of {} families, {series} series matching", visible.len(), metrics.families.len())
+        } else {
+            let visible = app.visible_families();
+            let series: usize = visible.iter().map(|(_, fam)| fam.iter_samples().count()).sum();
+            format!("{} of {} families, {series} series", visible.len(), metrics.families.len())
+        };
+        segments.push(Segment::new(counts, 4));
+    }
+    if app.hide_zero || app.hide_unchanged {
+        // Until a series has been seen for long enough, none is unchanged.
+        let (zero, unchanged) = app.hidden_series();
+        let to_go = app.steadiness.refreshes_to_go(app.unchanged_after);
+        let mut counts = Vec::new();
+        if app.hide_zero {
+            counts.push(format!("{zero} zero"));
+        }
+        if app.hide_unchanged && to_go == 0 {
+            counts.push(format!("{unchanged} unchanged"));
+        }
+        let text = match (counts.is_empty(), app.hide_unchanged && to_go > 0) {
+            (false, false) => format!("hiding {} series (z/Z)", counts.join(", ")),
+            (false, true) => format!("hiding {} series, unchanged ones after {to_go} more refreshes (z/Z)", counts[0]),
+            (true, _) => format!("hiding unchanged series after {to_go} more refreshes (z/Z)"),
+        };
+        segments.push(Segment::new(text, 6).style(theme.accent));
+    }
+    if let Some(baseline) = &app.baseline {
+        let age = humantime::format_duration(Duration::from_secs(baseline.taken_at.elapsed().as_secs()));
+        segments.push(Segment::new(format!("baseline taken {age} ago"), 3));
+    }
+    let counters = &app.counter_resets;
+    if counters.resets + counters.partial > 0 {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let text = format!(
+            "counters: {} reset{}, {} decrease{}",
+            counters.resets,
+            plural(counters.resets),
+            counters.partial,
+            plural(counters.partial)
+        );
+        let style = if counters.partial > 0 { theme.warning } else { Style::default() };
+        segments.push(Segment::new(text, 3).style(style));
+    }
+    if !app.duplicates.is_empty() {
+        let text = format!("{} duplicate lines (L)", app.duplicates.len());
+        segments.push(Segment::new(text, 5).style(theme.warning));
+    }
+    if !app.lints.is_empty() {
+        let errors = app.lints.iter().filter(|finding| finding.severity == lint::Severity::Error).count();
+        let style = if errors > 0 { theme.error } else { theme.warning };
+        segments.push(Segment::new(format!("lint: {} (L)", app.lints.len()), 3).style(style));
+    }
+
+    let activity = if let Some(replay) = &app.replay {
+        let at = humantime::format_rfc3339_seconds(replay.scrape(replay.position()).at);
+        let playback = if replay.is_playing() {
+            format!(" ▶ {}x", replay.speed())
+        } else {
+            String::new()
+        };
+        format!("scrape {}/{} at {at}{playback}", replay.position() + 1, replay.len())
+    } else {
+        match (app.fetcher.in_flight_since(), app.last_success_at) {
+            (Some(since), _) => {
+                const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+                let frame = SPINNER[(since.elapsed().as_millis() / 100) as usize % SPINNER.len()];
+                match app.fetcher.retry() {
+                    Some(retry) => format!(
+                        "{frame} retrying (attempt {}/{}) after: {}",
+                        retry.attempt, retry.attempts, retry.error
+                    ),
+                    None => format!("{frame} fetching…"),
+                }
+            }
+            (None, Some(at)) if app.last_error.is_some() => format!("last good scrape {}s ago", at.elapsed().as_secs()),
+            (None, Some(at)) => match app.unchanged {
+                Some((unchanged, checked)) => format!(
+                    "unchanged ({}), checked {}s ago, changed {}s ago",
+                    unchanged.text(),
+                    checked.elapsed().as_secs(),
+                    at.elapsed().as_secs()
+                ),
+                None => format!("refreshed {}s ago", at.elapsed().as_secs()),
+            },
+            (None, None) => String::from("not fetched yet"),
+        }
+    };
+    match &app.paused {
+        Some(paused) => segments.push(Segment::new(format!("{} (space resumes)", paused.text()), 8).style(theme.badge)),
+        None => segments.push(Segment::new(activity, 8)),
+    }
+
+    if let Some(warning) = app.merge_warnings.first() {
+        let text = match app.merge_warnings.len() - 1 {
+            0 => warning.clone(),
+            more => format!("{warning} (+{more} more TYPE conflicts)"),
+        };
+        segments.push(Segment::new(text, 5).style(theme.warning));
+    }
+    if let Some(warning) = app.fetch_warnings.first() {
+        let text = match app.fetch_warnings.len() - 1 {
+            0 => warning.clone(),
+            more => format!("{warning} (+{more} more)"),
+        };
+        segments.push(Segment::new(text, 4).style(theme.warning));
+    }
+    if let Some(first) = app.skewed.first() {
+        let text = match app.skewed.len() - 1 {
+            0 => model::format_skew(first),
+            more => format!("{} (+{more} more)", model::format_skew(first)),
+        };
+        segments.push(Segment::new(text, 5).style(theme.warning));
+    }
+    if let Some(files) = &app.textfiles {
+        let broken = files.iter().filter(|file| file.error.is_some()).count();
+        let oldest = files.iter().filter_map(|file| file.modified).min();
+        let mut text = format!("{} textfiles", files.len());
+        if broken > 0 {
+            text.push_str(&format!(", {broken} broken"));
+        }
+        if let Some(age) = oldest.and_then(|at| at.elapsed().ok()) {
+            let age = humantime::format_duration(Duration::from_secs(age.as_secs()));
+            text.push_str(&format!(", oldest written {age} ago"));
+        }
+        text.push_str(" (I)");
+        let style = if broken > 0 { theme.error } else { Style::default() };
+        segments.push(Segment::new(text, 5).style(style));
+    }
+    if let Some(invalid) = app.invalid_targets.first() {
+        let text = match app.invalid_targets.len() - 1 {
+            0 => format!("skipped target {invalid}"),
+            more => format!("skipped target {invalid} (+{more} more)"),
+        };
+        segments.push(Segment::new(text, 5).style(theme.warning));
+    }
+
+    if let Some(truncated) = &app.truncated {
+        let text = format!(
+            "truncated at --max-series {}: {} famil{} not read",
+            truncated.max_series,
+            truncated.families_left,
+            if truncated.families_left == 1 { "y" } else { "ies" }
+        );
+        segments.push(Segment::new(text, 8).style(theme.warning));
+    }
+
+    let skipped = app.skipped_lines.len();
+    let hint = match app.current_notice() {
+        Some(notice) => Segment::new(notice, 6).style(theme.notice),
+        None if skipped > 0 => Segment::new(
+            format!("{skipped} line{} skipped (press e to view)", if skipped == 1 { "" } else { "s" }),
+            5,
+        )
+        .style(theme.warning),
+        None if app.aggregation.is_some() => {
+            Segment::new("a to aggregate, r refreshes every endpoint, ? for help", 0).style(Style::default().dim())
+        }
+        None if app.replay.is_some() => {
+            Segment::new("[ and ] to step, space to play, ? for help", 0).style(Style::default().dim())
+        }
+        None if app.refreshable => Segment::new("press r to refresh, ? for help", 0).style(Style::default().dim()),
+        None => Segment::new("stdin input, refresh unavailable", 0).style(Style::default().dim()),
+    };
+    segments.push(hint);
+    segments
+}
+
+/// Joins segments with separators, dropping the lowest-priority ones until
+/// the rest fit in `width`. Whatever still overflows is cut off on the right.
+fn fit_segments(mut segments: Vec<Segment>, width: usize) -> Line<'static> {
+    const SEPARATOR: &str = " │ ";
+    let total = |segments: &[Segment]| {
+        segments.iter().map(|s| s.text.chars().count()).sum::<usize>()
+            + SEPARATOR.chars().count() * segments.len().saturating_sub(1)
+    };
+    while segments.len() > 1 && total(&segments) > width {
+        let lowest = segments
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, s)| s.priority)
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        segments.remove(lowest);
+    }
+
+    let mut spans = Vec::new();
+    for (index, segment) in segments.into_iter().enumerate() {
+        if index > 0 {
+            spans.push(Span::raw(SEPARATOR).dim());
+        }
+        spans.push(Span::styled(segment.text, segment.style));
+    }
+    Line::from(spans)
+}
+
+pub(crate) fn ui(f: &mut Frame, apps: &mut [App], active: usize) {
+    let tabbed = apps.len() > 1;
+    let constraints: &[Constraint] = if tabbed {
+        &[Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)]
+    } else {
+        &[Constraint::Length(1), Constraint::Min(0)]
+    };
+    let short = is_short(f.area());
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(if short { 0 } else { 1 })
+        .constraints(constraints)
+        .split(f.area());
+    let chunks = &split[split.len() - 2..];
+    if tabbed {
+        render_tabs(f, split[0], apps, active);
+    }
+    // Other endpoints failing is worth knowing about whichever tab is open.
+    let failing: Vec<String> = apps
+        .iter()
+        .enumerate()
+        .filter(|(index, app)| *index != active && app.last_error.is_some())
+        .map(|(index, _)| (index + 1).to_string())
+        .collect();
+    let app = &mut apps[active];
+
+    let status = if app.input_mode == InputMode::Search {
+        Line::from(vec![
+            Span::raw("/").bold(),
+            Span::raw(app.search_text().to_string()),
+            Span::raw("█"),
+            Span::raw(" — Enter to apply, Esc to clear").dim(),
+        ])
+    } else if app.input_mode == InputMode::Jump {
+        Line::from(vec![
+            Span::raw("jump to @").bold(),
+            Span::raw(app.jump.clone()),
+            Span::raw("█"),
+            Span::raw(" — e.g. 2024-06-26T20:00:00Z or 20:00 (UTC), Enter to jump, Esc to cancel").dim(),
+        ])
+    } else if app.input_mode == InputMode::Endpoint {
+        let prompt = &app.endpoint_prompt;
+        let hint = match &prompt.error {
+            _ if prompt.opening => Span::raw(" — opening…").dim(),
+            Some(error) => Span::raw(format!(" — {error}")).style(app.theme().error),
+            None => Span::raw(" — Enter to open here, Alt+Enter in a new tab, ↑/↓ for history, Esc to cancel").dim(),
+        };
+        Line::from(vec![
+            Span::raw("open: ").bold(),
+            Span::raw(prompt.input.clone()),
+            Span::raw("█"),
+            hint,
+        ])
+    } else if app.input_mode == InputMode::Note {
+        let hint = match app.note_series() {
+            Some(series) => format!(" — Enter to log it on {series}, Esc to cancel"),
+            None => String::from(" — Enter to log it, Esc to cancel"),
+        };
+        Line::from(vec![
+            Span::raw("note: ").bold(),
+            Span::raw(app.note.clone()),
+            Span::raw("█"),
+            Span::raw(hint).dim(),
+        ])
+    } else if let (InputMode::Query, Some(query)) = (&app.input_mode, &app.query) {
+        Line::from(vec![
+            Span::raw("query: ").bold(),
+            Span::raw(query.input.clone()),
+            Span::raw("█"),
+            Span::raw(" — Enter to run, Esc to cancel").dim(),
+        ])
+    } else {
+        let mut segments = status_segments(app);
+        if !failing.is_empty() {
+            let tabs = if failing.len() == 1 { "tab" } else { "tabs" };
+            segments.push(Segment::new(format!("{tabs} {} failing", failing.join(", ")), 7).style(app.theme().error));
+        }
+        if short {
+            segments.retain(|segment| segment.priority >= ESSENTIAL_PRIORITY);
+        }
+        fit_segments(segments, chunks[0].width as usize)
+    };
+    f.render_widget(status, chunks[0]);
+
+    let main = match &app.query {
+        Some(query) => {
+            let height = if query.error.is_some() || !query.warnings.is_empty() { 4 } else { 3 };
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(height), Constraint::Min(0)].as_ref())
+                .split(chunks[1]);
+            render_query(f, split[0], query, app.theme());
+            split[1]
+        }
+        None => chunks[1],
+    };
+    let firing = firing_rules(app).len();
+    let (main, highlights_area) = if firing == 0 {
+        (main, None)
+    } else {
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(firing.min(4) as u16 + 2)].as_ref())
+            .split(main);
+        (split[0], Some(split[1]))
+    };
+    let mut clicks = Vec::new();
+    match &app.view {
+        View::Detail(detail) => app.viewport_height = render_detail(f, main, app, detail, &mut clicks),
+        View::Table => render_table(f, main, app, &mut clicks),
+        View::ParseError(state) => app.viewport_height = render_parse_error(f, main, app, state),
+        View::Cardinality(state) => app.viewport_height = render_cardinality(f, main, app, state),
+        View::Lint(state) => app.viewport_height = render_lint(f, main, app, state),
+        View::Movers(state) => app.viewport_height = render_movers(f, main, app, state),
+        View::Raw(state) => app.viewport_height = render_raw(f, main, app, state, &mut clicks),
+        View::Textfiles(state) => app.viewport_height = render_textfiles(f, main, app, state),
+        View::Events(state) => app.viewport_height = render_events(f, main, app, state),
+    }
+    app.click_targets = clicks;
+    if let Some(area) = highlights_area {
+        render_highlights(f, area, app);
+    }
+
+    if let Some(cursor) = app.column_picker {
+        render_column_picker(f, f.area(), &app.columns, cursor, app.theme());
+    }
+    if let View::Detail(DetailState { picker: Some(picker), .. }) = &app.view {
+        render_label_picker(f, f.area(), &app.group_labels(), picker, app.theme());
+    }
+    if let View::Detail(DetailState {
+        family,
+        breakdown: Some(breakdown),
+        ..
+    }) = &app.view
+    {
+        render_label_breakdown(f, f.area(), family, breakdown, app.theme());
+    }
+    if app.show_keybindings {
+        render_keybindings(f, f.area(), app.theme());
+    } else if app.show_skipped {
+        render_skipped_lines(f, f.area(), &app.skipped_lines, app.theme());
+    } else if app.show_exchange {
+        render_exchange(f, f.area(), app);
+    } else if app.input_mode == InputMode::Find {
+        render_finder(f, f.area(), app);
+    }
+}
+
+/// The expression `promtool query` is showing, with the server's error or
+/// warnings for it underneath.
+fn render_query(f: &mut Frame, area: Rect, query: &QueryState, theme: &Theme) {
+    let mut lines = vec![Line::from(query.expr.as_str())];
+    if let Some(error) = &query.error {
+        lines.push(Line::from(error.as_str()).style(theme.error));
+    } else if let Some(warning) = query.warnings.first() {
+        lines.push(Line::from(warning.as_str()).style(theme.warning));
+    }
+    let title = format!("PromQL on {} — : to edit, r to re-run", query.server);
+    let widget = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+}
+
+/// One title per endpoint, marked with how its latest scrape went.
+fn render_tabs(f: &mut Frame, area: Rect, apps: &[App], active: usize) {
+    let theme = apps[active].theme();
+    let titles: Vec<Line> = apps
+        .iter()
+        .enumerate()
+        .map(|(index, app)| {
+            let (mark, style) = if app.last_error.is_some() {
+                ("✗", theme.error)
+            } else if app.current.is_some() {
+                ("✓", theme.success)
+            } else {
+                ("…", Style::default().dim())
+            };
+            Line::from(vec![
+                Span::raw(format!("{} {} ", index + 1, app.tab_name())),
+                Span::raw(mark).style(style),
+            ])
+        })
+        .collect();
+    let tabs = Tabs::new(titles).select(active).highlight_style(theme.selected);
+    f.render_widget(tabs, area);
+}
+
+/// Below this width the type column gives way to a badge before the name.
+const COMPACT_WIDTH: u16 = 100;
+
+/// Below this width each family takes two lines, its type and value under
+/// its name.
+const STACKED_WIDTH: u16 = 60;
+
+/// Below this height the screen loses its margin and the status bar keeps
+/// only what matters most.
+const SHORT_HEIGHT: u16 = 16;
+
+/// The status bar segments a short terminal still shows: errors, warnings,
+/// the family counts and refresh activity.
+const ESSENTIAL_PRIORITY: u8 = 4;
+
+/// How the main table is arranged, from its area alone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TableLayout {
+    /// Name, type and value columns.
+    Columns,
+    /// Name and value, with the type as a one-letter badge before the name.
+    Compact,
+    /// A two-line entry per family: the name, then its type and value.
+    Stacked,
+}
+
+fn table_layout(area: Rect) -> TableLayout {
+    if area.width < STACKED_WIDTH {
+        TableLayout::Stacked
+    } else if area.width < COMPACT_WIDTH {
+        TableLayout::Compact
+    } else {
+        TableLayout::Columns
+    }
+}
+
+fn is_short(area: Rect) -> bool {
+    area.height < SHORT_HEIGHT
+}
+
+/// Where a column's cells sit: names to the left, numbers to the right.
+fn column_alignment(column: Column) -> Alignment {
+    match column {
+        Column::Name => Alignment::Left,
+        Column::Type => Alignment::Center,
+        Column::Count | Column::Value | Column::Rate => Alignment::Right,
+    }
+}
+
+/// A cell's first line as plain text.
+fn cell_text(cell: &Text) -> String {
+    cell.lines
+        .first()
+        .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect())
+        .unwrap_or_default()
+}
+
+impl TableLayout {
+    /// Which of `columns` get a column of their own: the narrower layouts
+    /// fold the type into the name's badge or the second line.
+    fn shown(self, columns: &Columns) -> Vec<Column> {
+        match self {
+            TableLayout::Columns => columns.iter().collect(),
+            TableLayout::Compact | TableLayout::Stacked => columns.iter().filter(|c| *c != Column::Type).collect(),
+        }
+    }
+
+    /// The cells of each shown column in a table `width` wide, once the
+    /// borders, the highlight symbol and the spacing between them are taken.
+    fn column_widths(self, columns: &Columns, width: u16) -> Vec<(Column, u16)> {
+        let shown = self.shown(columns);
+        let width = match self {
+            TableLayout::Stacked => return vec![(Column::Name, width.saturating_sub(5))],
+            TableLayout::Columns | TableLayout::Compact => {
+                width.saturating_sub(5 + shown.len().saturating_sub(1) as u16)
+            }
+        };
+        let widths = columns::widths(&shown, width);
+        shown.into_iter().zip(widths).collect()
+    }
+
+    fn widths(self, columns: &Columns, width: u16) -> Vec<Constraint> {
+        match self {
+            TableLayout::Stacked => vec![Constraint::Percentage(100)],
+            TableLayout::Columns | TableLayout::Compact => self
+                .column_widths(columns, width)
+                .into_iter()
+                .map(|(_, width)| Constraint::Length(width))
+                .collect(),
+        }
+    }
+
+    /// The name column's share of a table `width` wide.
+    fn name_width(self, columns: &Columns, width: u16) -> usize {
+        let name = self
+            .column_widths(columns, width)
+            .into_iter()
+            .find_map(|(column, width)| (column == Column::Name).then_some(width as usize))
+            .unwrap_or(0);
+        let width = match self {
+            // Less the badge.
+            TableLayout::Compact if columns.contains(Column::Type) => name.saturating_sub(2),
+            TableLayout::Columns | TableLayout::Compact | TableLayout::Stacked => name,
+        };
+        width.max(1)
+    }
+
+    /// How many lines an entry whose value takes `value_lines` gets.
+    fn row_height(self, value_lines: usize) -> u16 {
+        let lines = value_lines.max(1) as u16;
+        match self {
+            TableLayout::Stacked => lines + 1,
+            TableLayout::Columns | TableLayout::Compact => lines,
+        }
+    }
+
+    /// One entry of the table and its height, from a cell per column.
+    fn row(self, cells: Vec<(Column, Text<'static>)>) -> (Row<'static>, u16) {
+        let lines = cells
+            .iter()
+            .filter(|(column, _)| *column != Column::Name)
+            .map(|(_, cell)| cell.height())
+            .max()
+            .unwrap_or(1);
+        let height = self.row_height(lines);
+        let row = match self {
+            TableLayout::Columns => {
+                Row::new(cells.into_iter().map(|(column, cell)| cell.alignment(column_alignment(column))))
+            }
+            TableLayout::Compact => {
+                let badge = cells
+                    .iter()
+                    .find(|(column, _)| *column == Column::Type)
+                    .and_then(|(_, cell)| cell_text(cell).chars().next());
+                Row::new(cells.into_iter().filter(|(column, _)| *column != Column::Type).map(|(column, mut cell)| {
+                    if let (Column::Name, Some(badge), Some(name)) = (column, badge, cell.lines.first_mut()) {
+                        name.spans.insert(0, Span::raw(format!("{} ", badge.to_ascii_uppercase())).dim());
+                    }
+                    cell.alignment(column_alignment(column))
+                }))
+            }
+            TableLayout::Stacked => {
+                let mut name = Text::default();
+                let mut value = Text::default();
+                let mut lead = String::from("  ");
+                for (column, cell) in cells {
+                    match column {
+                        Column::Name => name = cell,
+                        Column::Value => value = cell,
+                        Column::Type | Column::Count | Column::Rate => {
+                            let text = cell_text(&cell);
+                            if !text.is_empty() {
+                                lead.push_str(&text);
+                                lead.push_str("  ");
+                            }
+                        }
+                    }
+                }
+                let indent = lead.chars().count();
+                let mut lines = name.lines;
+                if value.lines.is_empty() {
+                    lines.push(Line::from(lead.clone()).dim());
+                }
+                for (index, line) in value.lines.into_iter().enumerate() {
+                    let lead = if index == 0 { lead.clone() } else { " ".repeat(indent) };
+                    let mut spans = vec![Span::raw(lead).dim()];
+                    spans.extend(line.spans.into_iter().map(|span| span.patch_style(value.style)));
+                    lines.push(Line::from(spans));
+                }
+                Row::new(vec![Text::from(lines)])
+            }
+        };
+        (row.height(height), height)
+    }
+}
+
+/// The type column: the family's type, and its unit when it has one.
+fn type_cell(fam: &Family, label_family: Option<model::LabelFamily>) -> Text<'static> {
+    Text::from(label_family.map_or_else(|| type_label(fam), label_family_type))
+}
+
+/// The count column: how many series the family has.
+fn count_cell(fam: &Family) -> Text<'static> {
+    Text::from(fam.iter_samples().count().to_string())
+}
+
+/// The value column: a single series shows its value; several show how many
+/// there are and a summary that suits the family's type. `R`, `B` and
+/// `--table-quantile` show what they make of them instead.
+fn value_cell(
+    app: &App,
+    name: &str,
+    fam: &Family,
+    label_family: Option<model::LabelFamily>,
+    movement: Option<Movement>,
+) -> Text<'static> {
+    if let Some(kind) = label_family {
+        return label_cell(model::label_lines(name, fam, kind));
+    }
+    let mut shows_value = false;
+    let text = if let Some(q) = app.table_quantile.filter(|_| fam.family_type == PrometheusType::Histogram) {
+        let buckets = app.histogram_buckets(name, fam.iter_samples());
+        app.quantile_text(name, fam, buckets.as_deref(), q)
+    } else if app.baseline.is_some() && app.show_baseline {
+        let mut samples = fam.iter_samples();
+        match (samples.next(), samples.next()) {
+            (Some(sample), None) => app
+                .since_baseline(name, sample)
+                .map_or(String::from("-"), |since| model::format_since_baseline(&since)),
+            _ => format!("{} series", fam.iter_samples().count()),
+        }
+    } else if app.show_rates {
+        let mut samples = fam.iter_samples();
+        match (samples.next(), samples.next()) {
+            (Some(sample), None) => app
+                .series_change(name, fam, sample)
+                .map_or(String::from("-"), |c| model::format_change(&c)),
+            _ => format!("{} series", fam.iter_samples().count()),
+        }
+    } else {
+        shows_value = true;
+        let mut samples = fam.iter_samples();
+        match (samples.next(), samples.next()) {
+            (Some(sample), None) => app.display_value(name, fam, &sample.value),
+            _ => app
+                .summaries
+                .get(name)
+                .map_or(String::new(), |summary| summary_text(app, name, fam, summary)),
+        }
+    };
+    let value = shows_value.then(|| single_value(fam)).flatten();
+    let style = number_style(value, movement_style(movement, app.theme()));
+    Text::from(text).style(style)
+}
+
+/// The rate column: a single series' rate or delta since the previous
+/// scrape, or the summed rate of a family of counters.
+fn rate_cell(app: &App, name: &str, fam: &Family) -> Text<'static> {
+    if !matches!(fam.family_type, PrometheusType::Counter | PrometheusType::Gauge) {
+        return Text::default();
+    }
+    let mut samples = fam.iter_samples();
+    let text = match (samples.next(), samples.next()) {
+        (Some(sample), None) => app
+            .series_change(name, fam, sample)
+            .map_or(String::from("-"), |c| model::format_change(&c)),
+        _ if fam.family_type == PrometheusType::Counter => {
+            let rates: Vec<f64> = fam
+                .iter_samples()
+                .filter_map(|sample| match app.series_change(name, fam, sample) {
+                    Some(Change::Rate(rate)) => Some(rate),
+                    _ => None,
+                })
+                .collect();
+            if rates.is_empty() {
+                String::from("-")
+            } else {
+                model::format_change(&Change::Rate(rates.iter().sum()))
+            }
+        }
+        _ => String::new(),
+    };
+    Text::from(text)
+}
+
+/// One entry of the main table and its height: a family with its value or
+/// a summary of its series, or a group of families sharing a prefix.
+fn family_row(app: &App, layout: TableLayout, name_width: usize, row: &TableRow) -> (Row<'static>, u16) {
+    let (name, fam, depth) = match *row {
+        TableRow::Family { name, fam, depth } => (name, fam, depth),
+        TableRow::Group {
+            prefix,
+            depth,
+            families,
+            series,
+            expanded,
+        } => {
+            let marker = if expanded { "▾" } else { "▸" };
+            let label = Line::from(format!("{}{marker} {prefix}_*", "  ".repeat(depth)));
+            let cells = app.columns.iter().map(|column| {
+                let cell = match column {
+                    Column::Name => Text::from(fit_line(label.clone(), app.scroll, name_width).bold()),
+                    Column::Count => Text::from(series.to_string()).dim(),
+                    Column::Value => Text::from(format!("{families} families, {series} series")).dim(),
+                    Column::Type | Column::Rate => Text::default(),
+                };
+                (column, cell)
+            });
+            return layout.row(cells.collect());
+        }
+    };
+
+    let label_family = app.label_family(name, fam);
+    let movement = app.movements.get(name.as_str()).copied().filter(|_| app.highlight_changes);
+    let mut name_line = highlighted_name(name, app.name_filter(), app.theme());
+    if app.pinned.contains(name.as_str()) {
+        name_line.spans.insert(0, Span::raw("★ ").style(app.theme().accent));
+    }
+    if app.churn.new_families.contains(name.as_str()) {
+        name_line.spans.push(Span::raw(" new").style(app.theme().added));
+    }
+    if app.counter_resets.family_has_partial(name) {
+        name_line.spans.push(Span::raw(" ⚠").style(app.theme().warning));
+    }
+    if depth > 0 {
+        name_line.spans.insert(0, Span::raw("  ".repeat(depth)));
+    }
+    let name_cell = Text::from(fit_line(name_line, app.scroll, name_width).bold());
+    let cells = app.columns.iter().map(|column| {
+        let cell = match column {
+            Column::Name => name_cell.clone(),
+            Column::Type => type_cell(fam, label_family),
+            Column::Count => count_cell(fam),
+            Column::Value => value_cell(app, name, fam, label_family, movement),
+            Column::Rate => rate_cell(app, name, fam),
+        };
+        (column, cell)
+    });
+    let (row, height) = layout.row(cells.collect());
+    let row = match app.highlights.family(name) {
+        Some(level) => row.style(highlight_style(level, app.theme())),
+        None if movement == Some(Movement::Unchanged) => row.dim(),
+        None => row,
+    };
+    (row, height)
+}
+
+/// The main table's column titles, with an arrow on the sort column.
+fn table_header(app: &App, layout: TableLayout) -> Row<'static> {
+    let arrow = if app.sort_reversed { " ▼" } else { " ▲" };
+    let header_cell = |column: Column| {
+        let keys: &[SortKey] = match column {
+            Column::Name => &[SortKey::Name],
+            Column::Type => &[SortKey::Type],
+            Column::Count => &[SortKey::SeriesCount],
+            Column::Value => &[SortKey::Value],
+            Column::Rate => &[],
+        };
+        if column == Column::Value && app.sort_key == SortKey::SeriesCount && !app.columns.contains(Column::Count) {
+            format!("Value (by series count{arrow})")
+        } else if keys.contains(&app.sort_key) {
+            format!("{}{arrow}", column.title())
+        } else {
+            column.title().to_string()
+        }
+    };
+    let shown = layout.shown(&app.columns);
+    match layout {
+        TableLayout::Columns | TableLayout::Compact => Row::new(
+            shown
+                .into_iter()
+                .map(|column| Text::from(header_cell(column)).alignment(column_alignment(column))),
+        ),
+        TableLayout::Stacked => {
+            let titles: Vec<String> = shown.into_iter().map(header_cell).collect();
+            Row::new(vec![Text::from(titles.join(" · "))])
+        }
+    }
+    .bold()
+}
+
+/// The rows of a table `room` lines high that share the screen with row
+/// `selected` of `count`, scrolled as little as possible from `offset`, the
+/// way ratatui's own `Table` scrolls. Only those are built, by `row`, which
+/// gives a row and its height; returned with the index of the first.
+fn visible_rows(
+    mut row: impl FnMut(usize) -> (Row<'static>, u16),
+    count: usize,
+    selected: usize,
+    offset: usize,
+    room: u16,
+) -> (usize, Vec<(Row<'static>, u16)>) {
+    if count == 0 {
+        return (0, Vec::new());
+    }
+    let selected = selected.min(count - 1);
+    // Rows are at least a line high, so none further up than `room` can be
+    // on screen with the selection.
+    let mut first = offset.min(selected).max(selected.saturating_sub(room as usize));
+    let mut shown: VecDeque<(Row<'static>, u16)> = (first..=selected).map(&mut row).collect();
+    let mut height: u16 = shown.iter().map(|(_, height)| height).sum();
+    while height > room && shown.len() > 1 {
+        if let Some((_, dropped)) = shown.pop_front() {
+            height -= dropped;
+            first += 1;
+        }
+    }
+    for index in selected + 1..count {
+        let (next, next_height) = row(index);
+        if height + next_height > room {
+            break;
+        }
+        height += next_height;
+        shown.push_back((next, next_height));
+    }
+    (first, shown.into())
+}
+
+fn render_table(f: &mut Frame, area: Rect, app: &mut App, clicks: &mut Vec<ClickTarget>) {
+    let (table_area, footer_area) = if app.show_help_footer {
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)].as_ref())
+            .split(area);
+        (split[0], Some(split[1]))
+    } else {
+        (area, None)
+    };
+
+    match (&app.current, &app.last_error) {
+        (None, None) => {
+            let widget = Span::raw(format!("Fetching metrics from {}…", app.endpoint));
+            f.render_widget(widget, area);
+        }
+        (None, Some(e)) => {
+            let widget = Paragraph::new(format!("{e:#}")).wrap(Wrap { trim: true }).style(app.theme().error);
+            f.render_widget(widget, area);
+        }
+        (Some(latest_metrics), _) => {
+            let visible = app.visible_families();
+            let mut title = match app.type_filter {
+                Some(family_type) => format!("Metrics of type {}", family_type.name()),
+                None => String::from("Metrics"),
+            };
+            if !app.filter.is_empty() {
+                title.push_str(&format!(" matching \"{}\"", app.filter));
+            }
+            if !app.filter.is_empty() || app.type_filter.is_some() {
+                title.push_str(&format!(
+                    " — showing {} of {} families",
+                    visible.len(),
+                    latest_metrics.families.len()
+                ));
+            }
+            if app.baseline.is_some() && app.show_baseline {
+                title.push_str(" — since baseline");
+            } else if app.show_rates {
+                title.push_str(" — rates");
+            }
+            let rows = app.table_rows();
+            if !rows.is_empty() {
+                title.push_str(&format!(" — {}/{}", app.selected() + 1, rows.len()));
+            }
+            let mut title = vec![Span::raw(title)];
+            if let Some(paused) = &app.paused {
+                title.push(Span::raw(" "));
+                title.push(Span::raw(format!(" {} ", paused.text())).style(app.theme().badge));
+            }
+            if let (true, Some(at)) = (app.is_stale(), app.last_success_at) {
+                title.push(Span::raw(" "));
+                title.push(Span::raw(format!(" STALE — {}s old ", at.elapsed().as_secs())).style(app.theme().badge));
+            }
+            if app.truncated.is_some() {
+                title.push(Span::raw(" "));
+                title.push(Span::raw(" TRUNCATED ").style(app.theme().badge));
+            }
+
+            let layout = table_layout(table_area);
+            let name_width = layout.name_width(&app.columns, table_area.width);
+            // Disappeared families trail the live ones and can't be selected.
+            let mut gone: Vec<(&String, &model::Gone)> = Vec::new();
+            if app.show_disappeared {
+                gone = app
+                    .churn
+                    .gone_families
+                    .iter()
+                    .filter(|(name, _)| app.matches_filter(name))
+                    .collect();
+                gone.sort_by(|a, b| a.0.cmp(b.0));
+            }
+            let live = rows.len();
+            let count = live + gone.len();
+            let row_at = |index: usize| match rows.get(index) {
+                Some(row) => family_row(app, layout, name_width, row),
+                None => {
+                    let (name, gone) = gone[index - live];
+                    let cells = app.columns.iter().map(|column| {
+                        let cell = match column {
+                            Column::Name => {
+                                Text::from(fit_line(Line::from(name.clone()), app.scroll, name_width).crossed_out())
+                            }
+                            Column::Type => Text::from(gone.family_type.clone()),
+                            Column::Value => Text::from(format!(
+                                "gone {} · was {}",
+                                refreshes_ago(gone.scrapes_ago),
+                                gone.last_value
+                            )),
+                            Column::Count | Column::Rate => Text::default(),
+                        };
+                        (column, cell)
+                    });
+                    let (row, height) = layout.row(cells.collect());
+                    (row.dim(), height)
+                }
+            };
+            // Only the rows on screen are built, however many families
+            // there are. Each one's height too, to tell which one a click
+            // landed on.
+            let room = table_area.height.saturating_sub(3);
+            let (offset, shown) = visible_rows(row_at, count, app.selected(), app.table_state.offset(), room);
+            let (metrics, heights): (Vec<Row>, Vec<u16>) = shown.into_iter().unzip();
+
+            let header = table_header(app, layout);
+
+            // A name cut short or scrolled is shown whole under the table.
+            let mut block = Block::default().borders(Borders::ALL).title(Line::from(title));
+            if let Some(name) = app.selected_family_name().filter(|name| is_cut(name, app.scroll, name_width)) {
+                block = block.title_bottom(Line::from(format!(" {name} ")).dim());
+            }
+            let mut metrics_list = Table::new(metrics, layout.widths(&app.columns, table_area.width))
+                .header(header)
+                .block(block)
+                .highlight_style(app.theme().selected)
+                .highlight_symbol(">> ");
+            if app.is_stale() {
+                metrics_list = metrics_list.dim();
+            }
+
+            let lines_per_row = if layout == TableLayout::Stacked { 2 } else { 1 };
+            app.viewport_height = (table_area.height.saturating_sub(3) as usize / lines_per_row).max(1);
+            *app.table_state.offset_mut() = offset;
+            let mut state = TableState::default().with_selected(Some(app.selected().saturating_sub(offset)));
+            f.render_stateful_widget(metrics_list, table_area, &mut state);
+
+            // Rows start under the top border and the header.
+            let bottom = table_area.bottom().saturating_sub(1);
+            let mut y = table_area.y + 2;
+            for (index, height) in heights.into_iter().enumerate() {
+                let index = offset + index;
+                if y >= bottom || index >= live {
+                    break;
+                }
+                let area = Rect {
+                    y,
+                    height: height.min(bottom - y),
+                    ..table_area
+                };
+                clicks.push(ClickTarget { area, index });
+                y += height;
+            }
+
+            if let Some(footer_area) = footer_area {
+                let help = app
+                    .selected_family_name()
+                    .and_then(|name| app.family(&name))
+                    .map(|fam| fam.help.clone())
+                    .unwrap_or_default();
+                let footer = Paragraph::new(help).wrap(Wrap { trim: true }).dim();
+                f.render_widget(footer, footer_area);
+            }
+        },
+    }
+
+}
+
+/// A `percent_x` by `percent_y` rectangle centered in `area`.
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+fn render_keybindings(f: &mut Frame, area: Rect, theme: &Theme) {
+    let mut lines = Vec::new();
+    for group in keys::GROUPS {
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        lines.push(Line::from(*group).bold());
+        for binding in keys::KEYBINDINGS.iter().filter(|b| b.group == *group) {
+            lines.push(Line::from(vec![
+                Span::raw(format!("  {:<8}", binding.label)).style(theme.accent),
+                Span::raw(binding.description),
+            ]));
+        }
+    }
+
+    let area = centered_rect(60, 80, area);
+    let help = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Keybindings — ? or Esc to close"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+fn render_skipped_lines(f: &mut Frame, area: Rect, skipped: &[SkippedLine], theme: &Theme) {
+    let area = centered_rect(80, 80, area);
+    let rows = (area.height as usize).saturating_sub(2);
+    let width = skipped.last().map_or(1, |s| s.line.to_string().len());
+    let mut lines: Vec<Line> = skipped
+        .iter()
+        .take(if skipped.len() > rows { rows.saturating_sub(1) } else { rows })
+        .map(|s| {
+            Line::from(vec![
+                Span::raw(format!("{:>width$} ", s.line)).style(theme.accent),
+                Span::raw(s.text.as_str()),
+            ])
+        })
+        .collect();
+    if lines.len() < skipped.len() {
+        lines.push(Line::from(format!("…and {} more", skipped.len() - lines.len())).dim());
+    }
+
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} skipped lines — e or Esc to close", skipped.len())),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// The `d` panel: what the last HTTP scrape sent and got back, much like
+/// `curl -v` would show it.
+fn render_exchange(f: &mut Frame, area: Rect, app: &App) {
+    let area = centered_rect(80, 80, area);
+    let theme = app.theme();
+    let mut lines = match &app.exchange {
+        Some(exchange) => exchange_lines(exchange, app, theme),
+        None => vec![Line::from("No HTTP request was made for this scrape.").dim()],
+    };
+    if let Some(peak) = peak_memory() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!("Peak memory so far: {}", format_size(peak))).dim());
+    }
+    let panel = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Last request — d or Esc to close"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(panel, area);
+}
+
+/// The most memory this process has held at once, as the kernel counts it.
+#[cfg(unix)]
+fn peak_memory() -> Option<usize> {
+    // SAFETY: rusage is plain data, and getrusage only writes to it.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let maxrss = usize::try_from(usage.ru_maxrss).ok()?;
+    // Linux counts in KiB, macOS in bytes.
+    Some(if cfg!(target_os = "macos") { maxrss } else { maxrss * 1024 })
+}
+
+#[cfg(not(unix))]
+fn peak_memory() -> Option<usize> {
+    None
+}
+
+fn exchange_lines<'a>(exchange: &'a fetch::Exchange, app: &App, theme: &Theme) -> Vec<Line<'a>> {
+    let heading = |text: &'static str| Line::from(text).style(theme.accent).bold();
+    let header = |(name, value): &'a (String, String)| {
+        Line::from(vec![Span::raw(format!("  {name}: ")).dim(), Span::raw(value.as_str())])
+    };
+    let millis = |duration: Duration| format!("{}ms", duration.as_millis());
+
+    let mut lines = vec![heading("Request"), Line::from(format!("  {} {}", exchange.method, exchange.url))];
+    lines.extend(exchange.request_headers.iter().map(header));
+    if let Some(proxy) = &exchange.proxy {
+        lines.push(Line::from(vec![Span::raw("  through proxy ").dim(), Span::raw(proxy.as_str())]));
+    }
+    if !exchange.redirects.is_empty() {
+        lines.push(heading("Redirects"));
+        lines.extend(
+            exchange
+                .redirects
+                .iter()
+                .map(|(status, location)| Line::from(format!("  {status} → {location}"))),
+        );
+    }
+    lines.push(heading("Response"));
+    match (exchange.status, exchange.version) {
+        (Some(status), Some(version)) => {
+            let style = if status.is_success() { theme.success } else { theme.warning };
+            let from = exchange.remote_addr.map(|addr| format!(" from {addr}")).unwrap_or_default();
+            lines.push(Line::from(vec![
+                Span::raw(format!("  {version:?} ")),
+                Span::raw(status.to_string()).style(style),
+                Span::raw(from),
+            ]));
+            lines.extend(exchange.response_headers.iter().map(header));
+        }
+        _ => lines.push(Line::from("  none arrived").style(theme.error)),
+    }
+    if exchange.final_url().starts_with("https://") {
+        lines.push(heading("TLS"));
+        lines.push(Line::from(
+            "  negotiated by the system's TLS library, which doesn't report the version or cipher",
+        ));
+        if exchange.insecure {
+            lines.push(Line::from("  certificate and hostname checks are off (--insecure)").style(theme.warning));
+        }
+    }
+    lines.push(heading("Timings"));
+    let mut timings = Vec::new();
+    if let Some(first_byte) = exchange.first_byte {
+        timings.push(format!("headers after {}", millis(first_byte)));
+    }
+    timings.push(format!("done after {}", millis(exchange.total)));
+    if exchange.status.is_some() && app.last_error.is_none() {
+        timings.push(format!("parsed in {}", millis(app.last_parse_duration)));
+    }
+    lines.push(Line::from(format!("  {}", timings.join(", "))));
+    if let (Some((transferred, decoded)), None) = (app.last_body_size, &app.last_error) {
+        let size = if transferred == decoded {
+            format_size(transferred)
+        } else {
+            format!("{} on the wire, {} uncompressed", format_size(transferred), format_size(decoded))
+        };
+        lines.push(Line::from(format!("  {size}")));
+    }
+    lines
+}
+
+/// Draws the fuzzy finder's prompt over its best matches, with the matched
+/// characters picked out.
+fn render_finder(f: &mut Frame, area: Rect, app: &App) {
+    let theme = app.theme();
+    let matches = app.finder_matches();
+    let height = (matches.len().max(1) + 3) as u16;
+    let area = centered_rect(60, 100, area);
+    let area = Rect {
+        y: area.y + area.height.saturating_sub(height) / 3,
+        height: height.min(area.height),
+        ..area
+    };
+
+    let mut lines = vec![Line::from(vec![
+        Span::raw("> ").bold(),
+        Span::raw(app.finder.clone()),
+        Span::raw("█"),
+    ])];
+    if matches.is_empty() {
+        lines.push(Line::from("no matching family").dim());
+    }
+    for (index, found) in matches.iter().enumerate() {
+        let mut spans: Vec<Span> = found
+            .name
+            .chars()
+            .enumerate()
+            .map(|(position, c)| {
+                let span = Span::raw(c.to_string());
+                if found.positions.contains(&position) {
+                    span.style(theme.search_match)
+                } else {
+                    span
+                }
+            })
+            .collect();
+        spans.insert(0, Span::raw("  "));
+        let line = Line::from(spans);
+        lines.push(if index == app.finder_selected {
+            line.style(theme.selected)
+        } else {
+            line
+        });
+    }
+
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Jump to family — ↑/↓ to choose, Enter to jump, Esc to cancel"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// Draws families ranked by series count and returns how many rows fit on a page.
+fn render_cardinality(f: &mut Frame, area: Rect, app: &App, state: &CardinalityState) -> usize {
+    let families = &app.cardinality;
+    let series: usize = families.iter().map(|c| c.series).sum();
+    let samples: usize = families.iter().map(|c| c.samples).sum();
+    let title = format!(
+        "Cardinality — {} families, {series} series, {samples} samples — Enter to jump, C to close",
+        families.len()
+    );
+
+    let page_height = (area.height.saturating_sub(3) as usize).max(1);
+    let selected = state.selected.min(families.len().saturating_sub(1));
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = families
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|c| {
+            let top_label = c
+                .top_label
+                .as_ref()
+                .map(|(label, values)| format!("{label} has {values} values"))
+                .unwrap_or_default();
+            Row::new(vec![
+                Text::from(c.family.as_str()),
+                Text::from(c.series.to_string()).alignment(Alignment::Right),
+                Text::from(c.samples.to_string()).alignment(Alignment::Right),
+                Text::from(top_label),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(45),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Family", "Series", "Samples", "Top label"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.theme().selected)
+        .highlight_symbol(">> ");
+    let mut table_state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, area, &mut table_state);
+    page_height
+}
+
+fn highlight_style(level: highlight::Level, theme: &Theme) -> Style {
+    match level {
+        highlight::Level::Critical => theme.error,
+        highlight::Level::Warning => theme.warning,
+    }
+}
+
+/// Rows for the rules holding for at least one series, in the order given.
+fn firing_rules(app: &App) -> Vec<(&highlight::Rule, Vec<&highlight::Firing>)> {
+    app.highlight_rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| (rule, app.highlights.firing.iter().filter(|f| f.rule == index).collect::<Vec<_>>()))
+        .filter(|(_, firing)| !firing.is_empty())
+        .collect()
+}
+
+/// Lists the firing highlight rules with how many series each holds for
+/// and the first of them.
+fn render_highlights(f: &mut Frame, area: Rect, app: &App) {
+    let lines: Vec<Line> = firing_rules(app)
+        .into_iter()
+        .map(|(rule, firing)| {
+            let first = firing[0];
+            let series = if first.key.labels.is_empty() {
+                first.key.family.clone()
+            } else {
+                format!("{}{{{}}}", first.key.family, first.key.labels)
+            };
+            let more = match firing.len() - 1 {
+                0 => String::new(),
+                more => format!(" and {more} more"),
+            };
+            Line::from(vec![
+                Span::raw(format!("● {}", rule.text)).style(highlight_style(rule.level, app.theme())),
+                Span::raw(format!(" — {series} = {}{more}", model::format_number(first.value))),
+            ])
+        })
+        .collect();
+    let title = format!("Firing ({} of {} rules)", lines.len(), app.highlight_rules.len());
+    let widget = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+}
+
+/// Draws the fastest-changing series and returns how many rows fit on a page.
+fn render_movers(f: &mut Frame, area: Rect, app: &App, state: &MoversState) -> usize {
+    let movers = &app.movers;
+    let title = format!("Fastest-changing series — top {} — Enter to open, H to close", app.top);
+    if movers.is_empty() {
+        let text = if app.previous.is_none() {
+            "Series are ranked once there are two scrapes to compare"
+        } else {
+            "Nothing changed since the previous scrape"
+        };
+        let widget = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(widget, area);
+        return 1;
+    }
+
+    let page_height = (area.height.saturating_sub(3) as usize).max(1);
+    let selected = state.selected.min(movers.len() - 1);
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = movers
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|mover| {
+            let format = |value: f64| match app.family(&mover.key.family) {
+                Some(fam) => app.display_number(value, &mover.key.family, fam),
+                None => model::format_number(value),
+            };
+            let style = match mover.current.total_cmp(&mover.previous) {
+                std::cmp::Ordering::Greater => app.theme().up,
+                std::cmp::Ordering::Less => app.theme().down,
+                std::cmp::Ordering::Equal => Style::default(),
+            };
+            Row::new(vec![
+                Text::from(mover.key.family.as_str()),
+                Text::from(mover.key.labels.as_str()),
+                Text::from(format(mover.previous)).alignment(Alignment::Right),
+                Text::from(format(mover.current)).alignment(Alignment::Right),
+                Text::from(model::format_change(&mover.change)).style(style).alignment(Alignment::Right),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(30),
+        Constraint::Min(20),
+        Constraint::Length(14),
+        Constraint::Length(14),
+        Constraint::Length(14),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Family", "Labels", "Previous", "Current", "Rate/Δ"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.theme().selected)
+        .highlight_symbol(">> ");
+    let mut table_state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, area, &mut table_state);
+    page_height
+}
+
+/// Draws the files of a `--textfile-dir` and returns how many rows fit on a
+/// page. Ages are shown rather than times, since a textfile a cron job
+/// stopped rewriting is the usual way they go wrong.
+fn render_textfiles(f: &mut Frame, area: Rect, app: &App, state: &TextfilesState) -> usize {
+    let files = app.textfiles.as_deref().unwrap_or_default();
+    let title = format!("Textfiles — {} files — I to close", files.len());
+    if files.is_empty() {
+        let widget = Paragraph::new(format!("{} has no *.prom files", app.endpoint))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(widget, area);
+        return 1;
+    }
+
+    let theme = app.theme();
+    let page_height = (area.height.saturating_sub(3) as usize).max(1);
+    let selected = state.selected.min(files.len() - 1);
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = files
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|file| {
+            let written = match file.modified.map(|at| (at, at.elapsed())) {
+                Some((_, Ok(age))) => format!("{} ago", humantime::format_duration(Duration::from_secs(age.as_secs()))),
+                Some((at, Err(_))) => humantime::format_rfc3339_seconds(at).to_string(),
+                None => String::from("?"),
+            };
+            let status = match &file.error {
+                Some(error) => Text::from(error.as_str()).style(theme.error),
+                None => Text::from("ok"),
+            };
+            Row::new(vec![
+                Text::from(file.name.as_str()),
+                Text::from(written).alignment(Alignment::Right),
+                Text::from(file.series.to_string()).alignment(Alignment::Right),
+                status,
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(30),
+        Constraint::Length(18),
+        Constraint::Length(8),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["File", "Written", "Series", "Status"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected)
+        .highlight_symbol(">> ");
+    let mut table_state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, area, &mut table_state);
+    page_height
+}
+
+/// Draws the event log, newest first, and returns how many rows fit on a page.
+fn render_events(f: &mut Frame, area: Rect, app: &App, state: &EventsState) -> usize {
+    let listed = app.listed_events();
+    let only = state.kind.map_or(String::new(), |kind| format!(" {}", kind.name()));
+    let title = format!(
+        "Events — {}{only} of {} — t filters by kind, m adds a note, E to close",
+        listed.len(),
+        app.events.len()
+    );
+    if listed.is_empty() {
+        let text = match state.kind {
+            Some(kind) => format!("No {} events yet", kind.name()),
+            None => String::from(concat!(
+                "Counter resets, series appearing and disappearing and --highlight rules firing are logged here, ",
+                "and m adds a note"
+            )),
+        };
+        let block = Block::default().borders(Borders::ALL).title(title);
+        f.render_widget(Paragraph::new(text).wrap(Wrap { trim: true }).block(block), area);
+        return 1;
+    }
+
+    let page_height = (area.height.saturating_sub(3) as usize).max(1);
+    let selected = state.selected.min(listed.len() - 1);
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = listed
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|event| {
+            Row::new(vec![
+                Text::from(humantime::format_rfc3339_seconds(event.at).to_string()),
+                Text::from(event.kind.name()).style(event_style(app.theme(), event)),
+                Text::from(event.series.as_deref().unwrap_or("")),
+                Text::from(event.text.as_str()),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(20),
+        Constraint::Length(13),
+        Constraint::Percentage(35),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Time", "Kind", "Series", "What"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(app.theme().selected)
+        .highlight_symbol(">> ");
+    let mut table_state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, area, &mut table_state);
+    page_height
+}
+
+fn event_style(theme: &Theme, event: &Event) -> Style {
+    match event.kind {
+        events::Kind::Annotation => theme.accent,
+        events::Kind::CounterReset => theme.warning,
+        events::Kind::Appeared => theme.up,
+        events::Kind::Disappeared => theme.down,
+        events::Kind::RuleFired => theme.error,
+    }
+}
+
+/// Draws the naming convention findings and returns how many rows fit on a page.
+fn render_lint(f: &mut Frame, area: Rect, app: &App, state: &LintState) -> usize {
+    let findings: Vec<&lint::Finding> = app.findings().collect();
+    let title = format!("Lint — {} findings — Enter to jump, L to close", findings.len());
+    if findings.is_empty() {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let widget = Paragraph::new("The current scrape follows the naming conventions").block(block);
+        f.render_widget(widget, area);
+        return 1;
+    }
+
+    let theme = app.theme();
+    let page_height = (area.height.saturating_sub(3) as usize).max(1);
+    let selected = state.selected.min(findings.len() - 1);
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = findings
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|finding| {
+            let style = match finding.severity {
+                lint::Severity::Error => theme.error,
+                lint::Severity::Warning => theme.warning,
+            };
+            Row::new(vec![
+                Text::from(finding.severity.name()).style(style),
+                Text::from(finding.family.as_str()),
+                Text::from(finding.message.as_str()),
+                Text::from(finding.rule),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Percentage(30),
+        Constraint::Min(30),
+        Constraint::Length(20),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Severity", "Family", "Problem", "Rule"]).bold())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(theme.selected)
+        .highlight_symbol(">> ");
+    let mut table_state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, area, &mut table_state);
+    page_height
+}
+
+/// Draws the parser error above the raw lines around the cursor, or just the
+/// raw text, and returns how many lines fit on a page.
+fn render_parse_error(f: &mut Frame, area: Rect, app: &App, state: &SourceState) -> usize {
+    let Some(failure) = &app.parse_failure else {
+        let widget = Span::raw("The last scrape parsed cleanly — press Esc to go back");
+        f.render_widget(widget, area);
+        return 1;
+    };
+
+    let text_area = if state.raw {
+        area
+    } else {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Max(6), Constraint::Min(0)].as_ref())
+            .split(area);
+        let location = failure.line.map_or(String::from("line unknown"), |line| format!("line {line}"));
+        let error = Paragraph::new(failure.message.as_str())
+            .wrap(Wrap { trim: false })
+            .style(app.theme().error)
+            .block(Block::default().borders(Borders::ALL).title(format!("Parse error at {location}")));
+        f.render_widget(error, chunks[0]);
+        chunks[1]
+    };
+
+    // Only the lines on screen are built, so 50k-line payloads scroll fine.
+    let total = failure.text.lines().count();
+    let page = (text_area.height as usize).saturating_sub(2).max(1);
+    let cursor = state.cursor.min(total.saturating_sub(1));
+    let offset = cursor.saturating_sub(page / 2).min(total.saturating_sub(page));
+    let width = total.to_string().len();
+    let lines: Vec<Line> = failure
+        .text
+        .lines()
+        .enumerate()
+        .skip(offset)
+        .take(page)
+        .map(|(index, line)| {
+            let mut style = Style::default();
+            if failure.line == Some(index + 1) {
+                style = style.patch(app.theme().error);
+            }
+            if index == cursor {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Line::from(vec![
+                Span::raw(format!("{:>width$} ", index + 1)).dim(),
+                Span::styled(line.to_string(), style),
+            ])
+        })
+        .collect();
+
+    let title = if state.raw {
+        format!("Raw response from {} — {total} lines — v for the error", app.endpoint)
+    } else {
+        format!(
+            "Lines {}–{} of {total} — v for the raw text",
+            (offset + 1).min(total),
+            offset + lines.len()
+        )
+    };
+    let text = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(text, text_area);
+    page
+}
+
+/// Draws the raw text of the response or one family, with the search
+/// highlighted, and returns how many lines fit on a page.
+fn render_raw(f: &mut Frame, area: Rect, app: &App, state: &RawState, clicks: &mut Vec<ClickTarget>) -> usize {
+    let Some((raw, lines)) = app.raw_lines(state) else {
+        let widget = Span::raw("The family isn't in the latest scrape — press Esc to go back");
+        f.render_widget(widget, area);
+        return 1;
+    };
+
+    let total = lines.len();
+    let page = (area.height as usize).saturating_sub(2).max(1);
+    let cursor = state.cursor.min(total.saturating_sub(1));
+    let offset = cursor.saturating_sub(page / 2).min(total.saturating_sub(page));
+    let width = lines.end.to_string().len();
+    let text: Vec<Line> = raw
+        .text
+        .lines()
+        .enumerate()
+        .skip(lines.start + offset)
+        .take(page.min(total - offset))
+        .map(|(index, line)| {
+            let mut text = highlighted_name(line, &state.search, app.theme());
+            text.spans.insert(0, Span::raw(format!("{:>width$} ", index + 1)).dim());
+            if index - lines.start == cursor {
+                text = text.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            text
+        })
+        .collect();
+
+    let mut title = match &state.family {
+        Some(family) => format!("{family} as exposed — lines {}–{} of the response", lines.start + 1, lines.end),
+        None => format!("Raw response from {} — {} lines", app.endpoint, raw.lines),
+    };
+    if !state.search.is_empty() {
+        title.push_str(&format!(" — n/N for lines containing {:?}", state.search));
+    }
+    let shown = text.len();
+    let widget = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+    clicks.extend((0..shown).map(|row| ClickTarget {
+        area: Rect {
+            y: area.y + 1 + row as u16,
+            height: 1,
+            ..area
+        },
+        index: offset + row,
+    }));
+    page
+}
+
+/// The series of `fam` in detail-view order, each with its value of the
+/// `group_by` label: sorted by their labels, numbers by value so `code="503"`
+/// follows `code="404"` and `cpu="10"` follows `cpu="2"`, and with a label
+/// grouped by that value first, with the series lacking it last.
+pub(crate) fn grouped_series<'a>(
+    fam: &'a Family,
+    group_by: Option<&str>,
+    label_search: Option<&str>,
+) -> Vec<(Option<String>, &'a Series)> {
+    let mut series: Vec<(Option<String>, Vec<(String, String)>, &Series)> = fam
+        .iter_samples()
+        .filter(|sample| label_search.is_none_or(|needle| search::series_matches(sample, needle)))
+        .map(|sample| {
+            let labels = model::label_pairs(sample);
+            let value = group_by.and_then(|label| {
+                labels
+                    .iter()
+                    .find(|(name, value)| name == label && !value.is_empty())
+                    .map(|(_, value)| value.clone())
+            });
+            (value, labels, sample)
+        })
+        .collect();
+    series.sort_by(|a, b| {
+        let group = match (&a.0, &b.0) {
+            (Some(a), Some(b)) => natural::compare(a, b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        };
+        group.then_with(|| natural::compare_labels(&a.1, &b.1))
+    });
+    series.into_iter().map(|(value, _, sample)| (value, sample)).collect()
+}
+
+enum DetailEntry<'a> {
+    /// The start of a group: its label value, or `None` for "(absent)".
+    Header(Option<&'a str>),
+    /// Index into `grouped_series`.
+    Series(usize),
+}
+
+/// A group's header row: its label value, series count and, for scalar
+/// families, the sum of its values.
+fn group_header<'a>(
+    app: &App,
+    name: &str,
+    fam: &Family,
+    label: &str,
+    value: Option<&str>,
+    series: &[(Option<String>, &Series)],
+) -> Row<'a> {
+    let members: Vec<&Series> = series
+        .iter()
+        .filter(|(group, _)| group.as_deref() == value)
+        .map(|(_, sample)| *sample)
+        .collect();
+    let values: Vec<f64> = members
+        .iter()
+        .filter_map(|sample| model::scalar_value(&sample.value))
+        .filter(|value| !value.is_nan())
+        .collect();
+    let sum = if values.is_empty() {
+        String::new()
+    } else {
+        format!("Σ {}", app.display_number(values.iter().sum(), name, fam))
+    };
+    let title = match value {
+        Some(value) => format!("{label}=\"{value}\""),
+        None => String::from("(absent)"),
+    };
+    let count = members.len();
+    Row::new(vec![
+        Text::from(title),
+        Text::from(sum).alignment(Alignment::Right),
+        Text::from(format!("{count} series")).alignment(Alignment::Right),
+        Text::from(""),
+    ])
+    .style(app.theme().accent)
+    .bold()
+}
+
+/// The `L` popup, "no grouping" followed by every label of the family, or
+/// the `w` one with a checkbox per label.
+fn render_label_picker(f: &mut Frame, area: Rect, labels: &[String], picker: &LabelPicker, theme: &Theme) {
+    let entries: Vec<String> = match &picker.ticked {
+        Some(ticked) => labels
+            .iter()
+            .map(|label| format!(" [{}] {label} ", if ticked.contains(label) { "x" } else { " " }))
+            .collect(),
+        None => std::iter::once("(no grouping)")
+            .chain(labels.iter().map(String::as_str))
+            .map(|label| format!(" {label} "))
+            .collect(),
+    };
+    let lines: Vec<Line> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let line = Line::from(entry);
+            if index == picker.cursor {
+                line.style(theme.selected)
+            } else {
+                line
+            }
+        })
+        .collect();
+    let title = match picker.ticked {
+        Some(_) => "Aggregate without — Space to tick, Enter to apply",
+        None => "Group by — Enter to pick, Esc to cancel",
+    };
+    let area = centered_rect(30, 50, area);
+    let picker = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(picker, area);
+}
+
+/// The `|` picker: every column, ticked where shown, in the table's order.
+fn render_column_picker(f: &mut Frame, area: Rect, columns: &Columns, cursor: usize, theme: &Theme) {
+    let lines: Vec<Line> = columns
+        .listed()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (column, shown))| {
+            let line = Line::from(format!(" [{}] {} ", if shown { "x" } else { " " }, column.name()));
+            if index == cursor {
+                line.style(theme.selected)
+            } else {
+                line
+            }
+        })
+        .collect();
+    let title = "Columns — Space to show or hide, K/J to move";
+    let area = centered_rect(30, 50, area);
+    let picker = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(picker, area);
+}
+
+/// The `C` pane: each label with its distinct values and how many times
+/// it multiplies the series, then the selected label's commonest values.
+fn render_label_breakdown(f: &mut Frame, area: Rect, family: &str, breakdown: &LabelBreakdown, theme: &Theme) {
+    let width = breakdown.labels.iter().map(|label| label.label.chars().count()).max().unwrap_or(0);
+    let mut lines: Vec<Line> = breakdown
+        .labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| {
+            let values = if label.distinct == 1 { "value" } else { "values" };
+            let line = Line::from(format!(
+                " {:<width$}  {:>7} {values:<6}  ×{:.1} ",
+                label.label, label.distinct, label.factor
+            ));
+            if index == breakdown.cursor {
+                line.style(theme.selected)
+            } else {
+                line
+            }
+        })
+        .collect();
+    if let Some(selected) = breakdown.labels.get(breakdown.cursor) {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!(" Most common {} values", selected.label), theme.accent)));
+        for (value, count) in &selected.top_values {
+            let share = *count as f64 / breakdown.series.max(1) as f64 * 100.0;
+            lines.push(Line::from(format!("   {value:?}  {count} series ({share:.0}%)")));
+        }
+    }
+    let title = format!(
+        "{family}: {} series by label — y copies a rule dropping it, Esc to close",
+        breakdown.series
+    );
+    let area = centered_rect(70, 70, area);
+    let pane = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(Clear, area);
+    f.render_widget(pane, area);
+}
+
+/// The metric an aggregation expression applies to: histograms are
+/// aggregated by their buckets.
+pub(crate) fn aggregated_metric(name: &str, fam: &Family) -> String {
+    match fam.family_type {
+        PrometheusType::Histogram => format!("{name}_bucket"),
+        _ => name.to_string(),
+    }
+}
+
+/// The detail view with `w` labels aggregated away: one row per remaining
+/// labelset, next to the bucket breakdown when the family is a histogram.
+/// Returns how many rows fit on a page.
+fn render_aggregates(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    detail: &DetailState,
+    fam: &Family,
+    aggregates: &[model::Aggregate],
+) -> usize {
+    let selected = detail.selected.min(aggregates.len().saturating_sub(1));
+    let list_area = match aggregates.get(selected).and_then(|aggregate| aggregate.value.as_ref()) {
+        Some(PrometheusValue::Histogram(histogram)) => {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+                .split(area);
+            let aggregate = &aggregates[selected];
+            let members = fam
+                .iter_samples()
+                .filter(|sample| model::in_aggregate(sample, &detail.without, &aggregate.labels));
+            let buckets = app.histogram_buckets(&detail.family, members);
+            render_histogram(f, panes[1], app, &detail.family, fam, histogram, buckets);
+            panes[0]
+        }
+        _ => area,
+    };
+
+    let rate = app.aggregates_rates(fam);
+    let page_height = (list_area.height.saturating_sub(3) as usize).max(1);
+    let offset = selected / page_height * page_height;
+    let rows: Vec<Row> = aggregates
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|aggregate| {
+            let value = match &aggregate.value {
+                None => String::from("-"),
+                Some(value) if rate => model::scalar_value(value)
+                    .map_or_else(|| String::from("-"), |rate| model::format_change(&Change::Rate(rate))),
+                Some(value) => app.display_value(&detail.family, fam, value),
+            };
+            Row::new(vec![
+                Text::from(model::format_labels(&aggregate.labels)),
+                Text::from(value).alignment(Alignment::Right),
+                Text::from(aggregate.series.to_string()).alignment(Alignment::Right),
+            ])
+        })
+        .collect();
+
+    let expression = model::aggregation_expression(
+        detail.aggregation,
+        &detail.without,
+        &aggregated_metric(&detail.family, fam),
+        rate,
+    );
+    let widths = [Constraint::Min(20), Constraint::Length(24), Constraint::Length(8)];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Labels", if rate { "Rate" } else { "Value" }, "Series"]).bold())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{expression} — {}/{}", selected + 1, aggregates.len())),
+        )
+        .highlight_style(app.theme().selected)
+        .highlight_symbol(">> ");
+    let mut state = TableState::default().with_selected(Some(selected - offset));
+    f.render_stateful_widget(table, list_area, &mut state);
+    page_height
+}
+
+/// What every row of the detail view's series table is laid out by.
+struct SeriesColumns {
+    labels_width: usize,
+    baseline_column: bool,
+    /// What sample timestamps are aged against.
+    now: SystemTime,
+}
+
+/// One series of the detail view: its labels, value, change, value since
+/// the baseline and timestamp.
+fn series_row(app: &App, detail: &DetailState, fam: &Family, sample: &Series, columns: &SeriesColumns) -> Row<'static> {
+    let mut pairs = model::label_pairs(sample);
+    if let Some(label) = &detail.group_by {
+        pairs.retain(|(name, _)| name != label);
+    }
+    let key = SeriesKey::new(&detail.family, sample);
+    let mut labels = Line::from(model::format_labels(&pairs));
+    if app.churn.new_series.contains(&key) {
+        labels.spans.insert(0, Span::raw("new ").style(app.theme().added));
+    }
+    if app.counter_resets.has_partial(&key) {
+        labels.spans.insert(0, Span::raw("⚠ ").style(app.theme().warning));
+    }
+    let timestamp = sample
+        .timestamp
+        .map(|ts| model::format_sample_time(ts, columns.now))
+        .unwrap_or_default();
+    let since_baseline = match app.since_baseline(&detail.family, sample) {
+        Some(since @ model::SinceBaseline::New(_)) => {
+            Text::from(model::format_since_baseline(&since)).style(app.theme().added)
+        }
+        Some(since) => Text::from(model::format_since_baseline(&since)),
+        None => Text::from(""),
+    };
+    let change = app
+        .series_change(&detail.family, fam, sample)
+        .map(|c| model::format_change(&c))
+        .unwrap_or_default();
+    let movement = match (app.highlight_changes, &app.previous, model::scalar_value(&sample.value)) {
+        (true, Some(previous), Some(current)) => {
+            let before = previous.values.get(&SeriesKey::new(&detail.family, sample)).copied();
+            Some(Movement::between(before, current))
+        }
+        _ => None,
+    };
+    let mut cells = vec![
+        Text::from(fit_line(labels, app.scroll, columns.labels_width)),
+        Text::from(app.display_value(&detail.family, fam, &sample.value))
+            .style(number_style(model::scalar_value(&sample.value), movement_style(movement, app.theme())))
+            .alignment(Alignment::Right),
+        Text::from(change).alignment(Alignment::Right),
+    ];
+    if columns.baseline_column {
+        cells.push(since_baseline.alignment(Alignment::Right));
+    }
+    cells.push(Text::from(timestamp).alignment(Alignment::Right));
+    let row = Row::new(cells);
+    match app.highlights.series(&key) {
+        Some(level) => row.style(highlight_style(level, app.theme())),
+        None => row,
+    }
+}
+
+/// Draws the series list for one family and returns how many rows fit on a page.
+fn render_detail(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    detail: &DetailState,
+    clicks: &mut Vec<ClickTarget>,
+) -> usize {
+    let Some(fam) = app.family(&detail.family) else {
+        let widget = Span::raw(format!("{} is no longer exposed by {} — press Esc to go back", detail.family, app.endpoint));
+        f.render_widget(widget, area);
+        return 1;
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Max(4), Constraint::Min(0)].as_ref())
+        .split(area);
+
+    let help = Paragraph::new(fam.help.as_str())
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title(format!("{} ({})", detail.family, type_label(fam))));
+    f.render_widget(help, chunks[0]);
+
+    // Label families get their labelsets as a table of their own first.
+    let rest = match app.label_family(&detail.family, fam) {
+        Some(kind) => {
+            let height = fam.iter_samples().count().min(8) as u16 + 3;
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(height), Constraint::Min(0)].as_ref())
+                .split(chunks[1]);
+            render_label_table(f, split[0], app, &detail.family, fam, kind);
+            split[1]
+        }
+        None => chunks[1],
+    };
+
+    if let Some(aggregates) = app.detail_aggregates(detail) {
+        return render_aggregates(f, rest, app, detail, fam, &aggregates);
+    }
+
+    let series = app.detail_series(fam, detail);
+    let series_count = series.len();
+    // The family may have lost series since the selection was made.
+    let selected = detail.selected.min(series_count.saturating_sub(1));
+    let selected_sample = series.get(selected).map(|(_, sample)| *sample);
+
+    // Decreases of the selected counter go at the very bottom.
+    let selected_key = selected_sample.map(|sample| SeriesKey::new(&detail.family, sample));
+    let events = selected_key.as_ref().and_then(|key| app.counter_resets.events.get(key));
+    let below_events = match events {
+        Some(events) => {
+            let height = events.len().min(4) as u16 + 2;
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(height)].as_ref())
+                .split(rest);
+            render_counter_events(f, split[1], app, &detail.family, fam, events);
+            split[0]
+        }
+        None => rest,
+    };
+
+    // Then the rest of its event log, which the decreases are already part of.
+    let logged: Vec<&Event> = selected_key.as_ref().map_or(Vec::new(), |key| {
+        let mut logged = app.events.of_series(&key.to_string());
+        logged.retain(|event| event.kind != events::Kind::CounterReset);
+        logged
+    });
+    let below_logged = if logged.is_empty() {
+        below_events
+    } else {
+        let height = logged.len().min(4) as u16 + 2;
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(height)].as_ref())
+            .split(below_events);
+        render_series_events(f, split[1], app, &logged);
+        split[0]
+    };
+
+    // Then its exemplars, which only OpenMetrics scrapes have.
+    let exemplars = selected_sample.map_or(Vec::new(), |sample| model::exemplars(&sample.value));
+    let below_exemplars = if exemplars.is_empty() {
+        below_logged
+    } else {
+        let height = exemplars.len().min(6) as u16 + 2;
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(height)].as_ref())
+            .split(below_logged);
+        render_exemplars(f, split[1], app, &exemplars);
+        split[0]
+    };
+
+    // Once there's more than one scrape of the selected series, chart it under the list.
+    let history = selected_key
+        .as_ref()
+        .and_then(|key| app.history.points(key))
+        .filter(|points| points.len() > 1);
+    let series_area = match history {
+        Some(points) => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(10)].as_ref())
+                .split(below_exemplars);
+            render_history(f, split[1], app, &detail.family, fam, points);
+            split[0]
+        }
+        None => below_exemplars,
+    };
+
+    // Histograms and summaries get a breakdown of the selected labelset next to the list.
+    let list_area = match selected_sample.map(|s| &s.value) {
+        Some(PrometheusValue::Histogram(histogram)) => {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+                .split(series_area);
+            let buckets = app.histogram_buckets(&detail.family, selected_sample);
+            render_histogram(f, panes[1], app, &detail.family, fam, histogram, buckets);
+            panes[0]
+        }
+        Some(PrometheusValue::Summary(summary)) => {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+                .split(series_area);
+            render_summary(f, panes[1], summary);
+            panes[0]
+        }
+        _ => series_area,
+    };
+
+    // Only format the page of series that's actually on screen, so families
+    // with thousands of labelsets stay responsive.
+    let page_height = (list_area.height.saturating_sub(3) as usize).max(1);
+
+    // With a label chosen, a header row goes in front of each group.
+    let mut entries: Vec<DetailEntry> = Vec::with_capacity(series.len());
+    for (index, (value, _)) in series.iter().enumerate() {
+        if detail.group_by.is_some() && (index == 0 || series[index - 1].0 != *value) {
+            entries.push(DetailEntry::Header(value.as_deref()));
+        }
+        entries.push(DetailEntry::Series(index));
+    }
+    let selected_row = entries
+        .iter()
+        .position(|entry| matches!(entry, DetailEntry::Series(index) if *index == selected))
+        .unwrap_or(0);
+    let offset = selected_row / page_height * page_height;
+    // What the fixed-width columns, borders, highlight symbol and spacing
+    // leave the labels.
+    let baseline_column = app.baseline.is_some() && app.show_baseline;
+    // Room for "2024-06-26T20:00:00Z, 3m12s ago" once any series has a
+    // timestamp of its own.
+    let timestamp_width = if series.iter().any(|(_, sample)| sample.timestamp.is_some()) { 34 } else { 16 };
+    let fixed_width = if baseline_column { 77 } else { 62 } + timestamp_width - 16;
+    // Replays show how old a timestamp was when it was recorded.
+    let now = match (&app.replay, app.last_success_at) {
+        (Some(replay), Some(at)) => replay.wall_time(at),
+        _ => SystemTime::now(),
+    };
+    let labels_width = (list_area.width.saturating_sub(fixed_width) as usize).max(20);
+    let columns = SeriesColumns {
+        labels_width,
+        baseline_column,
+        now,
+    };
+
+    let mut rows: Vec<Row> = entries
+        .iter()
+        .skip(offset)
+        .take(page_height)
+        .map(|entry| match *entry {
+            DetailEntry::Series(index) => series_row(app, detail, fam, series[index].1, &columns),
+            DetailEntry::Header(value) => {
+                let label = detail.group_by.as_deref().unwrap_or_default();
+                group_header(app, &detail.family, fam, label, value, &series)
+            }
+        })
+        .collect();
+
+    // Disappeared series fill whatever room the last page has left.
+    if app.show_disappeared && rows.len() < page_height {
+        let mut gone: Vec<(&SeriesKey, &model::Gone)> = app
+            .churn
+            .gone_series
+            .iter()
+            .filter(|(key, _)| key.family == detail.family)
+            .collect();
+        gone.sort_by(|a, b| natural::compare(&a.0.labels, &b.0.labels));
+        let room = page_height - rows.len();
+        rows.extend(gone.into_iter().take(room).map(|(key, gone)| {
+            let mut cells = vec![
+                Text::from(fit_line(Line::from(key.labels.clone()), app.scroll, labels_width)).crossed_out(),
+                Text::from(gone.last_value.clone()).alignment(Alignment::Right),
+                Text::from(format!("gone {}", refreshes_ago(gone.scrapes_ago))).alignment(Alignment::Right),
+                Text::from(""),
+            ];
+            if baseline_column {
+                cells.push(Text::from(""));
+            }
+            Row::new(cells).dim()
+        }));
+    }
+
+    let change_header = match fam.family_type {
+        PrometheusType::Counter => "Rate",
+        _ => "Δ",
+    };
+    let mut widths = vec![Constraint::Min(20), Constraint::Length(24), Constraint::Length(14)];
+    let mut header = vec!["Labels", "Value", change_header];
+    if baseline_column {
+        widths.push(Constraint::Length(14));
+        header.push("Since B");
+    }
+    widths.push(Constraint::Length(timestamp_width));
+    header.push("Timestamp");
+    let mut title = match &detail.group_by {
+        Some(label) => format!("Series {}/{series_count} by {label}", selected + 1),
+        None => format!("Series {}/{series_count}", selected + 1),
+    };
+    if let Some(needle) = &detail.label_search {
+        title.push_str(&format!(" with labels matching \"{needle}\""));
+    }
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    // Labels cut short or scrolled are shown whole under the table.
+    let labels = selected_sample.map(|sample| model::format_labels(&model::label_pairs(sample)));
+    if let Some(labels) = labels.filter(|labels| is_cut(labels, app.scroll, labels_width)) {
+        block = block.title_bottom(Line::from(format!(" {labels} ")).dim());
+    }
+    let series = Table::new(rows, widths)
+        .header(Row::new(header).bold())
+        .block(block)
+        .highlight_style(app.theme().selected)
+        .highlight_symbol(">> ");
+
+    let mut state = TableState::default().with_selected(Some(selected_row - offset));
+    f.render_stateful_widget(series, list_area, &mut state);
+
+    // Rows start under the top border and the header; group headers can't
+    // be selected.
+    for (row, entry) in entries.iter().skip(offset).take(page_height).enumerate() {
+        if let DetailEntry::Series(index) = *entry {
+            let area = Rect {
+                y: list_area.y + 2 + row as u16,
+                height: 1,
+                ..list_area
+            };
+            clicks.push(ClickTarget { area, index });
+        }
+    }
+    page_height
+}
+
+/// The times the selected counter went backwards, latest first.
+fn render_counter_events(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    name: &str,
+    fam: &Family,
+    events: &[model::CounterEvent],
+) {
+    let theme = app.theme();
+    let format = |value: f64| app.display_number(value, name, fam);
+    let lines: Vec<Line> = events
+        .iter()
+        .rev()
+        .map(|event| {
+            let (kind, style) = match event.decrease {
+                model::Decrease::Reset => ("reset   ", Style::default()),
+                model::Decrease::Partial => ("decrease", theme.warning),
+            };
+            Line::from(vec![
+                Span::raw(format!("{}  ", humantime::format_rfc3339_seconds(event.at))),
+                Span::raw(kind).style(style),
+                Span::raw(format!("  {} → {}", format(event.before), format(event.after))),
+            ])
+        })
+        .collect();
+    let title = format!("Counter went backwards {} times", events.len());
+    let widget = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+}
+
+/// Notes on the selected series, rules firing for it and when it
+/// appeared, newest first.
+fn render_series_events(f: &mut Frame, area: Rect, app: &App, logged: &[&Event]) {
+    let lines: Vec<Line> = logged
+        .iter()
+        .map(|event| {
+            Line::from(vec![
+                Span::raw(format!("{}  ", humantime::format_rfc3339_seconds(event.at))),
+                Span::raw(event.kind.name()).style(event_style(app.theme(), event)),
+                Span::raw(format!("  {}", event.text)),
+            ])
+        })
+        .collect();
+    let title = format!("Events — {} — E in the table lists every series'", logged.len());
+    let widget = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+}
+
+/// The exemplars of the selected series: the bucket each is in, its labels,
+/// value and when it was observed.
+fn render_exemplars(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    exemplars: &[(Option<f64>, &openmetrics_parser::Exemplar)],
+) {
+    let theme = app.theme();
+    let lines: Vec<Line> = exemplars
+        .iter()
+        .map(|(bucket, exemplar)| {
+            let mut labels: Vec<(String, String)> = exemplar
+                .labels
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            labels.sort();
+            let mut spans = Vec::new();
+            if let Some(bound) = bucket {
+                spans.push(Span::raw(format!("{:>8}  ", model::format_bound(*bound))).dim());
+            }
+            spans.push(Span::raw(format!("{{{}}}", model::format_labels(&labels))).style(theme.accent));
+            spans.push(Span::raw(format!(" = {}", model::format_number(exemplar.id))));
+            let observed = exemplar
+                .timestamp
+                .filter(|ts| ts.is_finite() && *ts >= 0.0)
+                .map(|ts| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(ts));
+            if let Some(at) = observed {
+                spans.push(Span::raw(format!("  at {}", humantime::format_rfc3339_millis(at))).dim());
+            }
+            Line::from(spans)
+        })
+        .collect();
+    let keys = if app.trace_url_template.is_some() {
+        "x copies the trace ID, X opens it"
+    } else {
+        "x copies the trace ID"
+    };
+    let title = format!("Exemplars ({}) — {keys}", exemplars.len());
+    let widget = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(widget, area);
+}
+
+/// The selected series across the last scrapes, or with `R` a counter's
+/// rate. Failed scrapes and scrapes without the series break the line.
+fn render_history(f: &mut Frame, area: Rect, app: &App, name: &str, fam: &Family, points: &VecDeque<Point>) {
+    let rate = app.show_rates && matches!(fam.family_type, PrometheusType::Counter);
+    let points: Vec<Point> = if rate {
+        history::rates(points)
+    } else {
+        points.iter().copied().collect()
+    };
+    let format = |value: f64| {
+        let text = app.display_number(value, name, fam);
+        if rate {
+            format!("{text}/s")
+        } else {
+            text
+        }
+    };
+
+    // Contiguous runs of values, as (seconds before the latest point, value).
+    let latest = points.last().map_or_else(Instant::now, |point| point.at);
+    let mut runs: Vec<Vec<(f64, f64)>> = vec![Vec::new()];
+    for point in &points {
+        match point.value {
+            Some(value) => {
+                let x = -latest.saturating_duration_since(point.at).as_secs_f64();
+                runs.last_mut().expect("never empty").push((x, value));
+            }
+            None if runs.last().is_some_and(|run| !run.is_empty()) => runs.push(Vec::new()),
+            None => {}
+        }
+    }
+    runs.retain(|run| !run.is_empty());
+
+    let what = if rate { "Rate" } else { "Value" };
+    let block = Block::default().borders(Borders::ALL);
+    let values = points.iter().filter_map(|point| point.value);
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+    if runs.is_empty() || !min.is_finite() || !max.is_finite() {
+        let empty = Paragraph::new(format!("no values in the last {} scrapes", points.len()).dim());
+        f.render_widget(empty.block(block.title(what)), area);
+        return;
+    }
+
+    let last = points.last().and_then(|point| point.value).map_or(String::from("gap"), format);
+    let title = format!(
+        "{what} over the last {} scrapes — min {} · max {} · last {last}",
+        points.len(),
+        format(min),
+        format(max)
+    );
+    let pad = if max > min { (max - min) * 0.05 } else { max.abs().max(1.0) * 0.1 };
+    let span = latest.saturating_duration_since(points[0].at);
+    let datasets: Vec<Dataset> = runs
+        .iter()
+        .map(|run| {
+            // A lone value has nothing to connect to, so draw it as a dot.
+            let graph_type = if run.len() == 1 { GraphType::Scatter } else { GraphType::Line };
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(graph_type)
+                .style(app.theme().chart)
+                .data(run)
+        })
+        .collect();
+    let span_label = humantime::format_duration(Duration::from_secs(span.as_secs())).to_string();
+    let chart = Chart::new(datasets)
+        .block(block.title(title))
+        .x_axis(
+            Axis::default()
+                .bounds([-span.as_secs_f64().max(1.0), 0.0])
+                .labels(vec![Span::raw(format!("-{span_label}")), Span::raw("latest")]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([min - pad, max + pad])
+                .labels(vec![Span::raw(format(min)), Span::raw(format(max))]),
+        );
+    f.render_widget(chart, area);
+}
+
+/// A histogram's count, sum and quantiles over a chart of its buckets. The
+/// quantiles are estimated from `buckets`, which hold what was observed
+/// since the previous scrape while rates are shown.
+fn render_histogram(
+    f: &mut Frame,
+    area: Rect,
+    app: &App,
+    name: &str,
+    fam: &Family,
+    histogram: &HistogramValue,
+    buckets: Option<Vec<(f64, f64)>>,
+) {
+    let block = Block::default().borders(Borders::ALL).title("Buckets");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(inner);
+
+    let count = histogram.count.map(|c| c as f64);
+    let sum = histogram.sum.map(|s| s.as_f64());
+    let average = match (sum, count) {
+        (Some(sum), Some(count)) if count > 0.0 => (sum / count).to_string(),
+        _ => String::from("-"),
+    };
+    let stats = Line::from(vec![
+        Span::raw("_count ").dim(),
+        Span::raw(count.map_or(String::from("-"), |c| c.to_string())),
+        Span::raw("  _sum ").dim(),
+        Span::raw(sum.map_or(String::from("-"), |s| s.to_string())),
+        Span::raw("  avg ").dim(),
+        Span::raw(average),
+    ]);
+    f.render_widget(stats, chunks[0]);
+
+    let mut quantiles: Vec<Span> = Vec::new();
+    for &q in &app.quantiles {
+        if !quantiles.is_empty() {
+            quantiles.push(Span::raw("  "));
+        }
+        quantiles.push(Span::raw(app.quantile_text(name, fam, buckets.as_deref(), q)));
+    }
+    if app.show_rates && !quantiles.is_empty() {
+        quantiles.push(Span::raw("  since the last scrape").dim());
+    }
+    f.render_widget(Line::from(quantiles), chunks[1]);
+
+    let bars: Vec<Bar> = model::decumulate_buckets(histogram)
+        .into_iter()
+        .map(|bucket| {
+            Bar::default()
+                .value(bucket.count.round() as u64)
+                .text_value(bucket.count.to_string())
+                .label(Line::from(model::format_bound(bucket.upper_bound)))
+        })
+        .collect();
+    let chart = BarChart::default()
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(app.theme().chart)
+        .data(BarGroup::default().bars(&bars));
+    f.render_widget(chart, chunks[2]);
+}
+
+fn render_summary(f: &mut Frame, area: Rect, summary: &SummaryValue) {
+    let mut rows: Vec<Row> = model::sorted_quantiles(summary)
+        .into_iter()
+        .map(|(quantile, value)| {
+            let value = match value {
+                Some(v) => Text::from(model::format_number(v)),
+                None => Text::from("no data").dim(),
+            };
+            Row::new(vec![Text::from(format!("quantile {quantile}")), value.alignment(Alignment::Right)])
+        })
+        .collect();
+
+    let count = summary.count.map_or(String::from("-"), |c| c.to_string());
+    let sum = summary.sum.map_or(String::from("-"), |s| model::format_number(s.as_f64()));
+    rows.push(Row::new(vec![Text::from("_sum").dim(), Text::from(sum).alignment(Alignment::Right)]));
+    rows.push(Row::new(vec![Text::from("_count").dim(), Text::from(count).alignment(Alignment::Right)]));
+
+    let widths = [Constraint::Percentage(50), Constraint::Percentage(50)];
+    let table = Table::new(rows, widths).block(Block::default().borders(Borders::ALL).title("Quantiles"));
+    f.render_widget(table, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+    use ratatui::Terminal;
+
+    use crate::app::tests::{app, scrape};
+    use crate::fetch::{Body, FetchResult};
+    use crate::keys::Action;
+    use crate::textfile;
+
+    /// The text `ui` draws at `width` × `height`, a string per row.
+    fn screen(app: &mut App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, std::slice::from_mut(app), 0)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn the_table_drops_the_type_column_then_stacks_as_the_terminal_narrows() {
+        let layout = |width, height| table_layout(Rect::new(0, 0, width, height));
+        assert_eq!(layout(120, 40), TableLayout::Columns);
+        assert_eq!(layout(80, 40), TableLayout::Compact);
+        assert_eq!(layout(50, 40), TableLayout::Stacked);
+        assert!(is_short(Rect::new(0, 0, 80, 10)));
+        assert!(!is_short(Rect::new(0, 0, 80, 24)));
+
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE process_open_fds gauge\nprocess_open_fds 42\n")));
+
+        let wide = screen(&mut app, 120, 24);
+        assert!(wide.iter().any(|line| line.contains("Type")));
+
+        let narrow = screen(&mut app, 80, 24);
+        assert!(!narrow.iter().any(|line| line.contains("Type")));
+        assert!(narrow.iter().any(|line| line.contains("G process_open_fds") && line.contains("42")));
+
+        let tiny = screen(&mut app, 50, 24);
+        let name = tiny.iter().position(|line| line.contains("process_open_fds")).unwrap();
+        assert!(!tiny[name].contains("42"));
+        assert!(tiny[name + 1].contains("gauge") && tiny[name + 1].contains("42"));
+
+        // No margin, and a status bar without the endpoint.
+        let short = screen(&mut app, 80, 10);
+        assert!(short[0].contains("1 families"));
+        assert!(!short[0].contains("stdin"));
+    }
+
+    #[test]
+    fn the_table_shows_the_picked_columns_in_their_order() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE queue_depth gauge\nqueue_depth 3\n")));
+        app.apply_fetch(scrape(Ok("# TYPE queue_depth gauge\nqueue_depth 5\n")));
+        app.columns = Columns::parse("rate,name,count").unwrap();
+        let lines = screen(&mut app, 120, 24);
+        let header = lines.iter().find(|line| line.contains("Name")).unwrap();
+        assert!(!header.contains("Type") && !header.contains("Value"), "{lines:#?}");
+        let at = |line: &str, text: &str| line.find(text).unwrap_or_else(|| panic!("no {text:?} in {line:?}"));
+        assert!(at(header, "Rate") < at(header, "Name") && at(header, "Name") < at(header, "Series"));
+        let row = lines.iter().find(|line| line.contains("queue_depth")).unwrap();
+        assert!(at(row, "+2") < at(row, "queue_depth"), "{lines:#?}");
+        assert!(row.trim_end_matches(['│', ' ']).ends_with('1'), "{lines:#?}");
+
+        app.perform(Action::PickColumns);
+        let lines = screen(&mut app, 120, 24);
+        let listed: Vec<&String> = lines.iter().filter(|line| line.contains("[x] ") || line.contains("[ ] ")).collect();
+        assert!(listed[0].contains("[x] rate") && listed[2].contains("[x] count"), "{lines:#?}");
+        assert!(listed[3].contains("[ ] type") && listed[4].contains("[ ] value"), "{lines:#?}");
+    }
+
+    #[test]
+    fn clicks_select_a_row_then_open_it() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE first_gauge gauge\nfirst_gauge 1\n# TYPE second_gauge gauge\nsecond_gauge 2\n")));
+        let lines = screen(&mut app, 120, 24);
+        let row = lines.iter().position(|line| line.contains("second_gauge")).unwrap() as u16;
+        let click = |row| MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 10,
+            row,
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+
+        app.handle_mouse(click(row));
+        assert_eq!(app.selected_family_name().as_deref(), Some("second_gauge"));
+        app.handle_mouse(MouseEvent {
+            kind: MouseEventKind::ScrollUp,
+            ..click(row)
+        });
+        assert_eq!(app.selected(), 0);
+        app.handle_mouse(click(row));
+        app.handle_mouse(click(row));
+        assert!(matches!(&app.view, View::Detail(detail) if detail.family == "second_gauge"));
+    }
+
+    #[test]
+    fn pausing_keeps_the_table_on_its_scrape_until_resumed() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE queue gauge\nqueue 10\n")));
+        app.perform(Action::TogglePause);
+        app.apply_fetch(scrape(Ok("# TYPE queue gauge\nqueue 20\n")));
+        app.apply_fetch(scrape(Ok("# TYPE queue gauge\nqueue 30\n# TYPE late gauge\nlate 1\n")));
+        let lines = screen(&mut app, 120, 24);
+        let row = |lines: &[String], name: &str| lines.iter().find(|line| line.contains(name)).cloned().unwrap_or_default();
+        assert!(row(&lines, "queue").contains("10"), "{lines:#?}");
+        assert!(row(&lines, "late").is_empty(), "{lines:#?}");
+        assert!(lines.iter().any(|line| line.contains("PAUSED — 2 refreshes behind")), "{lines:#?}");
+
+        app.perform(Action::TogglePause);
+        let lines = screen(&mut app, 120, 24);
+        assert!(row(&lines, "queue").contains("30"), "{lines:#?}");
+        assert!(!lines.iter().any(|line| line.contains("PAUSED")), "{lines:#?}");
+        // Rates compare the latest scrape with the one before it, not the
+        // one the display was frozen on.
+        let previous = app.previous.as_ref().unwrap();
+        assert_eq!(previous.values.values().copied().collect::<Vec<_>>(), vec![20.0]);
+    }
+
+    #[test]
+    fn the_table_shows_the_change_since_the_baseline() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok("# TYPE sent_total counter\nsent_total 100\n")));
+        app.perform(Action::SetBaseline);
+        app.apply_fetch(scrape(Ok("# TYPE sent_total counter\nsent_total 130\n# TYPE late gauge\nlate 7\n")));
+        let lines = screen(&mut app, 120, 24);
+        let row = |name: &str| lines.iter().find(|line| line.contains(name)).cloned().unwrap_or_default();
+        assert!(row("sent_total").contains("+30"), "{lines:#?}");
+        assert!(row("late").contains("7 new"), "{lines:#?}");
+        assert!(lines.iter().any(|line| line.contains("since baseline")), "{lines:#?}");
+
+        app.perform(Action::ToggleBaseline);
+        let lines = screen(&mut app, 120, 24);
+        assert!(!lines.iter().any(|line| line.contains("+30")), "{lines:#?}");
+    }
+
+    #[test]
+    fn long_cells_are_cut_in_the_middle_without_splitting_characters() {
+        let text = |line: Line| line.spans.iter().map(|span| span.content.as_ref()).collect::<String>();
+        assert_eq!(text(fit_line(Line::from("node_filesystem_avail_bytes"), 0, 21)), "node_files…vail_bytes");
+        assert_eq!(text(fit_line(Line::from("node_cpu_seconds_total"), 0, 40)), "node_cpu_seconds_total");
+        assert_eq!(text(fit_line(Line::from("ünïcödé"), 0, 5)), "ün…dé");
+        assert_eq!(text(fit_line(Line::from("http_requests_total"), 5, 40)), "…equests_total");
+
+        let highlighted = Line::from(vec![Span::raw("http_"), Span::styled("requests", Style::new().bold())]);
+        let fitted = fit_line(highlighted, 0, 5);
+        let spans: Vec<(&str, Style)> = fitted.spans.iter().map(|span| (span.content.as_ref(), span.style)).collect();
+        assert_eq!(spans, vec![("ht…", Style::default()), ("ts", Style::new().bold())]);
+    }
+
+    #[test]
+    fn series_with_old_timestamps_are_flagged_and_shown_with_their_age() {
+        let mut app = app();
+        let two_hours_ago = SystemTime::now() - Duration::from_secs(2 * 3600);
+        let millis = two_hours_ago.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+        app.apply_fetch(scrape(Ok(&format!(
+            "# TYPE backlog gauge\nbacklog{{queue=\"a\"}} 3 {millis}\nbacklog{{queue=\"b\"}} 1\n"
+        ))));
+        assert_eq!(app.skewed.len(), 1);
+        let lines = screen(&mut app, 160, 24);
+        assert!(
+            lines.iter().any(|line| line.contains("backlog{queue=\"a\"} is 2h behind")),
+            "{lines:#?}"
+        );
+
+        app.perform(Action::OpenDetail);
+        let lines = screen(&mut app, 160, 24);
+        let row = |queue: &str| lines.iter().find(|line| line.contains(queue)).cloned().unwrap_or_default();
+        let absolute = humantime::format_rfc3339_seconds(two_hours_ago).to_string();
+        assert!(row("queue=\"a\"").contains(&format!("{absolute}, 2h ago")), "{lines:#?}");
+        assert!(!row("queue=\"b\"").contains(" ago"), "{lines:#?}");
+    }
+
+    #[test]
+    fn a_broken_textfile_is_listed_without_hiding_the_others() {
+        let mut app = app();
+        app.textfiles = Some(Vec::new());
+        let file = |name: &str, text: &str| textfile::Textfile {
+            name: name.to_string(),
+            modified: Some(SystemTime::now() - Duration::from_secs(3 * 3600)),
+            text: Ok(text.to_string()),
+        };
+        let files = vec![
+            file("backup.prom", "# TYPE backup_last_success_seconds gauge\nbackup_last_success_seconds 1.7e9\n"),
+            file("rotate.prom", "rotate_last_success_seconds{ 1\n"),
+        ];
+        app.apply_fetch(FetchResult {
+            body: Ok(Body::textfiles(files)),
+            duration: Duration::ZERO,
+        });
+
+        let fam = app.family("backup_last_success_seconds").unwrap();
+        let labels: Vec<(String, String)> = fam.iter_samples().flat_map(model::label_pairs).collect();
+        assert_eq!(labels, vec![(String::from("textfile"), String::from("backup.prom"))]);
+        assert!(app.last_error.is_none());
+        let lines = screen(&mut app, 120, 24);
+        assert!(
+            lines.iter().any(|line| line.contains("2 textfiles, 1 broken, oldest written 3h ago (I)")),
+            "{lines:#?}"
+        );
+
+        app.perform(Action::ToggleTextfiles);
+        let lines = screen(&mut app, 120, 24);
+        let row = |name: &str| lines.iter().find(|line| line.contains(name)).cloned().unwrap_or_default();
+        assert!(row("backup.prom").contains("3h ago"), "{lines:#?}");
+        assert!(row("backup.prom").contains("ok"), "{lines:#?}");
+        assert!(!row("rotate.prom").contains(" ok"), "{lines:#?}");
+    }
+
+    #[test]
+    fn the_request_panel_shows_redirects_and_redacted_credentials() {
+        let mut app = app();
+        app.perform(Action::ShowExchange);
+        assert!(screen(&mut app, 100, 30).iter().any(|line| line.contains("No HTTP request was made")));
+
+        app.exchange = Some(fetch::Exchange {
+            method: String::from("GET"),
+            url: String::from("http://localhost:9100/"),
+            request_headers: vec![(String::from("authorization"), String::from("Bearer <redacted>"))],
+            redirects: vec![(reqwest::StatusCode::FOUND, String::from("https://localhost:9100/metrics"))],
+            status: Some(reqwest::StatusCode::OK),
+            version: Some(reqwest::Version::HTTP_11),
+            remote_addr: None,
+            response_headers: vec![(String::from("content-type"), String::from("text/plain; version=0.0.4"))],
+            first_byte: Some(Duration::from_millis(12)),
+            total: Duration::from_millis(40),
+            insecure: true,
+            proxy: Some(String::from("http://bastion:3128/")),
+        });
+        let lines = screen(&mut app, 100, 30);
+        let shown = |text: &str| lines.iter().any(|line| line.contains(text));
+        assert!(shown("GET http://localhost:9100/"), "{lines:#?}");
+        assert!(shown("authorization: Bearer <redacted>"), "{lines:#?}");
+        assert!(shown("302 Found → https://localhost:9100/metrics"), "{lines:#?}");
+        assert!(shown("HTTP/1.1 200 OK"), "{lines:#?}");
+        assert!(shown("certificate and hostname checks are off"), "{lines:#?}");
+        assert!(shown("through proxy http://bastion:3128/"), "{lines:#?}");
+        assert!(shown("headers after 12ms, done after 40ms"), "{lines:#?}");
+        assert_eq!(shown("Peak memory so far: "), cfg!(unix), "{lines:#?}");
+        assert!(!app.takes_mouse());
+    }
+
+    #[test]
+    fn scrapes_cut_short_by_max_series_say_so() {
+        let mut app = app();
+        app.max_series = Some(2);
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n# TYPE b gauge\nb 1\n# TYPE c gauge\nc 1\nc{n=\"2\"} 2\n")));
+        assert!(app.family("b").is_some() && app.family("c").is_none());
+        let lines = screen(&mut app, 140, 20);
+        let shown = |text: &str| lines.iter().any(|line| line.contains(text));
+        assert!(shown(" TRUNCATED "), "{lines:#?}");
+        assert!(shown("truncated at --max-series 2: 1 family not read"), "{lines:#?}");
+
+        app.max_series = None;
+        app.apply_fetch(scrape(Ok("# TYPE a gauge\na 1\n")));
+        assert!(app.truncated.is_none());
+    }
+
+    #[test]
+    fn the_label_breakdown_lists_labels_then_the_commonest_values() {
+        let mut app = app();
+        let series: String = (0..30)
+            .map(|n| format!("http_requests_total{{code=\"{}\",path=\"/p{n}\"}} 1\n", 200 + n % 2 * 300))
+            .collect();
+        app.apply_fetch(scrape(Ok(&format!("# TYPE http_requests_total counter\n{series}"))));
+        app.perform(Action::OpenDetail);
+        app.perform(Action::ShowLabelCardinality);
+        let lines = screen(&mut app, 120, 40);
+        let shown = |text: &str| lines.iter().any(|line| line.contains(text));
+        assert!(shown("http_requests_total: 30 series by label"), "{lines:#?}");
+        assert!(shown("path       30 values  ×15.0"), "{lines:#?}");
+        assert!(shown("code        2 values  ×1.0"), "{lines:#?}");
+        assert!(shown("Most common path values"), "{lines:#?}");
+        assert!(shown("\"/p0\"  1 series (3%)"), "{lines:#?}");
+    }
+
+    #[test]
+    fn histogram_quantiles_are_estimated_over_the_lifetime_or_the_last_scrape() {
+        let mut app = app();
+        app.raw_values = true;
+        app.table_quantile = Some(0.5);
+        let histogram = |le1: u32, le3: u32| {
+            format!(
+                "# TYPE latency_seconds histogram\n\
+                 latency_seconds_bucket{{le=\"1\"}} {le1}\n\
+                 latency_seconds_bucket{{le=\"3\"}} {le3}\n\
+                 latency_seconds_bucket{{le=\"+Inf\"}} {le3}\n\
+                 latency_seconds_sum 40\n\
+                 latency_seconds_count {le3}\n"
+            )
+        };
+        app.apply_fetch(scrape(Ok(&histogram(0, 10))));
+        app.apply_fetch(scrape(Ok(&histogram(10, 30))));
+        let lines = screen(&mut app, 120, 20);
+        assert!(lines.iter().any(|line| line.contains("p50 ≈ 1.5")), "{lines:#?}");
+
+        // 10 of the 20 observations since the first scrape were at most 1.
+        app.perform(Action::ToggleRates);
+        let lines = screen(&mut app, 120, 20);
+        assert!(lines.iter().any(|line| line.contains("p50 ≈ 1")), "{lines:#?}");
+        assert!(!lines.iter().any(|line| line.contains("p50 ≈ 1.5")), "{lines:#?}");
+
+        app.perform(Action::ToggleRates);
+        app.perform(Action::OpenDetail);
+        let lines = screen(&mut app, 120, 30);
+        assert!(
+            lines.iter().any(|line| line.contains("p50 ≈ 1.5  p90 ≈ 2.7  p99 ≈ 2.9")),
+            "{lines:#?}"
+        );
+    }
+
+    fn fixture(name: &str) -> String {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("couldn't read {}: {e}", path.display()))
+    }
+
+    /// Compares what `ui` draws with `tests/snapshots/{name}.txt`. A missing
+    /// snapshot fails like a changed one; `UPDATE_SNAPSHOTS=1` records them
+    /// all again, so a layout change shows up as a diff of those files to
+    /// review.
+    fn assert_snapshot(name: &str, app: &mut App, width: u16, height: u16) {
+        // The only timing that isn't already fixed by `scrape`.
+        app.last_parse_duration = Duration::ZERO;
+        let actual: String = screen(app, width, height)
+            .iter()
+            .map(|line| format!("{}\n", line.trim_end()))
+            .collect();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/snapshots")
+            .join(format!("{name}.txt"));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) => assert!(
+                actual == expected,
+                "{name} no longer matches {}; rerun with UPDATE_SNAPSHOTS=1 if that's intended.\n\
+                 expected:\n{expected}\nactual:\n{actual}",
+                path.display()
+            ),
+            Err(err) => panic!(
+                "can't read {}: {err}; record it with UPDATE_SNAPSHOTS=1.\nactual:\n{actual}",
+                path.display()
+            ),
+        }
+    }
+
+    #[test]
+    fn node_exporter_snapshots() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(&fixture("node_exporter.prom"))));
+        assert_snapshot("node_exporter_120x40", &mut app, 120, 40);
+        assert_snapshot("node_exporter_80x24", &mut app, 80, 24);
+        assert_snapshot("node_exporter_50x20", &mut app, 50, 20);
+    }
+
+    #[test]
+    fn histogram_snapshots() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(&fixture("histograms.prom"))));
+        assert_snapshot("histograms_120x40", &mut app, 120, 40);
+
+        app.filter = String::from("http_request_duration_seconds");
+        app.perform(Action::OpenDetail);
+        assert!(matches!(&app.view, View::Detail(detail) if detail.family == "http_request_duration_seconds"));
+        assert_snapshot("histograms_detail_120x40", &mut app, 120, 40);
+        assert_snapshot("histograms_detail_80x24", &mut app, 80, 24);
+    }
+
+    #[test]
+    fn parse_error_and_empty_snapshots() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(&fixture("malformed.prom"))));
+        assert!(matches!(app.view, View::ParseError(_)));
+        assert_snapshot("malformed_100x30", &mut app, 100, 30);
+        assert_snapshot("malformed_60x16", &mut app, 60, 16);
+
+        let mut empty = app();
+        empty.apply_fetch(scrape(Ok("")));
+        assert_snapshot("empty_80x24", &mut empty, 80, 24);
+    }
+
+    /// A family of `count` series, each its own request path.
+    fn many_series(count: usize) -> String {
+        let mut text = String::from("# TYPE requests_total counter\n");
+        for index in 0..count {
+            text.push_str(&format!("requests_total{{code=\"200\",path=\"/api/items/{index}\"}} {index}\n"));
+        }
+        text
+    }
+
+    #[test]
+    fn a_family_of_thousands_of_series_stays_responsive() {
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(&many_series(5_000))));
+        assert_snapshot("many_series_120x40", &mut app, 120, 40);
+        app.perform(Action::OpenDetail);
+        assert_snapshot("many_series_detail_120x30", &mut app, 120, 30);
+
+        // Listing and sorting the series is all a frame does with every one
+        // of them; only the page on screen is formatted.
+        // The bound is loose enough for a debug build, while work that grows
+        // with the square of the series, such as formatting each row by
+        // searching the rest, still blows well past it.
+        let started = Instant::now();
+        for _ in 0..20 {
+            app.perform(Action::Down);
+            screen(&mut app, 120, 30);
+        }
+        let per_frame = started.elapsed() / 20;
+        assert!(per_frame < Duration::from_millis(500), "{per_frame:?} a frame");
+    }
+
+    #[test]
+    fn only_the_rows_on_screen_are_built() {
+        let built = std::cell::Cell::new(0);
+        let row = |index: usize| {
+            built.set(built.get() + 1);
+            (Row::new(vec![index.to_string()]), if index % 10 == 0 { 3 } else { 1 })
+        };
+        let window = |selected, offset| {
+            built.set(0);
+            let (first, shown) = visible_rows(row, 100_000, selected, offset, 20);
+            (first, shown.len(), built.get())
+        };
+        // Jumping to the bottom builds the screenful above it and no more.
+        assert_eq!(window(99_999, 0), (99_982, 18, 21));
+        // Back up to the top of the screen, then down within it.
+        assert_eq!(window(50, 99_982), (50, 16, 17));
+        assert_eq!(window(55, 50), (50, 16, 17));
+        assert_eq!(visible_rows(row, 0, 0, 0, 20).1.len(), 0);
+    }
+
+    #[test]
+    fn the_table_scrolls_through_a_hundred_thousand_families() {
+        let text: String = (0..100_000).map(|index| format!("family_{index:06} {index}\n")).collect();
+        let mut app = app();
+        app.apply_fetch(scrape(Ok(&text)));
+        assert!(screen(&mut app, 120, 30).iter().any(|line| line.contains("family_000000")));
+
+        app.perform(Action::Bottom);
+        let lines = screen(&mut app, 120, 30);
+        assert!(lines.iter().any(|line| line.contains(">> family_099999")), "{lines:#?}");
+        assert!(lines.iter().any(|line| line.contains("100000/100000")), "{lines:#?}");
+        assert!(!lines.iter().any(|line| line.contains("family_000000")), "{lines:#?}");
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+mod compare;
+mod report;
+mod spec;
+
+pub use compare::compare;
+pub use report::Report;
+pub use spec::Spec;
+
+/// `promtool verify`: scrape once and compare what's exposed against a spec
+/// of the metrics an exporter promises, for CI after changing the exporter.
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyArgs {
+    /// The endpoint, saved scrape, or `-` for stdin to verify
+    #[arg(value_name = "ENDPOINT")]
+    pub endpoint: String,
+
+    /// The YAML file listing the expected metrics, their types and labels
+    #[arg(long, value_name = "FILE")]
+    pub spec: PathBuf,
+
+    /// Also fail on metrics the spec doesn't list
+    #[arg(long)]
+    pub strict: bool,
+
+    /// How to print the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+}
+
+/// How `verify`, `diff-files` and `metadata-check` print their reports.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// One line per finding, then a summary
+    Text,
+    /// One JSON object with every finding, for scripts and CI annotations
+    Json,
+}
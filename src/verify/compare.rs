@@ -0,0 +1,206 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+use super::spec::Spec;
+use crate::model::{self, Exposition, Family, FamilyType};
+
+/// One way the scrape differs from the spec.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Failure {
+    Missing {
+        metric: String,
+    },
+    WrongType {
+        metric: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// Required labels that some of the metric's series lack.
+    MissingLabels {
+        metric: String,
+        labels: Vec<String>,
+        series: usize,
+    },
+    /// Exposed but not in the spec, only checked with `--strict`.
+    Unexpected {
+        metric: String,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Missing { metric } => write!(f, "{metric} isn't exposed"),
+            Failure::WrongType {
+                metric,
+                expected,
+                actual,
+            } => write!(f, "{metric} is a {actual}, not a {expected}"),
+            Failure::MissingLabels { metric, labels, series } => {
+                let noun = if *series == 1 { "series lacks" } else { "series lack" };
+                write!(f, "{metric}: {series} {noun} {}", labels.join(", "))
+            }
+            Failure::Unexpected { metric } => write!(f, "{metric} isn't in the spec"),
+        }
+    }
+}
+
+/// The family `name` refers to. Like `--require`, a counter matches by its
+/// `_total` sample name as well as its family name, whichever of the two the
+/// parser keyed it by.
+fn find<'a>(metrics: &'a Exposition, name: &str) -> Option<(&'a String, &'a Family)> {
+    let candidates = [
+        Some(name.to_string()),
+        name.strip_suffix("_total").map(String::from),
+        Some(format!("{name}_total")),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|candidate| metrics.families.get_key_value(&candidate))
+}
+
+/// Every failure, in spec order, then the unexpected metrics by name when
+/// `strict`. A metric of the wrong type isn't checked for labels, since its
+/// series aren't the ones the spec describes.
+pub fn compare(spec: &Spec, metrics: &Exposition, strict: bool) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    let mut matched = HashSet::new();
+    for expected in &spec.metrics {
+        let Some((family_name, fam)) = find(metrics, &expected.name) else {
+            failures.push(Failure::Missing {
+                metric: expected.name.clone(),
+            });
+            continue;
+        };
+        matched.insert(family_name.as_str());
+        let actual = FamilyType::of(fam);
+        if actual != expected.family_type {
+            failures.push(Failure::WrongType {
+                metric: expected.name.clone(),
+                expected: expected.family_type.name(),
+                actual: actual.name(),
+            });
+            continue;
+        }
+        let mut lacking = vec![false; expected.labels.len()];
+        let mut series = 0;
+        for sample in fam.iter_samples() {
+            let labels = model::label_pairs(sample);
+            let mut short = false;
+            for (label, lacks) in expected.labels.iter().zip(&mut lacking) {
+                if !labels.iter().any(|(name, _)| name == label) {
+                    *lacks = true;
+                    short = true;
+                }
+            }
+            series += usize::from(short);
+        }
+        if series > 0 {
+            failures.push(Failure::MissingLabels {
+                metric: expected.name.clone(),
+                labels: expected
+                    .labels
+                    .iter()
+                    .zip(&lacking)
+                    .filter(|(_, lacks)| **lacks)
+                    .map(|(label, _)| label.clone())
+                    .collect(),
+                series,
+            });
+        }
+    }
+    if strict {
+        let mut unexpected: Vec<&String> = metrics
+            .families
+            .keys()
+            .filter(|name| !matched.contains(name.as_str()))
+            .collect();
+        unexpected.sort();
+        failures.extend(
+            unexpected
+                .into_iter()
+                .map(|name| Failure::Unexpected { metric: name.clone() }),
+        );
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRAPE: &str = concat!(
+        "# TYPE requests counter\n",
+        "requests_total{method=\"GET\",code=\"200\"} 7\n",
+        "requests_total{code=\"500\"} 1\n",
+        "# TYPE up gauge\n",
+        "up 1\n",
+        "# TYPE queue_depth gauge\n",
+        "queue_depth 3\n",
+        "# TYPE build_info gauge\n",
+        "build_info{version=\"1.2.3\"} 1\n",
+        "# EOF\n",
+    );
+
+    fn verify(spec: &str, strict: bool) -> Vec<Failure> {
+        let metrics = model::parse(SCRAPE, None, model::Format::Openmetrics).unwrap_or_else(|e| panic!("{e}"));
+        let spec = Spec::parse(spec).unwrap_or_else(|e| panic!("{e:#}"));
+        compare(&spec, &metrics, strict)
+    }
+
+    #[test]
+    fn a_matching_scrape_passes() {
+        let spec = concat!(
+            "metrics:\n",
+            "  - {name: requests_total, type: counter, labels: [code]}\n",
+            "  - {name: up, type: gauge}\n",
+        );
+        assert_eq!(verify(spec, false), Vec::new());
+    }
+
+    #[test]
+    fn missing_metrics_wrong_types_and_missing_labels_fail() {
+        let spec = concat!(
+            "metrics:\n",
+            "  - {name: requests, type: counter, labels: [code, method, handler]}\n",
+            "  - {name: up, type: counter, labels: [job]}\n",
+            "  - {name: scrape_errors_total, type: counter}\n",
+        );
+        assert_eq!(
+            verify(spec, false),
+            vec![
+                Failure::MissingLabels {
+                    metric: String::from("requests"),
+                    labels: vec![String::from("method"), String::from("handler")],
+                    series: 2,
+                },
+                Failure::WrongType {
+                    metric: String::from("up"),
+                    expected: "counter",
+                    actual: "gauge",
+                },
+                Failure::Missing {
+                    metric: String::from("scrape_errors_total"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_also_fails_on_unlisted_metrics() {
+        let spec = "metrics:\n  - {name: requests_total, type: counter}\n";
+        assert_eq!(verify(spec, false), Vec::new());
+        let unexpected: Vec<String> = verify(spec, true).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            unexpected,
+            vec![
+                "build_info isn't in the spec",
+                "queue_depth isn't in the spec",
+                "up isn't in the spec",
+            ]
+        );
+    }
+}
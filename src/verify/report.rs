@@ -0,0 +1,126 @@
+use serde::Serialize;
+
+use super::compare::Failure;
+
+/// The outcome of one `promtool verify`.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub endpoint: String,
+    /// How many metrics the spec lists.
+    pub checked: usize,
+    pub passed: bool,
+    pub failures: Vec<Entry>,
+}
+
+/// A failure with its message spelled out, so CI can annotate with it
+/// without knowing every kind.
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub failure: Failure,
+    pub message: String,
+}
+
+impl Report {
+    pub fn new(endpoint: &str, checked: usize, failures: Vec<Failure>) -> Report {
+        Report {
+            endpoint: endpoint.to_string(),
+            checked,
+            passed: failures.is_empty(),
+            failures: failures
+                .into_iter()
+                .map(|failure| Entry {
+                    message: failure.to_string(),
+                    failure,
+                })
+                .collect(),
+        }
+    }
+
+    /// `FAIL: <message>` per failure, then an `OK:` or `FAIL:` summary, like
+    /// `promtool check` prints.
+    pub fn text(&self) -> String {
+        let mut text: String = self
+            .failures
+            .iter()
+            .map(|entry| format!("FAIL: {}\n", entry.message))
+            .collect();
+        let metrics = if self.checked == 1 { "metric" } else { "metrics" };
+        if self.passed {
+            text.push_str(&format!(
+                "OK: {} matches the spec ({} {metrics})\n",
+                self.endpoint, self.checked
+            ));
+        } else {
+            let failures = if self.failures.len() == 1 {
+                "failure"
+            } else {
+                "failures"
+            };
+            text.push_str(&format!(
+                "FAIL: {} doesn't match the spec ({} {metrics}, {} {failures})\n",
+                self.endpoint,
+                self.checked,
+                self.failures.len()
+            ));
+        }
+        text
+    }
+
+    pub fn json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failing() -> Report {
+        let failures = vec![
+            Failure::Missing {
+                metric: String::from("scrape_errors_total"),
+            },
+            Failure::MissingLabels {
+                metric: String::from("requests"),
+                labels: vec![String::from("method")],
+                series: 1,
+            },
+        ];
+        Report::new("localhost:9100", 3, failures)
+    }
+
+    #[test]
+    fn text_reports_list_failures_then_a_summary() {
+        assert_eq!(
+            failing().text(),
+            concat!(
+                "FAIL: scrape_errors_total isn't exposed\n",
+                "FAIL: requests: 1 series lacks method\n",
+                "FAIL: localhost:9100 doesn't match the spec (3 metrics, 2 failures)\n",
+            )
+        );
+        assert_eq!(
+            Report::new("localhost:9100", 1, Vec::new()).text(),
+            "OK: localhost:9100 matches the spec (1 metric)\n"
+        );
+    }
+
+    #[test]
+    fn json_reports_tag_each_failure_with_its_kind() {
+        let json: serde_json::Value = serde_json::from_str(&failing().json()).unwrap();
+        assert_eq!(json["passed"], false);
+        assert_eq!(json["checked"], 3);
+        assert_eq!(
+            json["failures"][1],
+            serde_json::json!({
+                "kind": "missing_labels",
+                "metric": "requests",
+                "labels": ["method"],
+                "series": 1,
+                "message": "requests: 1 series lacks method",
+            })
+        );
+        assert_eq!(json["failures"][0]["kind"], "missing");
+    }
+}
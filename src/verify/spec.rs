@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::model::FamilyType;
+
+/// The metrics an exporter promises to expose, e.g.
+///
+/// ```yaml
+/// metrics:
+///   - name: http_requests_total
+///     type: counter
+///     labels: [method, code]
+///   - name: up
+///     type: gauge
+/// ```
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub metrics: Vec<Expected>,
+}
+
+/// One metric of the spec.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Expected {
+    /// The family name; a counter's `_total` sample name works too.
+    pub name: String,
+    #[serde(rename = "type")]
+    pub family_type: FamilyType,
+    /// Labels every one of its series must have.
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl Spec {
+    pub fn load(path: &Path) -> Result<Spec> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+        Spec::parse(&text).with_context(|| format!("{} isn't a valid spec", path.display()))
+    }
+
+    /// Misspelled keys and metrics listed twice are errors rather than
+    /// silently checking less than the file says.
+    pub fn parse(text: &str) -> Result<Spec> {
+        let spec: Spec = serde_yaml::from_str(text)?;
+        let mut seen = HashSet::new();
+        if let Some(twice) = spec.metrics.iter().find(|metric| !seen.insert(metric.name.as_str())) {
+            anyhow::bail!("{} is listed twice", twice.name);
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_list_names_types_and_optional_labels() {
+        let spec = Spec::parse(concat!(
+            "metrics:\n",
+            "  - name: http_requests_total\n",
+            "    type: counter\n",
+            "    labels: [method, code]\n",
+            "  - {name: up, type: gauge}\n",
+        ))
+        .unwrap_or_else(|e| panic!("{e:#}"));
+        assert_eq!(
+            spec.metrics,
+            vec![
+                Expected {
+                    name: String::from("http_requests_total"),
+                    family_type: FamilyType::Counter,
+                    labels: vec![String::from("method"), String::from("code")],
+                },
+                Expected {
+                    name: String::from("up"),
+                    family_type: FamilyType::Gauge,
+                    labels: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        let errors = [
+            ("metrics:\n  - {name: up}\n", "type"),
+            ("metrics:\n  - {name: up, type: meter}\n", "meter"),
+            ("metrics:\n  - {name: up, type: gauge, label: [job]}\n", "label"),
+            (
+                "metrics:\n  - {name: up, type: gauge}\n  - {name: up, type: gauge}\n",
+                "up is listed twice",
+            ),
+        ];
+        for (text, expected) in errors {
+            let error = format!("{:#}", Spec::parse(text).unwrap_err());
+            assert!(error.contains(expected), "{text:?}: {error}");
+        }
+    }
+}
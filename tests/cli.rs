@@ -0,0 +1,68 @@
+use assert_cmd::Command;
+use predicates::str::{contains, starts_with};
+
+const FIXTURE: &str = "tests/fixtures/node_exporter.prom";
+
+/// The binary with `args`, ignoring whatever config file the user has.
+fn promtool(args: &[&str]) -> Command {
+    let mut cmd = Command::cargo_bin("prometheus_metrics_viewer").unwrap();
+    cmd.args(args).arg("--no-config");
+    cmd
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = promtool(args).assert().success().get_output().stdout.clone();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn bare_endpoints_are_the_tui_subcommand() {
+    let bare = stdout(&[FIXTURE, "--no-tui"]);
+    assert!(bare.contains("node_cpu_seconds_total"), "{bare}");
+    assert_eq!(stdout(&["tui", FIXTURE, "--no-tui"]), bare);
+    assert_eq!(stdout(&["get", FIXTURE]), bare);
+    assert_eq!(
+        stdout(&[FIXTURE, "--output", "csv", "--type", "gauge"]),
+        stdout(&["get", FIXTURE, "--output", "csv", "--type", "gauge"])
+    );
+}
+
+#[test]
+fn subcommands_take_the_shared_options_after_their_name() {
+    promtool(&["check", FIXTURE, "--timeout", "5s", "--header", "X-Scope-OrgID: tenant1"])
+        .assert()
+        .success()
+        .stdout(starts_with("OK:"));
+    promtool(&["--timeout", "5s", "check", FIXTURE])
+        .assert()
+        .failure()
+        .stderr(contains("options of `promtool check` go after `check`"));
+}
+
+#[test]
+fn options_of_another_subcommand_say_where_they_belong() {
+    promtool(&["check", FIXTURE, "--interval", "5s"])
+        .assert()
+        .code(2)
+        .stderr(contains("--interval is an option of `promtool tui`"));
+    promtool(&[FIXTURE, "--no-tui", "--require", "node_cpu_seconds_total"])
+        .assert()
+        .code(2)
+        .stderr(contains("--require is an option of `promtool check`"));
+}
+
+#[test]
+fn diff_files_exits_1_when_the_scrapes_differ() {
+    promtool(&["diff-files", FIXTURE, FIXTURE])
+        .assert()
+        .success()
+        .stdout(contains("match"));
+    promtool(&["diff-files", FIXTURE, "tests/fixtures/histograms.prom", "--ignore-label", "instance"])
+        .assert()
+        .code(1)
+        .stdout(contains("series added"));
+    promtool(&["diff-files", FIXTURE, FIXTURE, "--threshold=-5%"])
+        .assert()
+        .code(2)
+        .stderr(contains("threshold -5% is negative"));
+}